@@ -0,0 +1,1384 @@
+//! A small assembler for the subset of RGBDS that test ROMs are written in, so tests can build
+//! cartridges inline without `rgbasm`, `rgblink` and `rgbfix`
+
+use crate::cartridge::fix::Fix;
+use crate::cartridge::parse::ParseError;
+use crate::opcodes::{Flow, Opcode, CB_OPCODES, OPCODES};
+use crate::symbols::Symbols;
+use std::collections::{HashMap, HashSet};
+use std::{error, fmt};
+
+const ROM_BANK_SIZE_BYTES: usize = 0x4000;
+const ROM0_END: u32 = 0x3FFF;
+const ROMX_START: u32 = 0x4000;
+const ROMX_END: u32 = 0x7FFF;
+
+/// What the ROM is padded with up to a valid ROM size, like `rgbfix -p 0xFF` in the Makefile
+const PAD: u8 = 0xFF;
+
+/// Macros calling macros, or loops in loops, any deeper than this are taken to have run away
+const MAX_DEPTH: usize = 64;
+
+/// The only include there is, since its registers are built in
+const HARDWARE_INC: &str = "hardware.inc";
+
+/// The registers and areas of memory from `hardware.inc`, and the cartridge values test ROMs use
+const HARDWARE_CONSTANTS: [(&str, i64); 42] = [
+    ("_VRAM", 0x8000),
+    ("_SCRN0", 0x9800),
+    ("_SCRN1", 0x9C00),
+    ("_SRAM", 0xA000),
+    ("_RAM", 0xC000),
+    ("_RAMBANK", 0xD000),
+    ("_OAMRAM", 0xFE00),
+    ("_IO", 0xFF00),
+    ("_HRAM", 0xFF80),
+    ("rP1", 0xFF00),
+    ("rSB", 0xFF01),
+    ("rSC", 0xFF02),
+    ("rDIV", 0xFF04),
+    ("rTIMA", 0xFF05),
+    ("rTMA", 0xFF06),
+    ("rTAC", 0xFF07),
+    ("rIF", 0xFF0F),
+    ("rLCDC", 0xFF40),
+    ("rSTAT", 0xFF41),
+    ("rSCY", 0xFF42),
+    ("rSCX", 0xFF43),
+    ("rLY", 0xFF44),
+    ("rLYC", 0xFF45),
+    ("rDMA", 0xFF46),
+    ("rBGP", 0xFF47),
+    ("rOBP0", 0xFF48),
+    ("rOBP1", 0xFF49),
+    ("rWY", 0xFF4A),
+    ("rWX", 0xFF4B),
+    ("rKEY1", 0xFF4D),
+    ("rVBK", 0xFF4F),
+    ("rHDMA1", 0xFF51),
+    ("rHDMA2", 0xFF52),
+    ("rHDMA3", 0xFF53),
+    ("rHDMA4", 0xFF54),
+    ("rHDMA5", 0xFF55),
+    ("rSVBK", 0xFF70),
+    ("rIE", 0xFFFF),
+    ("rRAMG", 0x0000),
+    ("CART_ROM_MBC1_RAM_BAT", 0x03),
+    ("CART_SRAM_8KB", 0x02),
+    ("CART_SRAM_ENABLE", 0x0A),
+];
+
+/// Instructions on A that can be written without it, like `sub b`
+const ACCUMULATOR_INSTRUCTIONS: [&str; 8] = ["add", "adc", "sub", "sbc", "and", "xor", "or", "cp"];
+
+/// Binary operators from loosest to tightest binding, as RGBDS ranks them
+const OPERATOR_LEVELS: [&[&str]; 7] = [
+    &["||"],
+    &["&&"],
+    &["==", "!=", "<=", ">=", "<", ">"],
+    &["+", "-"],
+    &["&", "|", "^"],
+    &["<<", ">>"],
+    &["*", "/", "%"],
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum AssemblyError {
+    /// A line that couldn't be assembled
+    Line {
+        /// Counting from 1, like a text editor
+        line_number: usize,
+        line: String,
+        message: String,
+    },
+    /// The header fields asked for can't be written
+    Header(ParseError),
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AssemblyError::Line {
+                line_number,
+                line,
+                message,
+            } => write!(f, "line {}: {}: {:?}", line_number, message, line.trim()),
+            AssemblyError::Header(error) => write!(f, "can't write the header: {}", error),
+        }
+    }
+}
+
+impl error::Error for AssemblyError {}
+
+/// An assembled cartridge, with its labels as `rgblink -n` would have written them
+#[derive(Clone, Debug)]
+pub struct Assembly {
+    pub rom: Vec<u8>,
+    pub symbols: Symbols,
+}
+
+/// Assembles RGBDS source into a cartridge image with a valid header, logo and checksums
+///
+/// Sources are a single file, with `SECTION`s in ROM0 or ROMX, labels, `DEF`/`EQU` constants,
+/// `db`, `dw` and `ds`, `IF`, `REPT`, `FOR` and `MACRO` blocks, and every SM83 instruction.
+/// `INCLUDE "hardware.inc"` defines the usual register names rather than reading the file.
+/// Sections without an address follow the last one in their bank, instead of being placed by a
+/// linker, and a bank's unused bytes are 0 like `rgblink` leaves them.
+pub struct Assembler {
+    constants: HashMap<String, i64>,
+    header: Fix,
+    templates: HashMap<&'static str, Vec<Template>>,
+    /// Operands that are registers or conditions, rather than expressions
+    registers: HashSet<&'static str>,
+}
+
+impl Default for Assembler {
+    fn default() -> Self {
+        Assembler::new()
+    }
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        let opcodes = OPCODES
+            .iter()
+            .enumerate()
+            .map(|(code, opcode)| (vec![code as u8], opcode))
+            .chain(
+                CB_OPCODES
+                    .iter()
+                    .enumerate()
+                    .map(|(code, opcode)| (vec![0xCB, code as u8], opcode)),
+            )
+            .filter(|(_, opcode)| !matches!(opcode.flow, Flow::Illegal | Flow::Prefix));
+
+        let mut templates: HashMap<&'static str, Vec<Template>> = HashMap::new();
+        let mut registers = HashSet::new();
+        for (bytes, opcode) in opcodes {
+            let template = Template::new(bytes, opcode);
+            for operand in &template.operands {
+                if let Pattern::Register(register) = operand {
+                    registers.insert(*register);
+                }
+            }
+            templates.entry(template.name).or_default().push(template);
+        }
+
+        Assembler {
+            constants: HashMap::new(),
+            header: Fix {
+                pad: Some(PAD),
+                ..Fix::default()
+            },
+            templates,
+            registers,
+        }
+    }
+
+    /// Define a constant for the source to use, like `rgbasm -D`
+    pub fn define(mut self, name: &str, value: i64) -> Self {
+        self.constants.insert(name.to_string(), value);
+        self
+    }
+
+    /// Header fields to write, where the default only pads the ROM with $FF
+    /// The logo and checksums are always written. Without `pad`, the ROM is as many banks long
+    /// as the source fills, which needs to be a valid ROM size.
+    pub fn with_header(mut self, header: Fix) -> Self {
+        self.header = header;
+        self
+    }
+
+    pub fn assemble(&self, source: &str) -> Result<Assembly, AssemblyError> {
+        let lines: Vec<Line> = source
+            .lines()
+            .enumerate()
+            .map(|(index, text)| Line {
+                number: index + 1,
+                text: text.to_string(),
+            })
+            .collect();
+
+        // Sizes never depend on labels, so the first pass places every label for the second
+        let labels = Pass::new(self, HashMap::new(), false).run(&lines)?.labels;
+        let pass = Pass::new(self, labels, true).run(&lines)?;
+
+        let mut rom = pass.rom;
+        let banks = rom.len().div_ceil(ROM_BANK_SIZE_BYTES).max(1);
+        rom.resize(banks * ROM_BANK_SIZE_BYTES, 0);
+        self.header.apply(&mut rom).map_err(AssemblyError::Header)?;
+
+        let mut symbols = Symbols::default();
+        for (name, (bank, address)) in &pass.labels {
+            symbols.insert(*bank, *address, name);
+        }
+
+        Ok(Assembly { rom, symbols })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Placeholder {
+    N8,
+    N16,
+    A8,
+    A16,
+    E8,
+    S8,
+}
+
+impl Placeholder {
+    const ALL: [(&'static str, Placeholder); 6] = [
+        ("n16", Placeholder::N16),
+        ("n8", Placeholder::N8),
+        ("a16", Placeholder::A16),
+        ("a8", Placeholder::A8),
+        ("e8", Placeholder::E8),
+        ("s8", Placeholder::S8),
+    ];
+}
+
+/// One operand of an opcode's mnemonic
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Pattern {
+    /// A register, or a condition like `nz`, written as is
+    Register(&'static str),
+    /// A number fixed by the opcode, like a bit index or `rst` vector
+    Number(i64),
+    /// A value encoded after the opcode, maybe in brackets or as an offset from SP
+    Value {
+        placeholder: Placeholder,
+        brackets: bool,
+        sp_offset: bool,
+    },
+}
+
+impl Pattern {
+    fn new(operand: &'static str) -> Pattern {
+        if let Some((_, placeholder)) = Placeholder::ALL
+            .iter()
+            .find(|(name, _)| operand.contains(name))
+        {
+            return Pattern::Value {
+                placeholder: *placeholder,
+                brackets: operand.starts_with('['),
+                sp_offset: operand.starts_with("sp+"),
+            };
+        }
+
+        match operand.strip_prefix('$') {
+            Some(hex) => Pattern::Number(i64::from_str_radix(hex, 16).unwrap()),
+            None => match operand.parse() {
+                Ok(number) => Pattern::Number(number),
+                Err(_) => Pattern::Register(operand),
+            },
+        }
+    }
+}
+
+/// How to assemble one opcode, from its mnemonic in the opcode table
+#[derive(Clone, Debug)]
+struct Template {
+    name: &'static str,
+    operands: Vec<Pattern>,
+    /// The opcode, and the prefix before it if there is one
+    bytes: Vec<u8>,
+    length: usize,
+}
+
+impl Template {
+    fn new(bytes: Vec<u8>, opcode: &'static Opcode) -> Template {
+        let (name, operands) = opcode
+            .mnemonic
+            .split_once(' ')
+            .unwrap_or((opcode.mnemonic, ""));
+
+        Template {
+            name,
+            operands: operands
+                .split(", ")
+                .filter(|operand| !operand.is_empty())
+                .map(Pattern::new)
+                .collect(),
+            bytes,
+            length: opcode.length as usize,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Line {
+    number: usize,
+    text: String,
+}
+
+/// The arguments of the macro being expanded, which `SHIFT` moves along
+struct MacroArguments {
+    arguments: Vec<String>,
+    shift: usize,
+}
+
+/// Why an expression has no value
+enum ExpressionError {
+    /// Not yet, at least, since labels further on aren't known in the first pass
+    Undefined(String),
+    Invalid(String),
+}
+
+impl From<ExpressionError> for String {
+    fn from(error: ExpressionError) -> String {
+        match error {
+            ExpressionError::Undefined(name) => format!("{} isn't defined", name),
+            ExpressionError::Invalid(message) => message,
+        }
+    }
+}
+
+/// Where bytes are going, in the section being assembled
+struct Cursor {
+    bank: u8,
+    address: u32,
+    end: u32,
+}
+
+/// One pass over the source, placing every label and writing every byte
+struct Pass<'a> {
+    assembler: &'a Assembler,
+    /// Whether labels are all known, so operands can be evaluated
+    last: bool,
+    /// Labels from the pass before, so ones further on can be used
+    earlier_labels: HashMap<String, (u8, u16)>,
+    labels: HashMap<String, (u8, u16)>,
+    constants: HashMap<String, i64>,
+    macros: HashMap<String, Vec<Line>>,
+    hardware: bool,
+    /// The last label not starting with `.`, which local labels belong to
+    scope: Option<String>,
+    cursor: Option<Cursor>,
+    /// Where each bank's last section ended, for sections without an address
+    bank_ends: HashMap<u8, u32>,
+    rom: Vec<u8>,
+    written: Vec<bool>,
+    depth: usize,
+}
+
+impl<'a> Pass<'a> {
+    fn new(
+        assembler: &'a Assembler,
+        earlier_labels: HashMap<String, (u8, u16)>,
+        last: bool,
+    ) -> Self {
+        Pass {
+            assembler,
+            last,
+            earlier_labels,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            macros: HashMap::new(),
+            hardware: false,
+            scope: None,
+            cursor: None,
+            bank_ends: HashMap::new(),
+            rom: Vec::new(),
+            written: Vec::new(),
+            depth: 0,
+        }
+    }
+
+    fn run(mut self, lines: &[Line]) -> Result<Self, AssemblyError> {
+        self.run_lines(lines, &mut None)?;
+        Ok(self)
+    }
+
+    fn run_lines(
+        &mut self,
+        lines: &[Line],
+        arguments: &mut Option<MacroArguments>,
+    ) -> Result<(), AssemblyError> {
+        let mut index = 0;
+
+        while index < lines.len() {
+            let line = &lines[index];
+            let error = |message: String| AssemblyError::Line {
+                line_number: line.number,
+                line: line.text.clone(),
+                message,
+            };
+
+            let text = substitute(&line.text, arguments.as_ref()).map_err(error)?;
+            let (label, statement) = split_label(strip_comment(&text));
+            if let Some(label) = label {
+                self.define_label(label).map_err(error)?;
+            }
+            let (keyword, rest) = split_keyword(statement);
+
+            match keyword.to_ascii_uppercase().as_str() {
+                "REPT" | "FOR" => {
+                    let (_, end) =
+                        find_block(lines, index, &["REPT", "FOR"], "ENDR", None).map_err(error)?;
+                    self.repeat(keyword, rest, &lines[index + 1..end], arguments, line)?;
+                    index = end;
+                }
+                "IF" => {
+                    let (otherwise, end) =
+                        find_block(lines, index, &["IF"], "ENDC", Some("ELSE")).map_err(error)?;
+                    let condition = self.evaluate(rest).map_err(|e| error(e.into()))?;
+                    let body = match (condition != 0, otherwise) {
+                        (true, Some(otherwise)) => &lines[index + 1..otherwise],
+                        (true, None) => &lines[index + 1..end],
+                        (false, Some(otherwise)) => &lines[otherwise + 1..end],
+                        (false, None) => &[],
+                    };
+                    self.nested(body, arguments, line)?;
+                    index = end;
+                }
+                "MACRO" => {
+                    let (_, end) =
+                        find_block(lines, index, &["MACRO"], "ENDM", None).map_err(error)?;
+                    self.macros
+                        .insert(rest.trim().to_string(), lines[index + 1..end].to_vec());
+                    index = end;
+                }
+                "ENDR" | "ENDC" | "ELSE" | "ENDM" => {
+                    return Err(error(format!("{} without a block to end", keyword)));
+                }
+                "SHIFT" => {
+                    let count = match rest.trim() {
+                        "" => 1,
+                        count => self.evaluate(count).map_err(|e| error(e.into()))? as usize,
+                    };
+                    match arguments {
+                        Some(arguments) => arguments.shift += count,
+                        None => return Err(error("SHIFT outside a macro".to_string())),
+                    }
+                }
+                _ => match self.macros.get(keyword) {
+                    Some(body) => {
+                        let body = body.clone();
+                        let mut arguments = Some(MacroArguments {
+                            arguments: split_arguments(rest)
+                                .into_iter()
+                                .map(str::to_string)
+                                .collect(),
+                            shift: 0,
+                        });
+                        self.nested(&body, &mut arguments, line)?;
+                    }
+                    None => self.statement(keyword, rest).map_err(error)?,
+                },
+            }
+
+            index += 1;
+        }
+
+        Ok(())
+    }
+
+    fn nested(
+        &mut self,
+        lines: &[Line],
+        arguments: &mut Option<MacroArguments>,
+        from: &Line,
+    ) -> Result<(), AssemblyError> {
+        if self.depth == MAX_DEPTH {
+            return Err(AssemblyError::Line {
+                line_number: from.number,
+                line: from.text.clone(),
+                message: format!("blocks nest more than {} deep", MAX_DEPTH),
+            });
+        }
+
+        self.depth += 1;
+        let result = self.run_lines(lines, arguments);
+        self.depth -= 1;
+        result
+    }
+
+    /// `REPT count` or `FOR name, [start,] stop[, step]`
+    fn repeat(
+        &mut self,
+        keyword: &str,
+        rest: &str,
+        body: &[Line],
+        arguments: &mut Option<MacroArguments>,
+        line: &Line,
+    ) -> Result<(), AssemblyError> {
+        let error = |message: String| AssemblyError::Line {
+            line_number: line.number,
+            line: line.text.clone(),
+            message,
+        };
+
+        if keyword.eq_ignore_ascii_case("REPT") {
+            let count = self.evaluate(rest).map_err(|e| error(e.into()))?;
+            for _ in 0..count {
+                self.nested(body, arguments, line)?;
+            }
+            return Ok(());
+        }
+
+        let parts = split_arguments(rest);
+        let values = parts
+            .iter()
+            .skip(1)
+            .map(|part| self.evaluate(part))
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|e| error(e.into()))?;
+        let (name, (start, stop, step)) = match (parts.first(), values.as_slice()) {
+            (Some(name), [stop]) => (name, (0, *stop, 1)),
+            (Some(name), [start, stop]) => (name, (*start, *stop, 1)),
+            (Some(name), [start, stop, step]) if *step != 0 => (name, (*start, *stop, *step)),
+            _ => {
+                return Err(error(
+                    "FOR needs a name, and a stop or start, stop and step".into(),
+                ))
+            }
+        };
+
+        let mut value = start;
+        while (step > 0 && value < stop) || (step < 0 && value > stop) {
+            self.constants.insert(name.to_string(), value);
+            self.nested(body, arguments, line)?;
+            value += step;
+        }
+        self.constants.insert(name.to_string(), value);
+
+        Ok(())
+    }
+
+    fn statement(&mut self, keyword: &str, rest: &str) -> Result<(), String> {
+        match keyword.to_ascii_uppercase().as_str() {
+            "" => Ok(()),
+            "SECTION" => self.section(rest),
+            "INCLUDE" => match rest.trim().trim_matches('"') {
+                HARDWARE_INC => {
+                    self.hardware = true;
+                    Ok(())
+                }
+                file => Err(format!(
+                    "only {} can be included, with its registers built in, not {}",
+                    HARDWARE_INC, file
+                )),
+            },
+            "DEF" => {
+                let (name, definition) = split_keyword(rest);
+                self.define_constant(name, definition)
+            }
+            "DB" => self.data(rest, 1),
+            "DW" => self.data(rest, 2),
+            "DS" => {
+                let parts = split_arguments(rest);
+                let count = match parts.first() {
+                    Some(count) => check_range(self.evaluate(count)?, 0..=ROMX_END as i64)?,
+                    None => return Err("ds needs a count".to_string()),
+                };
+                let fill = match parts.len() {
+                    1 => vec![0],
+                    _ => self.bytes(&parts[1..], 1)?,
+                };
+                let bytes: Vec<u8> = fill.iter().copied().cycle().take(count as usize).collect();
+                self.emit(&bytes)
+            }
+            _ => match split_keyword(rest) {
+                (word, _) if word.eq_ignore_ascii_case("EQU") || word == "=" => {
+                    self.define_constant(keyword, rest)
+                }
+                _ => self.instruction(keyword, rest),
+            },
+        }
+    }
+
+    /// `SECTION "name", ROM0[$address]` or `SECTION "name", ROMX[$address], BANK[bank]`,
+    /// where the address and bank are optional
+    fn section(&mut self, rest: &str) -> Result<(), String> {
+        self.end_section();
+        self.cursor = None;
+
+        let parts = split_arguments(rest);
+        let (kind, address) = match parts.get(1) {
+            Some(kind) => match kind.split_once('[') {
+                Some((kind, address)) => (kind.trim(), Some(address.trim_end_matches(']'))),
+                None => (kind.trim(), None),
+            },
+            None => return Err("a section needs a name and a type".to_string()),
+        };
+        let bank = match parts.get(2).and_then(|bank| bank.strip_prefix("BANK[")) {
+            Some(bank) => Some(self.evaluate(bank.trim_end_matches(']'))?),
+            None => None,
+        };
+
+        let (bank, start, end) = match (kind.to_ascii_uppercase().as_str(), bank) {
+            ("ROM0", None) => (0, 0, ROM0_END),
+            ("ROMX", None) => (1, ROMX_START, ROMX_END),
+            ("ROMX", Some(bank @ 1..=0xFF)) => (bank as u8, ROMX_START, ROMX_END),
+            ("ROMX", Some(bank)) => return Err(format!("ROMX can't be bank {}", bank)),
+            ("ROM0", Some(_)) => return Err("only ROMX sections have banks".into()),
+            (kind, _) => {
+                return Err(format!(
+                    "only ROM0 and ROMX sections can be used, not {}",
+                    kind
+                ))
+            }
+        };
+        let address = match address {
+            Some(address) => self.evaluate(address)? as u32,
+            None => *self.bank_ends.get(&bank).unwrap_or(&start),
+        };
+        if !(start..=end).contains(&address) {
+            return Err(format!("{:04X} isn't in {}", address, kind));
+        }
+
+        self.cursor = Some(Cursor { bank, address, end });
+        Ok(())
+    }
+
+    fn end_section(&mut self) {
+        if let Some(cursor) = &self.cursor {
+            let bank_end = self.bank_ends.entry(cursor.bank).or_default();
+            *bank_end = (*bank_end).max(cursor.address);
+        }
+    }
+
+    fn define_label(&mut self, label: &str) -> Result<(), String> {
+        let name = self.scoped(label)?;
+        let (bank, address) = match &self.cursor {
+            Some(cursor) => (cursor.bank, cursor.address as u16),
+            None => return Err(format!("{} is outside a section", name)),
+        };
+        if !label.starts_with('.') {
+            self.scope = Some(name.clone());
+        }
+
+        match self.labels.insert(name.clone(), (bank, address)) {
+            Some(_) => Err(format!("{} is already defined", name)),
+            None => Ok(()),
+        }
+    }
+
+    /// Local labels starting with `.` are named after the label before them
+    fn scoped(&self, name: &str) -> Result<String, String> {
+        match (name.starts_with('.'), &self.scope) {
+            (false, _) => Ok(name.to_string()),
+            (true, Some(scope)) => Ok(format!("{}{}", scope, name)),
+            (true, None) => Err(format!("{} has no label before it to belong to", name)),
+        }
+    }
+
+    /// `NAME EQU value` or `NAME = value`, where only `=` can be redefined
+    fn define_constant(&mut self, name: &str, definition: &str) -> Result<(), String> {
+        let (operator, value) = split_keyword(definition);
+        let value = self.evaluate(value)?;
+
+        if operator.eq_ignore_ascii_case("EQU") {
+            if self.constants.contains_key(name) {
+                return Err(format!("{} is already defined", name));
+            }
+        } else if operator != "=" {
+            return Err(format!("{} needs EQU or = and a value", name));
+        }
+
+        self.constants.insert(name.to_string(), value);
+        Ok(())
+    }
+
+    fn data(&mut self, rest: &str, width: usize) -> Result<(), String> {
+        let items = split_arguments(rest);
+        let bytes = self.bytes(&items, width)?;
+        self.emit(&bytes)
+    }
+
+    /// Values `width` bytes wide, little endian, and strings as their bytes
+    fn bytes(&self, items: &[&str], width: usize) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+
+        for item in items {
+            if let Some(text) = item.strip_prefix('"') {
+                bytes.extend(unescape(text.strip_suffix('"').unwrap_or(text))?);
+                continue;
+            }
+
+            let value = match width {
+                1 => check_range(self.operand(item)?, -0x80..=0xFF)?,
+                _ => check_range(self.operand(item)?, -0x8000..=0xFFFF)?,
+            };
+            bytes.extend(&value.to_le_bytes()[..width]);
+        }
+
+        Ok(bytes)
+    }
+
+    fn instruction(&mut self, mnemonic: &str, rest: &str) -> Result<(), String> {
+        let name = mnemonic.to_ascii_lowercase();
+        let templates = match self.assembler.templates.get(name.as_str()) {
+            Some(templates) => templates,
+            None => {
+                return Err(format!(
+                    "{} isn't an instruction, directive or macro",
+                    mnemonic
+                ))
+            }
+        };
+
+        let operands = split_arguments(rest);
+        // Like RGBDS, `sub b` and friends can leave out A
+        let with_a: Vec<&str> = match ACCUMULATOR_INSTRUCTIONS.contains(&name.as_str()) {
+            true => std::iter::once("a")
+                .chain(operands.iter().copied())
+                .collect(),
+            false => Vec::new(),
+        };
+        let (template, operands) = templates
+            .iter()
+            .find_map(|template| {
+                [&operands, &with_a].into_iter().find_map(|operands| {
+                    self.matches(template, operands)
+                        .then_some((template, operands))
+                })
+            })
+            .ok_or_else(|| format!("{} can't take the operands {:?}", name, rest.trim()))?;
+
+        let address = self
+            .cursor
+            .as_ref()
+            .map_or(0, |cursor| cursor.address as i64);
+        let mut bytes = template.bytes.clone();
+        for (pattern, operand) in template.operands.iter().zip(operands.iter()) {
+            if let Pattern::Value {
+                placeholder,
+                brackets,
+                sp_offset,
+            } = pattern
+            {
+                let expression = match (brackets, sp_offset) {
+                    (true, _) => &operand[1..operand.len() - 1],
+                    // The sign goes with the offset
+                    (_, true) => operand.trim()[2..].trim(),
+                    _ => operand,
+                };
+                let value = self.operand(expression)?;
+                bytes.extend(self.encode(*placeholder, value, address + template.length as i64)?);
+            }
+        }
+        // `stop` is followed by a byte that it ignores
+        bytes.resize(template.length, 0);
+
+        self.emit(&bytes)
+    }
+
+    fn matches(&self, template: &Template, operands: &[&str]) -> bool {
+        template.operands.len() == operands.len()
+            && template
+                .operands
+                .iter()
+                .zip(operands)
+                .all(|(pattern, operand)| {
+                    let normalized = normalize(operand);
+                    let is_register = self.assembler.registers.contains(normalized.as_str());
+
+                    match pattern {
+                        Pattern::Register(register) => normalized == *register,
+                        Pattern::Number(number) => {
+                            !is_register && self.evaluate(operand).ok() == Some(*number)
+                        }
+                        Pattern::Value {
+                            brackets,
+                            sp_offset,
+                            ..
+                        } => {
+                            let is_bracketed =
+                                normalized.starts_with('[') && normalized.ends_with(']');
+                            let is_sp_offset =
+                                normalized.starts_with("sp+") || normalized.starts_with("sp-");
+                            !is_register && *brackets == is_bracketed && *sp_offset == is_sp_offset
+                        }
+                    }
+                })
+    }
+
+    /// The bytes after the opcode for a value, where `next` is the address after the instruction
+    fn encode(&self, placeholder: Placeholder, value: i64, next: i64) -> Result<Vec<u8>, String> {
+        if !self.last {
+            let width = match placeholder {
+                Placeholder::N16 | Placeholder::A16 => 2,
+                _ => 1,
+            };
+            return Ok(vec![0; width]);
+        }
+
+        Ok(match placeholder {
+            Placeholder::N8 => vec![check_range(value, -0x80..=0xFF)? as u8],
+            Placeholder::N16 => check_range(value, -0x8000..=0xFFFF)?.to_le_bytes()[..2].to_vec(),
+            Placeholder::A16 => check_range(value, 0..=0xFFFF)?.to_le_bytes()[..2].to_vec(),
+            Placeholder::A8 => match value {
+                0xFF00..=0xFFFF | 0x00..=0xFF => vec![value as u8],
+                _ => return Err(format!("${:04X} isn't in $FF00-$FFFF", value)),
+            },
+            Placeholder::E8 => match value - next {
+                offset @ -0x80..=0x7F => vec![offset as u8],
+                _ => {
+                    return Err(format!(
+                        "${:04X} is too far away to jump to relatively",
+                        value
+                    ))
+                }
+            },
+            Placeholder::S8 => vec![check_range(value, -0x80..=0x7F)? as u8],
+        })
+    }
+
+    fn emit(&mut self, bytes: &[u8]) -> Result<(), String> {
+        let cursor = match &mut self.cursor {
+            Some(cursor) => cursor,
+            None => return Err("there is no section for this to go in".to_string()),
+        };
+
+        for byte in bytes {
+            if cursor.address > cursor.end {
+                return Err(format!("the section runs past ${:04X}", cursor.end));
+            }
+
+            let offset =
+                cursor.bank as usize * ROM_BANK_SIZE_BYTES + (cursor.address & ROM0_END) as usize;
+            if offset >= self.rom.len() {
+                self.rom.resize(offset + 1, 0);
+                self.written.resize(offset + 1, false);
+            }
+            if self.written[offset] {
+                return Err(format!(
+                    "sections overlap at {:02X}:{:04X}",
+                    cursor.bank, cursor.address
+                ));
+            }
+
+            self.rom[offset] = *byte;
+            self.written[offset] = true;
+            cursor.address += 1;
+        }
+
+        Ok(())
+    }
+
+    /// An instruction's operand or data, which can be a label further on
+    fn operand(&self, expression: &str) -> Result<i64, String> {
+        match self.evaluate(expression) {
+            Err(ExpressionError::Undefined(_)) if !self.last => Ok(0),
+            result => Ok(result?),
+        }
+    }
+
+    fn evaluate(&self, expression: &str) -> Result<i64, ExpressionError> {
+        let mut parser = Parser {
+            pass: self,
+            text: expression.as_bytes(),
+            position: 0,
+        };
+
+        let value = parser.binary(0)?;
+        parser.skip_whitespace();
+        match parser.text.get(parser.position) {
+            None => Ok(value),
+            Some(_) => Err(ExpressionError::Invalid(format!(
+                "unexpected {:?} in {:?}",
+                &expression[parser.position..],
+                expression.trim()
+            ))),
+        }
+    }
+
+    fn symbol(&self, name: &str) -> Option<i64> {
+        if name == "@" {
+            return self.cursor.as_ref().map(|cursor| cursor.address as i64);
+        }
+
+        let scoped = self.scoped(name).ok()?;
+        self.constants
+            .get(name)
+            .copied()
+            .or_else(|| self.label(&scoped).map(|(_, address)| address as i64))
+            .or_else(|| self.assembler.constants.get(name).copied())
+            .or_else(|| {
+                HARDWARE_CONSTANTS
+                    .iter()
+                    .find(|(constant, _)| self.hardware && *constant == name)
+                    .map(|(_, value)| *value)
+            })
+    }
+
+    fn label(&self, name: &str) -> Option<(u8, u16)> {
+        self.labels
+            .get(name)
+            .or_else(|| self.earlier_labels.get(name))
+            .copied()
+    }
+}
+
+/// A recursive descent over one expression
+struct Parser<'p, 'a> {
+    pass: &'p Pass<'a>,
+    text: &'p [u8],
+    position: usize,
+}
+
+impl<'p, 'a> Parser<'p, 'a> {
+    fn binary(&mut self, level: usize) -> Result<i64, ExpressionError> {
+        let operators = match OPERATOR_LEVELS.get(level) {
+            Some(operators) => operators,
+            None => return self.unary(),
+        };
+
+        let mut left = self.binary(level + 1)?;
+        loop {
+            self.skip_whitespace();
+            let operator = match self.operator() {
+                Some(operator) if operators.contains(&operator) => operator,
+                _ => return Ok(left),
+            };
+            self.position += operator.len();
+
+            let right = self.binary(level + 1)?;
+            left = match operator {
+                "||" => (left != 0 || right != 0) as i64,
+                "&&" => (left != 0 && right != 0) as i64,
+                "==" => (left == right) as i64,
+                "!=" => (left != right) as i64,
+                "<=" => (left <= right) as i64,
+                ">=" => (left >= right) as i64,
+                "<" => (left < right) as i64,
+                ">" => (left > right) as i64,
+                "+" => left.wrapping_add(right),
+                "-" => left.wrapping_sub(right),
+                "&" => left & right,
+                "|" => left | right,
+                "^" => left ^ right,
+                "<<" => left.wrapping_shl(right as u32),
+                ">>" => left.wrapping_shr(right as u32),
+                "*" => left.wrapping_mul(right),
+                _ if right == 0 => {
+                    return Err(ExpressionError::Invalid("division by zero".to_string()))
+                }
+                "/" => left / right,
+                _ => left % right,
+            };
+        }
+    }
+
+    /// The longest operator at the cursor, so `<<` isn't taken for `<`
+    fn operator(&self) -> Option<&'static str> {
+        let rest = &self.text[self.position..];
+        OPERATOR_LEVELS
+            .iter()
+            .flat_map(|operators| operators.iter())
+            .filter(|operator| rest.starts_with(operator.as_bytes()))
+            .max_by_key(|operator| operator.len())
+            .copied()
+    }
+
+    fn unary(&mut self) -> Result<i64, ExpressionError> {
+        self.skip_whitespace();
+        let operator = self.text.get(self.position).copied();
+        match operator {
+            Some(b'-' | b'+' | b'~' | b'!') => {
+                self.position += 1;
+                let value = self.unary()?;
+                Ok(match operator {
+                    Some(b'-') => value.wrapping_neg(),
+                    Some(b'~') => !value,
+                    Some(b'!') => (value == 0) as i64,
+                    _ => value,
+                })
+            }
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, ExpressionError> {
+        match self.text.get(self.position) {
+            Some(b'(') => {
+                self.position += 1;
+                let value = self.binary(0)?;
+                self.expect(b')')?;
+                Ok(value)
+            }
+            Some(b'$') => {
+                self.position += 1;
+                self.number(16)
+            }
+            Some(b'%') => {
+                self.position += 1;
+                self.number(2)
+            }
+            Some(b'0') if matches!(self.text.get(self.position + 1), Some(b'x' | b'X')) => {
+                self.position += 2;
+                self.number(16)
+            }
+            Some(b'0') if matches!(self.text.get(self.position + 1), Some(b'b' | b'B')) => {
+                self.position += 2;
+                self.number(2)
+            }
+            Some(byte) if byte.is_ascii_digit() => self.number(10),
+            Some(byte) if is_identifier_byte(*byte) => self.identifier(),
+            _ => Err(ExpressionError::Invalid("expected a value".to_string())),
+        }
+    }
+
+    fn number(&mut self, radix: u32) -> Result<i64, ExpressionError> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|byte| byte.is_ascii_alphanumeric() || *byte == b'_')
+        {
+            self.position += 1;
+        }
+
+        let digits = String::from_utf8_lossy(&self.text[start..self.position]).replace('_', "");
+        i64::from_str_radix(&digits, radix).map_err(|_| {
+            ExpressionError::Invalid(format!("{:?} isn't a base {} number", digits, radix))
+        })
+    }
+
+    fn identifier(&mut self) -> Result<i64, ExpressionError> {
+        let start = self.position;
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(|byte| is_identifier_byte(*byte))
+        {
+            self.position += 1;
+        }
+        let name = String::from_utf8_lossy(&self.text[start..self.position]).into_owned();
+
+        self.skip_whitespace();
+        if self.text.get(self.position) != Some(&b'(') {
+            return self
+                .pass
+                .symbol(&name)
+                .ok_or(ExpressionError::Undefined(name));
+        }
+
+        self.position += 1;
+        let value = match name.to_ascii_uppercase().as_str() {
+            "HIGH" => (self.binary(0)? >> 8) & 0xFF,
+            "LOW" => self.binary(0)? & 0xFF,
+            "BANK" => {
+                self.skip_whitespace();
+                let start = self.position;
+                while self
+                    .text
+                    .get(self.position)
+                    .is_some_and(|byte| is_identifier_byte(*byte))
+                {
+                    self.position += 1;
+                }
+                let label = String::from_utf8_lossy(&self.text[start..self.position]).into_owned();
+                let scoped = self.pass.scoped(&label).map_err(ExpressionError::Invalid)?;
+                match self.pass.label(&scoped) {
+                    Some((bank, _)) => bank as i64,
+                    None => return Err(ExpressionError::Undefined(label)),
+                }
+            }
+            _ => {
+                return Err(ExpressionError::Invalid(format!(
+                    "{} isn't a function",
+                    name
+                )))
+            }
+        };
+        self.expect(b')')?;
+
+        Ok(value)
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ExpressionError> {
+        self.skip_whitespace();
+        match self.text.get(self.position) {
+            Some(found) if *found == byte => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(ExpressionError::Invalid(format!(
+                "expected {:?}",
+                byte as char
+            ))),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self
+            .text
+            .get(self.position)
+            .is_some_and(u8::is_ascii_whitespace)
+        {
+            self.position += 1;
+        }
+    }
+}
+
+fn is_identifier_byte(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'.' | b'@' | b'#')
+}
+
+fn check_range(value: i64, range: std::ops::RangeInclusive<i64>) -> Result<i64, String> {
+    match range.contains(&value) {
+        true => Ok(value),
+        false => Err(format!(
+            "{} isn't in {}..={}",
+            value,
+            range.start(),
+            range.end()
+        )),
+    }
+}
+
+/// Lowercase without whitespace, for comparing against registers
+fn normalize(operand: &str) -> String {
+    let normalized: String = operand
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    match normalized.as_str() {
+        "[hli]" => "[hl+]".to_string(),
+        "[hld]" => "[hl-]".to_string(),
+        _ => normalized,
+    }
+}
+
+/// Replace `\1` to `\9` with the macro's arguments
+fn substitute(text: &str, arguments: Option<&MacroArguments>) -> Result<String, String> {
+    if !text.contains('\\') {
+        return Ok(text.to_string());
+    }
+
+    let mut substituted = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let digit = chars.peek().and_then(|next| next.to_digit(10));
+        match (c, digit) {
+            ('\\', Some(digit @ 1..=9)) => {
+                chars.next();
+                let argument = arguments.and_then(|arguments| {
+                    arguments
+                        .arguments
+                        .get(arguments.shift + digit as usize - 1)
+                });
+                match argument {
+                    Some(argument) => substituted.push_str(argument),
+                    None => return Err(format!("there is no macro argument \\{}", digit)),
+                }
+            }
+            _ => substituted.push(c),
+        }
+    }
+
+    Ok(substituted)
+}
+
+fn unescape(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        let c = match c {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(c @ ('\\' | '"')) => c,
+                escape => return Err(format!("\\{} isn't an escape", escape.unwrap_or(' '))),
+            },
+            c => c,
+        };
+        match u8::try_from(c) {
+            Ok(byte) => bytes.push(byte),
+            Err(_) => return Err(format!("{:?} isn't one byte", c)),
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Everything before a `;` that isn't in a string
+fn strip_comment(text: &str) -> &str {
+    let mut in_string = false;
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            ';' if !in_string => return &text[..index],
+            _ => {}
+        }
+    }
+    text
+}
+
+/// A leading `Label:`, or exported `Label::`, and the statement after it
+fn split_label(text: &str) -> (Option<&str>, &str) {
+    let trimmed = text.trim_start();
+    let end = trimmed
+        .bytes()
+        .position(|byte| !is_identifier_byte(byte))
+        .unwrap_or(trimmed.len());
+
+    match trimmed[end..].strip_prefix(':') {
+        Some(rest) if end > 0 => (
+            Some(&trimmed[..end]),
+            rest.strip_prefix(':').unwrap_or(rest),
+        ),
+        _ => (None, text),
+    }
+}
+
+/// The first word and everything after it
+fn split_keyword(text: &str) -> (&str, &str) {
+    let text = text.trim();
+    text.split_once(char::is_whitespace).unwrap_or((text, ""))
+}
+
+/// Comma separated arguments, not splitting inside strings, brackets or parentheses
+fn split_arguments(text: &str) -> Vec<&str> {
+    let mut arguments = Vec::new();
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut start = 0;
+
+    for (index, c) in text.char_indices() {
+        match c {
+            '"' => in_string = !in_string,
+            '(' | '[' if !in_string => depth += 1,
+            ')' | ']' if !in_string => depth -= 1,
+            ',' if !in_string && depth == 0 => {
+                arguments.push(text[start..index].trim());
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    if !text[start..].trim().is_empty() || !arguments.is_empty() {
+        arguments.push(text[start..].trim());
+    }
+
+    arguments
+}
+
+/// The line of the `closer` that ends the block opened at `start`, and of the `middle` keyword
+/// between them if there is one, counting nested blocks opened by any of `openers`
+fn find_block(
+    lines: &[Line],
+    start: usize,
+    openers: &[&str],
+    closer: &str,
+    middle: Option<&str>,
+) -> Result<(Option<usize>, usize), String> {
+    let mut depth = 0;
+    let mut middle_index = None;
+
+    for (index, line) in lines.iter().enumerate().skip(start + 1) {
+        let (_, statement) = split_label(strip_comment(&line.text));
+        let keyword = split_keyword(statement).0.to_ascii_uppercase();
+
+        if openers.contains(&keyword.as_str()) {
+            depth += 1;
+        } else if keyword == closer {
+            if depth == 0 {
+                return Ok((middle_index, index));
+            }
+            depth -= 1;
+        } else if depth == 0 && Some(keyword.as_str()) == middle {
+            middle_index = Some(index);
+        }
+    }
+
+    Err(format!("{} has no {} to end it", openers[0], closer))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assemble(code: &str) -> Result<Vec<u8>, AssemblyError> {
+        let source = format!("SECTION \"Code\", ROM0[$150]\n{}", code);
+        let rom = Assembler::new().assemble(&source)?.rom;
+        let end = rom[0x150..0x4000]
+            .iter()
+            .rposition(|byte| *byte != 0)
+            .map_or(0, |end| end + 1);
+        Ok(rom[0x150..0x150 + end].to_vec())
+    }
+
+    #[test]
+    fn test_instructions() {
+        assert_eq!(vec![0x7E], assemble("ld a, [hl]").unwrap());
+        assert_eq!(vec![0xFA, 0x34, 0x12], assemble("ld a, [$1234]").unwrap());
+        assert_eq!(vec![0x3E, 0xFF], assemble("ld a, -1").unwrap());
+        assert_eq!(vec![0x2A], assemble("ld a, [hli]").unwrap());
+        assert_eq!(vec![0xE0, 0x44], assemble("ldh [$FF44], a").unwrap());
+        assert_eq!(vec![0x90], assemble("sub b").unwrap());
+        assert_eq!(vec![0xF8, 0xFE], assemble("ld hl, sp - 2").unwrap());
+        assert_eq!(vec![0xFF], assemble("rst $38").unwrap());
+        assert_eq!(vec![0xCB, 0x7C], assemble("bit 7, h").unwrap());
+        assert_eq!(vec![0xDA, 0x00, 0x40], assemble("jp c, $4000").unwrap());
+    }
+
+    #[test]
+    fn test_labels() {
+        // Jumping back to the label at $150, and forward past the jump
+        let code = "Back:\n nop\n jr Back\n jr .forward\n.forward:\n jp Back.forward";
+        assert_eq!(
+            vec![0x00, 0x18, 0xFD, 0x18, 0x00, 0xC3, 0x55, 0x01],
+            assemble(code).unwrap()
+        );
+
+        let far = "jr Far\nds 200\nFar:";
+        assert!(assemble(far).is_err());
+    }
+
+    #[test]
+    fn test_expressions() {
+        assert_eq!(vec![7], assemble("db 1 + 2 * 3").unwrap());
+        // RGBDS binds & tighter than +
+        assert_eq!(vec![3], assemble("db 1 + 6 & 2").unwrap());
+        assert_eq!(
+            vec![0x12, 0x34],
+            assemble("db HIGH($1234), LOW($1234)").unwrap()
+        );
+        assert_eq!(vec![0b101, 0x0A], assemble("db %101, 0x0A").unwrap());
+        assert_eq!(
+            vec![0x34, 0x12, b'O', b'k'],
+            assemble("dw $1234\ndb \"Ok\"").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_blocks() {
+        let code = "
+MACRO pair
+    db \\1
+    SHIFT
+    db \\1
+ENDM
+DEF COUNT EQU 2
+FOR N, COUNT
+    pair N, N + 10
+ENDR
+IF COUNT > 2
+    db $FF
+ELSE
+    REPT 2
+        db $EE
+    ENDR
+ENDC";
+        assert_eq!(vec![0, 10, 1, 11, 0xEE, 0xEE], assemble(code).unwrap());
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = assemble("nop\nld a, Missing").unwrap_err();
+        assert!(matches!(error, AssemblyError::Line { line_number: 3, .. }));
+
+        assert!(assemble("ld [bc], b").is_err());
+        assert!(assemble("frobnicate").is_err());
+        assert!(assemble("ld a, 256").is_err());
+        assert!(assemble("REPT 2\nnop").is_err());
+    }
+}
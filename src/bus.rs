@@ -1,44 +1,449 @@
 use crate::cartridge::mbc1::Mbc1;
-use crate::memory::MemoryMapped;
+use crate::hdma::{self, Block, Hdma};
+use crate::interrupts::{self, Interrupts};
+use crate::joypad::{self, Joypad};
+use crate::memory::{MemoryMapped, OPEN_BUS_VALUE};
 use crate::ram::Ram;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
+use crate::serial::{self, Serial};
+use crate::sgb::super_game_boy::SuperGameBoy;
+use crate::speed::{self, Key1};
+use crate::watchpoint::Watchpoint;
+use std::cell::{Cell, RefCell};
+use std::fmt;
 
 const CARTRIDGE_ADDRESS_START: u16 = 0x0000;
 const CARTRIDGE_ADDRESS_END: u16 = 0x7FFF;
+const VRAM_ADDRESS_START: u16 = 0x8000;
+const VRAM_ADDRESS_END: u16 = 0x9FFF;
+const CARTRIDGE_RAM_ADDRESS_START: u16 = 0xA000;
+const CARTRIDGE_RAM_ADDRESS_END: u16 = 0xBFFF;
 const RAM_ADDRESS_START: u16 = 0xC000;
 const RAM_ADDRESS_END: u16 = 0xDFFF;
+const HRAM_ADDRESS_START: u16 = 0xFF80;
+const HRAM_ADDRESS_END: u16 = 0xFFFE;
+
+/// The save state version HRAM and the interrupt registers were first saved in
+const HRAM_SAVED_SINCE_VERSION: u16 = 4;
+
+/// An access to an address that nothing on the bus responds to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum UnmappedAccess {
+    Read { address: u16 },
+    Write { address: u16, value: u8 },
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AccessKind {
+    Read,
+    Write,
+    /// The CPU fetching the opcode of an instruction it is about to run
+    Execute,
+    /// A DMA reading its source, rather than the CPU reading
+    Dma,
+}
+
+/// One byte of memory traffic
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Access {
+    pub kind: AccessKind,
+    /// The address of the instruction that made the access
+    pub pc: u16,
+    pub address: u16,
+    /// The ROM bank mapped at the address, if it is in ROM
+    pub bank: Option<u8>,
+    /// What the address held before the access
+    pub old_value: u8,
+    /// What was read, written, or executed
+    pub new_value: u8,
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let address = match self.bank {
+            Some(bank) => format!("{:02X}:{:04X}", bank, self.address),
+            None => format!("{:04X}", self.address),
+        };
+
+        match self.kind {
+            AccessKind::Read => write!(
+                f,
+                "read {:02X} from {} at PC {:04X}",
+                self.new_value, address, self.pc
+            ),
+            AccessKind::Write => write!(
+                f,
+                "wrote {:02X} to {}, which held {:02X}, at PC {:04X}",
+                self.new_value, address, self.old_value, self.pc
+            ),
+            AccessKind::Execute => write!(f, "executed {:02X} at {}", self.new_value, address),
+            AccessKind::Dma => write!(
+                f,
+                "DMA read {:02X} from {}, started at PC {:04X}",
+                self.new_value, address, self.pc
+            ),
+        }
+    }
+}
+
+/// Something that wants to see every access made over the bus, like a logger or cheat finder
+pub trait BusObserver {
+    fn observe(&mut self, access: &Access);
+}
+
+impl<F: FnMut(&Access)> BusObserver for F {
+    fn observe(&mut self, access: &Access) {
+        self(access)
+    }
+}
+
+/// Identifies an observer so it can be removed again
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct ObserverId(usize);
 
 pub struct Bus {
     pub cartridge: Mbc1,
+    // TODO CGB VRAM bank 1 and VBK
+    pub vram: Ram<0x2000>,
     pub ram: Ram<0x2000>,
+    pub hram: Ram<0x7F>,
+
+    pub joypad: Joypad,
+    pub key1: Key1,
+    pub hdma: Hdma,
+    pub serial: Serial,
+    pub interrupts: Interrupts,
+
+    /// Present when running as a Super Game Boy
+    pub sgb: Option<SuperGameBoy>,
+
+    /// Machine cycles the CPU has been halted for by DMA since last taken
+    stalled_cycles: usize,
+    /// The first unmapped access since last taken
+    /// Reads only borrow the bus immutably, so this needs interior mutability
+    unmapped_access: Cell<Option<UnmappedAccess>>,
+
+    /// The address of the instruction the CPU is running, for reporting accesses
+    instruction_pc: u16,
+    /// Reads notify observers too, so these also need interior mutability
+    observers: RefCell<Vec<(ObserverId, Box<dyn BusObserver>)>>,
+    next_observer_id: usize,
+    watchpoints: Vec<Watchpoint>,
+    /// The first access to hit a watchpoint since last taken
+    watchpoint_hit: Cell<Option<Access>>,
 }
 
 impl Bus {
     pub fn new(cartridge: Mbc1) -> Self {
         Bus {
             cartridge,
+            vram: Ram::default(),
             ram: Ram::default(),
+            hram: Ram::default(),
+
+            joypad: Joypad::default(),
+            key1: Key1::default(),
+            hdma: Hdma::default(),
+            serial: Serial::default(),
+            interrupts: Interrupts::default(),
+
+            sgb: None,
+
+            stalled_cycles: 0,
+            unmapped_access: Cell::new(None),
+
+            instruction_pc: 0,
+            observers: RefCell::new(Vec::new()),
+            next_observer_id: 0,
+            watchpoints: Vec::new(),
+            watchpoint_hit: Cell::new(None),
         }
     }
-}
 
-impl MemoryMapped for Bus {
-    fn read_byte(&self, address: u16) -> u8 {
-        match address {
+    /// Read without anything seeing it: no observers, watchpoints, or unmapped access reports
+    /// For tools inspecting memory, rather than emulation
+    pub fn peek(&self, address: u16) -> u8 {
+        self.read_mapped(address).unwrap_or(OPEN_BUS_VALUE)
+    }
+
+    /// Called by the CPU as it starts each instruction, reporting an execute access for it
+    pub fn begin_instruction(&mut self, pc: u16) {
+        self.instruction_pc = pc;
+
+        if self.is_watched() {
+            let opcode = self.peek(pc);
+            self.report_access(AccessKind::Execute, pc, opcode, opcode);
+        }
+    }
+
+    /// The observer sees every access from now until it is removed
+    pub fn add_observer(&mut self, observer: impl BusObserver + 'static) -> ObserverId {
+        let id = ObserverId(self.next_observer_id);
+        self.next_observer_id += 1;
+
+        self.observers.get_mut().push((id, Box::new(observer)));
+        id
+    }
+
+    /// Returns false if there was no such observer
+    pub fn remove_observer(&mut self, id: ObserverId) -> bool {
+        let observers = self.observers.get_mut();
+        let len = observers.len();
+        observers.retain(|(observer_id, _)| *observer_id != id);
+
+        observers.len() != len
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Returns false if there was no such watchpoint
+    pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|existing| existing != watchpoint);
+
+        self.watchpoints.len() != len
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Returns the first access to hit a watchpoint since the last call, and resets it
+    pub fn take_watchpoint_hit(&mut self) -> Option<Access> {
+        self.watchpoint_hit.take()
+    }
+
+    fn is_watched(&self) -> bool {
+        !self.watchpoints.is_empty() || !self.observers.borrow().is_empty()
+    }
+
+    fn report_access(&self, kind: AccessKind, address: u16, old_value: u8, new_value: u8) {
+        let access = Access {
+            kind,
+            pc: self.instruction_pc,
+            address,
+            bank: self.cartridge.rom_bank(address),
+            old_value,
+            new_value,
+        };
+
+        for (_, observer) in self.observers.borrow_mut().iter_mut() {
+            observer.observe(&access);
+        }
+
+        if self.watchpoint_hit.get().is_none()
+            && self
+                .watchpoints
+                .iter()
+                .any(|watchpoint| watchpoint.matches(&access))
+        {
+            self.watchpoint_hit.set(Some(access));
+        }
+    }
+
+    /// Returns None when nothing responds at the address
+    fn read_mapped(&self, address: u16) -> Option<u8> {
+        let value = match address {
             CARTRIDGE_ADDRESS_START..=CARTRIDGE_ADDRESS_END => self.cartridge.read_byte(address),
+            VRAM_ADDRESS_START..=VRAM_ADDRESS_END => {
+                self.vram.read_byte(address - VRAM_ADDRESS_START)
+            }
+            CARTRIDGE_RAM_ADDRESS_START..=CARTRIDGE_RAM_ADDRESS_END
+                if self.cartridge.is_ram_mapped() =>
+            {
+                self.cartridge.read_byte(address)
+            }
             RAM_ADDRESS_START..=RAM_ADDRESS_END => self.ram.read_byte(address - RAM_ADDRESS_START),
-            _ => todo!("Memory address {:#06X} not mapped for bus reads", address),
+            joypad::JOYPAD_ADDRESS => self.read_joypad(),
+            serial::SB_ADDRESS..=serial::SC_ADDRESS => self.serial.read_byte(address),
+            speed::KEY1_ADDRESS => self.key1.read_byte(address),
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS => self.hdma.read_byte(address),
+            HRAM_ADDRESS_START..=HRAM_ADDRESS_END => {
+                self.hram.read_byte(address - HRAM_ADDRESS_START)
+            }
+            interrupts::IF_ADDRESS | interrupts::IE_ADDRESS => self.interrupts.read_byte(address),
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    /// Signals that the LCD has entered HBlank, which runs one block of an active HBlank DMA
+    pub fn hblank(&mut self) {
+        if self.hdma.is_hblank() {
+            if let Some(block) = self.hdma.next_block() {
+                self.copy_hdma_block(block);
+            }
+        }
+    }
+
+    /// Returns the machine cycles the CPU was halted for since the last call, and resets the count
+    pub fn take_stalled_cycles(&mut self) -> usize {
+        std::mem::take(&mut self.stalled_cycles)
+    }
+
+    /// Returns the first unmapped access since the last call, and resets it
+    pub fn take_unmapped_access(&mut self) -> Option<UnmappedAccess> {
+        self.unmapped_access.take()
+    }
+
+    fn record_unmapped_access(&self, access: UnmappedAccess) {
+        if self.unmapped_access.get().is_none() {
+            self.unmapped_access.set(Some(access));
         }
     }
 
+    fn read_joypad(&self) -> u8 {
+        let value = self.joypad.read_byte(joypad::JOYPAD_ADDRESS);
+
+        // The SGB reports which player's joypad is active when no buttons are selected
+        match &self.sgb {
+            Some(sgb) if self.joypad.is_deselected() => (value & 0xF0) | sgb.joypad_id(),
+            _ => value,
+        }
+    }
+
+    /// Read as the CPU or a DMA would, reporting the access as `kind`
+    fn read_as(&self, kind: AccessKind, address: u16) -> u8 {
+        let value = self.read_mapped(address).unwrap_or_else(|| {
+            self.record_unmapped_access(UnmappedAccess::Read { address });
+            OPEN_BUS_VALUE
+        });
+
+        if self.is_watched() {
+            self.report_access(kind, address, value, value);
+        }
+
+        value
+    }
+
+    fn run_general_purpose_hdma(&mut self) {
+        while self.hdma.is_general_purpose() {
+            if let Some(block) = self.hdma.next_block() {
+                self.copy_hdma_block(block);
+            }
+        }
+    }
+
+    fn copy_hdma_block(&mut self, block: Block) {
+        for offset in 0..hdma::BLOCK_SIZE_BYTES {
+            let value = self.read_as(AccessKind::Dma, block.source.wrapping_add(offset));
+            self.vram
+                .write_byte(block.destination + offset - VRAM_ADDRESS_START, value);
+        }
+
+        self.stalled_cycles += Hdma::block_cycles(self.key1.speed);
+    }
+}
+
+impl MemoryMapped for Bus {
+    fn read_byte(&self, address: u16) -> u8 {
+        self.read_as(AccessKind::Read, address)
+    }
+
     fn write_byte(&mut self, address: u16, value: u8) {
+        // Observers should see a write before anything it sets off, like a DMA's reads
+        if self.is_watched() {
+            let old_value = self.peek(address);
+            self.report_access(AccessKind::Write, address, old_value, value);
+        }
+
         match address {
             CARTRIDGE_ADDRESS_START..=CARTRIDGE_ADDRESS_END => {
                 self.cartridge.write_byte(address, value)
             }
+            VRAM_ADDRESS_START..=VRAM_ADDRESS_END => {
+                self.vram.write_byte(address - VRAM_ADDRESS_START, value)
+            }
+            CARTRIDGE_RAM_ADDRESS_START..=CARTRIDGE_RAM_ADDRESS_END
+                if self.cartridge.is_ram_mapped() =>
+            {
+                self.cartridge.write_byte(address, value)
+            }
             RAM_ADDRESS_START..=RAM_ADDRESS_END => {
                 self.ram.write_byte(address - RAM_ADDRESS_START, value)
             }
-            _ => todo!("Memory address {:#06X} not mapped for bus writes", address),
+            joypad::JOYPAD_ADDRESS => {
+                self.joypad.write_byte(address, value);
+
+                if let Some(sgb) = &mut self.sgb {
+                    sgb.write_joypad(value, &self.vram);
+                }
+            }
+            serial::SB_ADDRESS..=serial::SC_ADDRESS => self.serial.write_byte(address, value),
+            speed::KEY1_ADDRESS => self.key1.write_byte(address, value),
+            hdma::HDMA1_ADDRESS..=hdma::HDMA5_ADDRESS => {
+                self.hdma.write_byte(address, value);
+                // A general purpose transfer halts the CPU until it completes, so run it all now
+                self.run_general_purpose_hdma();
+            }
+            HRAM_ADDRESS_START..=HRAM_ADDRESS_END => {
+                self.hram.write_byte(address - HRAM_ADDRESS_START, value)
+            }
+            interrupts::IF_ADDRESS | interrupts::IE_ADDRESS => {
+                self.interrupts.write_byte(address, value)
+            }
+            _ => self.record_unmapped_access(UnmappedAccess::Write { address, value }),
+        }
+    }
+}
+
+impl SaveState for Bus {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cartridge.save_state(writer);
+        self.vram.save_state(writer);
+        self.ram.save_state(writer);
+
+        self.joypad.save_state(writer);
+        self.key1.save_state(writer);
+        self.hdma.save_state(writer);
+        self.serial.save_state(writer);
+
+        writer.write_bool(self.sgb.is_some());
+        if let Some(sgb) = &self.sgb {
+            sgb.save_state(writer);
         }
+
+        writer.write_u64(self.stalled_cycles as u64);
+
+        self.hram.save_state(writer);
+        self.interrupts.save_state(writer);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.cartridge.load_state(reader)?;
+        self.vram.load_state(reader)?;
+        self.ram.load_state(reader)?;
+
+        self.joypad.load_state(reader)?;
+        self.key1.load_state(reader)?;
+        self.hdma.load_state(reader)?;
+        self.serial.load_state(reader)?;
+
+        self.sgb = if reader.read_bool()? {
+            let mut sgb = self.sgb.take().unwrap_or_default();
+            sgb.load_state(reader)?;
+            Some(sgb)
+        } else {
+            None
+        };
+
+        self.stalled_cycles = reader.read_u64()? as usize;
+
+        if reader.version() >= HRAM_SAVED_SINCE_VERSION {
+            self.hram.load_state(reader)?;
+            self.interrupts.load_state(reader)?;
+        } else {
+            self.hram = Ram::default();
+            self.interrupts = Interrupts::default();
+        }
+        self.unmapped_access.set(None);
+        self.watchpoint_hit.set(None);
+
+        Ok(())
     }
 }
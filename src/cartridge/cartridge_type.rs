@@ -1,6 +1,8 @@
-use crate::cartridge::parse::{Parse, ParseResult};
+use crate::cartridge::parse::{Field, Parse, ParseError, ParseResult};
+use serde::Serialize;
 
-#[derive(Debug, Eq, Hash, PartialEq)]
+#[derive(Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum CartridgeType {
     Rom {
         battery: bool,
@@ -25,7 +27,9 @@ pub enum CartridgeType {
     },
     Mbc6,
     Mbc7,
+    #[serde(rename = "huc1")]
     HuC1,
+    #[serde(rename = "huc3")]
     HuC3,
     Mmm01 {
         battery: bool,
@@ -137,7 +141,7 @@ impl Parse for CartridgeType {
             0xFD => Ok(CartridgeType::BandaiTama5),
             0xFE => Ok(CartridgeType::HuC3),
             0xFF => Ok(CartridgeType::HuC1),
-            _ => Err(format!("unrecognized cartridge type code {:#04X}", code)),
+            _ => Err(ParseError::invalid_code(Field::CartridgeType, code)),
         }
     }
 }
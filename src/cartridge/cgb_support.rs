@@ -0,0 +1,35 @@
+use crate::cartridge::parse::{Parse, ParseResult};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CgbSupport {
+    /// Made before the CGB, or for DMG only, so the byte is the last character of the title
+    DmgOnly,
+    /// Uses CGB features, but still runs on a DMG
+    Enhanced,
+    /// Only runs on a CGB
+    Required,
+}
+
+impl Parse for CgbSupport {
+    fn parse(code: u8) -> ParseResult<Self> {
+        // The CGB only checks bit 7, and treats bit 6 as a hint that the game is CGB only
+        Ok(match code & 0xC0 {
+            0xC0 => CgbSupport::Required,
+            0x80 => CgbSupport::Enhanced,
+            _ => CgbSupport::DmgOnly,
+        })
+    }
+}
+
+impl CgbSupport {
+    /// The value written to the CGB flag for this level of support
+    pub fn code(self) -> u8 {
+        match self {
+            CgbSupport::DmgOnly => 0x00,
+            CgbSupport::Enhanced => 0x80,
+            CgbSupport::Required => 0xC0,
+        }
+    }
+}
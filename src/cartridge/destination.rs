@@ -0,0 +1,21 @@
+use crate::cartridge::parse::{Parse, ParseResult};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Destination {
+    Japanese,
+    Overseas,
+    /// Anything else, which the hardware never looks at
+    Unknown(u8),
+}
+
+impl Parse for Destination {
+    fn parse(code: u8) -> ParseResult<Self> {
+        Ok(match code {
+            0x00 => Destination::Japanese,
+            0x01 => Destination::Overseas,
+            _ => Destination::Unknown(code),
+        })
+    }
+}
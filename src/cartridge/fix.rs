@@ -0,0 +1,302 @@
+use crate::cartridge::cartridge_type::CartridgeType;
+use crate::cartridge::cgb_support::CgbSupport;
+use crate::cartridge::constants;
+use crate::cartridge::header::{self, Header, HEADER_OFFSET};
+use crate::cartridge::parse::{Field, Parse, ParseError, ParseResult};
+
+const ROM_BANK_SIZE_BYTES: usize = 0x4000;
+const MAX_ROM_SIZE_CODE: u8 = 0x08;
+
+// The SGB only enables its functions with both of these set
+const SGB_FLAG: u8 = 0x03;
+const SGB_OLD_LICENSEE_CODE: u8 = 0x33;
+
+/// Header fields to overwrite when fixing up a ROM, along the lines of rgbfix
+/// Fields left as None are kept as they already are in the ROM
+#[derive(Debug, Default)]
+pub struct Fix {
+    pub title: Option<String>,
+    pub cartridge_type: Option<u8>,
+    pub rom_size_code: Option<u8>,
+    pub ram_size_code: Option<u8>,
+    pub cgb_support: Option<CgbSupport>,
+    /// Enabling SGB support also sets the old licensee code to 0x33, which the SGB requires
+    pub sgb: Option<bool>,
+    /// Pad the ROM with this byte up to the next valid ROM size, and set the ROM size to match
+    pub pad: Option<u8>,
+}
+
+impl Fix {
+    /// Apply the fix to a full ROM image in place
+    /// Besides the requested fields, this always rewrites the logo and recomputes both checksums
+    /// Only padding can grow a ROM, so without it the ROM has to reach the end of the header
+    /// Returns the fixed ROM's header
+    pub fn apply(&self, rom: &mut Vec<u8>) -> ParseResult<Header> {
+        let header_end = HEADER_OFFSET + header::HEADER_BYTES;
+        if rom.len() < header_end {
+            if self.pad.is_none() {
+                return Err(ParseError::TooShort {
+                    field: Field::Rom,
+                    minimum: header_end,
+                    actual: rom.len(),
+                });
+            }
+            // Zeroes are valid for every header field, unlike most padding bytes
+            rom.resize(header_end, 0);
+        }
+
+        self.apply_rom_size(rom)?;
+
+        let header = &mut rom[HEADER_OFFSET..header_end];
+
+        if let Some(cgb_support) = self.cgb_support {
+            header[header::CGB_FLAG_ADDRESS] = cgb_support.code();
+        }
+
+        if let Some(title) = &self.title {
+            Fix::write_title(header, title);
+        }
+
+        if let Some(sgb) = self.sgb {
+            if sgb {
+                header[header::SGB_FLAG_ADDRESS] = SGB_FLAG;
+                header[header::OLD_LICENSEE_CODE_ADDRESS] = SGB_OLD_LICENSEE_CODE;
+            } else {
+                header[header::SGB_FLAG_ADDRESS] = 0x00;
+            }
+        }
+
+        if let Some(code) = self.cartridge_type {
+            CartridgeType::parse(code)?;
+            header[header::CARTRIDGE_TYPE_ADDRESS] = code;
+        }
+
+        if let Some(code) = self.ram_size_code {
+            Header::parse_ram_banks(code)?;
+            header[header::RAM_BANKS_ADDRESS] = code;
+        }
+
+        header[header::LOGO_ADDRESS_RANGE].copy_from_slice(&constants::LOGO);
+        header[header::HEADER_CHECKSUM_ADDRESS] = Header::compute_header_checksum(header);
+
+        // The global checksum covers the header checksum, so it has to come last
+        let global_checksum = Header::compute_global_checksum(rom);
+        let global_checksum_address = HEADER_OFFSET + header::GLOBAL_CHECKSUM_ADDRESS_RANGE.start();
+        rom[global_checksum_address..global_checksum_address + 2]
+            .copy_from_slice(&global_checksum.to_be_bytes());
+
+        Header::parse_rom(rom)
+    }
+
+    fn apply_rom_size(&self, rom: &mut Vec<u8>) -> ParseResult<()> {
+        let code = match (self.rom_size_code, self.pad) {
+            (Some(code), _) => code,
+            (None, Some(_)) => Fix::rom_size_code_for(rom.len())?,
+            (None, None) => return Ok(()),
+        };
+
+        if code > MAX_ROM_SIZE_CODE {
+            return Err(ParseError::invalid_code(Field::RomBanks, code));
+        }
+
+        if let Some(pad) = self.pad {
+            let size = Fix::rom_size_bytes(code);
+            if rom.len() > size {
+                return Err(ParseError::TooLong {
+                    field: Field::Rom,
+                    maximum: size,
+                    actual: rom.len(),
+                });
+            }
+
+            rom.resize(size, pad);
+        }
+
+        rom[HEADER_OFFSET + header::ROM_BANKS_ADDRESS] = code;
+
+        Ok(())
+    }
+
+    /// The title is padded with zeroes, and shares its last byte with the CGB flag when it is set
+    /// Like rgbfix, a title too long for the space is cut short, and other text is written as is
+    fn write_title(header: &mut [u8], title: &str) {
+        let mut title_range = header::TITLE_ADDRESS_RANGE;
+        if header[header::CGB_FLAG_ADDRESS] & 0x80 != 0 {
+            title_range = *title_range.start()..=(header::CGB_FLAG_ADDRESS - 1);
+        }
+
+        let title_bytes = &mut header[title_range];
+        let length = title.len().min(title_bytes.len());
+
+        title_bytes.fill(0);
+        title_bytes[..length].copy_from_slice(&title.as_bytes()[..length]);
+    }
+
+    fn rom_size_bytes(code: u8) -> usize {
+        (2 << code) * ROM_BANK_SIZE_BYTES
+    }
+
+    /// The smallest ROM size code that fits `len` bytes
+    fn rom_size_code_for(len: usize) -> ParseResult<u8> {
+        (0..=MAX_ROM_SIZE_CODE)
+            .find(|code| Fix::rom_size_bytes(*code) >= len)
+            .ok_or(ParseError::TooLong {
+                field: Field::Rom,
+                maximum: Fix::rom_size_bytes(MAX_ROM_SIZE_CODE),
+                actual: len,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::header::Checksum;
+
+    #[test]
+    fn test_fix_empty_rom() {
+        let mut rom = Vec::new();
+
+        let header = Fix {
+            title: Some("HOMEBREW".to_string()),
+            pad: Some(0xFF),
+            ..Fix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+
+        assert_eq!(0x8000, rom.len());
+        assert_eq!(0xFF, rom[0x7FFF]);
+        assert_eq!("HOMEBREW", header.title);
+        assert_eq!(2, header.rom_banks);
+        assert!(header.validation.logo);
+        assert!(matches!(header.validation.header_checksum, Checksum::Ok(_)));
+        assert!(matches!(header.validation.global_checksum, Checksum::Ok(_)));
+    }
+
+    #[test]
+    fn test_short_rom_needs_padding() {
+        let mut rom = vec![0; 0x100];
+
+        let error = Fix::default().apply(&mut rom).unwrap_err();
+
+        assert_eq!(
+            ParseError::TooShort {
+                field: Field::Rom,
+                minimum: 0x150,
+                actual: 0x100,
+            },
+            error
+        );
+        assert_eq!(0x100, rom.len());
+    }
+
+    #[test]
+    fn test_pad_to_next_size() {
+        let mut rom = vec![0; 0x8001];
+
+        let header = Fix {
+            pad: Some(0x00),
+            ..Fix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+
+        assert_eq!(0x10000, rom.len());
+        assert_eq!(4, header.rom_banks);
+    }
+
+    #[test]
+    fn test_rom_size_too_small_to_pad() {
+        let mut rom = vec![0; 0x8001];
+
+        let result = Fix {
+            rom_size_code: Some(0x00),
+            pad: Some(0x00),
+            ..Fix::default()
+        }
+        .apply(&mut rom);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_sgb_flag() {
+        let mut rom = vec![0; 0x8000];
+
+        let header = Fix {
+            sgb: Some(true),
+            ..Fix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+
+        assert!(header.sgb);
+        assert_eq!(0x03, rom[0x146]);
+        assert_eq!(0x33, rom[0x14B]);
+    }
+
+    #[test]
+    fn test_cgb_title() {
+        let mut rom = vec![0; 0x8000];
+
+        let header = Fix {
+            title: Some("FIFTEEN CHARS!!".to_string()),
+            cgb_support: Some(CgbSupport::Required),
+            ..Fix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+
+        assert_eq!("FIFTEEN CHARS!!", header.title);
+        assert_eq!(CgbSupport::Required, header.cgb_support);
+        assert_eq!(0xC0, rom[0x143]);
+    }
+
+    #[test]
+    fn test_cgb_flag_shortens_title() {
+        let mut rom = vec![0; 0x8000];
+
+        // A full 16 character title would overwrite the CGB flag, so it loses its last character
+        let header = Fix {
+            title: Some("SIXTEEN CHARS!!!".to_string()),
+            cgb_support: Some(CgbSupport::Enhanced),
+            ..Fix::default()
+        }
+        .apply(&mut rom)
+        .unwrap();
+
+        assert_eq!("SIXTEEN CHARS!!", header.title);
+        assert_eq!(0x80, rom[0x143]);
+    }
+
+    #[test]
+    fn test_invalid_cartridge_type() {
+        let mut rom = vec![0; 0x8000];
+
+        let result = Fix {
+            cartridge_type: Some(0x04),
+            ..Fix::default()
+        }
+        .apply(&mut rom);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_invalid_ram_size() {
+        let mut rom = vec![0; 0x8000];
+
+        // 0x01 was never used, and 0x06 and up are undefined
+        for code in [0x01, 0x06] {
+            let result = Fix {
+                ram_size_code: Some(code),
+                ..Fix::default()
+            }
+            .apply(&mut rom);
+
+            assert_eq!(Err(ParseError::invalid_code(Field::RamBanks, code)), result);
+        }
+        assert_eq!(0x00, rom[0x149]);
+    }
+}
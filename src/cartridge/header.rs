@@ -1,75 +1,121 @@
 use super::cartridge_type::CartridgeType;
+use crate::cartridge::cgb_support::CgbSupport;
 use crate::cartridge::constants;
-use crate::cartridge::parse::{Parse, ParseResult};
+use crate::cartridge::destination::Destination;
+use crate::cartridge::licensee::Publisher;
+use crate::cartridge::parse::{Field, Parse, ParseError, ParseResult};
+use crate::cartridge::title::{Title, TitleEncoding};
+use serde::Serialize;
 use std::ops::RangeInclusive;
 
-const HEADER_BYTES: usize = 0x50;
-
-const LOGO_ADDRESS_RANGE: RangeInclusive<usize> = 0x0004..=0x0033;
-const TITLE_ADDRESS_RANGE: RangeInclusive<usize> = 0x0034..=0x0043;
-const CARTRIDGE_TYPE_ADDRESS: usize = 0x0047;
-const ROM_BANKS_ADDRESS: usize = 0x0048;
-const RAM_BANKS_ADDRESS: usize = 0x0049;
+/// The header's offset within the full ROM
+pub const HEADER_OFFSET: usize = 0x100;
+pub(crate) const HEADER_BYTES: usize = 0x50;
+
+pub(crate) const LOGO_ADDRESS_RANGE: RangeInclusive<usize> = 0x0004..=0x0033;
+pub(crate) const TITLE_ADDRESS_RANGE: RangeInclusive<usize> = 0x0034..=0x0043;
+pub(crate) const MANUFACTURER_CODE_ADDRESS_RANGE: RangeInclusive<usize> = 0x003F..=0x0042;
+pub(crate) const CGB_FLAG_ADDRESS: usize = 0x0043;
+const NEW_LICENSEE_CODE_ADDRESS_RANGE: RangeInclusive<usize> = 0x0044..=0x0045;
+pub(crate) const SGB_FLAG_ADDRESS: usize = 0x0046;
+pub(crate) const CARTRIDGE_TYPE_ADDRESS: usize = 0x0047;
+pub(crate) const ROM_BANKS_ADDRESS: usize = 0x0048;
+pub(crate) const RAM_BANKS_ADDRESS: usize = 0x0049;
+const DESTINATION_CODE_ADDRESS: usize = 0x004A;
+pub(crate) const OLD_LICENSEE_CODE_ADDRESS: usize = 0x004B;
 const VERSION_ADDRESS: usize = 0x004C;
-const HEADER_CHECKSUM_ADDRESS: usize = 0x004D;
-const GLOBAL_CHECKSUM_ADDRESS_RANGE: RangeInclusive<usize> = 0x004E..=0x004F;
+pub(crate) const HEADER_CHECKSUM_ADDRESS: usize = 0x004D;
+pub(crate) const GLOBAL_CHECKSUM_ADDRESS_RANGE: RangeInclusive<usize> = 0x004E..=0x004F;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize)]
 pub struct Header {
     pub title: String,
+    /// Only newer CGB cartridges have one, in what used to be the end of the title
+    /// Left out when serialized without one, since TOML has no null
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub publisher: Publisher,
+    pub sgb: bool,
     pub cartridge_type: CartridgeType,
     pub rom_banks: usize,
     pub ram_banks: usize,
+    pub destination: Destination,
     pub version: u8,
 
     pub validation: Validation,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize)]
 pub struct Validation {
     pub logo: bool,
     pub header_checksum: Checksum<u8>,
-    pub global_checksum: u16,
+    pub global_checksum: Checksum<u16>,
 }
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "status", content = "value", rename_all = "snake_case")]
 pub enum Checksum<T> {
     Ok(T),
-    Err { actual: T, expected: T },
+    Err {
+        actual: T,
+        expected: T,
+    },
+    /// Only the expected value is known, because the data it covers wasn't available
+    Unchecked(T),
 }
 
 impl Parse<&[u8]> for Header {
     /// Parse the header from its raw bytes.
     /// The header appears starting at 0x100 in a cartridge's memory.
     /// The slice given here should be just the header, so roughly &rom[100..150]
-    ///
-    /// Currently, this does not parse:
-    /// 0x0143 - CGB Flag
-    /// 0x0144..=0x0145 - New Licensee Code
-    /// 0x0146 - SGB Flag
-    /// 0x014A - Destination Code
-    /// 0x014B - Licensee Code
     fn parse(header: &[u8]) -> ParseResult<Header> {
+        Header::parse_with_encoding(header, TitleEncoding::default())
+    }
+}
+
+impl Header {
+    /// Like `Header::parse`, but decoding the title with the given encoding
+    pub fn parse_with_encoding(header: &[u8], encoding: TitleEncoding) -> ParseResult<Header> {
         Header::precondition_len(header)?;
 
         let logo_valid = Header::check_logo_valid(&header[LOGO_ADDRESS_RANGE]);
-        let title = Header::parse_title(&header[TITLE_ADDRESS_RANGE])?;
-        let cartridge_type = CartridgeType::parse(header[CARTRIDGE_TYPE_ADDRESS])?;
-        let rom_banks = Header::parse_rom_banks(header[ROM_BANKS_ADDRESS])?;
-        let ram_banks: usize = Header::parse_ram_banks(header[RAM_BANKS_ADDRESS])?;
+        let Title {
+            title,
+            manufacturer_code,
+        } = Title::decode(header, encoding);
+        let cgb_support = CgbSupport::parse(header[CGB_FLAG_ADDRESS])?;
+        let publisher = Publisher::lookup(
+            header[OLD_LICENSEE_CODE_ADDRESS],
+            [
+                header[*NEW_LICENSEE_CODE_ADDRESS_RANGE.start()],
+                header[*NEW_LICENSEE_CODE_ADDRESS_RANGE.end()],
+            ],
+        );
+        let sgb = Header::check_sgb(header);
+        let cartridge_type = CartridgeType::parse(header[CARTRIDGE_TYPE_ADDRESS])
+            .map_err(|e| e.at(HEADER_OFFSET + CARTRIDGE_TYPE_ADDRESS))?;
+        let rom_banks = Header::parse_rom_banks(header[ROM_BANKS_ADDRESS])
+            .map_err(|e| e.at(HEADER_OFFSET + ROM_BANKS_ADDRESS))?;
+        let ram_banks: usize = Header::parse_ram_banks(header[RAM_BANKS_ADDRESS])
+            .map_err(|e| e.at(HEADER_OFFSET + RAM_BANKS_ADDRESS))?;
+        let destination = Destination::parse(header[DESTINATION_CODE_ADDRESS])?;
 
         let version = header[VERSION_ADDRESS];
         let header_checksum = Header::check_header_checksum(header);
-        let global_checksum = u16::from_le_bytes([
-            header[*GLOBAL_CHECKSUM_ADDRESS_RANGE.start()],
-            header[*GLOBAL_CHECKSUM_ADDRESS_RANGE.end()],
-        ]);
+        // The global checksum covers the whole ROM, so it can only be checked by parse_rom
+        let global_checksum = Checksum::Unchecked(Header::expected_global_checksum(header));
 
         Ok(Header {
             title,
+            manufacturer_code,
+            cgb_support,
+            publisher,
+            sgb,
             cartridge_type,
             rom_banks,
             ram_banks,
+            destination,
             version,
 
             validation: Validation {
@@ -79,17 +125,63 @@ impl Parse<&[u8]> for Header {
             },
         })
     }
-}
 
-impl Header {
+    /// Parse the header out of a full ROM image
+    /// Unlike `Header::parse`, this also verifies the global checksum against the whole ROM
+    pub fn parse_rom(rom: &[u8]) -> ParseResult<Header> {
+        Header::parse_rom_with_encoding(rom, TitleEncoding::default())
+    }
+
+    /// Like `Header::parse_rom`, but decoding the title with the given encoding
+    pub fn parse_rom_with_encoding(rom: &[u8], encoding: TitleEncoding) -> ParseResult<Header> {
+        if rom.len() < HEADER_OFFSET + HEADER_BYTES {
+            return Err(ParseError::TooShort {
+                field: Field::Rom,
+                minimum: HEADER_OFFSET + HEADER_BYTES,
+                actual: rom.len(),
+            });
+        }
+
+        let mut header = Header::parse_with_encoding(&rom[HEADER_OFFSET..], encoding)?;
+        header.validation.global_checksum = Header::check_global_checksum(rom);
+
+        Ok(header)
+    }
+
+    /// One `field: value` line per field, named as in JSON and TOML and sorted by name
+    /// Nested fields are joined with dots, like `validation.header_checksum.status: ok`
+    pub fn to_text(&self) -> String {
+        let value = serde_json::to_value(self).expect("headers serialize to JSON");
+        let mut lines = Vec::new();
+        Header::text_lines("", &value, &mut lines);
+
+        lines.join("\n")
+    }
+
+    fn text_lines(name: &str, value: &serde_json::Value, lines: &mut Vec<String>) {
+        match value {
+            serde_json::Value::Object(fields) => {
+                for (field, value) in fields {
+                    let name = match name {
+                        "" => field.clone(),
+                        _ => format!("{}.{}", name, field),
+                    };
+                    Header::text_lines(&name, value, lines);
+                }
+            }
+            // Strings are printed as they are, without JSON's quotes
+            serde_json::Value::String(text) => lines.push(format!("{}: {}", name, text)),
+            _ => lines.push(format!("{}: {}", name, value)),
+        }
+    }
+
     fn precondition_len(header: &[u8]) -> ParseResult<()> {
         if header.len() < HEADER_BYTES {
-            return Err(format!(
-                "Provided header data was {} bytes, but header must be {} bytes",
-                header.len(),
-                HEADER_BYTES
-            )
-            .to_string());
+            return Err(ParseError::TooShort {
+                field: Field::Header,
+                minimum: HEADER_BYTES,
+                actual: header.len(),
+            });
         }
 
         Ok(())
@@ -99,42 +191,78 @@ impl Header {
         logo_bytes == constants::LOGO
     }
 
-    fn parse_title(title_bytes: &[u8]) -> ParseResult<String> {
-        match std::str::from_utf8(title_bytes) {
-            Ok(title_str) => Ok(title_str.trim_matches('\0').trim().to_string()),
-            Err(e) => Err(e.to_string()),
+    /// The SGB only enables its functions when the flag is 0x03 and the old licensee code is 0x33
+    fn check_sgb(header: &[u8]) -> bool {
+        header[SGB_FLAG_ADDRESS] == 0x03 && header[OLD_LICENSEE_CODE_ADDRESS] == 0x33
+    }
+
+    // Unlike the rest of the header, the global checksum is big-endian
+    fn expected_global_checksum(header: &[u8]) -> u16 {
+        u16::from_be_bytes([
+            header[*GLOBAL_CHECKSUM_ADDRESS_RANGE.start()],
+            header[*GLOBAL_CHECKSUM_ADDRESS_RANGE.end()],
+        ])
+    }
+
+    fn check_global_checksum(rom: &[u8]) -> Checksum<u16> {
+        let expected_checksum = Header::expected_global_checksum(&rom[HEADER_OFFSET..]);
+        let actual_checksum = Header::compute_global_checksum(rom);
+
+        if expected_checksum == actual_checksum {
+            Checksum::Ok(actual_checksum)
+        } else {
+            Checksum::Err {
+                actual: actual_checksum,
+                expected: expected_checksum,
+            }
         }
     }
 
+    /// The global checksum is the sum of every byte in the ROM, except the checksum itself
+    pub(crate) fn compute_global_checksum(rom: &[u8]) -> u16 {
+        let checksum_range = (HEADER_OFFSET + GLOBAL_CHECKSUM_ADDRESS_RANGE.start())
+            ..=(HEADER_OFFSET + GLOBAL_CHECKSUM_ADDRESS_RANGE.end());
+
+        rom.iter()
+            .enumerate()
+            .filter(|(address, _)| !checksum_range.contains(address))
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16))
+    }
+
     // Rom size is defined as number of banks
     fn parse_rom_banks(code: u8) -> ParseResult<usize> {
         // Only codes in 0x00..=0x08 are defined
         if code <= 0x08 {
             Ok(2 << code)
         } else {
-            Err(format!("invalid rom banks code {:#04X}", code))
+            Err(ParseError::invalid_code(Field::RomBanks, code))
         }
     }
 
-    fn parse_ram_banks(code: u8) -> ParseResult<usize> {
+    pub(crate) fn parse_ram_banks(code: u8) -> ParseResult<usize> {
         match code {
             0x00 => Ok(0),
             0x02 => Ok(1),
             0x03 => Ok(4),
             0x04 => Ok(16),
             0x05 => Ok(8),
-            _ => Err(format!("invalid ram banks code {:#04X}", code)),
+            _ => Err(ParseError::invalid_code(Field::RamBanks, code)),
         }
     }
 
-    fn check_header_checksum(header: &[u8]) -> Checksum<u8> {
-        let expected_checksum = header[HEADER_CHECKSUM_ADDRESS];
-
-        let mut actual_checksum: u8 = 0;
+    pub(crate) fn compute_header_checksum(header: &[u8]) -> u8 {
+        let mut checksum: u8 = 0;
         for byte in &header[*TITLE_ADDRESS_RANGE.start()..=VERSION_ADDRESS] {
-            actual_checksum = actual_checksum.wrapping_sub(*byte).wrapping_sub(1);
+            checksum = checksum.wrapping_sub(*byte).wrapping_sub(1);
         }
 
+        checksum
+    }
+
+    fn check_header_checksum(header: &[u8]) -> Checksum<u8> {
+        let expected_checksum = header[HEADER_CHECKSUM_ADDRESS];
+        let actual_checksum = Header::compute_header_checksum(header);
+
         if expected_checksum == actual_checksum {
             Checksum::Ok(actual_checksum)
         } else {
@@ -151,6 +279,7 @@ mod tests {
     use super::super::cartridge_type::CartridgeType;
     use super::*;
     use crate::cartridge::header::Checksum::Err;
+    use crate::cartridge::licensee::Licensee;
 
     #[test]
     fn test_handcrafted_header() {
@@ -170,6 +299,13 @@ mod tests {
         assert_eq!(
             Header {
                 title: "POKEMON RED".to_string(),
+                manufacturer_code: None,
+                cgb_support: CgbSupport::DmgOnly,
+                publisher: Publisher {
+                    licensee: Licensee::Old(0x00),
+                    name: Some("None"),
+                },
+                sgb: false,
                 cartridge_type: CartridgeType::Mbc3 {
                     battery: true,
                     ram: true,
@@ -177,6 +313,7 @@ mod tests {
                 },
                 rom_banks: 64,
                 ram_banks: 4,
+                destination: Destination::Japanese,
                 version: 0,
 
                 validation: Validation {
@@ -185,13 +322,60 @@ mod tests {
                         actual: 184,
                         expected: 0
                     },
-                    global_checksum: 0x3412,
+                    global_checksum: Checksum::Unchecked(0x1234),
                 },
             },
             header
         );
     }
 
+    #[test]
+    fn test_global_checksum() {
+        let mut rom = vec![0; 0x8000];
+        rom[0x0000] = 0xFF;
+        rom[0x7FFF] = 0x02;
+        // The checksum bytes themselves don't count towards the sum
+        rom[0x014E] = 0x01;
+        rom[0x014F] = 0x01;
+
+        assert_eq!(
+            0x0101,
+            Header::expected_global_checksum(&rom[HEADER_OFFSET..])
+        );
+        assert_eq!(Checksum::Ok(0x0101), Header::check_global_checksum(&rom));
+
+        rom[0x4000] = 0xFF;
+        assert_eq!(
+            Checksum::Err {
+                actual: 0x0200,
+                expected: 0x0101
+            },
+            Header::check_global_checksum(&rom)
+        );
+    }
+
+    #[test]
+    fn test_unknown_destination() {
+        let mut header = [0; HEADER_BYTES];
+        header[DESTINATION_CODE_ADDRESS] = 0x05;
+
+        let header = Header::parse(&header[..]).unwrap();
+        assert_eq!(Destination::Unknown(0x05), header.destination);
+    }
+
+    #[test]
+    fn test_sgb_flag() {
+        let mut header = [0; HEADER_BYTES];
+        header[SGB_FLAG_ADDRESS] = 0x03;
+        assert!(!Header::check_sgb(&header));
+
+        header[OLD_LICENSEE_CODE_ADDRESS] = 0x33;
+        assert!(Header::check_sgb(&header));
+
+        header[SGB_FLAG_ADDRESS] = 0x00;
+        assert!(!Header::check_sgb(&header));
+    }
+
     #[test]
     fn test_rom_banks() {
         let banks = Header::parse_rom_banks(0x00).unwrap();
@@ -201,7 +385,7 @@ mod tests {
         assert_eq!(512, banks);
 
         let banks = Header::parse_rom_banks(0x09);
-        assert!(banks.is_err());
+        assert_eq!(Err(ParseError::invalid_code(Field::RomBanks, 0x09)), banks);
 
         let banks = Header::parse_rom_banks(0xFF);
         assert!(banks.is_err());
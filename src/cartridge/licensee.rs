@@ -0,0 +1,296 @@
+use serde::Serialize;
+
+/// Old licensee code that redirects to the new licensee code
+const USE_NEW_LICENSEE_CODE: u8 = 0x33;
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
+#[serde(tag = "kind", content = "code", rename_all = "snake_case")]
+pub enum Licensee {
+    /// The single byte code at 0x014B, used by games released before the SGB
+    Old(u8),
+    /// The two ASCII characters at 0x0144..=0x0145
+    New(String),
+}
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize)]
+pub struct Publisher {
+    pub licensee: Licensee,
+    /// None when the code isn't a known publisher, and then left out when serialized
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<&'static str>,
+}
+
+impl Publisher {
+    /// Look up the publisher from the old licensee code, following it to the new code when it is 0x33
+    pub fn lookup(old_code: u8, new_code: [u8; 2]) -> Publisher {
+        if old_code == USE_NEW_LICENSEE_CODE {
+            let code = String::from_utf8_lossy(&new_code).to_string();
+            let name = Publisher::new_licensee_name(&code);
+
+            Publisher {
+                licensee: Licensee::New(code),
+                name,
+            }
+        } else {
+            Publisher {
+                licensee: Licensee::Old(old_code),
+                name: Publisher::old_licensee_name(old_code),
+            }
+        }
+    }
+
+    fn new_licensee_name(code: &str) -> Option<&'static str> {
+        match code {
+            "00" => Some("None"),
+            "01" => Some("Nintendo Research & Development 1"),
+            "08" => Some("Capcom"),
+            "13" => Some("EA (Electronic Arts)"),
+            "18" => Some("Hudson Soft"),
+            "19" => Some("B-AI"),
+            "20" => Some("KSS"),
+            "22" => Some("Planning Office WADA"),
+            "24" => Some("PCM Complete"),
+            "25" => Some("San-X"),
+            "28" => Some("Kemco"),
+            "29" => Some("SETA Corporation"),
+            "30" => Some("Viacom"),
+            "31" => Some("Nintendo"),
+            "32" => Some("Bandai"),
+            "33" => Some("Ocean Software/Acclaim Entertainment"),
+            "34" => Some("Konami"),
+            "35" => Some("HectorSoft"),
+            "37" => Some("Taito"),
+            "38" => Some("Hudson Soft"),
+            "39" => Some("Banpresto"),
+            "41" => Some("Ubi Soft"),
+            "42" => Some("Atlus"),
+            "44" => Some("Malibu Interactive"),
+            "46" => Some("Angel"),
+            "47" => Some("Bullet-Proof Software"),
+            "49" => Some("Irem"),
+            "50" => Some("Absolute"),
+            "51" => Some("Acclaim Entertainment"),
+            "52" => Some("Activision"),
+            "53" => Some("Sammy USA Corporation"),
+            "54" => Some("Konami"),
+            "55" => Some("Hi Tech Expressions"),
+            "56" => Some("LJN"),
+            "57" => Some("Matchbox"),
+            "58" => Some("Mattel"),
+            "59" => Some("Milton Bradley Company"),
+            "60" => Some("Titus Interactive"),
+            "61" => Some("Virgin Games Ltd."),
+            "64" => Some("Lucasfilm Games"),
+            "67" => Some("Ocean Software"),
+            "69" => Some("EA (Electronic Arts)"),
+            "70" => Some("Infogrames"),
+            "71" => Some("Interplay Entertainment"),
+            "72" => Some("Broderbund"),
+            "73" => Some("Sculptured Software"),
+            "75" => Some("The Sales Curve Limited"),
+            "78" => Some("THQ"),
+            "79" => Some("Accolade"),
+            "80" => Some("Misawa Entertainment"),
+            "83" => Some("LOZC G."),
+            "86" => Some("Tokuma Shoten"),
+            "87" => Some("Tsukuda Original"),
+            "91" => Some("Chunsoft Co."),
+            "92" => Some("Video System"),
+            "93" => Some("Ocean Software/Acclaim Entertainment"),
+            "95" => Some("Varie"),
+            "96" => Some("Yonezawa/S'Pal"),
+            "97" => Some("Kaneko"),
+            "99" => Some("Pack-In-Video"),
+            "9H" => Some("Bottom Up"),
+            "A4" => Some("Konami (Yu-Gi-Oh!)"),
+            "BL" => Some("MTO"),
+            "DK" => Some("Kodansha"),
+            _ => None,
+        }
+    }
+
+    fn old_licensee_name(code: u8) -> Option<&'static str> {
+        match code {
+            0x00 => Some("None"),
+            0x01 => Some("Nintendo"),
+            0x08 => Some("Capcom"),
+            0x09 => Some("HOT-B"),
+            0x0A => Some("Jaleco"),
+            0x0B => Some("Coconuts Japan"),
+            0x0C => Some("Elite Systems"),
+            0x13 => Some("EA (Electronic Arts)"),
+            0x18 => Some("Hudson Soft"),
+            0x19 => Some("ITC Entertainment"),
+            0x1A => Some("Yanoman"),
+            0x1D => Some("Japan Clary"),
+            0x1F => Some("Virgin Games Ltd."),
+            0x24 => Some("PCM Complete"),
+            0x25 => Some("San-X"),
+            0x28 => Some("Kemco"),
+            0x29 => Some("SETA Corporation"),
+            0x30 => Some("Infogrames"),
+            0x31 => Some("Nintendo"),
+            0x32 => Some("Bandai"),
+            0x34 => Some("Konami"),
+            0x35 => Some("HectorSoft"),
+            0x38 => Some("Capcom"),
+            0x39 => Some("Banpresto"),
+            0x3C => Some("Entertainment Interactive"),
+            0x3E => Some("Gremlin"),
+            0x41 => Some("Ubi Soft"),
+            0x42 => Some("Atlus"),
+            0x44 => Some("Malibu Interactive"),
+            0x46 => Some("Angel"),
+            0x47 => Some("Spectrum HoloByte"),
+            0x49 => Some("Irem"),
+            0x4A => Some("Virgin Games Ltd."),
+            0x4D => Some("Malibu Interactive"),
+            0x4F => Some("U.S. Gold"),
+            0x50 => Some("Absolute"),
+            0x51 => Some("Acclaim Entertainment"),
+            0x52 => Some("Activision"),
+            0x53 => Some("Sammy USA Corporation"),
+            0x54 => Some("GameTek"),
+            0x55 => Some("Park Place"),
+            0x56 => Some("LJN"),
+            0x57 => Some("Matchbox"),
+            0x59 => Some("Milton Bradley Company"),
+            0x5A => Some("Mindscape"),
+            0x5B => Some("Romstar"),
+            0x5C => Some("Naxat Soft"),
+            0x5D => Some("Tradewest"),
+            0x60 => Some("Titus Interactive"),
+            0x61 => Some("Virgin Games Ltd."),
+            0x67 => Some("Ocean Software"),
+            0x69 => Some("EA (Electronic Arts)"),
+            0x6E => Some("Elite Systems"),
+            0x6F => Some("Electro Brain"),
+            0x70 => Some("Infogrames"),
+            0x71 => Some("Interplay Entertainment"),
+            0x72 => Some("Broderbund"),
+            0x73 => Some("Sculptured Software"),
+            0x75 => Some("The Sales Curve Limited"),
+            0x78 => Some("THQ"),
+            0x79 => Some("Accolade"),
+            0x7A => Some("Triffix Entertainment"),
+            0x7C => Some("MicroProse"),
+            0x7F => Some("Kemco"),
+            0x80 => Some("Misawa Entertainment"),
+            0x83 => Some("LOZC G."),
+            0x86 => Some("Tokuma Shoten"),
+            0x8B => Some("Bullet-Proof Software"),
+            0x8C => Some("Vic Tokai Corp."),
+            0x8E => Some("Ape Inc."),
+            0x8F => Some("I'Max"),
+            0x91 => Some("Chunsoft Co."),
+            0x92 => Some("Video System"),
+            0x93 => Some("Tsubaraya Productions"),
+            0x95 => Some("Varie"),
+            0x96 => Some("Yonezawa/S'Pal"),
+            0x97 => Some("Kemco"),
+            0x99 => Some("Arc"),
+            0x9A => Some("Nihon Bussan"),
+            0x9B => Some("Tecmo"),
+            0x9C => Some("Imagineer"),
+            0x9D => Some("Banpresto"),
+            0x9F => Some("Nova"),
+            0xA1 => Some("Hori Electric"),
+            0xA2 => Some("Bandai"),
+            0xA4 => Some("Konami"),
+            0xA6 => Some("Kawada"),
+            0xA7 => Some("Takara"),
+            0xA9 => Some("Technos Japan"),
+            0xAA => Some("Broderbund"),
+            0xAC => Some("Toei Animation"),
+            0xAD => Some("Toho"),
+            0xAF => Some("Namco"),
+            0xB0 => Some("Acclaim Entertainment"),
+            0xB1 => Some("ASCII Corporation or Nexsoft"),
+            0xB2 => Some("Bandai"),
+            0xB4 => Some("Square Enix"),
+            0xB6 => Some("HAL Laboratory"),
+            0xB7 => Some("SNK"),
+            0xB9 => Some("Pony Canyon"),
+            0xBA => Some("Culture Brain"),
+            0xBB => Some("Sunsoft"),
+            0xBD => Some("Sony Imagesoft"),
+            0xBF => Some("Sammy Corporation"),
+            0xC0 => Some("Taito"),
+            0xC2 => Some("Kemco"),
+            0xC3 => Some("Square"),
+            0xC4 => Some("Tokuma Shoten"),
+            0xC5 => Some("Data East"),
+            0xC6 => Some("Tonkin House"),
+            0xC8 => Some("Koei"),
+            0xC9 => Some("UFL"),
+            0xCA => Some("Ultra Games"),
+            0xCB => Some("VAP, Inc."),
+            0xCC => Some("Use Corporation"),
+            0xCD => Some("Meldac"),
+            0xCE => Some("Pony Canyon"),
+            0xCF => Some("Angel"),
+            0xD0 => Some("Taito"),
+            0xD1 => Some("SOFEL (Software Engineering Lab)"),
+            0xD2 => Some("Quest"),
+            0xD3 => Some("Sigma Enterprises"),
+            0xD4 => Some("ASK Kodansha Co."),
+            0xD6 => Some("Naxat Soft"),
+            0xD7 => Some("Copya System"),
+            0xD9 => Some("Banpresto"),
+            0xDA => Some("Tomy"),
+            0xDB => Some("LJN"),
+            0xDD => Some("Nippon Computer Systems"),
+            0xDE => Some("Human Ent."),
+            0xDF => Some("Altron"),
+            0xE0 => Some("Jaleco"),
+            0xE1 => Some("Towa Chiki"),
+            0xE2 => Some("Yutaka"),
+            0xE3 => Some("Varie"),
+            0xE5 => Some("Epoch"),
+            0xE7 => Some("Athena"),
+            0xE8 => Some("Asmik Ace Entertainment"),
+            0xE9 => Some("Natsume"),
+            0xEA => Some("King Records"),
+            0xEB => Some("Atlus"),
+            0xEC => Some("Epic/Sony Records"),
+            0xEE => Some("IGS"),
+            0xF0 => Some("A Wave"),
+            0xF3 => Some("Extreme Entertainment"),
+            0xFF => Some("LJN"),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_old_licensee() {
+        assert_eq!(
+            Publisher {
+                licensee: Licensee::Old(0x01),
+                name: Some("Nintendo"),
+            },
+            Publisher::lookup(0x01, *b"99")
+        );
+    }
+
+    #[test]
+    fn test_new_licensee_redirect() {
+        assert_eq!(
+            Publisher {
+                licensee: Licensee::New("A4".to_string()),
+                name: Some("Konami (Yu-Gi-Oh!)"),
+            },
+            Publisher::lookup(0x33, *b"A4")
+        );
+    }
+
+    #[test]
+    fn test_unknown_licensee() {
+        assert_eq!(None, Publisher::lookup(0x02, [0, 0]).name);
+        assert_eq!(None, Publisher::lookup(0x33, *b"ZZ").name);
+    }
+}
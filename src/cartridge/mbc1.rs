@@ -1,7 +1,7 @@
 use crate::cartridge::header::Header;
-use crate::cartridge::parse::{Parse, ParseResult};
-use crate::memory::MemoryMapped;
-use std::ops::RangeInclusive;
+use crate::cartridge::parse::{Field, ParseError, ParseResult};
+use crate::memory::{MemoryMapped, OPEN_BUS_VALUE};
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
 
 const RAM_GATE_REGISTER_ADDRESS_START: u16 = 0x0000;
 const RAM_GATE_REGISTER_ADDRESS_END: u16 = 0x1FFF;
@@ -17,9 +17,14 @@ const LOW_ROM_BANK_ADDRESS_END: u16 = 0x3FFF;
 const HIGH_ROM_BANK_ADDRESS_START: u16 = 0x4000;
 const HIGH_ROM_BANK_ADDRESS_END: u16 = 0x7FFF;
 
-const HEADER_ADDRESS_RANGE: RangeInclusive<usize> = 0x100..=0x150;
+const RAM_ADDRESS_START: u16 = 0xA000;
+const RAM_ADDRESS_END: u16 = 0xBFFF;
 
 const ROM_BANK_SIZE_BYTES: usize = 0x4000;
+const RAM_BANK_SIZE_BYTES: usize = 0x2000;
+
+/// The save state version cartridge RAM was first saved in
+const RAM_SAVED_SINCE_VERSION: u16 = 3;
 
 // Note: As implemented, this only supports the common memory bank controller MBC1
 // It does not support MBC1M (aka "multicart"), MBC2, MBC3, MBC30, MBC5, MBC6, MBC7, etc...
@@ -31,7 +36,8 @@ pub struct Mbc1 {
     mode_register: bool,
 
     rom: Vec<u8>,
-    // TODO ram
+    /// As many banks as the header asks for, which may be none
+    ram: Vec<u8>,
 }
 
 impl Mbc1 {
@@ -44,12 +50,20 @@ impl Mbc1 {
 
             // TODO what is "standard"?
             rom: Mbc1::create_rom(4),
+            ram: Vec::new(),
         }
     }
 
     pub fn from_bytes(rom_bytes: &[u8]) -> ParseResult<Mbc1> {
-        let header = Header::parse(&rom_bytes[HEADER_ADDRESS_RANGE])?;
+        let header = Header::parse_rom(rom_bytes)?;
         let mut rom = Mbc1::create_rom(header.rom_banks);
+        if rom_bytes.len() != rom.len() {
+            return Err(ParseError::WrongLength {
+                field: Field::Rom,
+                expected: rom.len(),
+                actual: rom_bytes.len(),
+            });
+        }
 
         rom.copy_from_slice(rom_bytes);
 
@@ -61,9 +75,52 @@ impl Mbc1 {
             mode_register: false,
 
             rom,
+            ram: vec![0; header.ram_banks * RAM_BANK_SIZE_BYTES],
         })
     }
 
+    /// Identifies the ROM, for checking a save state belongs to it
+    pub fn rom_checksum(&self) -> u16 {
+        Header::compute_global_checksum(&self.rom)
+    }
+
+    /// The ROM bank mapped at the address, or None if the address isn't ROM
+    pub fn rom_bank(&self, address: u16) -> Option<u8> {
+        match address {
+            LOW_ROM_BANK_ADDRESS_START..=HIGH_ROM_BANK_ADDRESS_END => {
+                Some(self.active_rom_bank_number(address))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn rom_banks(&self) -> usize {
+        self.rom.len() / ROM_BANK_SIZE_BYTES
+    }
+
+    /// Read from a ROM bank whether or not it is mapped, at an address in either ROM window
+    /// None if there is no such bank or the address isn't ROM
+    pub fn read_rom_bank(&self, bank: usize, address: u16) -> Option<u8> {
+        if address > HIGH_ROM_BANK_ADDRESS_END {
+            return None;
+        }
+
+        let address_within_bank = address as usize % ROM_BANK_SIZE_BYTES;
+        self.rom
+            .get(bank * ROM_BANK_SIZE_BYTES + address_within_bank)
+            .copied()
+    }
+
+    /// Where an address in a ROM bank is within the whole ROM, whether or not the bank exists
+    pub fn rom_offset(bank: u8, address: u16) -> usize {
+        bank as usize * ROM_BANK_SIZE_BYTES + address as usize % ROM_BANK_SIZE_BYTES
+    }
+
+    /// Whether RAM is there to read and write at $A000-$BFFF, which it is once enabled
+    pub fn is_ram_mapped(&self) -> bool {
+        self.ram_gate_register && !self.ram.is_empty()
+    }
+
     fn create_rom(banks: usize) -> Vec<u8> {
         vec![0; banks * ROM_BANK_SIZE_BYTES]
     }
@@ -71,10 +128,18 @@ impl Mbc1 {
     /// Translate a 16-bit GameBoy address to a usize indexing the full MBC1 ROM vector
     /// This takes the current bank registers into account
     fn rom_address_to_rom_index(&self, address: u16) -> usize {
-        let address_within_bank = (address as usize) % ROM_BANK_SIZE_BYTES;
-        let bank_offset = self.active_rom_bank_number(address) as usize * ROM_BANK_SIZE_BYTES;
+        Mbc1::rom_offset(self.active_rom_bank_number(address), address)
+    }
 
-        address_within_bank + bank_offset
+    /// Bank register 2 picks the RAM bank in mode 1, and smaller RAMs repeat to fill the banks
+    fn ram_address_to_ram_index(&self, address: u16) -> usize {
+        let bank = if self.mode_register {
+            self.bank_register_2 as usize
+        } else {
+            0
+        };
+
+        (bank * RAM_BANK_SIZE_BYTES + (address - RAM_ADDRESS_START) as usize) % self.ram.len()
     }
 
     // TODO how should this behave when the bank number would be greater than the number of banks on the chip?
@@ -92,24 +157,31 @@ impl Mbc1 {
             HIGH_ROM_BANK_ADDRESS_START..=HIGH_ROM_BANK_ADDRESS_END => {
                 (self.bank_register_2 << 5) + self.bank_register_1
             }
-            _ => {
-                panic!(
-                    "ROM is only readable in the range {:#06X}..={:#06X}, but a read was attempted at {:#06X}",
-                    LOW_ROM_BANK_ADDRESS_START,
-                    HIGH_ROM_BANK_ADDRESS_END,
-                    address);
-            }
+            _ => unreachable!(
+                "ROM banks only cover {:#06X}..={:#06X}, but {:#06X} was translated",
+                LOW_ROM_BANK_ADDRESS_START, HIGH_ROM_BANK_ADDRESS_END, address
+            ),
         }
     }
 }
 
+impl Default for Mbc1 {
+    fn default() -> Self {
+        Mbc1::new()
+    }
+}
+
 impl MemoryMapped for Mbc1 {
     fn read_byte(&self, address: u16) -> u8 {
         match address {
             LOW_ROM_BANK_ADDRESS_START..=HIGH_ROM_BANK_ADDRESS_END => {
                 self.rom[self.rom_address_to_rom_index(address)]
             }
-            _ => todo!("RAM reads not implemented"),
+            RAM_ADDRESS_START..=RAM_ADDRESS_END if self.is_ram_mapped() => {
+                self.ram[self.ram_address_to_ram_index(address)]
+            }
+            // Nothing drives the bus while RAM is disabled
+            _ => OPEN_BUS_VALUE,
         }
     }
 
@@ -134,13 +206,38 @@ impl MemoryMapped for Mbc1 {
             MODE_REGISTER_ADDRESS_START..=MODE_REGISTER_ADDRESS_END => {
                 self.mode_register = value & 0x1 == 1;
             }
-            _ => {
-                panic!(
-                    "ROM is only addressable in range 0x0000..=0x7FFF, but was written at {:#06X}",
-                    address
-                )
+            RAM_ADDRESS_START..=RAM_ADDRESS_END if self.is_ram_mapped() => {
+                let index = self.ram_address_to_ram_index(address);
+                self.ram[index] = value;
             }
+            _ => {}
+        }
+    }
+}
+
+// The ROM itself isn't saved, since it never changes
+impl SaveState for Mbc1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.ram_gate_register);
+        writer.write_u8(self.bank_register_1);
+        writer.write_u8(self.bank_register_2);
+        writer.write_bool(self.mode_register);
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.ram_gate_register = reader.read_bool()?;
+        self.bank_register_1 = reader.read_u8()? & 0x1F;
+        self.bank_register_2 = reader.read_u8()? & 0x3;
+        self.mode_register = reader.read_bool()?;
+
+        // The RAM's size comes from the ROM's header, which the state was checked against
+        if reader.version() >= RAM_SAVED_SINCE_VERSION {
+            let ram = reader.read_bytes(self.ram.len())?;
+            self.ram.copy_from_slice(ram);
         }
+
+        Ok(())
     }
 }
 
@@ -191,6 +288,8 @@ mod tests {
         assert_eq!(1, rom.bank_register_1);
     }
 
+    // TODO this range is empty, so the loop below never runs
+    #[allow(clippy::reversed_empty_ranges)]
     #[test]
     fn test_bank_2() {
         let mut rom = Mbc1::new();
@@ -285,6 +384,72 @@ mod tests {
         assert_eq!(0x20, rom.active_rom_bank_number(0x1234));
     }
 
+    #[test]
+    fn test_ram() {
+        let mut rom = Mbc1 {
+            ram: vec![0; 4 * RAM_BANK_SIZE_BYTES],
+            ..Mbc1::new()
+        };
+
+        // Disabled RAM can't be written or read
+        rom.write_byte(0xA000, 0x12);
+        assert_eq!(OPEN_BUS_VALUE, rom.read_byte(0xA000));
+
+        rom.write_byte(RAM_GATE_REGISTER_ADDRESS_START, 0x0A);
+        rom.write_byte(0xA000, 0x12);
+        assert_eq!(0x12, rom.read_byte(0xA000));
+
+        // Bank register 2 only picks the RAM bank in mode 1
+        rom.write_byte(BANK_2_REGISTER_ADDRESS_START, 0b10);
+        assert_eq!(0x12, rom.read_byte(0xA000));
+        rom.write_byte(MODE_REGISTER_ADDRESS_START, 0x1);
+        rom.write_byte(0xBFFF, 0x34);
+        assert_eq!(0x34, rom.ram[2 * RAM_BANK_SIZE_BYTES + 0x1FFF]);
+        assert_eq!(0, rom.read_byte(0xA000));
+
+        // Without any RAM there is nothing to enable
+        let mut rom = Mbc1::new();
+        rom.write_byte(RAM_GATE_REGISTER_ADDRESS_START, 0x0A);
+        assert!(!rom.is_ram_mapped());
+    }
+
+    #[test]
+    fn test_ram_state() {
+        let mut rom = Mbc1 {
+            ram: vec![0; RAM_BANK_SIZE_BYTES],
+            ..Mbc1::new()
+        };
+        rom.write_byte(RAM_GATE_REGISTER_ADDRESS_START, 0x0A);
+        rom.write_byte(0xA000, 0x12);
+        rom.write_byte(0xBFFF, 0x34);
+
+        let mut writer = StateWriter::default();
+        rom.save_state(&mut writer);
+        let state = writer.into_bytes();
+
+        let mut restored = Mbc1 {
+            ram: vec![0; RAM_BANK_SIZE_BYTES],
+            ..Mbc1::new()
+        };
+        let mut reader = StateReader::new(&state);
+        restored.load_state(&mut reader).unwrap();
+        reader.finish().unwrap();
+        assert_eq!(rom.ram, restored.ram);
+        assert!(restored.is_ram_mapped());
+        assert_eq!(0x34, restored.read_byte(0xBFFF));
+
+        // Version 2 states end before the RAM, which is left as it was
+        let mut old = Mbc1 {
+            ram: vec![0x56; RAM_BANK_SIZE_BYTES],
+            ..Mbc1::new()
+        };
+        let mut reader = StateReader::with_version(&state[..4], RAM_SAVED_SINCE_VERSION - 1);
+        old.load_state(&mut reader).unwrap();
+        reader.finish().unwrap();
+        assert!(old.ram.iter().all(|byte| *byte == 0x56));
+        assert!(old.ram_gate_register);
+    }
+
     #[test]
     fn test_rom_addressing() {
         let mut rom = Mbc1::new();
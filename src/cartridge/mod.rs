@@ -1,5 +1,10 @@
 pub mod cartridge_type;
+pub mod cgb_support;
 pub mod constants;
+pub mod destination;
+pub mod fix;
 pub mod header;
+pub mod licensee;
 pub mod mbc1;
 pub mod parse;
+pub mod title;
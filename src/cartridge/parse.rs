@@ -1,5 +1,180 @@
-pub type ParseResult<T> = Result<T, String>;
+use std::{error, fmt};
+
+pub type ParseResult<T> = Result<T, ParseError>;
 
 pub trait Parse<T = u8, V = Self> {
     fn parse(t: T) -> ParseResult<V>;
 }
+
+/// The part of the data that failed to parse
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Field {
+    Rom,
+    Header,
+    CartridgeType,
+    RomBanks,
+    RamBanks,
+    SgbPackets,
+    SgbCommand,
+    SgbPlayers,
+    SgbData,
+}
+
+impl fmt::Display for Field {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Field::Rom => "ROM",
+            Field::Header => "header",
+            Field::CartridgeType => "cartridge type",
+            Field::RomBanks => "rom banks",
+            Field::RamBanks => "ram banks",
+            Field::SgbPackets => "SGB packets",
+            Field::SgbCommand => "SGB command",
+            Field::SgbPlayers => "MLT_REQ players",
+            Field::SgbData => "SGB data",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    /// Fewer bytes than the field needs
+    TooShort {
+        field: Field,
+        minimum: usize,
+        actual: usize,
+    },
+    /// More bytes than fit in the field
+    TooLong {
+        field: Field,
+        maximum: usize,
+        actual: usize,
+    },
+    /// A length that disagrees with what another field declares
+    WrongLength {
+        field: Field,
+        expected: usize,
+        actual: usize,
+    },
+    /// A byte that isn't one of the field's defined codes
+    /// The offset is into the ROM, and is only known when parsing more than the single byte
+    InvalidCode {
+        field: Field,
+        offset: Option<usize>,
+        value: u8,
+    },
+}
+
+impl ParseError {
+    pub fn invalid_code(field: Field, value: u8) -> ParseError {
+        ParseError::InvalidCode {
+            field,
+            offset: None,
+            value,
+        }
+    }
+
+    /// Fill in where an invalid code was found
+    pub fn at(self, offset: usize) -> ParseError {
+        match self {
+            ParseError::InvalidCode { field, value, .. } => ParseError::InvalidCode {
+                field,
+                offset: Some(offset),
+                value,
+            },
+            other => other,
+        }
+    }
+
+    pub fn field(&self) -> Field {
+        match self {
+            ParseError::TooShort { field, .. }
+            | ParseError::TooLong { field, .. }
+            | ParseError::WrongLength { field, .. }
+            | ParseError::InvalidCode { field, .. } => *field,
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::TooShort {
+                field,
+                minimum,
+                actual,
+            } => write!(
+                f,
+                "{} was {} bytes, but must be at least {} bytes",
+                field, actual, minimum
+            ),
+            ParseError::TooLong {
+                field,
+                maximum,
+                actual,
+            } => write!(
+                f,
+                "{} was {} bytes, but only {} bytes fit",
+                field, actual, maximum
+            ),
+            ParseError::WrongLength {
+                field,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "{} was {} bytes, but should be {} bytes",
+                field, actual, expected
+            ),
+            ParseError::InvalidCode {
+                field,
+                offset: Some(offset),
+                value,
+            } => write!(
+                f,
+                "invalid {} code {:#04X} at {:#06X}",
+                field, value, offset
+            ),
+            ParseError::InvalidCode {
+                field,
+                offset: None,
+                value,
+            } => write!(f, "invalid {} code {:#04X}", field, value),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_at_sets_offset() {
+        let error = ParseError::invalid_code(Field::RamBanks, 0x06).at(0x0149);
+
+        assert_eq!(
+            ParseError::InvalidCode {
+                field: Field::RamBanks,
+                offset: Some(0x0149),
+                value: 0x06,
+            },
+            error
+        );
+        assert_eq!("invalid ram banks code 0x06 at 0x0149", error.to_string());
+    }
+
+    #[test]
+    fn test_at_ignores_other_errors() {
+        let error = ParseError::TooShort {
+            field: Field::Header,
+            minimum: 0x50,
+            actual: 0x10,
+        };
+
+        assert_eq!(error.clone(), error.at(0x0100));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::cartridge::header::{
+    CGB_FLAG_ADDRESS, MANUFACTURER_CODE_ADDRESS_RANGE, TITLE_ADDRESS_RANGE,
+};
+
+const HALF_WIDTH_KATAKANA_START: u8 = 0xA1;
+const HALF_WIDTH_KATAKANA_END: u8 = 0xDF;
+const HALF_WIDTH_KATAKANA_CHAR_START: u32 = 0xFF61;
+
+/// How to decode title bytes that aren't printable ASCII
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum TitleEncoding {
+    /// Anything outside printable ASCII becomes U+FFFD
+    #[default]
+    Ascii,
+    /// Also decode the single byte half-width katakana of Shift-JIS
+    /// Two byte characters need Shift-JIS's full tables, so they still become U+FFFD
+    ShiftJis,
+}
+
+/// The title, and the manufacturer code that later cartridges carve out of its end
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Title {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+}
+
+impl Title {
+    /// Decode the title from the full header
+    ///
+    /// The title is 16 bytes on the DMG, but CGB cartridges take its last byte for the CGB flag.
+    /// Newer CGB cartridges also take the 4 bytes before that for a manufacturer code.
+    /// Whether that code is there isn't flagged anywhere, so it is assumed to be there when
+    /// those bytes are all uppercase ASCII letters or digits.
+    pub fn decode(header: &[u8], encoding: TitleEncoding) -> Title {
+        let cgb = header[CGB_FLAG_ADDRESS] & 0x80 != 0;
+        let manufacturer_code_bytes = &header[MANUFACTURER_CODE_ADDRESS_RANGE];
+        let has_manufacturer_code = cgb
+            && manufacturer_code_bytes
+                .iter()
+                .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit());
+
+        let title_end = if has_manufacturer_code {
+            *MANUFACTURER_CODE_ADDRESS_RANGE.start()
+        } else if cgb {
+            CGB_FLAG_ADDRESS
+        } else {
+            CGB_FLAG_ADDRESS + 1
+        };
+
+        Title {
+            title: decode(&header[*TITLE_ADDRESS_RANGE.start()..title_end], encoding),
+            manufacturer_code: has_manufacturer_code
+                .then(|| String::from_utf8_lossy(manufacturer_code_bytes).to_string()),
+        }
+    }
+}
+
+/// Decode text from the header, dropping the zero padding around it
+pub fn decode(bytes: &[u8], encoding: TitleEncoding) -> String {
+    let text: String = bytes
+        .iter()
+        .map(|byte| match (*byte, encoding) {
+            (0x00, _) => '\0',
+            (0x20..=0x7E, _) => *byte as char,
+            (HALF_WIDTH_KATAKANA_START..=HALF_WIDTH_KATAKANA_END, TitleEncoding::ShiftJis) => {
+                char::from_u32(
+                    HALF_WIDTH_KATAKANA_CHAR_START + (*byte - HALF_WIDTH_KATAKANA_START) as u32,
+                )
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+            }
+            _ => char::REPLACEMENT_CHARACTER,
+        })
+        .collect();
+
+    text.trim_matches('\0').trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header_with_title(title: &[u8], cgb_flag: u8) -> [u8; 0x50] {
+        let mut header = [0; 0x50];
+        header[CGB_FLAG_ADDRESS] = cgb_flag;
+        // A full 16 byte title overwrites the CGB flag, as it does on the DMG
+        let title_start = *TITLE_ADDRESS_RANGE.start();
+        header[title_start..title_start + title.len()].copy_from_slice(title);
+        header
+    }
+
+    #[test]
+    fn test_dmg_title() {
+        let header = header_with_title(b"SIXTEEN CHARS!!!", 0x00);
+
+        assert_eq!(
+            Title {
+                title: "SIXTEEN CHARS!!!".to_string(),
+                manufacturer_code: None,
+            },
+            Title::decode(&header, TitleEncoding::Ascii)
+        );
+    }
+
+    #[test]
+    fn test_cgb_title() {
+        let header = header_with_title(b"FIFTEEN chars!!", 0x80);
+
+        assert_eq!(
+            Title {
+                title: "FIFTEEN chars!!".to_string(),
+                manufacturer_code: None,
+            },
+            Title::decode(&header, TitleEncoding::Ascii)
+        );
+    }
+
+    #[test]
+    fn test_manufacturer_code() {
+        let header = header_with_title(b"PM_CRYSTAL\0BYTE", 0xC0);
+
+        assert_eq!(
+            Title {
+                title: "PM_CRYSTAL".to_string(),
+                manufacturer_code: Some("BYTE".to_string()),
+            },
+            Title::decode(&header, TitleEncoding::Ascii)
+        );
+    }
+
+    #[test]
+    fn test_replacement_characters() {
+        assert_eq!("A\u{FFFD}B", decode(b"A\xB1B\0\0", TitleEncoding::Ascii));
+        assert_eq!("\u{FFFD}", decode(b"\x81\0", TitleEncoding::ShiftJis));
+    }
+
+    #[test]
+    fn test_half_width_katakana() {
+        // ｱｲｳ
+        assert_eq!(
+            "\u{FF71}\u{FF72}\u{FF73}",
+            decode(b"\xB1\xB2\xB3", TitleEncoding::ShiftJis)
+        );
+    }
+}
@@ -0,0 +1,374 @@
+use crate::bus::{Access, AccessKind, ObserverId};
+use crate::cartridge::mbc1::Mbc1;
+use crate::opcodes::OPCODES;
+use crate::system::System;
+use serde::Serialize;
+use std::cell::{Ref, RefCell};
+use std::collections::VecDeque;
+use std::ops::{BitOr, BitOrAssign};
+use std::rc::Rc;
+
+const ROM_BANK_SIZE_BYTES: usize = 0x4000;
+const SWITCHABLE_BANK_ADDRESS: usize = 0x4000;
+
+/// How a byte of ROM has been used, as flags since a byte can be used more than one way
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Usage(u8);
+
+impl Usage {
+    /// Fetched as the opcode of an instruction, including the byte after a $CB prefix
+    pub const OPCODE: Usage = Usage(1 << 0);
+    /// Fetched as an instruction's immediate value or address
+    pub const OPERAND: Usage = Usage(1 << 1);
+    /// Read by an instruction, rather than fetched as part of it
+    pub const DATA: Usage = Usage(1 << 2);
+    /// Read by a DMA, like graphics copied to VRAM
+    pub const DMA_SOURCE: Usage = Usage(1 << 3);
+
+    const NAMES: [(Usage, &'static str); 4] = [
+        (Usage::OPCODE, "opcode"),
+        (Usage::OPERAND, "operand"),
+        (Usage::DATA, "data"),
+        (Usage::DMA_SOURCE, "dma_source"),
+    ];
+
+    /// Bits that aren't flags are ignored
+    pub fn from_bits(bits: u8) -> Usage {
+        Usage(bits & 0b1111)
+    }
+
+    pub fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub fn contains(self, other: Usage) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn names(self) -> Vec<&'static str> {
+        Usage::NAMES
+            .iter()
+            .filter(|(usage, _)| self.contains(*usage))
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl BitOr for Usage {
+    type Output = Usage;
+
+    fn bitor(self, other: Usage) -> Usage {
+        Usage(self.0 | other.0)
+    }
+}
+
+impl BitOrAssign for Usage {
+    fn bitor_assign(&mut self, other: Usage) {
+        self.0 |= other.0;
+    }
+}
+
+/// A code/data log: how each byte of a cartridge's ROM has been used while running it
+///
+/// Bytes are kept by their offset in the ROM, not by address, so the same address in different
+/// banks is logged separately. Logs from several runs can be merged to cover more of the ROM.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CodeDataLog {
+    usage: Vec<Usage>,
+    /// The reads the CPU is expected to make to fetch the rest of the current instruction
+    fetches: VecDeque<(u16, Usage)>,
+}
+
+impl CodeDataLog {
+    pub fn new(rom_size: usize) -> CodeDataLog {
+        CodeDataLog {
+            usage: vec![Usage::default(); rom_size],
+            fetches: VecDeque::new(),
+        }
+    }
+
+    /// One byte of flags for each byte of ROM, in `Usage`'s bit order
+    pub fn from_bytes(bytes: &[u8]) -> CodeDataLog {
+        CodeDataLog {
+            usage: bytes.iter().map(|bits| Usage::from_bits(*bits)).collect(),
+            fetches: VecDeque::new(),
+        }
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.usage.iter().map(|usage| usage.bits()).collect()
+    }
+
+    /// Runs of bytes used the same way, leaving out unused bytes
+    pub fn to_json(&self) -> String {
+        let mut ranges: Vec<JsonRange> = Vec::new();
+
+        for (offset, usage) in self.usage.iter().enumerate() {
+            if usage.is_empty() {
+                continue;
+            }
+
+            // Ranges stay within a bank, so each has one address
+            match ranges.last_mut() {
+                Some(range)
+                    if range.usage == usage.names()
+                        && range.offset + range.length == offset
+                        && !offset.is_multiple_of(ROM_BANK_SIZE_BYTES) =>
+                {
+                    range.length += 1;
+                }
+                _ => ranges.push(JsonRange {
+                    offset,
+                    length: 1,
+                    bank: offset / ROM_BANK_SIZE_BYTES,
+                    address: bank_address(offset),
+                    usage: usage.names(),
+                }),
+            }
+        }
+
+        serde_json::to_string_pretty(&JsonLog {
+            rom_size: self.usage.len(),
+            ranges,
+        })
+        .expect("a code/data log always serializes")
+    }
+
+    pub fn len(&self) -> usize {
+        self.usage.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.usage.is_empty()
+    }
+
+    /// How the byte at this ROM offset has been used, which is not at all past the end
+    pub fn usage(&self, offset: usize) -> Usage {
+        self.usage.get(offset).copied().unwrap_or_default()
+    }
+
+    /// How many bytes have been used in at least this way
+    pub fn count(&self, usage: Usage) -> usize {
+        self.usage
+            .iter()
+            .filter(|byte_usage| byte_usage.contains(usage))
+            .count()
+    }
+
+    /// How many bytes have been used in any way
+    pub fn used(&self) -> usize {
+        self.usage.iter().filter(|usage| !usage.is_empty()).count()
+    }
+
+    /// Add everything another log has seen, growing to the larger of the two
+    pub fn merge(&mut self, other: &CodeDataLog) {
+        if other.usage.len() > self.usage.len() {
+            self.usage.resize(other.usage.len(), Usage::default());
+        }
+
+        for (usage, other_usage) in self.usage.iter_mut().zip(&other.usage) {
+            *usage |= *other_usage;
+        }
+    }
+
+    /// Log one access, as a bus observer would see it
+    pub fn record(&mut self, access: &Access) {
+        match access.kind {
+            AccessKind::Execute => {
+                let (opcode_len, len) = match access.new_value {
+                    0xCB => (2, 2),
+                    opcode => (1, OPCODES[opcode as usize].length),
+                };
+
+                self.fetches = (0..len)
+                    .map(|offset| {
+                        let usage = if offset < opcode_len {
+                            Usage::OPCODE
+                        } else {
+                            Usage::OPERAND
+                        };
+                        (access.address.wrapping_add(offset as u16), usage)
+                    })
+                    .collect();
+            }
+            AccessKind::Read => {
+                let usage = match self.fetches.front() {
+                    Some((address, usage)) if *address == access.address => {
+                        let usage = *usage;
+                        self.fetches.pop_front();
+                        usage
+                    }
+                    _ => Usage::DATA,
+                };
+                self.mark(access, usage);
+            }
+            AccessKind::Dma => self.mark(access, Usage::DMA_SOURCE),
+            // Writes to ROM go to the mapper's registers, so don't use the ROM's bytes
+            AccessKind::Write => {}
+        }
+    }
+
+    fn mark(&mut self, access: &Access, usage: Usage) {
+        // Only ROM has a bank, and only ROM is logged
+        if let Some(bank) = access.bank {
+            if let Some(byte_usage) = self.usage.get_mut(Mbc1::rom_offset(bank, access.address)) {
+                *byte_usage |= usage;
+            }
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct JsonLog {
+    rom_size: usize,
+    ranges: Vec<JsonRange>,
+}
+
+#[derive(Serialize)]
+struct JsonRange {
+    /// From the start of the ROM
+    offset: usize,
+    length: usize,
+    bank: usize,
+    /// Where the CPU sees the range's first byte when its bank is mapped
+    address: usize,
+    usage: Vec<&'static str>,
+}
+
+fn bank_address(offset: usize) -> usize {
+    match offset / ROM_BANK_SIZE_BYTES {
+        0 => offset,
+        _ => SWITCHABLE_BANK_ADDRESS + offset % ROM_BANK_SIZE_BYTES,
+    }
+}
+
+/// Keeps a code/data log of a system's ROM as it runs
+pub struct CodeDataLogger {
+    log: Rc<RefCell<CodeDataLog>>,
+    observer: ObserverId,
+}
+
+impl CodeDataLogger {
+    pub fn attach(system: &mut System) -> CodeDataLogger {
+        let rom_size = system.bus().cartridge.rom_banks() * ROM_BANK_SIZE_BYTES;
+        let log = Rc::new(RefCell::new(CodeDataLog::new(rom_size)));
+
+        let observed_log = Rc::clone(&log);
+        let observer = system
+            .bus_mut()
+            .add_observer(move |access: &Access| observed_log.borrow_mut().record(access));
+
+        CodeDataLogger { log, observer }
+    }
+
+    /// The log so far
+    pub fn log(&self) -> Ref<'_, CodeDataLog> {
+        self.log.borrow()
+    }
+
+    /// Stop logging, returning the log
+    pub fn detach(self, system: &mut System) -> CodeDataLog {
+        system.bus_mut().remove_observer(self.observer);
+
+        Rc::try_unwrap(self.log)
+            .map(RefCell::into_inner)
+            .unwrap_or_else(|log| log.borrow().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn access(kind: AccessKind, address: u16, value: u8) -> Access {
+        Access {
+            kind,
+            pc: 0x150,
+            address,
+            bank: Some(if address < 0x4000 { 0 } else { 1 }),
+            old_value: value,
+            new_value: value,
+        }
+    }
+
+    #[test]
+    fn test_fetches_are_code() {
+        let mut log = CodeDataLog::new(0x8000);
+
+        // ld a, [$4000]
+        log.record(&access(AccessKind::Execute, 0x150, 0xFA));
+        log.record(&access(AccessKind::Read, 0x150, 0xFA));
+        log.record(&access(AccessKind::Read, 0x151, 0x00));
+        log.record(&access(AccessKind::Read, 0x152, 0x40));
+        log.record(&access(AccessKind::Read, 0x4000, 0x12));
+
+        assert_eq!(Usage::OPCODE, log.usage(0x150));
+        assert_eq!(Usage::OPERAND, log.usage(0x151));
+        assert_eq!(Usage::OPERAND, log.usage(0x152));
+        assert_eq!(Usage::DATA, log.usage(0x4000));
+
+        // The second byte of a prefixed instruction is part of its opcode
+        log.record(&access(AccessKind::Execute, 0x153, 0xCB));
+        log.record(&access(AccessKind::Read, 0x153, 0xCB));
+        log.record(&access(AccessKind::Read, 0x154, 0x37));
+        assert_eq!(Usage::OPCODE, log.usage(0x154));
+    }
+
+    #[test]
+    fn test_round_trip_and_merge() {
+        let mut log = CodeDataLog::new(4);
+        log.usage[1] = Usage::OPCODE;
+        log.usage[2] = Usage::DATA | Usage::DMA_SOURCE;
+        assert_eq!(vec![0, 1, 12, 0], log.to_bytes());
+        assert_eq!(log, CodeDataLog::from_bytes(&log.to_bytes()));
+
+        let mut other = CodeDataLog::new(5);
+        other.usage[2] = Usage::OPCODE;
+        other.usage[4] = Usage::OPERAND;
+        log.merge(&other);
+        assert_eq!(vec![0, 1, 13, 0, 2], log.to_bytes());
+        assert_eq!(2, log.count(Usage::OPCODE));
+        assert_eq!(3, log.used());
+    }
+
+    #[test]
+    fn test_json_ranges() {
+        let mut log = CodeDataLog::new(0x8000);
+        log.usage[0x100] = Usage::OPCODE;
+        log.usage[0x101] = Usage::OPERAND;
+        log.usage[0x102] = Usage::OPERAND;
+        log.usage[0x3FFF] = Usage::DATA;
+        log.usage[0x4000] = Usage::DATA;
+
+        let json: serde_json::Value = serde_json::from_str(&log.to_json()).unwrap();
+        assert_eq!(0x8000, json["rom_size"]);
+
+        let ranges = json["ranges"].as_array().unwrap();
+        assert_eq!(4, ranges.len());
+        assert_eq!(
+            serde_json::json!({
+                "offset": 0x101,
+                "length": 2,
+                "bank": 0,
+                "address": 0x101,
+                "usage": ["operand"]
+            }),
+            ranges[1]
+        );
+        // Ranges are split between banks
+        assert_eq!(
+            serde_json::json!({
+                "offset": 0x4000,
+                "length": 1,
+                "bank": 1,
+                "address": 0x4000,
+                "usage": ["data"]
+            }),
+            ranges[3]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use crate::bus::AccessKind;
+use crate::error::EmulationError;
+use crate::headless::{self, Stop};
+use crate::screen;
+use crate::serial;
+use crate::system::System;
+use crate::watchpoint::Watchpoint;
+use std::fmt;
+
+/// Where Blargg's tests keep their result, once the signature after it is written
+const BLARGG_STATUS_ADDRESS: u16 = 0xA000;
+const BLARGG_SIGNATURE_ADDRESS: u16 = 0xA001;
+const BLARGG_SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const BLARGG_TEXT_ADDRESS: u16 = 0xA004;
+/// The status while the test is still running
+const BLARGG_RUNNING: u8 = 0x80;
+
+/// `LD B,B`, which Mooneye's tests run once they are done, as a breakpoint for emulators
+const LD_B_B: u8 = 0x40;
+/// B, C, D, E, H and L when a Mooneye test passes
+const MOONEYE_PASSED: [u8; 6] = [3, 5, 8, 13, 21, 34];
+const MOONEYE_FAILED: [u8; 6] = [0x42; 6];
+
+/// How a test ROM said whether it passed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Protocol {
+    /// Blargg's tests print "Passed" or "Failed" over the serial port
+    BlarggSerial,
+    /// Blargg's tests also leave a status code and text in cartridge RAM, 0 meaning passed
+    BlarggMemory,
+    /// Mooneye's tests run `LD B,B` with Fibonacci numbers in B-L to pass, or $42s to fail
+    Mooneye,
+    /// The acid2 tests are judged by the screen they leave, against a reference image
+    Screenshot,
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Protocol::BlarggSerial => "blargg serial",
+            Protocol::BlarggMemory => "blargg memory",
+            Protocol::Mooneye => "mooneye",
+            Protocol::Screenshot => "screenshot",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a test ROM came to
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Verdict {
+    Passed(Protocol),
+    /// With whatever the ROM gave as the reason
+    Failed(Protocol, String),
+    /// The CPU hung on an illegal opcode, which no test does on purpose
+    LockedUp {
+        pc: u16,
+    },
+    /// The frames ran out before the ROM said anything
+    TimedOut,
+}
+
+impl Verdict {
+    pub fn is_pass(&self) -> bool {
+        matches!(self, Verdict::Passed(_))
+    }
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Verdict::Passed(protocol) => write!(f, "passed ({})", protocol),
+            Verdict::Failed(protocol, reason) => write!(f, "failed ({}): {}", protocol, reason),
+            Verdict::LockedUp { pc } => write!(f, "locked up at PC {:04X}", pc),
+            Verdict::TimedOut => write!(f, "timed out"),
+        }
+    }
+}
+
+/// Run a test ROM for up to `frames` frames, until it reports a result in any of the ways the
+/// common suites do. A reference screen means the ROM is judged by what it leaves on screen,
+/// once it runs `LD B,B` or the frames run out.
+pub fn run_test(
+    system: &mut System,
+    frames: u64,
+    reference: Option<&[u8]>,
+) -> Result<Verdict, EmulationError> {
+    let watchpoints = [
+        Watchpoint {
+            value: Some(LD_B_B),
+            ..Watchpoint::new(AccessKind::Execute, 0x0000..=0xFFFF)
+        },
+        Watchpoint::new(AccessKind::Write, serial::SC_ADDRESS..=serial::SC_ADDRESS),
+    ];
+
+    let stop = headless::run_watching(system, frames, &watchpoints, |system| {
+        let ran_ld_b_b = system
+            .watchpoint_hit()
+            .is_some_and(|hit| hit.kind == AccessKind::Execute);
+        match reference {
+            Some(reference) => ran_ld_b_b.then(|| compare_screen(system, reference)),
+            None => ran_ld_b_b
+                .then(|| mooneye_verdict(system))
+                .flatten()
+                .or_else(|| blargg_serial_verdict(system))
+                .or_else(|| blargg_memory_verdict(system)),
+        }
+    })?;
+
+    Ok(match stop {
+        Stop::Stopped(verdict) => verdict,
+        Stop::LockedUp { pc } => Verdict::LockedUp { pc },
+        Stop::RanAllFrames => match reference {
+            Some(reference) => compare_screen(system, reference),
+            None => Verdict::TimedOut,
+        },
+    })
+}
+
+fn blargg_serial_verdict(system: &System) -> Option<Verdict> {
+    let output = String::from_utf8_lossy(system.bus().serial.output());
+
+    if output.contains("Passed") {
+        Some(Verdict::Passed(Protocol::BlarggSerial))
+    } else if output.contains("Failed") {
+        Some(Verdict::Failed(
+            Protocol::BlarggSerial,
+            output.trim().to_string(),
+        ))
+    } else {
+        None
+    }
+}
+
+fn blargg_memory_verdict(system: &System) -> Option<Verdict> {
+    let bus = system.bus();
+    let peek_from = |address: u16| (address..=0xBFFF).map(|address| bus.peek(address));
+
+    if !peek_from(BLARGG_SIGNATURE_ADDRESS)
+        .take(3)
+        .eq(BLARGG_SIGNATURE)
+    {
+        return None;
+    }
+
+    match bus.peek(BLARGG_STATUS_ADDRESS) {
+        BLARGG_RUNNING => None,
+        0 => Some(Verdict::Passed(Protocol::BlarggMemory)),
+        status => {
+            let text: Vec<u8> = peek_from(BLARGG_TEXT_ADDRESS)
+                .take_while(|byte| *byte != 0)
+                .collect();
+            Some(Verdict::Failed(
+                Protocol::BlarggMemory,
+                format!(
+                    "status {:02X}: {}",
+                    status,
+                    String::from_utf8_lossy(&text).trim()
+                ),
+            ))
+        }
+    }
+}
+
+/// Mooneye's tests are the only ones that run `LD B,B` to finish, but other ROMs may run it
+/// for other reasons, so only the two signatures give a verdict
+fn mooneye_verdict(system: &System) -> Option<Verdict> {
+    let cpu = system.cpu();
+    let registers = [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l];
+
+    if registers == MOONEYE_PASSED {
+        Some(Verdict::Passed(Protocol::Mooneye))
+    } else if registers == MOONEYE_FAILED {
+        Some(Verdict::Failed(
+            Protocol::Mooneye,
+            "registers B-L are all $42".to_string(),
+        ))
+    } else {
+        None
+    }
+}
+
+fn compare_screen(system: &System, reference: &[u8]) -> Verdict {
+    let screen = screen::background(&system.bus().vram);
+    let differences = screen
+        .iter()
+        .zip(reference)
+        .filter(|(shade, expected)| shade != expected)
+        .count();
+
+    match differences {
+        0 => Verdict::Passed(Protocol::Screenshot),
+        _ => Verdict::Failed(
+            Protocol::Screenshot,
+            format!("{} pixels differ from the reference", differences),
+        ),
+    }
+}
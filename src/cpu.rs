@@ -1,7 +1,19 @@
-use crate::bus::Bus;
+use crate::error::EmulationError;
 use crate::memory::MemoryMapped;
+use crate::opcodes::OPCODES;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
 
 const DEFAULT_PC: u16 = 0x100; // TODO support running a boot ROM
+const DEFAULT_SP: u16 = 0xFFFE;
+
+/// The save state version SP and the interrupt master enable were first saved in
+const SP_SAVED_SINCE_VERSION: u16 = 4;
+
+/// Machine cycles taken to enter an interrupt handler: two waiting, two pushing PC, one jumping
+const INTERRUPT_CYCLES: u8 = 5;
+
+const ZERO_FLAG: u8 = 0x80;
+const CARRY_FLAG: u8 = 0x10;
 
 pub struct Cpu {
     pub a: u8,
@@ -14,79 +26,127 @@ pub struct Cpu {
     pub l: u8,
 
     pub pc: u16,
-    // TODO sp
+    pub sp: u16,
+
+    /// IME, whether interrupts are taken at all
+    pub ime: bool,
+    /// EI only sets IME once the instruction after it has run
+    pub ime_scheduled: bool,
 }
 
 impl Cpu {
+    /// The registers as the DMG boot ROM leaves them, which reference traces like Gameboy
+    /// Doctor's start from
+    pub fn after_boot() -> Self {
+        Cpu {
+            a: 0x01,
+            f: 0xB0,
+            b: 0x00,
+            c: 0x13,
+            d: 0x00,
+            e: 0xD8,
+            h: 0x01,
+            l: 0x4D,
+
+            pc: DEFAULT_PC,
+            sp: DEFAULT_SP,
+
+            ime: false,
+            ime_scheduled: false,
+        }
+    }
+
     // TODO need a deep dive on timing
     /// Performs one read->decode->execute cycle on the CPU
-    /// Returns the number of machine cycles the instruction takes to execute
-    pub fn read_decode_execute(&mut self, bus: &mut Bus) -> u8 {
+    /// Returns the number of machine cycles the instruction takes to execute, from the opcode
+    /// table, or an error for opcodes that can't be executed
+    /// Any memory will do, so tests can run instructions against a flat 64 KiB rather than a `Bus`
+    // ld!(a, a) and friends are legitimate no-op instructions
+    #[allow(clippy::self_assignment)]
+    pub fn read_decode_execute(
+        &mut self,
+        bus: &mut impl MemoryMapped,
+    ) -> Result<u8, EmulationError> {
+        let pc = self.pc;
         let instruction = self.read_byte_advance_pc(bus);
+        let opcode = &OPCODES[instruction as usize];
+
+        if self.ime_scheduled {
+            self.ime = true;
+            self.ime_scheduled = false;
+        }
+
+        if opcode.is_illegal() {
+            return Err(EmulationError::IllegalOpcode {
+                pc,
+                opcode: instruction,
+            });
+        }
 
         // Macro for implementing loads that are repetitive across multiple registers
         macro_rules! ld {
             ($a: ident immediate value) => {{
                 self.$a = self.read_byte_advance_pc(bus);
-                2
             }};
             ($a: ident, [hl]) => {{
                 self.$a = bus.read_byte(self.hl());
-                2
             }};
             ([hl], $a: ident) => {{
                 bus.write_byte(self.hl(), self.$a);
-                2
             }};
             ($a: ident, $b: ident) => {{
                 self.$a = self.$b;
-                1
             }};
         }
 
+        // Whether a conditional instruction's condition held, which makes it take longer
+        let mut branched = false;
+
+        // The condition in bits 3 and 4 of conditional calls and returns: NZ, Z, NC, or C
+        let condition = match (instruction >> 3) & 0x3 {
+            0 => self.f & ZERO_FLAG == 0,
+            1 => self.f & ZERO_FLAG != 0,
+            2 => self.f & CARRY_FLAG == 0,
+            _ => self.f & CARRY_FLAG != 0,
+        };
+
         match instruction {
-            0x00 => 1,
+            0x00 => {}
             0x02 => {
                 bus.write_byte(self.bc(), self.a);
-                2
             }
             0x06 => ld!(b immediate value),
             0x0A => {
                 self.a = bus.read_byte(self.bc());
-                2
             }
             0x0E => ld!(c immediate value),
             0x12 => {
                 bus.write_byte(self.de(), self.a);
-                2
             }
             0x16 => ld!(d immediate value),
             0x1A => {
                 self.a = bus.read_byte(self.de());
-                2
             }
             0x1E => ld!(e immediate value),
             0x22 => {
                 bus.write_byte(self.get_and_increment_hl(), self.a);
-                2
             }
             0x26 => ld!(h immediate value),
             0x2A => {
                 self.a = bus.read_byte(self.get_and_increment_hl());
-                2
             }
             0x2E => ld!(l immediate value),
+            0x31 => {
+                self.sp = self.read_word_advance_pc(bus);
+            }
             0x32 => {
                 bus.write_byte(self.get_and_decrement_hl(), self.a);
-                2
             }
             0x36 => {
                 bus.write_byte(self.hl(), self.read_byte_advance_pc(bus));
-                3
             }
             0x3A => {
                 self.a = bus.read_byte(self.get_and_decrement_hl());
-                2
             }
             0x3E => ld!(a immediate value),
             0x40 => ld!(b, b),
@@ -152,52 +212,146 @@ impl Cpu {
             0x7D => ld!(a, l),
             0x7E => ld!(a, [hl]),
             0x7F => ld!(a, a),
+            0xC0 | 0xC8 | 0xD0 | 0xD8 => {
+                if condition {
+                    self.pc = self.pop(bus);
+                    branched = true;
+                }
+            }
+            0xC1 => {
+                [self.c, self.b] = self.pop(bus).to_le_bytes();
+            }
             0xC3 => {
                 self.pc = self.read_word_advance_pc(bus);
-                4
+            }
+            0xC4 | 0xCC | 0xD4 | 0xDC => {
+                let address = self.read_word_advance_pc(bus);
+                if condition {
+                    self.call(bus, address);
+                    branched = true;
+                }
+            }
+            0xC5 => {
+                self.push(bus, self.bc());
+            }
+            0xC9 => {
+                self.pc = self.pop(bus);
+            }
+            0xCD => {
+                let address = self.read_word_advance_pc(bus);
+                self.call(bus, address);
+            }
+            // RST's target is bits 3-5 of the opcode, times 8
+            0xC7 | 0xCF | 0xD7 | 0xDF | 0xE7 | 0xEF | 0xF7 | 0xFF => {
+                self.call(bus, (instruction & 0x38) as u16);
+            }
+            0xD1 => {
+                [self.e, self.d] = self.pop(bus).to_le_bytes();
+            }
+            0xD5 => {
+                self.push(bus, self.de());
+            }
+            0xD9 => {
+                self.pc = self.pop(bus);
+                self.ime = true;
             }
             0xE0 => {
                 bus.write_byte(
                     Cpu::u8_to_high_ram_address(self.read_byte_advance_pc(bus)),
                     self.a,
                 );
-                3
+            }
+            0xE1 => {
+                [self.l, self.h] = self.pop(bus).to_le_bytes();
             }
             0xE2 => {
                 bus.write_byte(self.c_as_high_ram_address(), self.a);
-                2
+            }
+            0xE5 => {
+                self.push(bus, self.hl());
             }
             0xEA => {
                 bus.write_byte(self.read_word_advance_pc(bus), self.a);
-                4
             }
             0xF0 => {
                 self.a = bus.read_byte(Cpu::u8_to_high_ram_address(self.read_byte_advance_pc(bus)));
-                3
+            }
+            0xF1 => {
+                let [f, a] = self.pop(bus).to_le_bytes();
+                self.a = a;
+                // The low nibble of F has no flags, and always reads as 0
+                self.f = f & 0xF0;
             }
             0xF2 => {
                 self.a = bus.read_byte(self.c_as_high_ram_address());
-                2
+            }
+            0xF3 => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
+            0xF5 => {
+                self.push(bus, Cpu::compound_register(self.a, self.f));
             }
             0xFA => {
                 self.a = bus.read_byte(self.read_word_advance_pc(bus));
-                4
             }
-            _ => unimplemented!(
-                "unimplemented opcode {:#04X} at address {:#04X}",
-                instruction,
-                self.pc - 1
-            ),
+            0xFB => {
+                self.ime_scheduled = true;
+            }
+            _ => {
+                return Err(EmulationError::UnimplementedOpcode {
+                    pc,
+                    opcode: instruction,
+                })
+            }
         }
+
+        if branched {
+            Ok(opcode.branch_cycles)
+        } else {
+            Ok(opcode.cycles)
+        }
+    }
+
+    /// Enter the interrupt handler at `vector`, returning the machine cycles it took
+    /// Interrupts are disabled until the handler enables them again, usually with RETI
+    pub fn interrupt(&mut self, bus: &mut impl MemoryMapped, vector: u16) -> u8 {
+        self.ime = false;
+        self.call(bus, vector);
+
+        INTERRUPT_CYCLES
+    }
+
+    fn call(&mut self, bus: &mut impl MemoryMapped, address: u16) {
+        self.push(bus, self.pc);
+        self.pc = address;
+    }
+
+    /// The stack grows down, with the high byte pushed first
+    fn push(&mut self, bus: &mut impl MemoryMapped, value: u16) {
+        let [low, high] = value.to_le_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write_byte(self.sp, high);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write_byte(self.sp, low);
+    }
+
+    fn pop(&mut self, bus: &impl MemoryMapped) -> u16 {
+        let low = bus.read_byte(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let high = bus.read_byte(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+
+        u16::from_le_bytes([low, high])
     }
 
-    fn read_byte_advance_pc(&mut self, bus: &Bus) -> u8 {
+    fn read_byte_advance_pc(&mut self, bus: &impl MemoryMapped) -> u8 {
         let byte = bus.read_byte(self.pc);
-        self.pc += 1;
+        self.pc = self.pc.wrapping_add(1);
         byte
     }
 
-    fn read_word_advance_pc(&mut self, bus: &Bus) -> u16 {
+    fn read_word_advance_pc(&mut self, bus: &impl MemoryMapped) -> u16 {
         let least_significant_byte = self.read_byte_advance_pc(bus);
         let most_significant_byte = self.read_byte_advance_pc(bus);
 
@@ -216,6 +370,7 @@ impl Cpu {
         Cpu::compound_register(self.h, self.l)
     }
 
+    #[cfg(test)]
     fn set_hl(&mut self, hl: u16) {
         self.h = (hl >> 8) as u8;
         self.l = hl as u8;
@@ -272,13 +427,61 @@ impl Default for Cpu {
             l: 0,
 
             pc: DEFAULT_PC,
+            sp: DEFAULT_SP,
+
+            ime: false,
+            ime_scheduled: false,
         }
     }
 }
 
+impl SaveState for Cpu {
+    fn save_state(&self, writer: &mut StateWriter) {
+        for register in [
+            self.a, self.f, self.b, self.c, self.d, self.e, self.h, self.l,
+        ] {
+            writer.write_u8(register);
+        }
+        writer.write_u16(self.pc);
+        writer.write_u16(self.sp);
+        writer.write_bool(self.ime);
+        writer.write_bool(self.ime_scheduled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        for register in [
+            &mut self.a,
+            &mut self.f,
+            &mut self.b,
+            &mut self.c,
+            &mut self.d,
+            &mut self.e,
+            &mut self.h,
+            &mut self.l,
+        ] {
+            *register = reader.read_u8()?;
+        }
+        self.pc = reader.read_u16()?;
+
+        // Older states come from before the stack and interrupts, when SP was never moved
+        if reader.version() >= SP_SAVED_SINCE_VERSION {
+            self.sp = reader.read_u16()?;
+            self.ime = reader.read_bool()?;
+            self.ime_scheduled = reader.read_bool()?;
+        } else {
+            self.sp = DEFAULT_SP;
+            self.ime = false;
+            self.ime_scheduled = false;
+        }
+
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use proptest::prelude::*;
 
     #[test]
     fn test_u8_as_high_ram_address() {
@@ -329,4 +532,17 @@ mod tests {
         assert_eq!(0x0000, cpu.get_and_increment_hl());
         assert_eq!(0x0001, cpu.get_and_increment_hl());
     }
+
+    proptest! {
+        #[test]
+        fn test_hl_wraps_around(hl in any::<u16>()) {
+            let mut cpu = Cpu::default();
+            cpu.set_hl(hl);
+
+            prop_assert_eq!(hl, cpu.get_and_increment_hl());
+            prop_assert_eq!(hl.wrapping_add(1), cpu.get_and_decrement_hl());
+            prop_assert_eq!(hl, cpu.get_and_decrement_hl());
+            prop_assert_eq!(hl.wrapping_sub(1), cpu.hl());
+        }
+    }
 }
@@ -0,0 +1,658 @@
+use crate::bus::{Access, AccessKind};
+use crate::disassembler::Instruction;
+use crate::error::EmulationError;
+use crate::memory::MemoryMapped;
+use crate::symbols::Symbols;
+use crate::system::{Gas, System};
+use crate::watchpoint::Watchpoint;
+use std::collections::BTreeSet;
+use std::fmt;
+
+const HELP: &str = "\
+Addresses and values are hex, with an optional $ or 0x prefix. Counts are decimal.
+Addresses can also be labels from a symbol file, which win over bare hex like `Add`.
+  s, step [COUNT]           Run COUNT instructions, 1 by default
+  c, continue               Run until a breakpoint, a fault, or the CPU locks up
+  b, break [[BANK:]ADDR]    Break before running ADDR, in any bank unless BANK is given
+                            With no address, list the breakpoints
+  d, delete [BANK:]ADDR     Remove a breakpoint
+  watch [r|w|x [BANK:]ADDR[-END] [VALUE]]
+                            Pause after an instruction reads, writes, or executes in a range,
+                            optionally only for one value. With no arguments, list them
+  unwatch r|w|x [BANK:]ADDR[-END] [VALUE]
+                            Remove a watchpoint
+  r, registers              Show the registers
+  x, examine ADDR [LEN]     Dump LEN bytes of memory, 16 by default
+  set REG VALUE             Set a register: a f b c d e h l, bc de hl, or pc
+  w, write ADDR BYTE...     Write bytes to memory, through the bus as the CPU would
+  h, help                   Show this help
+  q, quit                   Exit the debugger";
+
+/// Stop before running the instruction at an address
+/// Without a bank it matches whichever bank is mapped, with one it only matches that bank
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Breakpoint {
+    pub bank: Option<u8>,
+    pub address: u16,
+}
+
+impl Breakpoint {
+    /// Parses `ADDR` or `BANK:ADDR`, the way RGBDS writes symbols
+    pub fn parse(text: &str) -> Result<Breakpoint, String> {
+        Breakpoint::parse_with_symbols(text, &Symbols::default())
+    }
+
+    /// Like `parse`, but also takes a label, which only matches in the label's bank if it's ROM
+    pub fn parse_with_symbols(text: &str, symbols: &Symbols) -> Result<Breakpoint, String> {
+        if let Some((bank, address)) = symbols.get(text) {
+            return Ok(Breakpoint {
+                bank: is_rom(address).then_some(bank),
+                address,
+            });
+        }
+
+        match text.split_once(':') {
+            Some((bank, address)) => Ok(Breakpoint {
+                bank: Some(parse_hex(bank)?),
+                address: parse_hex(address)?,
+            }),
+            None => Ok(Breakpoint {
+                bank: None,
+                address: parse_hex(text)?,
+            }),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.bank {
+            Some(bank) => write!(f, "{:02X}:{:04X}", bank, self.address),
+            None => write!(f, "{:04X}", self.address),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Register {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Bc,
+    De,
+    Hl,
+    Pc,
+}
+
+impl Register {
+    fn parse(text: &str) -> Result<Register, String> {
+        match text.to_ascii_lowercase().as_str() {
+            "a" => Ok(Register::A),
+            "f" => Ok(Register::F),
+            "b" => Ok(Register::B),
+            "c" => Ok(Register::C),
+            "d" => Ok(Register::D),
+            "e" => Ok(Register::E),
+            "h" => Ok(Register::H),
+            "l" => Ok(Register::L),
+            "bc" => Ok(Register::Bc),
+            "de" => Ok(Register::De),
+            "hl" => Ok(Register::Hl),
+            "pc" => Ok(Register::Pc),
+            _ => Err(format!("{:?} isn't a register", text)),
+        }
+    }
+
+    fn is_pair(self) -> bool {
+        matches!(
+            self,
+            Register::Bc | Register::De | Register::Hl | Register::Pc
+        )
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Step(usize),
+    Continue,
+    Break(Breakpoint),
+    ListBreakpoints,
+    Delete(Breakpoint),
+    Watch(Watchpoint),
+    ListWatchpoints,
+    Unwatch(Watchpoint),
+    Registers,
+    Examine { address: u16, len: usize },
+    Set { register: Register, value: u16 },
+    Write { address: u16, bytes: Vec<u8> },
+    Help,
+    Quit,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        Command::parse_with_symbols(line, &Symbols::default())
+    }
+
+    /// Like `parse`, but addresses can also be labels
+    pub fn parse_with_symbols(line: &str, symbols: &Symbols) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let name = words.next().ok_or("no command given")?;
+        let args: Vec<&str> = words.collect();
+
+        let command = match (name, args.as_slice()) {
+            ("s" | "step", []) => Command::Step(1),
+            ("s" | "step", [count]) => Command::Step(
+                count
+                    .parse()
+                    .map_err(|_| format!("{:?} isn't a count", count))?,
+            ),
+            ("c" | "continue", []) => Command::Continue,
+            ("b" | "break", []) => Command::ListBreakpoints,
+            ("b" | "break", [breakpoint]) => {
+                Command::Break(Breakpoint::parse_with_symbols(breakpoint, symbols)?)
+            }
+            ("d" | "delete", [breakpoint]) => {
+                Command::Delete(Breakpoint::parse_with_symbols(breakpoint, symbols)?)
+            }
+            ("watch", []) => Command::ListWatchpoints,
+            ("watch", watchpoint) => Command::Watch(parse_watchpoint(watchpoint, symbols)?),
+            ("unwatch", watchpoint) => Command::Unwatch(parse_watchpoint(watchpoint, symbols)?),
+            ("r" | "registers", []) => Command::Registers,
+            ("x" | "examine", [address]) => Command::Examine {
+                address: parse_address(address, symbols)?,
+                len: 16,
+            },
+            ("x" | "examine", [address, len]) => Command::Examine {
+                address: parse_address(address, symbols)?,
+                len: len
+                    .parse()
+                    .map_err(|_| format!("{:?} isn't a length", len))?,
+            },
+            ("set", [register, value]) => {
+                let register = Register::parse(register)?;
+                let value = if register.is_pair() {
+                    parse_hex(value)?
+                } else {
+                    parse_hex::<u8>(value)? as u16
+                };
+
+                Command::Set { register, value }
+            }
+            ("w" | "write", [address, bytes @ ..]) if !bytes.is_empty() => Command::Write {
+                address: parse_address(address, symbols)?,
+                bytes: bytes
+                    .iter()
+                    .map(|byte| parse_hex(byte))
+                    .collect::<Result<_, _>>()?,
+            },
+            ("h" | "help", []) => Command::Help,
+            ("q" | "quit", []) => Command::Quit,
+            _ => return Err(format!("can't understand {:?}, try help", line.trim())),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Why running stopped before it was asked to
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop {
+    Breakpoint(Breakpoint),
+    Watchpoint(Access),
+    LockedUp,
+    Fault(EmulationError),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::Breakpoint(breakpoint) => write!(f, "hit breakpoint {}", breakpoint),
+            Stop::Watchpoint(access) => write!(f, "hit watchpoint, {}", access),
+            Stop::LockedUp => write!(f, "the CPU has locked up"),
+            Stop::Fault(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+/// Runs a system under the control of debugger commands
+pub struct Debugger {
+    system: System,
+    breakpoints: BTreeSet<Breakpoint>,
+    symbols: Symbols,
+}
+
+impl Debugger {
+    pub fn new(system: System) -> Debugger {
+        Debugger {
+            system,
+            breakpoints: BTreeSet::new(),
+            symbols: Symbols::default(),
+        }
+    }
+
+    /// Show these labels wherever addresses appear
+    pub fn set_symbols(&mut self, symbols: Symbols) {
+        self.symbols = symbols;
+    }
+
+    /// For parsing commands that refer to labels
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    pub fn system(&self) -> &System {
+        &self.system
+    }
+
+    pub fn system_mut(&mut self) -> &mut System {
+        &mut self.system
+    }
+
+    /// Returns false if there was already a breakpoint there
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> bool {
+        self.breakpoints.insert(breakpoint)
+    }
+
+    /// Returns false if there was no such breakpoint
+    pub fn remove_breakpoint(&mut self, breakpoint: &Breakpoint) -> bool {
+        self.breakpoints.remove(breakpoint)
+    }
+
+    /// Run a command, returning what to show the user
+    pub fn execute(&mut self, command: &Command) -> String {
+        match command {
+            Command::Step(count) => match self.step(*count) {
+                Some(stop) => format!("Stopped, {}\n{}", stop, self.registers()),
+                None => self.registers(),
+            },
+            Command::Continue => format!("Stopped, {}\n{}", self.run(), self.registers()),
+            Command::Break(breakpoint) => {
+                self.add_breakpoint(*breakpoint);
+                format!("Breakpoint set at {}", breakpoint)
+            }
+            Command::ListBreakpoints if self.breakpoints.is_empty() => "No breakpoints".to_string(),
+            Command::ListBreakpoints => self
+                .breakpoints
+                .iter()
+                .map(|breakpoint| {
+                    let label = match breakpoint.bank {
+                        Some(bank) => self.symbols.label_in_bank(bank, breakpoint.address),
+                        None => self
+                            .symbols
+                            .label(&self.system.bus().cartridge, breakpoint.address),
+                    };
+
+                    match label {
+                        Some(label) => format!("{} <{}>", breakpoint, label),
+                        None => breakpoint.to_string(),
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Command::Delete(breakpoint) => {
+                if self.remove_breakpoint(breakpoint) {
+                    format!("Breakpoint at {} deleted", breakpoint)
+                } else {
+                    format!("No breakpoint at {}", breakpoint)
+                }
+            }
+            Command::Watch(watchpoint) => {
+                let message = format!("Watchpoint set on {}", watchpoint);
+                self.system.bus_mut().add_watchpoint(watchpoint.clone());
+                message
+            }
+            Command::ListWatchpoints if self.system.bus().watchpoints().is_empty() => {
+                "No watchpoints".to_string()
+            }
+            Command::ListWatchpoints => self
+                .system
+                .bus()
+                .watchpoints()
+                .iter()
+                .map(Watchpoint::to_string)
+                .collect::<Vec<_>>()
+                .join("\n"),
+            Command::Unwatch(watchpoint) => {
+                if self.system.bus_mut().remove_watchpoint(watchpoint) {
+                    format!("Watchpoint on {} deleted", watchpoint)
+                } else {
+                    format!("No watchpoint on {}", watchpoint)
+                }
+            }
+            Command::Registers => self.registers(),
+            Command::Examine { address, len } => self.examine(*address, *len),
+            Command::Set { register, value } => {
+                self.set_register(*register, *value);
+                self.registers()
+            }
+            Command::Write { address, bytes } => {
+                self.write(*address, bytes);
+                self.examine(*address, bytes.len())
+            }
+            Command::Help => HELP.to_string(),
+            Command::Quit => String::new(),
+        }
+    }
+
+    /// Write bytes through the bus as the CPU would, wrapping around at the top of memory
+    pub fn write(&mut self, address: u16, bytes: &[u8]) {
+        let bus = self.system.bus_mut();
+        for (offset, byte) in bytes.iter().enumerate() {
+            bus.write_byte(address.wrapping_add(offset as u16), *byte);
+        }
+        // Edits aren't the ROM's doing, so shouldn't be reported as faults
+        bus.take_unmapped_access();
+    }
+
+    /// Run up to `count` instructions, stopping early at breakpoints after the first
+    pub fn step(&mut self, count: usize) -> Option<Stop> {
+        for step in 0..count {
+            if step > 0 {
+                if let Some(breakpoint) = self.breakpoint_hit() {
+                    return Some(Stop::Breakpoint(breakpoint));
+                }
+            }
+
+            if let Some(stop) = self.step_one() {
+                return Some(stop);
+            }
+        }
+
+        None
+    }
+
+    /// Run until something stops it
+    /// The first instruction always runs, so continuing from a breakpoint moves past it
+    pub fn run(&mut self) -> Stop {
+        loop {
+            if let Some(stop) = self.run_for(usize::MAX) {
+                return stop;
+            }
+        }
+    }
+
+    /// Like `run`, but give up after `count` instructions, for callers that need to check in
+    /// Unlike `step`, a breakpoint after the last instruction still stops it
+    pub fn run_for(&mut self, count: usize) -> Option<Stop> {
+        for _ in 0..count {
+            if let Some(stop) = self.step_one() {
+                return Some(stop);
+            }
+
+            if let Some(breakpoint) = self.breakpoint_hit() {
+                return Some(Stop::Breakpoint(breakpoint));
+            }
+        }
+
+        None
+    }
+
+    fn step_one(&mut self) -> Option<Stop> {
+        if self.system.is_locked_up() {
+            return Some(Stop::LockedUp);
+        }
+
+        match self.system.run_with_gas(Gas::LIMITED(1)) {
+            Err(error) => Some(Stop::Fault(error)),
+            Ok(()) => match self.system.watchpoint_hit() {
+                Some(access) => Some(Stop::Watchpoint(access)),
+                None if self.system.is_locked_up() => Some(Stop::LockedUp),
+                None => None,
+            },
+        }
+    }
+
+    /// The breakpoint at the instruction about to run, if any
+    pub fn breakpoint_hit(&self) -> Option<Breakpoint> {
+        let pc = self.system.cpu().pc;
+        let bank = self.system.bus().cartridge.rom_bank(pc);
+
+        self.breakpoints
+            .iter()
+            .find(|breakpoint| {
+                breakpoint.address == pc && breakpoint.bank.is_none_or(|b| Some(b) == bank)
+            })
+            .copied()
+    }
+
+    fn registers(&self) -> String {
+        let cpu = self.system.cpu();
+        let cartridge = &self.system.bus().cartridge;
+        let mut location = match cartridge.rom_bank(cpu.pc) {
+            Some(bank) => format!("{:02X}:{:04X}", bank, cpu.pc),
+            None => format!("{:04X}", cpu.pc),
+        };
+        if let Some(symbol) = self.symbols.describe(cartridge, cpu.pc) {
+            location = format!("{} <{}>", location, symbol);
+        }
+
+        format!(
+            "A={:02X} F={:02X} B={:02X} C={:02X} D={:02X} E={:02X} H={:02X} L={:02X} PC={} cycles={}  {}",
+            cpu.a,
+            cpu.f,
+            cpu.b,
+            cpu.c,
+            cpu.d,
+            cpu.e,
+            cpu.h,
+            cpu.l,
+            location,
+            self.system.cycles(),
+            Instruction::decode_with(cpu.pc, |address| self.system.bus().peek(address))
+                .to_string_with_labels(|address| self
+                    .symbols
+                    .label(cartridge, address)
+                    .map(str::to_string))
+        )
+    }
+
+    /// A hex dump, 16 bytes to a line
+    fn examine(&self, address: u16, len: usize) -> String {
+        let bus = self.system.bus();
+
+        (0..len)
+            .step_by(16)
+            .map(|line_offset| {
+                let line_address = address.wrapping_add(line_offset as u16);
+                let bytes: Vec<String> = (line_offset..len.min(line_offset + 16))
+                    .map(|offset| format!("{:02X}", bus.peek(address.wrapping_add(offset as u16))))
+                    .collect();
+
+                format!("{:04X}: {}", line_address, bytes.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn set_register(&mut self, register: Register, value: u16) {
+        let cpu = self.system.cpu_mut();
+        let [high, low] = value.to_be_bytes();
+
+        match register {
+            Register::A => cpu.a = low,
+            Register::F => cpu.f = low,
+            Register::B => cpu.b = low,
+            Register::C => cpu.c = low,
+            Register::D => cpu.d = low,
+            Register::E => cpu.e = low,
+            Register::H => cpu.h = low,
+            Register::L => cpu.l = low,
+            Register::Bc => (cpu.b, cpu.c) = (high, low),
+            Register::De => (cpu.d, cpu.e) = (high, low),
+            Register::Hl => (cpu.h, cpu.l) = (high, low),
+            Register::Pc => cpu.pc = value,
+        }
+    }
+}
+
+/// Parses `r|w|x [BANK:]ADDR[-END] [VALUE]`, where either address can be a label
+fn parse_watchpoint(args: &[&str], symbols: &Symbols) -> Result<Watchpoint, String> {
+    let (kind, addresses, value) = match args {
+        [kind, addresses] => (kind, addresses, None),
+        [kind, addresses, value] => (kind, addresses, Some(parse_hex(value)?)),
+        _ => return Err("expected r|w|x [BANK:]ADDR[-END] [VALUE]".to_string()),
+    };
+
+    let kind = match *kind {
+        "r" | "read" => AccessKind::Read,
+        "w" | "write" => AccessKind::Write,
+        "x" | "execute" => AccessKind::Execute,
+        _ => return Err(format!("{:?} isn't r, w, or x", kind)),
+    };
+
+    let (bank, addresses) = match addresses.split_once(':') {
+        Some((bank, addresses)) => (Some(parse_hex(bank)?), addresses),
+        None => (None, *addresses),
+    };
+    let (start_text, end_text) = addresses.split_once('-').unwrap_or((addresses, addresses));
+    let start = parse_address(start_text, symbols)?;
+    let end = parse_address(end_text, symbols)?;
+    if start > end {
+        return Err(format!("{:04X}-{:04X} is backwards", start, end));
+    }
+
+    // A label in ROM only means its own bank
+    let bank = bank.or_else(|| {
+        symbols
+            .get(start_text)
+            .filter(|(_, address)| is_rom(*address))
+            .map(|(bank, _)| bank)
+    });
+
+    Ok(Watchpoint {
+        value,
+        bank,
+        ..Watchpoint::new(kind, start..=end)
+    })
+}
+
+/// A label, or else hex
+fn parse_address(text: &str, symbols: &Symbols) -> Result<u16, String> {
+    match symbols.get(text) {
+        Some((_, address)) => Ok(address),
+        None => parse_hex(text),
+    }
+}
+
+fn is_rom(address: u16) -> bool {
+    address <= 0x7FFF
+}
+
+/// Hex, with or without a $ or 0x prefix
+fn parse_hex<T: TryFrom<u32>>(text: &str) -> Result<T, String> {
+    let digits = text
+        .strip_prefix("0x")
+        .or_else(|| text.strip_prefix('$'))
+        .unwrap_or(text);
+
+    u32::from_str_radix(digits, 16)
+        .ok()
+        .and_then(|value| T::try_from(value).ok())
+        .ok_or_else(|| format!("{:?} isn't a valid hex value", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_breakpoint() {
+        assert_eq!(
+            Ok(Breakpoint {
+                bank: None,
+                address: 0x0150
+            }),
+            Breakpoint::parse("$150")
+        );
+        assert_eq!(
+            Ok(Breakpoint {
+                bank: Some(1),
+                address: 0x4000
+            }),
+            Breakpoint::parse("01:4000")
+        );
+        assert!(Breakpoint::parse("1:10000").is_err());
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!(Ok(Command::Step(1)), Command::parse("s"));
+        assert_eq!(Ok(Command::Step(10)), Command::parse("step 10"));
+        assert_eq!(
+            Ok(Command::Set {
+                register: Register::Hl,
+                value: 0xC000
+            }),
+            Command::parse("set HL $C000")
+        );
+        assert_eq!(
+            Ok(Command::Write {
+                address: 0xC000,
+                bytes: vec![0x12, 0x34]
+            }),
+            Command::parse("w C000 12 34")
+        );
+        assert_eq!(
+            Ok(Command::Watch(Watchpoint {
+                value: Some(0x42),
+                bank: None,
+                ..Watchpoint::new(AccessKind::Write, 0xC000..=0xC0FF)
+            })),
+            Command::parse("watch w C000-C0FF 42")
+        );
+        assert_eq!(
+            Ok(Command::Unwatch(Watchpoint {
+                bank: Some(1),
+                ..Watchpoint::new(AccessKind::Execute, 0x4000..=0x4000)
+            })),
+            Command::parse("unwatch x 01:4000")
+        );
+        assert!(Command::parse("watch w C0FF-C000").is_err());
+        assert!(Command::parse("set a 100").is_err());
+        assert!(Command::parse("w C000").is_err());
+        assert!(Command::parse("").is_err());
+    }
+
+    #[test]
+    fn test_parse_labels() {
+        let symbols = Symbols::parse("00:0150 Add\n01:4000 Banked\n00:C000 wBuffer").unwrap();
+
+        // Labels win over hex that looks the same
+        assert_eq!(
+            Ok(Command::Break(Breakpoint {
+                bank: Some(0),
+                address: 0x0150
+            })),
+            Command::parse_with_symbols("b Add", &symbols)
+        );
+        assert_eq!(
+            Ok(Command::Break(Breakpoint {
+                bank: None,
+                address: 0x0ADD
+            })),
+            Command::parse_with_symbols("b $ADD", &symbols)
+        );
+
+        assert_eq!(
+            Ok(Command::Examine {
+                address: 0xC000,
+                len: 16
+            }),
+            Command::parse_with_symbols("x wBuffer", &symbols)
+        );
+        assert_eq!(
+            Ok(Command::Watch(Watchpoint {
+                bank: Some(1),
+                ..Watchpoint::new(AccessKind::Read, 0x4000..=0x4010)
+            })),
+            Command::parse_with_symbols("watch r Banked-4010", &symbols)
+        );
+        assert!(Command::parse_with_symbols("b Missing", &symbols).is_err());
+    }
+}
@@ -0,0 +1,479 @@
+use crate::opcodes::{Flow, Opcode, Operand, CB_OPCODES, OPCODES};
+use crate::symbols::Symbols;
+use std::collections::BTreeSet;
+use std::fmt;
+
+const ROM_BANK_SIZE_BYTES: usize = 0x4000;
+const SWITCHABLE_BANK_ADDRESS: u16 = 0x4000;
+const ROM_ADDRESS_END: u16 = 0x7FFF;
+const WRAMX_ADDRESSES: std::ops::RangeInclusive<u16> = 0xD000..=0xDFFF;
+
+/// Where execution can start without being jumped to: the cartridge entry point, then the
+/// VBlank, STAT, timer, serial, and joypad interrupt vectors
+pub const ENTRY_POINTS: [u16; 6] = [0x0100, 0x0040, 0x0048, 0x0050, 0x0058, 0x0060];
+
+/// Bytes of data shown on each line of a disassembly
+const DATA_BYTES_PER_LINE: usize = 8;
+
+/// One decoded instruction
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Instruction {
+    pub address: u16,
+    /// The raw bytes, including any prefix and operands
+    pub bytes: Vec<u8>,
+    pub opcode: &'static Opcode,
+}
+
+impl Instruction {
+    /// Decode the instruction at the start of `bytes`, which were read from `address`
+    /// Returns None if the bytes stop part way through the instruction
+    pub fn decode(bytes: &[u8], address: u16) -> Option<Instruction> {
+        let opcode = match *bytes.first()? {
+            0xCB => &CB_OPCODES[*bytes.get(1)? as usize],
+            byte => &OPCODES[byte as usize],
+        };
+
+        Some(Instruction {
+            address,
+            bytes: bytes.get(..opcode.length as usize)?.to_vec(),
+            opcode,
+        })
+    }
+
+    /// Decode the instruction at `address`, reading memory with `read`
+    pub fn decode_with(address: u16, read: impl Fn(u16) -> u8) -> Instruction {
+        let bytes: Vec<u8> = (0..3)
+            .map(|offset| read(address.wrapping_add(offset)))
+            .collect();
+
+        Instruction::decode(&bytes, address).expect("no instruction is longer than 3 bytes")
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Where a jump, call, or restart goes, if it can be known without running
+    pub fn target(&self) -> Option<u16> {
+        match self.opcode.flow {
+            Flow::Jump | Flow::ConditionalJump | Flow::Call | Flow::ConditionalCall => {
+                match self.opcode.operand {
+                    Operand::E8 => Some(self.relative_target()),
+                    _ => Some(self.word_operand()),
+                }
+            }
+            Flow::Restart => Some((self.bytes[0] & 0x38) as u16),
+            _ => None,
+        }
+    }
+
+    /// Whether execution can carry on to the next instruction
+    pub fn falls_through(&self) -> bool {
+        !matches!(
+            self.opcode.flow,
+            Flow::Jump | Flow::Return | Flow::JumpIndirect | Flow::Illegal
+        )
+    }
+
+    fn byte_operand(&self) -> u8 {
+        self.bytes[1]
+    }
+
+    fn word_operand(&self) -> u16 {
+        u16::from_le_bytes([self.bytes[1], self.bytes[2]])
+    }
+
+    fn relative_target(&self) -> u16 {
+        self.address
+            .wrapping_add(self.len() as u16)
+            .wrapping_add(self.byte_operand() as i8 as u16)
+    }
+}
+
+/// Prints the instruction in RGBDS syntax, with relative jumps as their target address
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.to_string_with_labels(|_| None))
+    }
+}
+
+impl Instruction {
+    /// Like the `Display` text, but with addresses as labels wherever `label` has one
+    /// Only operands that are always addresses are labelled, never 16-bit constants
+    pub fn to_string_with_labels(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        let mnemonic = self.opcode.mnemonic;
+        let address = |address: u16| label(address).unwrap_or_else(|| format!("${:04X}", address));
+
+        match self.opcode.operand {
+            Operand::None => mnemonic.to_string(),
+            Operand::N8 => mnemonic.replace("n8", &format!("${:02X}", self.byte_operand())),
+            Operand::N16 => mnemonic.replace("n16", &format!("${:04X}", self.word_operand())),
+            Operand::A8 => mnemonic.replace("a8", &address(0xFF00 + self.byte_operand() as u16)),
+            Operand::A16 => mnemonic.replace("a16", &address(self.word_operand())),
+            Operand::E8 => mnemonic.replace("e8", &address(self.relative_target())),
+            Operand::S8 => {
+                let offset = self.byte_operand() as i8;
+                let sign = if offset < 0 { "-" } else { "+" };
+                mnemonic
+                    .replace("+s8", &format!("{}{}", sign, offset.unsigned_abs()))
+                    .replace("s8", &offset.to_string())
+            }
+        }
+    }
+}
+
+/// One line of a ROM's disassembly: an instruction, or bytes that aren't code
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Line {
+    pub bank: usize,
+    pub address: u16,
+    pub bytes: Vec<u8>,
+    pub text: String,
+    /// The symbol at the line's address, shown on a line of its own before it
+    pub label: Option<String>,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(label) = &self.label {
+            writeln!(f, "{}:", label)?;
+        }
+
+        let bytes: Vec<String> = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+
+        write!(
+            f,
+            "{:02X}:{:04X}  {:<24}{}",
+            self.bank,
+            self.address,
+            bytes.join(" "),
+            self.text
+        )
+    }
+}
+
+/// Decode every byte of every bank as code, whether it is or not
+pub fn disassemble_linear(rom: &[u8]) -> Vec<Line> {
+    disassemble_linear_with_symbols(rom, &Symbols::default())
+}
+
+/// Like `disassemble_linear`, with labels for lines and addresses that have symbols
+pub fn disassemble_linear_with_symbols(rom: &[u8], symbols: &Symbols) -> Vec<Line> {
+    let mut lines = Vec::new();
+
+    for (bank, bank_bytes) in rom.chunks(ROM_BANK_SIZE_BYTES).enumerate() {
+        let mut offset = 0;
+        while offset < bank_bytes.len() {
+            let address = bank_address(bank, offset);
+            match Instruction::decode(&bank_bytes[offset..], address) {
+                Some(instruction) => {
+                    offset += instruction.len();
+                    lines.push(instruction_line(bank, instruction, symbols));
+                }
+                None => {
+                    lines.push(data_line(bank, address, &bank_bytes[offset..], symbols));
+                    offset = bank_bytes.len();
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+/// Decode only what execution can reach from the entry points, showing the rest as data
+///
+/// Which bank is mapped at $4000-$7FFF isn't known without running, so jumps there from
+/// bank 0 are assumed to go to bank 1, as mapped at power on, and jumps from a switchable
+/// bank are assumed to stay in it. Jumps through HL, and code copied to RAM, aren't followed.
+pub fn disassemble_recursive(rom: &[u8], entry_points: &[u16]) -> Vec<Line> {
+    disassemble_recursive_with_symbols(rom, entry_points, &Symbols::default())
+}
+
+/// Like `disassemble_recursive`, with labels for lines and addresses that have symbols
+/// Data is split at labels, so none are hidden part way through a line
+pub fn disassemble_recursive_with_symbols(
+    rom: &[u8],
+    entry_points: &[u16],
+    symbols: &Symbols,
+) -> Vec<Line> {
+    let starts = find_instructions(rom, entry_points);
+    let mut lines = Vec::new();
+
+    for (bank, bank_bytes) in rom.chunks(ROM_BANK_SIZE_BYTES).enumerate() {
+        let bank_start = bank * ROM_BANK_SIZE_BYTES;
+
+        let mut offset = 0;
+        while offset < bank_bytes.len() {
+            let address = bank_address(bank, offset);
+
+            if starts.contains(&(bank_start + offset)) {
+                let instruction = Instruction::decode(&bank_bytes[offset..], address)
+                    .expect("only whole instructions are found");
+                offset += instruction.len();
+                lines.push(instruction_line(bank, instruction, symbols));
+                continue;
+            }
+
+            let data_end = (offset + 1..bank_bytes.len())
+                .take(DATA_BYTES_PER_LINE - 1)
+                .find(|end| {
+                    starts.contains(&(bank_start + end))
+                        || symbols
+                            .label_in_bank(bank as u8, bank_address(bank, *end))
+                            .is_some()
+                })
+                .unwrap_or_else(|| (offset + DATA_BYTES_PER_LINE).min(bank_bytes.len()));
+            lines.push(data_line(
+                bank,
+                address,
+                &bank_bytes[offset..data_end],
+                symbols,
+            ));
+            offset = data_end;
+        }
+    }
+
+    lines
+}
+
+/// The ROM offsets of every instruction reachable from the entry points
+fn find_instructions(rom: &[u8], entry_points: &[u16]) -> BTreeSet<usize> {
+    let mut starts = BTreeSet::new();
+    let mut pending: Vec<(usize, u16)> = entry_points.iter().map(|address| (0, *address)).collect();
+
+    while let Some((from_bank, address)) = pending.pop() {
+        // Code in RAM isn't in the ROM to decode
+        if address > ROM_ADDRESS_END {
+            continue;
+        }
+        let mut bank = assumed_bank(from_bank, address);
+        let mut address = address;
+
+        loop {
+            let offset = rom_offset(bank, address);
+            let bank_end = (offset / ROM_BANK_SIZE_BYTES + 1) * ROM_BANK_SIZE_BYTES;
+            if offset >= rom.len() || starts.contains(&offset) {
+                break;
+            }
+
+            let instruction =
+                match Instruction::decode(&rom[offset..bank_end.min(rom.len())], address) {
+                    Some(instruction) => instruction,
+                    None => break,
+                };
+            starts.insert(offset);
+
+            if let Some(target) = instruction.target() {
+                pending.push((bank, target));
+            }
+            if !instruction.falls_through() {
+                break;
+            }
+
+            // Running off the end of bank 0 carries on into whichever bank is mapped next
+            address = address.wrapping_add(instruction.len() as u16);
+            if address > ROM_ADDRESS_END {
+                break;
+            }
+            if address >= SWITCHABLE_BANK_ADDRESS && bank == 0 {
+                bank = 1;
+            }
+        }
+    }
+
+    starts
+}
+
+/// The bank an address is assumed to be in, when referred to from code in `from_bank`
+/// See `disassemble_recursive` for why
+fn assumed_bank(from_bank: usize, address: u16) -> usize {
+    match address {
+        0..=0x3FFF => 0,
+        SWITCHABLE_BANK_ADDRESS..=ROM_ADDRESS_END => from_bank.max(1),
+        // RGBDS puts switchable WRAM in bank 1 and up, and everything else in bank 0
+        _ if WRAMX_ADDRESSES.contains(&address) => 1,
+        _ => 0,
+    }
+}
+
+fn bank_address(bank: usize, offset: usize) -> u16 {
+    let base = if bank == 0 {
+        0
+    } else {
+        SWITCHABLE_BANK_ADDRESS
+    };
+    base + offset as u16
+}
+
+fn rom_offset(bank: usize, address: u16) -> usize {
+    bank * ROM_BANK_SIZE_BYTES + (address as usize % ROM_BANK_SIZE_BYTES)
+}
+
+fn instruction_line(bank: usize, instruction: Instruction, symbols: &Symbols) -> Line {
+    let text = instruction.to_string_with_labels(|target| {
+        symbols
+            .label_in_bank(assumed_bank(bank, target) as u8, target)
+            .map(str::to_string)
+    });
+
+    Line {
+        bank,
+        address: instruction.address,
+        text,
+        label: label(bank, instruction.address, symbols),
+        bytes: instruction.bytes,
+    }
+}
+
+fn data_line(bank: usize, address: u16, bytes: &[u8], symbols: &Symbols) -> Line {
+    let values: Vec<String> = bytes.iter().map(|byte| format!("${:02X}", byte)).collect();
+
+    Line {
+        bank,
+        address,
+        bytes: bytes.to_vec(),
+        text: format!("db {}", values.join(", ")),
+        label: label(bank, address, symbols),
+    }
+}
+
+fn label(bank: usize, address: u16, symbols: &Symbols) -> Option<String> {
+    symbols
+        .label_in_bank(bank as u8, address)
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(bytes: &[u8], address: u16) -> String {
+        Instruction::decode(bytes, address).unwrap().to_string()
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!("nop", text(&[0x00], 0));
+        assert_eq!("ld a, $1A", text(&[0x3E, 0x1A], 0));
+        assert_eq!("ld hl, $C000", text(&[0x21, 0x00, 0xC0], 0));
+        assert_eq!("jp $0150", text(&[0xC3, 0x50, 0x01], 0x100));
+        assert_eq!("ldh [$FF00], a", text(&[0xE0, 0x00], 0));
+        assert_eq!("ld [$DEAD], a", text(&[0xEA, 0xAD, 0xDE], 0));
+        assert_eq!("ld a, [hl+]", text(&[0x2A], 0));
+        assert_eq!("rst $38", text(&[0xFF], 0));
+    }
+
+    #[test]
+    fn test_relative_and_signed_operands() {
+        // jr -2 loops forever
+        assert_eq!("jr $0200", text(&[0x18, 0xFE], 0x200));
+        assert_eq!("jr nz, $0212", text(&[0x20, 0x10], 0x200));
+        assert_eq!("add sp, -2", text(&[0xE8, 0xFE], 0));
+        assert_eq!("ld hl, sp+5", text(&[0xF8, 0x05], 0));
+        assert_eq!("ld hl, sp-128", text(&[0xF8, 0x80], 0));
+    }
+
+    #[test]
+    fn test_prefixed() {
+        let instruction = Instruction::decode(&[0xCB, 0x7C], 0).unwrap();
+        assert_eq!("bit 7, h", instruction.to_string());
+        assert_eq!(2, instruction.len());
+
+        assert_eq!(None, Instruction::decode(&[0xCB], 0));
+        assert_eq!(None, Instruction::decode(&[0xC3, 0x50], 0));
+    }
+
+    #[test]
+    fn test_flow() {
+        let call = Instruction::decode(&[0xCD, 0x00, 0x40], 0).unwrap();
+        assert_eq!(Some(0x4000), call.target());
+        assert!(call.falls_through());
+
+        let ret = Instruction::decode(&[0xC9], 0).unwrap();
+        assert_eq!(None, ret.target());
+        assert!(!ret.falls_through());
+
+        let rst = Instruction::decode(&[0xDF], 0).unwrap();
+        assert_eq!(Some(0x18), rst.target());
+    }
+
+    #[test]
+    fn test_recursive_skips_data() {
+        let mut rom = vec![0xFF; 2 * ROM_BANK_SIZE_BYTES];
+        // jp $0150, then at $0150: call $4000, jr back to $0150
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        rom[0x150..0x155].copy_from_slice(&[0xCD, 0x00, 0x40, 0x18, 0xFB]);
+        // In bank 1: ret
+        rom[ROM_BANK_SIZE_BYTES] = 0xC9;
+
+        let starts = find_instructions(&rom, &[0x100]);
+        assert_eq!(
+            vec![0x100, 0x150, 0x153, ROM_BANK_SIZE_BYTES],
+            starts.into_iter().collect::<Vec<_>>()
+        );
+
+        let lines = disassemble_recursive(&rom, &[0x100]);
+        let code: Vec<String> = lines
+            .iter()
+            .filter(|line| !line.text.starts_with("db"))
+            .map(Line::to_string)
+            .collect();
+        assert_eq!(
+            vec![
+                "00:0100  C3 50 01                jp $0150",
+                "00:0150  CD 00 40                call $4000",
+                "00:0153  18 FB                   jr $0150",
+                "01:4000  C9                      ret",
+            ],
+            code
+        );
+
+        // The filler between them is data, split where code starts
+        assert!(lines.contains(&Line {
+            bank: 0,
+            address: 0x103,
+            bytes: vec![0xFF; 8],
+            text: "db $FF, $FF, $FF, $FF, $FF, $FF, $FF, $FF".to_string(),
+            label: None,
+        }));
+    }
+
+    #[test]
+    fn test_symbols() {
+        let mut rom = vec![0xFF; 2 * ROM_BANK_SIZE_BYTES];
+        rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+        rom[0x150..0x155].copy_from_slice(&[0xCD, 0x00, 0x40, 0x18, 0xFB]);
+        rom[ROM_BANK_SIZE_BYTES] = 0xC9;
+
+        let symbols =
+            Symbols::parse("00:0150 Main\n01:4000 Routine\n00:0200 Table\n02:4000 Elsewhere")
+                .unwrap();
+        let lines = disassemble_recursive_with_symbols(&rom, &[0x100], &symbols);
+        let line_at = |bank: usize, address: u16| {
+            lines
+                .iter()
+                .find(|line| line.bank == bank && line.address == address)
+                .unwrap()
+        };
+
+        assert_eq!("jp Main", line_at(0, 0x100).text);
+        assert_eq!(
+            "Main:\n00:0150  CD 00 40                call Routine",
+            line_at(0, 0x150).to_string()
+        );
+        assert_eq!("jr Main", line_at(0, 0x153).text);
+        assert_eq!(Some("Routine".to_string()), line_at(1, 0x4000).label);
+
+        // Data is split so its label starts a line
+        assert_eq!(Some("Table".to_string()), line_at(0, 0x200).label);
+    }
+}
@@ -0,0 +1,39 @@
+use std::{error, fmt};
+
+/// A fault while running a ROM, as reported by `System` in strict mode
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum EmulationError {
+    /// One of the opcodes the SM83 doesn't define, which locks up the real CPU
+    IllegalOpcode { pc: u16, opcode: u8 },
+    /// A real opcode that this emulator can't execute yet
+    UnimplementedOpcode { pc: u16, opcode: u8 },
+    /// A read from an address nothing on the bus responds to
+    UnmappedRead { pc: u16, address: u16 },
+    /// A write to an address nothing on the bus responds to
+    UnmappedWrite { pc: u16, address: u16, value: u8 },
+}
+
+impl fmt::Display for EmulationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EmulationError::IllegalOpcode { pc, opcode } => {
+                write!(f, "illegal opcode {:#04X} at {:#06X}", opcode, pc)
+            }
+            EmulationError::UnimplementedOpcode { pc, opcode } => {
+                write!(f, "unimplemented opcode {:#04X} at {:#06X}", opcode, pc)
+            }
+            EmulationError::UnmappedRead { pc, address } => write!(
+                f,
+                "read from unmapped address {:#06X} at {:#06X}",
+                address, pc
+            ),
+            EmulationError::UnmappedWrite { pc, address, value } => write!(
+                f,
+                "write of {:#04X} to unmapped address {:#06X} at {:#06X}",
+                value, address, pc
+            ),
+        }
+    }
+}
+
+impl error::Error for EmulationError {}
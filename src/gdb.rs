@@ -0,0 +1,736 @@
+use crate::bus::{Access, AccessKind};
+use crate::debugger::{Breakpoint, Debugger, Stop};
+use crate::error::EmulationError;
+use crate::system::System;
+use crate::watchpoint::Watchpoint;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::ops::RangeInclusive;
+
+/// Instructions run between checks for GDB interrupting a continue
+const INSTRUCTIONS_PER_POLL: usize = 4096;
+
+/// What GDB sends, outside of any packet, to interrupt a continue
+const INTERRUPT: u8 = 0x03;
+
+// Signals reported in stop replies, numbered as GDB numbers them
+const SIGINT: u8 = 2;
+const SIGILL: u8 = 4;
+const SIGTRAP: u8 = 5;
+const SIGSEGV: u8 = 11;
+
+/// GDB's z80 target, which also covers the SM83, numbers its registers in this order
+/// Registers the SM83 doesn't have read as 0 and can't be changed
+const REGISTERS: [&str; 13] = [
+    "af", "bc", "de", "hl", "sp", "pc", "ix", "iy", "af'", "bc'", "de'", "hl'", "ir",
+];
+const SP_REGISTER: usize = 4;
+
+/// Where the ROM's switchable bank is mapped
+const BANKED_ROM_ADDRESSES: RangeInclusive<u16> = 0x4000..=0x7FFF;
+
+/// Where an address GDB uses points
+///
+/// GDB sees one flat address space. Its low 64 KiB is whatever the CPU sees right now, and each
+/// switchable ROM bank is above that at `BANK << 16 | ADDRESS`, like RGBDS's `BANK:ADDRESS`, so
+/// 0x14000 is bank 1's 0x4000 whichever bank is mapped.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Location {
+    Cpu(u16),
+    Banked { bank: u8, address: u16 },
+}
+
+impl Location {
+    /// None for addresses outside the flat address space
+    pub fn from_flat(flat: u32) -> Option<Location> {
+        let address = flat as u16;
+
+        match flat >> 16 {
+            0 => Some(Location::Cpu(address)),
+            bank @ 1..=0xFF if BANKED_ROM_ADDRESSES.contains(&address) => Some(Location::Banked {
+                bank: bank as u8,
+                address,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn to_flat(self) -> u32 {
+        match self {
+            Location::Cpu(address) => address as u32,
+            Location::Banked { bank, address } => (bank as u32) << 16 | address as u32,
+        }
+    }
+}
+
+/// What to do once a packet has been handled
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Reply {
+    Packet(String),
+    /// Run until something stops the system, then reply with why
+    Resume {
+        step: bool,
+    },
+    /// Send the reply, if any, then end the session
+    Close(Option<String>),
+}
+
+/// A server for GDB's remote serial protocol, debugging a system for a GDB front end
+///
+/// Breakpoints and watchpoints are kept by the stub rather than written into memory, so GDB's
+/// software and hardware breakpoints are the same thing here.
+pub struct GdbStub {
+    debugger: Debugger,
+    /// The stop reply for the last time the system stopped, which GDB can ask for again
+    last_stop: String,
+    /// The halves of access watchpoints, which GDB wants reported as such whichever way the
+    /// memory was accessed
+    access_watchpoints: Vec<Watchpoint>,
+}
+
+impl GdbStub {
+    pub fn new(system: System) -> GdbStub {
+        GdbStub {
+            debugger: Debugger::new(system),
+            last_stop: signal(SIGTRAP),
+            access_watchpoints: Vec::new(),
+        }
+    }
+
+    pub fn system(&self) -> &System {
+        self.debugger.system()
+    }
+
+    /// Talk to GDB over the stream until it detaches, kills the session, or disconnects
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        // Packets are small and each waits on the last, so batching them only adds latency
+        stream.set_nodelay(true)?;
+        let mut connection = Connection {
+            stream,
+            no_ack: false,
+        };
+
+        while let Some(packet) = connection.read_packet()? {
+            if packet == "QStartNoAckMode" {
+                connection.send("OK")?;
+                connection.no_ack = true;
+                continue;
+            }
+
+            match self.handle(&packet) {
+                Reply::Packet(reply) => connection.send(&reply)?,
+                Reply::Resume { step } => {
+                    self.last_stop = if step {
+                        let stop = self.debugger.step(1);
+                        self.stop_reply(stop)
+                    } else {
+                        self.continue_running(&mut connection)?
+                    };
+                    connection.send(&self.last_stop)?;
+                }
+                Reply::Close(reply) => {
+                    if let Some(reply) = reply {
+                        connection.send(&reply)?;
+                    }
+                    return Ok(());
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Reply {
+        // Packets are ASCII, so this can't split a character
+        let (command, args) = packet.split_at(packet.len().min(1));
+
+        let reply = match command {
+            "?" => Some(self.last_stop.clone()),
+            "g" => Some(self.read_registers()),
+            "G" => self.write_registers(args),
+            "p" => usize::from_str_radix(args, 16)
+                .ok()
+                .and_then(|number| self.register(number))
+                .map(|value| encode_hex(&value.to_le_bytes())),
+            "P" => self.write_register(args),
+            "m" => self.read_memory(args),
+            "M" => self.write_memory(args),
+            "Z" => self.insert_point(args),
+            "z" => self.remove_point(args),
+            "c" | "s" => {
+                return match self.jump(args) {
+                    Some(()) => Reply::Resume {
+                        step: command == "s",
+                    },
+                    None => Reply::Packet(error()),
+                }
+            }
+            "D" => return Reply::Close(Some("OK".to_string())),
+            "k" => return Reply::Close(None),
+            // There's only one thread to pick
+            "H" => Some("OK".to_string()),
+            "q" => Some(self.query(args)),
+            // Anything else is unsupported, which an empty reply tells GDB
+            _ => Some(String::new()),
+        };
+
+        Reply::Packet(reply.unwrap_or_else(error))
+    }
+
+    fn query(&self, query: &str) -> String {
+        if query.starts_with("Supported") {
+            "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+        } else if query == "Attached" {
+            // Detaching leaves the system as it is, rather than killing it
+            "1".to_string()
+        } else if let Some(range) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            match parse_pair(range, ',') {
+                Some((offset, len)) => {
+                    let xml = target_xml();
+                    let start = (offset as usize).min(xml.len());
+                    let end = (start + len as usize).min(xml.len());
+                    let more = if end < xml.len() { 'm' } else { 'l' };
+
+                    format!("{}{}", more, &xml[start..end])
+                }
+                None => error(),
+            }
+        } else {
+            String::new()
+        }
+    }
+
+    fn register(&self, number: usize) -> Option<u16> {
+        let cpu = self.debugger.system().cpu();
+        let pair = |high, low| u16::from_be_bytes([high, low]);
+
+        let value = match number {
+            0 => pair(cpu.a, cpu.f),
+            1 => pair(cpu.b, cpu.c),
+            2 => pair(cpu.d, cpu.e),
+            3 => pair(cpu.h, cpu.l),
+            SP_REGISTER => cpu.sp,
+            5 => cpu.pc,
+            _ if number < REGISTERS.len() => 0,
+            _ => return None,
+        };
+
+        Some(value)
+    }
+
+    fn set_register(&mut self, number: usize, value: u16) -> Option<()> {
+        // GDB writes every register back when changing one, so leaving the others be is fine
+        if number >= 6 {
+            return (self.register(number) == Some(value)).then_some(());
+        }
+
+        let cpu = self.debugger.system_mut().cpu_mut();
+        let [high, low] = value.to_be_bytes();
+        match number {
+            0 => (cpu.a, cpu.f) = (high, low),
+            1 => (cpu.b, cpu.c) = (high, low),
+            2 => (cpu.d, cpu.e) = (high, low),
+            3 => (cpu.h, cpu.l) = (high, low),
+            SP_REGISTER => cpu.sp = value,
+            _ => cpu.pc = value,
+        }
+
+        Some(())
+    }
+
+    /// Every register, each 16 bits little endian
+    fn read_registers(&self) -> String {
+        (0..REGISTERS.len())
+            .filter_map(|number| self.register(number))
+            .map(|value| encode_hex(&value.to_le_bytes()))
+            .collect()
+    }
+
+    fn write_registers(&mut self, args: &str) -> Option<String> {
+        let bytes = decode_hex(args)?;
+        if bytes.len() != REGISTERS.len() * 2 {
+            return None;
+        }
+
+        for (number, value) in bytes.chunks(2).enumerate() {
+            self.set_register(number, u16::from_le_bytes([value[0], value[1]]))?;
+        }
+
+        Some("OK".to_string())
+    }
+
+    fn write_register(&mut self, args: &str) -> Option<String> {
+        let (number, value) = args.split_once('=')?;
+        let number = usize::from_str_radix(number, 16).ok()?;
+        let value: [u8; 2] = decode_hex(value)?.try_into().ok()?;
+
+        self.set_register(number, u16::from_le_bytes(value))?;
+        Some("OK".to_string())
+    }
+
+    /// Reads stop early at the end of the flat address space
+    fn read_memory(&self, args: &str) -> Option<String> {
+        let (start, len) = parse_pair(args, ',')?;
+        let bus = self.debugger.system().bus();
+
+        let bytes: Vec<u8> = (0..len)
+            .map_while(
+                |offset| match Location::from_flat(start.checked_add(offset)?)? {
+                    Location::Cpu(address) => Some(bus.peek(address)),
+                    Location::Banked { bank, address } => {
+                        bus.cartridge.read_rom_bank(bank as usize, address)
+                    }
+                },
+            )
+            .collect();
+
+        (len == 0 || !bytes.is_empty()).then(|| encode_hex(&bytes))
+    }
+
+    /// Writes go through the bus as the CPU's would, so ROM banks can only be read
+    fn write_memory(&mut self, args: &str) -> Option<String> {
+        let (range, data) = args.split_once(':')?;
+        let (start, len) = parse_pair(range, ',')?;
+        let bytes = decode_hex(data)?;
+        if bytes.len() != len as usize {
+            return None;
+        }
+
+        // Only what the CPU sees can be written, and all of it, without wrapping around
+        let end = start.checked_add(len.max(1) - 1)?;
+        let address = match (Location::from_flat(start)?, Location::from_flat(end)?) {
+            (Location::Cpu(address), Location::Cpu(_)) => address,
+            _ => return None,
+        };
+        self.debugger.write(address, &bytes);
+
+        Some("OK".to_string())
+    }
+
+    fn insert_point(&mut self, args: &str) -> Option<String> {
+        match parse_point(args)? {
+            Point::Breakpoint(breakpoint) => {
+                self.debugger.add_breakpoint(breakpoint);
+            }
+            Point::Watchpoints {
+                watchpoints,
+                access,
+            } => {
+                for watchpoint in watchpoints {
+                    if access {
+                        self.access_watchpoints.push(watchpoint.clone());
+                    }
+                    self.debugger
+                        .system_mut()
+                        .bus_mut()
+                        .add_watchpoint(watchpoint);
+                }
+            }
+            Point::Unsupported => return Some(String::new()),
+        }
+
+        Some("OK".to_string())
+    }
+
+    fn remove_point(&mut self, args: &str) -> Option<String> {
+        match parse_point(args)? {
+            Point::Breakpoint(breakpoint) => {
+                self.debugger.remove_breakpoint(&breakpoint);
+            }
+            Point::Watchpoints {
+                watchpoints,
+                access,
+            } => {
+                for watchpoint in watchpoints {
+                    if access {
+                        if let Some(index) = self
+                            .access_watchpoints
+                            .iter()
+                            .position(|w| *w == watchpoint)
+                        {
+                            self.access_watchpoints.remove(index);
+                        }
+                    }
+                    self.debugger
+                        .system_mut()
+                        .bus_mut()
+                        .remove_watchpoint(&watchpoint);
+                }
+            }
+            Point::Unsupported => return Some(String::new()),
+        }
+
+        Some("OK".to_string())
+    }
+
+    /// Resume packets can say where to resume from
+    fn jump(&mut self, args: &str) -> Option<()> {
+        if !args.is_empty() {
+            let address = u32::from_str_radix(args, 16).ok()?;
+            match Location::from_flat(address)? {
+                Location::Cpu(address) => self.debugger.system_mut().cpu_mut().pc = address,
+                Location::Banked { .. } => return None,
+            }
+        }
+
+        Some(())
+    }
+
+    fn continue_running(&mut self, connection: &mut Connection) -> io::Result<String> {
+        loop {
+            if let Some(stop) = self.debugger.run_for(INSTRUCTIONS_PER_POLL) {
+                return Ok(self.stop_reply(Some(stop)));
+            }
+
+            if connection.interrupted()? {
+                return Ok(signal(SIGINT));
+            }
+        }
+    }
+
+    fn stop_reply(&self, stop: Option<Stop>) -> String {
+        match stop {
+            None | Some(Stop::Breakpoint(_)) => signal(SIGTRAP),
+            Some(Stop::Watchpoint(access)) => {
+                let access_watched = self
+                    .access_watchpoints
+                    .iter()
+                    .any(|watchpoint| watchpoint.matches(&access));
+                let kind = match access.kind {
+                    AccessKind::Execute => return signal(SIGTRAP),
+                    _ if access_watched => "awatch",
+                    AccessKind::Write => "watch",
+                    AccessKind::Read | AccessKind::Dma => "rwatch",
+                };
+
+                format!(
+                    "T{:02x}{}:{:x};",
+                    SIGTRAP,
+                    kind,
+                    self.watched_address(&access)
+                )
+            }
+            Some(Stop::LockedUp) => signal(SIGILL),
+            Some(Stop::Fault(
+                EmulationError::IllegalOpcode { .. } | EmulationError::UnimplementedOpcode { .. },
+            )) => signal(SIGILL),
+            Some(Stop::Fault(
+                EmulationError::UnmappedRead { .. } | EmulationError::UnmappedWrite { .. },
+            )) => signal(SIGSEGV),
+        }
+    }
+
+    /// The address GDB set the watchpoint at, which is banked if the watchpoint was
+    fn watched_address(&self, access: &Access) -> u32 {
+        let banked = self
+            .debugger
+            .system()
+            .bus()
+            .watchpoints()
+            .iter()
+            .any(|watchpoint| watchpoint.bank.is_some() && watchpoint.matches(access));
+
+        match access.bank {
+            Some(bank) if banked => Location::Banked {
+                bank,
+                address: access.address,
+            }
+            .to_flat(),
+            _ => access.address as u32,
+        }
+    }
+}
+
+enum Point {
+    Breakpoint(Breakpoint),
+    Watchpoints {
+        watchpoints: Vec<Watchpoint>,
+        /// From a Z4, which watches reads and writes together
+        access: bool,
+    },
+    Unsupported,
+}
+
+/// Parses the `TYPE,ADDR,KIND` of Z and z packets, where KIND is the length for watchpoints
+fn parse_point(args: &str) -> Option<Point> {
+    let (point_type, rest) = args.split_once(',')?;
+    let (start, kind) = parse_pair(rest, ',')?;
+    let start_location = Location::from_flat(start)?;
+
+    let kinds: &[AccessKind] = match point_type {
+        // Software and hardware breakpoints both stay out of memory
+        "0" | "1" => {
+            let breakpoint = match start_location {
+                Location::Cpu(address) => Breakpoint {
+                    bank: None,
+                    address,
+                },
+                Location::Banked { bank, address } => Breakpoint {
+                    bank: Some(bank),
+                    address,
+                },
+            };
+            return Some(Point::Breakpoint(breakpoint));
+        }
+        "2" => &[AccessKind::Write],
+        "3" => &[AccessKind::Read],
+        "4" => &[AccessKind::Read, AccessKind::Write],
+        _ => return Some(Point::Unsupported),
+    };
+
+    // Watched ranges can't run from one bank into the next
+    let end_location = Location::from_flat(start.checked_add(kind.max(1) - 1)?)?;
+    let (bank, addresses) = match (start_location, end_location) {
+        (Location::Cpu(start), Location::Cpu(end)) => (None, start..=end),
+        (
+            Location::Banked { bank, address },
+            Location::Banked {
+                bank: end_bank,
+                address: end,
+            },
+        ) if bank == end_bank => (Some(bank), address..=end),
+        _ => return None,
+    };
+
+    let watchpoints = kinds
+        .iter()
+        .map(|kind| Watchpoint {
+            bank,
+            ..Watchpoint::new(*kind, addresses.clone())
+        })
+        .collect();
+    Some(Point::Watchpoints {
+        watchpoints,
+        access: point_type == "4",
+    })
+}
+
+/// Describes the registers to GDB, so it needn't be told the architecture
+fn target_xml() -> String {
+    let registers: String = REGISTERS
+        .iter()
+        .enumerate()
+        .map(|(number, name)| {
+            let register_type = match number {
+                SP_REGISTER => "data_ptr",
+                5 => "code_ptr",
+                _ => "int",
+            };
+            format!(
+                "<reg name=\"{}\" bitsize=\"16\" type=\"{}\"/>",
+                name, register_type
+            )
+        })
+        .collect();
+
+    format!(
+        "<?xml version=\"1.0\"?><!DOCTYPE target SYSTEM \"gdb-target.dtd\">\
+        <target version=\"1.0\"><architecture>z80</architecture>\
+        <feature name=\"org.gnu.gdb.z80.cpu\">{}</feature></target>",
+        registers
+    )
+}
+
+struct Connection {
+    stream: TcpStream,
+    /// Once GDB agrees, packets are no longer acknowledged, since TCP is already reliable
+    no_ack: bool,
+}
+
+impl Connection {
+    /// None once GDB has disconnected
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        let mut byte = [0];
+        match self.stream.read(&mut byte)? {
+            0 => Ok(None),
+            _ => Ok(Some(byte[0])),
+        }
+    }
+
+    /// The next packet's data, skipping acknowledgements and packets with bad checksums
+    fn read_packet(&mut self) -> io::Result<Option<String>> {
+        loop {
+            match self.read_byte()? {
+                Some(b'$') => {}
+                Some(_) => continue,
+                None => return Ok(None),
+            }
+
+            let mut data = Vec::new();
+            loop {
+                match self.read_byte()? {
+                    Some(b'#') => break,
+                    Some(byte) => data.push(byte),
+                    None => return Ok(None),
+                }
+            }
+
+            let mut digits = [0; 2];
+            self.stream.read_exact(&mut digits)?;
+            let valid = std::str::from_utf8(&digits)
+                .ok()
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                == Some(checksum(&data));
+
+            if !self.no_ack {
+                self.stream.write_all(if valid { b"+" } else { b"-" })?;
+            }
+            if valid {
+                return Ok(Some(String::from_utf8_lossy(&data).into_owned()));
+            }
+        }
+    }
+
+    /// Replies are never retransmitted, since nothing corrupts them over TCP
+    fn send(&mut self, data: &str) -> io::Result<()> {
+        write!(self.stream, "${}#{:02x}", data, checksum(data.as_bytes()))?;
+        self.stream.flush()
+    }
+
+    /// Whether GDB has asked to interrupt the running system, without waiting for it to
+    fn interrupted(&mut self) -> io::Result<bool> {
+        self.stream.set_nonblocking(true)?;
+        let mut byte = [0];
+        let result = self.stream.read(&mut byte);
+        self.stream.set_nonblocking(false)?;
+
+        match result {
+            // GDB has gone, so stop and let the next read notice
+            Ok(0) => Ok(true),
+            Ok(_) => Ok(byte[0] == INTERRUPT),
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+fn checksum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, byte| sum.wrapping_add(*byte))
+}
+
+fn signal(number: u8) -> String {
+    format!("S{:02x}", number)
+}
+
+fn error() -> String {
+    "E01".to_string()
+}
+
+/// Two hex numbers, separated as they are in the packet
+fn parse_pair(text: &str, separator: char) -> Option<(u32, u32)> {
+    let (first, second) = text.split_once(separator)?;
+    Some((
+        u32::from_str_radix(first, 16).ok()?,
+        u32::from_str_radix(second, 16).ok()?,
+    ))
+}
+
+fn encode_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return None;
+    }
+
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::mbc1::Mbc1;
+
+    fn stub() -> GdbStub {
+        GdbStub::new(System::load_cartridge(Mbc1::new()))
+    }
+
+    fn reply(stub: &mut GdbStub, packet: &str) -> String {
+        match stub.handle(packet) {
+            Reply::Packet(reply) => reply,
+            other => panic!("expected a reply to {:?}, got {:?}", packet, other),
+        }
+    }
+
+    #[test]
+    fn test_locations() {
+        assert_eq!(Some(Location::Cpu(0xC000)), Location::from_flat(0xC000));
+        assert_eq!(
+            Some(Location::Banked {
+                bank: 2,
+                address: 0x4010
+            }),
+            Location::from_flat(0x2_4010)
+        );
+        assert_eq!(None, Location::from_flat(0x1_0000));
+        assert_eq!(None, Location::from_flat(0x100_4000));
+        assert_eq!(0x2_4010, Location::from_flat(0x2_4010).unwrap().to_flat());
+    }
+
+    #[test]
+    fn test_checksum() {
+        assert_eq!(0x00, checksum(b""));
+        assert_eq!(0x3F, checksum(b"?"));
+        assert_eq!(0x9A, checksum(b"OK"));
+    }
+
+    #[test]
+    fn test_registers() {
+        let mut stub = stub();
+
+        assert_eq!("OK", reply(&mut stub, "P0=b001"));
+        assert_eq!("OK", reply(&mut stub, "P5=5001"));
+        assert_eq!("b001000000000000feff5001", &reply(&mut stub, "g")[..24]);
+        assert_eq!("5001", reply(&mut stub, "p5"));
+        assert_eq!(0x0150, stub.system().cpu().pc);
+
+        assert_eq!("OK", reply(&mut stub, "P4=f0df"));
+        assert_eq!(0xDFF0, stub.system().cpu().sp);
+
+        // There's no IX at all
+        assert_eq!("E01", reply(&mut stub, "P6=0100"));
+        assert_eq!("E01", reply(&mut stub, "pd"));
+
+        // Writing back every register unchanged is fine
+        let registers = reply(&mut stub, "g");
+        assert_eq!("OK", reply(&mut stub, &format!("G{}", registers)));
+    }
+
+    #[test]
+    fn test_memory() {
+        let mut stub = stub();
+
+        assert_eq!("OK", reply(&mut stub, "Mc000,2:1234"));
+        assert_eq!("1234", reply(&mut stub, "mc000,2"));
+
+        // A blank ROM has 4 banks
+        assert_eq!("00", reply(&mut stub, "m34000,1"));
+        assert_eq!("E01", reply(&mut stub, "m44000,1"));
+        assert_eq!("E01", reply(&mut stub, "M14000,1:00"));
+    }
+
+    #[test]
+    fn test_target_xml_in_pieces() {
+        let mut stub = stub();
+        let xml = target_xml();
+
+        let first = reply(&mut stub, "qXfer:features:read:target.xml:0,10");
+        assert_eq!(format!("m{}", &xml[..16]), first);
+
+        let rest = reply(&mut stub, "qXfer:features:read:target.xml:10,1000");
+        assert_eq!(format!("l{}", &xml[16..]), rest);
+    }
+
+    #[test]
+    fn test_unsupported_packets_reply_empty() {
+        let mut stub = stub();
+
+        assert_eq!("", reply(&mut stub, "vMustReplyEmpty"));
+        assert_eq!("", reply(&mut stub, "qUnknown"));
+        assert_eq!("", reply(&mut stub, "Z5,100,1"));
+    }
+}
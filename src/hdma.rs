@@ -0,0 +1,256 @@
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateError, SaveStateResult, StateReader, StateWriter};
+use crate::speed::Speed;
+
+pub const HDMA1_ADDRESS: u16 = 0xFF51;
+pub const HDMA2_ADDRESS: u16 = 0xFF52;
+pub const HDMA3_ADDRESS: u16 = 0xFF53;
+pub const HDMA4_ADDRESS: u16 = 0xFF54;
+pub const HDMA5_ADDRESS: u16 = 0xFF55;
+
+pub const BLOCK_SIZE_BYTES: u16 = 0x10;
+
+const VRAM_ADDRESS_START: u16 = 0x8000;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Mode {
+    Idle,
+    GeneralPurpose,
+    HBlank,
+}
+
+/// One 16-byte block to be copied from `source` into VRAM at `destination`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Block {
+    pub source: u16,
+    pub destination: u16,
+}
+
+/// CGB VRAM DMA controller, registers HDMA1-HDMA5
+///
+/// This only tracks the register state; the bus performs the actual copies
+/// by draining blocks with `next_block` when a transfer is due.
+pub struct Hdma {
+    source: u16,
+    destination: u16,
+    remaining_blocks: u8,
+    mode: Mode,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Hdma {
+            source: 0,
+            destination: VRAM_ADDRESS_START,
+            remaining_blocks: 0,
+            mode: Mode::Idle,
+        }
+    }
+}
+
+impl Hdma {
+    /// Machine cycles the CPU is halted for while one block is copied
+    /// A block takes the same wall time at either speed, so double speed costs twice the cycles
+    pub fn block_cycles(speed: Speed) -> usize {
+        match speed {
+            Speed::Normal => 8,
+            Speed::Double => 16,
+        }
+    }
+
+    /// True while a general purpose transfer is waiting to be run to completion
+    pub fn is_general_purpose(&self) -> bool {
+        self.mode == Mode::GeneralPurpose
+    }
+
+    /// True while an HBlank transfer is waiting for its next HBlank
+    pub fn is_hblank(&self) -> bool {
+        self.mode == Mode::HBlank
+    }
+
+    /// Takes the next block of the active transfer, advancing the source and destination
+    /// Returns None if no transfer is active
+    pub fn next_block(&mut self) -> Option<Block> {
+        if self.mode == Mode::Idle {
+            return None;
+        }
+
+        let block = Block {
+            source: self.source,
+            destination: self.destination,
+        };
+
+        self.source = self.source.wrapping_add(BLOCK_SIZE_BYTES);
+        // The destination wraps within VRAM
+        self.destination =
+            VRAM_ADDRESS_START | (self.destination.wrapping_add(BLOCK_SIZE_BYTES) & 0x1FF0);
+
+        self.remaining_blocks -= 1;
+        if self.remaining_blocks == 0 {
+            self.mode = Mode::Idle;
+        }
+
+        Some(block)
+    }
+
+    fn status(&self) -> u8 {
+        // Bits 0-6 hold the remaining length minus one, so a finished transfer reads 0x7F
+        let length = self.remaining_blocks.wrapping_sub(1) & 0x7F;
+
+        match self.mode {
+            Mode::Idle => 0x80 | length,
+            Mode::GeneralPurpose | Mode::HBlank => length,
+        }
+    }
+
+    fn start(&mut self, value: u8) {
+        let hblank = value & 0x80 != 0;
+
+        // Writing bit 7 = 0 while an HBlank transfer is running cancels it instead of starting
+        if !hblank && self.mode == Mode::HBlank {
+            self.mode = Mode::Idle;
+            return;
+        }
+
+        self.remaining_blocks = (value & 0x7F) + 1;
+        self.mode = if hblank {
+            Mode::HBlank
+        } else {
+            Mode::GeneralPurpose
+        };
+    }
+}
+
+impl MemoryMapped for Hdma {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            HDMA5_ADDRESS => self.status(),
+            // HDMA1-HDMA4 are write-only
+            _ => 0xFF,
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            HDMA1_ADDRESS => self.source = (self.source & 0x00FF) | ((value as u16) << 8),
+            // The lower 4 bits of both addresses are ignored
+            HDMA2_ADDRESS => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            // Only bits 0-4 of the destination's high byte are used, it always targets VRAM
+            HDMA3_ADDRESS => {
+                self.destination =
+                    VRAM_ADDRESS_START | (self.destination & 0x00F0) | ((value as u16 & 0x1F) << 8)
+            }
+            HDMA4_ADDRESS => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5_ADDRESS => self.start(value),
+            _ => panic!(
+                "HDMA is only addressable in range {:#06X}..={:#06X}, but was written at {:#06X}",
+                HDMA1_ADDRESS, HDMA5_ADDRESS, address
+            ),
+        }
+    }
+}
+
+impl SaveState for Hdma {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.source);
+        writer.write_u16(self.destination);
+        writer.write_u8(self.remaining_blocks);
+        writer.write_u8(match self.mode {
+            Mode::Idle => 0,
+            Mode::GeneralPurpose => 1,
+            Mode::HBlank => 2,
+        });
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.source = reader.read_u16()?;
+        self.destination = reader.read_u16()?;
+        self.remaining_blocks = reader.read_u8()?;
+        self.mode = match reader.read_u8()? {
+            0 => Mode::Idle,
+            1 => Mode::GeneralPurpose,
+            2 => Mode::HBlank,
+            value => {
+                return Err(SaveStateError::InvalidValue {
+                    field: "HDMA mode",
+                    value,
+                })
+            }
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hdma_with_addresses(source: u16, destination: u16) -> Hdma {
+        let mut hdma = Hdma::default();
+
+        hdma.write_byte(HDMA1_ADDRESS, (source >> 8) as u8);
+        hdma.write_byte(HDMA2_ADDRESS, source as u8);
+        hdma.write_byte(HDMA3_ADDRESS, (destination >> 8) as u8);
+        hdma.write_byte(HDMA4_ADDRESS, destination as u8);
+
+        hdma
+    }
+
+    #[test]
+    fn test_address_masking() {
+        let mut hdma = hdma_with_addresses(0xC12F, 0xFF3F);
+        hdma.write_byte(HDMA5_ADDRESS, 0x00);
+
+        // Lower nibbles are dropped and the destination is forced into VRAM
+        assert_eq!(
+            Some(Block {
+                source: 0xC120,
+                destination: 0x9F30
+            }),
+            hdma.next_block()
+        );
+    }
+
+    #[test]
+    fn test_general_purpose_blocks() {
+        let mut hdma = hdma_with_addresses(0x4000, 0x9FE0);
+        hdma.write_byte(HDMA5_ADDRESS, 0x02);
+        assert!(hdma.is_general_purpose());
+        assert_eq!(0x02, hdma.read_byte(HDMA5_ADDRESS));
+
+        assert_eq!(0x9FE0, hdma.next_block().unwrap().destination);
+        assert_eq!(0x9FF0, hdma.next_block().unwrap().destination);
+        // The destination wraps back to the start of VRAM
+        let last = hdma.next_block().unwrap();
+        assert_eq!(0x4020, last.source);
+        assert_eq!(0x8000, last.destination);
+
+        assert_eq!(None, hdma.next_block());
+        assert_eq!(0xFF, hdma.read_byte(HDMA5_ADDRESS));
+    }
+
+    #[test]
+    fn test_hblank_cancel() {
+        let mut hdma = hdma_with_addresses(0xC000, 0x8000);
+        hdma.write_byte(HDMA5_ADDRESS, 0x83);
+        assert!(hdma.is_hblank());
+        assert_eq!(0x03, hdma.read_byte(HDMA5_ADDRESS));
+
+        hdma.next_block();
+        assert_eq!(0x02, hdma.read_byte(HDMA5_ADDRESS));
+
+        // Cancelling reports the remaining length with bit 7 set
+        hdma.write_byte(HDMA5_ADDRESS, 0x00);
+        assert!(!hdma.is_hblank());
+        assert!(!hdma.is_general_purpose());
+        assert_eq!(0x82, hdma.read_byte(HDMA5_ADDRESS));
+        assert_eq!(None, hdma.next_block());
+    }
+
+    #[test]
+    fn test_block_cycles() {
+        assert_eq!(8, Hdma::block_cycles(Speed::Normal));
+        assert_eq!(16, Hdma::block_cycles(Speed::Double));
+    }
+}
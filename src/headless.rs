@@ -0,0 +1,172 @@
+use crate::bus::AccessKind;
+use crate::debugger::Breakpoint;
+use crate::error::EmulationError;
+use crate::screen;
+use crate::serial;
+use crate::sgb::super_game_boy::{BORDER_HEIGHT, BORDER_WIDTH};
+use crate::system::{System, CYCLES_PER_FRAME};
+use crate::watchpoint::Watchpoint;
+use std::io;
+
+/// What ends a headless run early, besides the CPU locking up
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Until {
+    /// Ran the instruction here
+    pub pc: Option<Breakpoint>,
+    /// The serial port has sent this, anywhere in its output
+    pub serial: Option<Vec<u8>>,
+}
+
+impl Until {
+    pub fn is_empty(&self) -> bool {
+        self.pc.is_none() && self.serial.is_none()
+    }
+}
+
+/// Why a headless run ended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Outcome {
+    ReachedPc,
+    ReceivedSerial,
+    /// Ran every frame without anything else ending the run
+    RanAllFrames,
+    /// The CPU hung on the illegal opcode at `pc`
+    LockedUp {
+        pc: u16,
+    },
+}
+
+impl Outcome {
+    /// Whether the run did what was asked: reached what it was run until, or else kept running
+    pub fn is_success(self, until: &Until) -> bool {
+        match self {
+            Outcome::ReachedPc | Outcome::ReceivedSerial => true,
+            Outcome::RanAllFrames => until.is_empty(),
+            Outcome::LockedUp { .. } => false,
+        }
+    }
+}
+
+/// Run for up to `frames` frames, stopping as soon as `until` is met
+/// Both are met once the instruction that reached them has finished, like watchpoints.
+pub fn run(system: &mut System, frames: u64, until: &Until) -> Result<Outcome, EmulationError> {
+    if until.serial.as_ref().is_some_and(|text| sent(system, text)) {
+        return Ok(Outcome::ReceivedSerial);
+    }
+
+    let watchpoints: Vec<Watchpoint> =
+        until
+            .pc
+            .iter()
+            .map(|breakpoint| Watchpoint {
+                bank: breakpoint.bank,
+                ..Watchpoint::new(AccessKind::Execute, breakpoint.address..=breakpoint.address)
+            })
+            .chain(until.serial.iter().map(|_| {
+                Watchpoint::new(AccessKind::Write, serial::SC_ADDRESS..=serial::SC_ADDRESS)
+            }))
+            .collect();
+
+    let stop = run_watching(system, frames, &watchpoints, |system| {
+        match system.watchpoint_hit() {
+            Some(hit)
+                if hit.kind == AccessKind::Execute
+                    && until.pc.is_some_and(|pc| pc.address == hit.address) =>
+            {
+                Some(Outcome::ReachedPc)
+            }
+            Some(_) if until.serial.as_ref().is_some_and(|text| sent(system, text)) => {
+                Some(Outcome::ReceivedSerial)
+            }
+            _ => None,
+        }
+    })?;
+
+    Ok(match stop {
+        Stop::Stopped(outcome) => outcome,
+        Stop::RanAllFrames => Outcome::RanAllFrames,
+        Stop::LockedUp { pc } => Outcome::LockedUp { pc },
+    })
+}
+
+/// Why `run_watching` ended
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stop<T> {
+    /// The check asked to stop, with what it found
+    Stopped(T),
+    RanAllFrames,
+    /// The CPU hung on the illegal opcode at `pc`
+    LockedUp {
+        pc: u16,
+    },
+}
+
+/// Run for up to `frames` frames with these watchpoints added, asking `check` whether to stop
+/// whenever a frame ends, or a watchpoint ends one early
+/// The watchpoints are removed again however the run ends.
+pub fn run_watching<T>(
+    system: &mut System,
+    frames: u64,
+    watchpoints: &[Watchpoint],
+    check: impl FnMut(&System) -> Option<T>,
+) -> Result<Stop<T>, EmulationError> {
+    for watchpoint in watchpoints {
+        system.bus_mut().add_watchpoint(watchpoint.clone());
+    }
+
+    let result = run_frames(system, frames, check);
+
+    for watchpoint in watchpoints {
+        system.bus_mut().remove_watchpoint(watchpoint);
+    }
+    result
+}
+
+fn run_frames<T>(
+    system: &mut System,
+    frames: u64,
+    mut check: impl FnMut(&System) -> Option<T>,
+) -> Result<Stop<T>, EmulationError> {
+    let last_frame = system.cycles() / CYCLES_PER_FRAME + frames;
+
+    while system.cycles() / CYCLES_PER_FRAME < last_frame {
+        system.run_frame()?;
+
+        if let Some(pc) = system.locked_up_at() {
+            return Ok(Stop::LockedUp { pc });
+        }
+        if let Some(found) = check(system) {
+            return Ok(Stop::Stopped(found));
+        }
+    }
+
+    Ok(Stop::RanAllFrames)
+}
+
+/// Save the screen as a PNG: the Super Game Boy's whole 256x224 picture with the border
+/// around it when one is connected, or else just the Game Boy's 160x144
+pub fn screenshot(system: &mut System, writer: impl io::Write) -> io::Result<()> {
+    let background = screen::background(&system.bus().vram);
+
+    match &mut system.bus_mut().sgb {
+        Some(sgb) => {
+            let pixels: Vec<[u8; 3]> = sgb
+                .render(&background)
+                .into_iter()
+                .map(|color| color.to_rgb())
+                .collect();
+            screen::to_rgb_png(&pixels, BORDER_WIDTH, BORDER_HEIGHT, writer)
+        }
+        None => screen::to_png(&background, writer),
+    }
+}
+
+fn sent(system: &System, text: &[u8]) -> bool {
+    text.is_empty()
+        || system
+            .bus()
+            .serial
+            .output()
+            .windows(text.len())
+            .any(|window| window == text)
+}
@@ -0,0 +1,92 @@
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
+
+pub const IF_ADDRESS: u16 = 0xFF0F;
+pub const IE_ADDRESS: u16 = 0xFFFF;
+
+/// Where each interrupt's handler starts, highest priority first: VBlank, STAT, timer, serial,
+/// and joypad, which are also the bits of IF and IE from bit 0 up
+pub const VECTORS: [u16; 5] = [0x0040, 0x0048, 0x0050, 0x0058, 0x0060];
+
+/// The bits of IF and IE that have interrupts
+const INTERRUPT_BITS: u8 = 0x1F;
+
+/// IF and IE - which interrupts have been requested, and which are enabled
+/// Nothing requests interrupts by itself yet, but a ROM can request them by writing IF.
+// TODO: Request VBlank, STAT, timer, serial, and joypad interrupts from their hardware
+#[derive(Default)]
+pub struct Interrupts {
+    requested: u8,
+    enabled: u8,
+}
+
+impl Interrupts {
+    /// The highest priority interrupt that is both requested and enabled, as an index into
+    /// `VECTORS`
+    pub fn pending(&self) -> Option<usize> {
+        let pending = self.requested & self.enabled & INTERRUPT_BITS;
+
+        (pending != 0).then(|| pending.trailing_zeros() as usize)
+    }
+
+    /// Clear the request for an interrupt as its handler is entered
+    pub fn acknowledge(&mut self, interrupt: usize) {
+        self.requested &= !(1 << interrupt);
+    }
+}
+
+impl MemoryMapped for Interrupts {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            // Unused bits read back as 1
+            IF_ADDRESS => self.requested | !INTERRUPT_BITS,
+            _ => self.enabled,
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            IF_ADDRESS => self.requested = value & INTERRUPT_BITS,
+            // All of IE can be written, even the bits without interrupts
+            _ => self.enabled = value,
+        }
+    }
+}
+
+impl SaveState for Interrupts {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.requested);
+        writer.write_u8(self.enabled);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.requested = reader.read_u8()? & INTERRUPT_BITS;
+        self.enabled = reader.read_u8()?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending() {
+        let mut interrupts = Interrupts::default();
+        assert_eq!(None, interrupts.pending());
+
+        // Requested but not enabled
+        interrupts.write_byte(IF_ADDRESS, 0x0C);
+        assert_eq!(0xEC, interrupts.read_byte(IF_ADDRESS));
+        assert_eq!(None, interrupts.pending());
+
+        // The timer beats serial
+        interrupts.write_byte(IE_ADDRESS, 0xFF);
+        assert_eq!(Some(2), interrupts.pending());
+
+        interrupts.acknowledge(2);
+        assert_eq!(Some(3), interrupts.pending());
+        assert_eq!(0xE8, interrupts.read_byte(IF_ADDRESS));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
+
+pub const JOYPAD_ADDRESS: u16 = 0xFF00;
+
+const SELECT_DIRECTIONS: u8 = 0x10;
+const SELECT_ACTIONS: u8 = 0x20;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Buttons {
+    pub right: bool,
+    pub left: bool,
+    pub up: bool,
+    pub down: bool,
+    pub a: bool,
+    pub b: bool,
+    pub select: bool,
+    pub start: bool,
+}
+
+impl Buttons {
+    /// One bit per button, directions in the low nibble and actions in the high nibble,
+    /// each in the order P1 reports them
+    pub fn to_bits(self) -> u8 {
+        Joypad::pack(self.right, self.left, self.up, self.down)
+            | Joypad::pack(self.a, self.b, self.select, self.start) << 4
+    }
+
+    pub fn from_bits(bits: u8) -> Buttons {
+        let pressed = |bit: u8| bits & (1 << bit) != 0;
+
+        Buttons {
+            right: pressed(0),
+            left: pressed(1),
+            up: pressed(2),
+            down: pressed(3),
+            a: pressed(4),
+            b: pressed(5),
+            select: pressed(6),
+            start: pressed(7),
+        }
+    }
+}
+
+/// P1 - the joypad register
+/// Bits 4 and 5 select the direction and action buttons respectively, and are active low
+/// Bits 0-3 read back the selected buttons, also active low
+pub struct Joypad {
+    pub buttons: Buttons,
+    select: u8,
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Joypad {
+            buttons: Buttons::default(),
+            select: SELECT_DIRECTIONS | SELECT_ACTIONS,
+        }
+    }
+}
+
+impl Joypad {
+    /// True when neither button group is selected
+    pub fn is_deselected(&self) -> bool {
+        self.select == SELECT_DIRECTIONS | SELECT_ACTIONS
+    }
+
+    fn pressed_bits(&self) -> u8 {
+        let mut pressed = 0;
+
+        if self.select & SELECT_DIRECTIONS == 0 {
+            pressed |= Joypad::pack(
+                self.buttons.right,
+                self.buttons.left,
+                self.buttons.up,
+                self.buttons.down,
+            );
+        }
+
+        if self.select & SELECT_ACTIONS == 0 {
+            pressed |= Joypad::pack(
+                self.buttons.a,
+                self.buttons.b,
+                self.buttons.select,
+                self.buttons.start,
+            );
+        }
+
+        pressed
+    }
+
+    fn pack(bit0: bool, bit1: bool, bit2: bool, bit3: bool) -> u8 {
+        bit0 as u8 | (bit1 as u8) << 1 | (bit2 as u8) << 2 | (bit3 as u8) << 3
+    }
+}
+
+impl MemoryMapped for Joypad {
+    fn read_byte(&self, _address: u16) -> u8 {
+        // The top two bits are unused and read back as 1
+        0xC0 | self.select | (!self.pressed_bits() & 0x0F)
+    }
+
+    fn write_byte(&mut self, _address: u16, value: u8) {
+        self.select = value & (SELECT_DIRECTIONS | SELECT_ACTIONS);
+    }
+}
+
+impl SaveState for Joypad {
+    fn save_state(&self, writer: &mut StateWriter) {
+        let buttons = self.buttons;
+        for pressed in [
+            buttons.right,
+            buttons.left,
+            buttons.up,
+            buttons.down,
+            buttons.a,
+            buttons.b,
+            buttons.select,
+            buttons.start,
+        ] {
+            writer.write_bool(pressed);
+        }
+        writer.write_u8(self.select);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        let buttons = &mut self.buttons;
+        for pressed in [
+            &mut buttons.right,
+            &mut buttons.left,
+            &mut buttons.up,
+            &mut buttons.down,
+            &mut buttons.a,
+            &mut buttons.b,
+            &mut buttons.select,
+            &mut buttons.start,
+        ] {
+            *pressed = reader.read_bool()?;
+        }
+        self.select = reader.read_u8()? & (SELECT_DIRECTIONS | SELECT_ACTIONS);
+
+        Ok(())
+    }
+}
@@ -1,6 +1,30 @@
+pub mod assembler;
 pub mod bus;
 pub mod cartridge;
+pub mod code_data_log;
+pub mod conformance;
 pub mod cpu;
+pub mod debugger;
+pub mod disassembler;
+pub mod error;
+pub mod gdb;
+pub mod hdma;
+pub mod headless;
+pub mod interrupts;
+pub mod joypad;
 pub mod memory;
+pub mod movie;
+pub mod opcodes;
+pub mod profiler;
 pub mod ram;
+pub mod rewind;
+pub mod rtc;
+pub mod save_state;
+pub mod screen;
+pub mod serial;
+pub mod sgb;
+pub mod speed;
+pub mod symbols;
 pub mod system;
+pub mod trace;
+pub mod watchpoint;
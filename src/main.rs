@@ -1,36 +1,607 @@
-use clap::{Parser, Subcommand};
+use clap::{ArgEnum, Args, Parser, Subcommand};
+use gameboy_dot_rs::cartridge::cgb_support::CgbSupport;
+use gameboy_dot_rs::cartridge::fix::Fix;
 use gameboy_dot_rs::cartridge::header;
-use gameboy_dot_rs::cartridge::parse::Parse;
-use std::io::Read;
-use std::{error, fs, io};
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::cartridge::title::TitleEncoding;
+use gameboy_dot_rs::code_data_log::{CodeDataLog, CodeDataLogger};
+use gameboy_dot_rs::conformance::{self, Verdict};
+use gameboy_dot_rs::cpu::Cpu;
+use gameboy_dot_rs::debugger::{Breakpoint, Command, Debugger};
+use gameboy_dot_rs::disassembler::{self, ENTRY_POINTS};
+use gameboy_dot_rs::error::EmulationError;
+use gameboy_dot_rs::gdb::GdbStub;
+use gameboy_dot_rs::headless::{self, Outcome, Until};
+use gameboy_dot_rs::movie::Movie;
+use gameboy_dot_rs::profiler::Profiler;
+use gameboy_dot_rs::screen;
+use gameboy_dot_rs::symbols::Symbols;
+use gameboy_dot_rs::system::{FaultPolicy, Gas, Model, System};
+use gameboy_dot_rs::trace::{self, TraceFormat, TraceWriter, Tracer};
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
+use std::io::{BufRead, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::{error, fs, io, process};
+
+/// Extensions of the files picked up when a directory is given to the header command
+const ROM_EXTENSIONS: [&str; 3] = ["gb", "gbc", "sgb"];
 
 fn main() -> Result<(), Box<dyn error::Error>> {
     let cli: Cli = Cli::parse();
 
     match cli.commands {
-        Commands::Header { mut file } => header_command(&mut file),
-    }?;
+        Commands::Header {
+            paths,
+            format,
+            title_encoding,
+        } => {
+            let failures = header_command(&paths, &format, title_encoding.into())?;
+            if failures > 0 {
+                process::exit(1);
+            }
+        }
+        Commands::Fix {
+            file,
+            output,
+            title,
+            cartridge_type,
+            rom_size,
+            ram_size,
+            cgb,
+            sgb,
+            pad,
+        } => {
+            let fix = Fix {
+                title,
+                cartridge_type,
+                rom_size_code: rom_size,
+                ram_size_code: ram_size,
+                cgb_support: cgb.map(CgbSupport::from),
+                sgb: sgb.then_some(true),
+                pad,
+            };
+            fix_command(&file, output.as_ref().unwrap_or(&file), &fix)?
+        }
+        Commands::Run {
+            rom,
+            frames,
+            until_pc,
+            until_serial,
+            screenshot,
+            faults,
+            symbols,
+            model,
+        } => {
+            let until = Until {
+                pc: match until_pc {
+                    Some(text) => {
+                        Some(Breakpoint::parse_with_symbols(&text, &symbols.load(&rom)?)?)
+                    }
+                    None => None,
+                },
+                serial: until_serial.map(String::into_bytes),
+            };
+            let passed = run_command(
+                &rom,
+                frames,
+                &until,
+                screenshot.as_deref(),
+                &faults,
+                model.map(Model::from),
+            )?;
+            if !passed {
+                process::exit(1);
+            }
+        }
+        Commands::Conformance { paths, frames } => {
+            if !conformance_command(&paths, frames)? {
+                process::exit(1);
+            }
+        }
+        Commands::Play { rom, movie, cdl } => play_command(&rom, &movie, cdl.as_deref())?,
+        Commands::Debug {
+            rom,
+            faults,
+            symbols,
+        } => debug_command(&rom, &faults, &symbols)?,
+        Commands::Gdb {
+            rom,
+            address,
+            faults,
+        } => gdb_command(&rom, &address, &faults)?,
+        Commands::Disasm {
+            rom,
+            follow,
+            bank,
+            symbols,
+        } => disasm_command(&rom, follow, bank, &symbols)?,
+        Commands::Trace {
+            rom,
+            output,
+            instructions,
+            format,
+            symbols,
+        } => trace_command(&rom, output.as_deref(), instructions, format, &symbols)?,
+        Commands::Profile {
+            rom,
+            frames,
+            report,
+            output,
+            symbols,
+        } => profile_command(&rom, frames, report, output.as_deref(), &symbols)?,
+        Commands::TraceDiff { left, right } => {
+            if !trace_diff_command(&left, &right)? {
+                process::exit(1);
+            }
+        }
+    };
+
+    Ok(())
+}
+
+/// Print the header of every ROM given, returning how many of them failed to parse
+fn header_command(
+    paths: &[PathBuf],
+    format: &Format,
+    title_encoding: TitleEncoding,
+) -> io::Result<usize> {
+    let rom_paths = collect_rom_paths(paths)?;
+    let batch = rom_paths.len() > 1 || paths.iter().any(|path| path.is_dir());
+
+    let mut failures = 0;
+    for path in rom_paths {
+        let record = HeaderRecord::read(&path, title_encoding);
+        if record.result.is_err() {
+            failures += 1;
+        }
+
+        record.print(format, batch);
+    }
+
+    Ok(failures)
+}
+
+/// Expand any directories into the ROMs inside them, recursively and in a stable order
+fn collect_rom_paths(paths: &[PathBuf]) -> io::Result<Vec<PathBuf>> {
+    let mut rom_paths = Vec::new();
+
+    for path in paths {
+        if !path.is_dir() {
+            rom_paths.push(path.clone());
+            continue;
+        }
+
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+
+        for entry in entries {
+            if entry.is_dir() {
+                rom_paths.extend(collect_rom_paths(&[entry])?);
+            } else if has_rom_extension(&entry) {
+                rom_paths.push(entry);
+            }
+        }
+    }
+
+    Ok(rom_paths)
+}
+
+fn has_rom_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| ROM_EXTENSIONS.contains(&extension.to_ascii_lowercase().as_str()))
+        .unwrap_or(false)
+}
+
+/// One record of the header command's output, for a single ROM
+struct HeaderRecord {
+    path: String,
+    result: Result<header::Header, String>,
+}
+
+/// Serialized as the path and then either an `error` message or a `header` table
+impl Serialize for HeaderRecord {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut record = serializer.serialize_struct("HeaderRecord", 2)?;
+        record.serialize_field("path", &self.path)?;
+        // TOML needs tables after plain values, so the header stays last
+        match &self.result {
+            Ok(header) => record.serialize_field("header", header)?,
+            Err(message) => record.serialize_field("error", message)?,
+        }
+
+        record.end()
+    }
+}
+
+/// TOML has no top level arrays, so each record is printed as a [[rom]] table
+#[derive(Serialize)]
+struct TomlRecord<'a> {
+    rom: [&'a HeaderRecord; 1],
+}
+
+impl HeaderRecord {
+    fn read(path: &Path, title_encoding: TitleEncoding) -> HeaderRecord {
+        let result = fs::read(path)
+            .map_err(|e| e.to_string())
+            .and_then(|rom_bytes| {
+                header::Header::parse_rom_with_encoding(&rom_bytes, title_encoding)
+                    .map_err(|e| e.to_string())
+            });
+
+        HeaderRecord {
+            path: path.display().to_string(),
+            result,
+        }
+    }
+
+    fn print(&self, format: &Format, batch: bool) {
+        match format {
+            Format::Text => {
+                let prefix = if batch {
+                    format!("{}: ", self.path)
+                } else {
+                    String::new()
+                };
+
+                match &self.result {
+                    Ok(header) => {
+                        for line in header.to_text().lines() {
+                            println!("{}{}", prefix, line);
+                        }
+                    }
+                    Err(message) => eprintln!("{}ROM has an invalid header: {}", prefix, message),
+                }
+            }
+            Format::Json => {
+                println!(
+                    "{}",
+                    serde_json::to_string(self).expect("records serialize to JSON")
+                );
+            }
+            Format::Toml => {
+                let record = TomlRecord { rom: [self] };
+                println!(
+                    "{}",
+                    toml::to_string(&record).expect("records serialize to TOML")
+                );
+            }
+        }
+    }
+}
+
+fn fix_command(input: &Path, output: &Path, fix: &Fix) -> Result<(), Box<dyn error::Error>> {
+    let mut rom_bytes = fs::read(input)?;
+
+    let header = fix.apply(&mut rom_bytes)?;
+    fs::write(output, &rom_bytes)?;
+
+    println!("{}", header.to_text());
+
+    Ok(())
+}
+
+/// Run a ROM without any video or audio, returning whether it got where it should
+/// Without anything to run until, that means running every frame without locking up
+fn run_command(
+    rom: &Path,
+    frames: u64,
+    until: &Until,
+    screenshot: Option<&Path>,
+    faults: &FaultArgs,
+    model: Option<Model>,
+) -> Result<bool, Box<dyn error::Error>> {
+    let bytes = fs::read(rom)?;
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&bytes)?);
+    system.set_model(match model {
+        Some(model) => model,
+        None if header::Header::parse_rom(&bytes)?.sgb => Model::Sgb,
+        None => Model::Dmg,
+    });
+    system.set_fault_policy(faults.policy());
+
+    let result = headless::run(&mut system, frames, until);
+    let outcome = save_then_report(result, || {
+        let serial = system.bus().serial.output();
+        if !serial.is_empty() {
+            println!("{}", String::from_utf8_lossy(serial));
+        }
+        if let Some(path) = screenshot {
+            headless::screenshot(&mut system, io::BufWriter::new(fs::File::create(path)?))?;
+        }
+        Ok(())
+    })?;
+    match outcome {
+        Outcome::ReachedPc => println!("Reached PC after {} cycles", system.cycles()),
+        Outcome::ReceivedSerial => {
+            println!("Received serial text after {} cycles", system.cycles())
+        }
+        Outcome::RanAllFrames => println!("Ran {} frames", frames),
+        Outcome::LockedUp { pc } => println!("Locked up at PC {:04X}", pc),
+    }
+
+    Ok(outcome.is_success(until))
+}
+
+/// Run every test ROM given, printing a row for each, and return whether they all passed
+/// A ROM with a PNG of the same name beside it is judged by its screen against that image
+fn conformance_command(paths: &[PathBuf], frames: u64) -> io::Result<bool> {
+    let rom_paths = collect_rom_paths(paths)?;
+    let width = rom_paths
+        .iter()
+        .map(|path| path.display().to_string().len())
+        .max()
+        .unwrap_or_default();
+
+    let mut passes = 0;
+    for path in &rom_paths {
+        let result = conformance_test(path, frames);
+        let passed = result.as_ref().is_ok_and(Verdict::is_pass);
+        if passed {
+            passes += 1;
+        }
+
+        let detail = match result {
+            Ok(verdict) => verdict.to_string(),
+            Err(error) => format!("error: {}", error),
+        };
+        println!(
+            "{}  {:<width$}  {}",
+            if passed { "PASS" } else { "FAIL" },
+            path.display(),
+            detail,
+            width = width
+        );
+    }
+    println!("{} of {} passed", passes, rom_paths.len());
+
+    Ok(passes == rom_paths.len())
+}
+
+fn conformance_test(rom: &Path, frames: u64) -> Result<Verdict, Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+
+    let reference_path = rom.with_extension("png");
+    let reference = match reference_path.exists() {
+        true => Some(screen::from_png(fs::File::open(reference_path)?)?),
+        false => None,
+    };
+
+    Ok(conformance::run_test(
+        &mut system,
+        frames,
+        reference.as_deref(),
+    )?)
+}
+
+/// Replay a movie without any video or audio, failing if it desyncs
+fn play_command(rom: &Path, movie: &Path, cdl: Option<&Path>) -> Result<(), Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+    let movie = Movie::from_bytes(&fs::read(movie)?)?;
+
+    let logger = cdl.map(|_| CodeDataLogger::attach(&mut system));
+    movie.play(&mut system)?;
+    println!("Played {} frames in sync", movie.frames.len());
+
+    if let (Some(path), Some(logger)) = (cdl, logger) {
+        save_code_data_log(path, logger.detach(&mut system))?;
+    }
+
+    Ok(())
+}
+
+/// Write a code/data log as JSON if the file name ends in `.json`, or else as one byte per ROM
+/// byte, merged with what the file already holds so several runs add up
+fn save_code_data_log(path: &Path, mut log: CodeDataLog) -> Result<(), Box<dyn error::Error>> {
+    if path
+        .extension()
+        .is_some_and(|extension| extension == "json")
+    {
+        fs::write(path, log.to_json())?;
+    } else {
+        if path.exists() {
+            log.merge(&CodeDataLog::from_bytes(&fs::read(path)?));
+        }
+        fs::write(path, log.to_bytes())?;
+    }
+
+    println!("Logged {} of {} ROM bytes", log.used(), log.len());
+    Ok(())
+}
+
+/// Serve one GDB session for a ROM
+fn gdb_command(rom: &Path, address: &str, faults: &FaultArgs) -> Result<(), Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+    system.set_fault_policy(faults.policy());
+
+    let listener = TcpListener::bind(address)?;
+    println!("Waiting for GDB on {}", listener.local_addr()?);
+    let (stream, peer) = listener.accept()?;
+    println!("GDB connected from {}", peer);
 
+    GdbStub::new(system).serve(stream)?;
     Ok(())
 }
 
-fn header_command(file: &mut fs::File) -> io::Result<()> {
-    let mut rom_bytes = Vec::new();
-    file.read_to_end(&mut rom_bytes)?;
+/// Read debugger commands from stdin until quit or end of input
+fn debug_command(
+    rom: &Path,
+    faults: &FaultArgs,
+    symbols: &SymbolsArgs,
+) -> Result<(), Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+    system.set_fault_policy(faults.policy());
 
-    let header = header::Header::parse(&rom_bytes[0x100..]);
-    match header {
-        Ok(header) => {
-            println!("{:?}", header);
+    let mut debugger = Debugger::new(system);
+    debugger.set_symbols(symbols.load(rom)?);
+    println!("{}", debugger.execute(&Command::Registers));
+
+    let stdin = io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("(gb) ");
+        io::stdout().flush()?;
+
+        let line = match lines.next() {
+            Some(line) => line?,
+            None => break,
+        };
+        if line.trim().is_empty() {
+            continue;
         }
-        Err(message) => {
-            eprintln!("ROM has an invalid header: {}", message);
+
+        match Command::parse_with_symbols(&line, debugger.symbols()) {
+            Ok(Command::Quit) => break,
+            Ok(command) => println!("{}", debugger.execute(&command)),
+            Err(message) => println!("{}", message),
         }
     }
 
     Ok(())
 }
 
+/// Print a ROM's disassembly, optionally only the code reachable from its entry points
+fn disasm_command(
+    rom: &Path,
+    follow: bool,
+    bank: Option<usize>,
+    symbols: &SymbolsArgs,
+) -> Result<(), Box<dyn error::Error>> {
+    let rom_bytes = fs::read(rom)?;
+    let symbols = symbols.load(rom)?;
+
+    let lines = if follow {
+        disassembler::disassemble_recursive_with_symbols(&rom_bytes, &ENTRY_POINTS, &symbols)
+    } else {
+        disassembler::disassemble_linear_with_symbols(&rom_bytes, &symbols)
+    };
+
+    let stdout = io::stdout();
+    let mut stdout = stdout.lock();
+    for line in lines
+        .iter()
+        .filter(|line| bank.is_none_or(|bank| line.bank == bank))
+    {
+        writeln!(stdout, "{}", line)?;
+    }
+
+    Ok(())
+}
+
+/// Run a ROM for a number of instructions, tracing each one
+fn trace_command(
+    rom: &Path,
+    output: Option<&Path>,
+    instructions: usize,
+    format: TraceFormat,
+    symbols: &SymbolsArgs,
+) -> Result<(), Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+    // So the first line matches reference traces, which start where the boot ROM leaves off
+    *system.cpu_mut() = Cpu::after_boot();
+
+    let writer: Box<dyn Write> = match output {
+        Some(path) => Box::new(io::BufWriter::new(fs::File::create(path)?)),
+        None => Box::new(io::BufWriter::new(io::stdout())),
+    };
+    system
+        .set_tracer(Tracer::new(format, TraceWriter::new(writer)).with_symbols(symbols.load(rom)?));
+
+    let result = system.run_with_gas(Gas::LIMITED(instructions));
+    save_then_report(result, || {
+        if let Some(tracer) = system.take_tracer() {
+            tracer.finish()?;
+        }
+        Ok(())
+    })
+}
+
+/// Save what a run produced, and only then report the fault that ended it, if any
+/// Whatever a run produced up to a fault is still worth having.
+fn save_then_report<T>(
+    result: Result<T, EmulationError>,
+    save: impl FnOnce() -> Result<(), Box<dyn error::Error>>,
+) -> Result<T, Box<dyn error::Error>> {
+    save()?;
+    Ok(result?)
+}
+
+/// Profile a ROM for a number of frames, or until it locks up
+fn profile_command(
+    rom: &Path,
+    frames: u64,
+    report: Report,
+    output: Option<&Path>,
+    symbols: &SymbolsArgs,
+) -> Result<(), Box<dyn error::Error>> {
+    let cartridge = Mbc1::from_bytes(&fs::read(rom)?)?;
+    let mut system = System::load_cartridge(cartridge);
+    system.set_profiler(Profiler::new().with_symbols(symbols.load(rom)?));
+
+    let mut result = Ok(());
+    for _ in 0..frames {
+        result = system.run_frame();
+        if result.is_err() || system.is_locked_up() {
+            break;
+        }
+    }
+
+    save_then_report(result, || {
+        let profiler = system.take_profiler().expect("the profiler was set above");
+        let text = match report {
+            Report::Flat => profiler.flat_report(),
+            Report::Tree => profiler.tree_report(),
+            Report::Collapsed => profiler.collapsed_stacks(),
+        };
+        match output {
+            Some(path) => fs::write(path, text)?,
+            None => print!("{}", text),
+        }
+        Ok(())
+    })
+}
+
+/// Report where two traces first differ, returning whether they are the same
+fn trace_diff_command(left: &Path, right: &Path) -> Result<bool, Box<dyn error::Error>> {
+    let divergence = trace::first_divergence(
+        io::BufReader::new(fs::File::open(left)?),
+        io::BufReader::new(fs::File::open(right)?),
+    )?;
+
+    let divergence = match divergence {
+        Some(divergence) => divergence,
+        None => {
+            println!("Traces are identical");
+            return Ok(true);
+        }
+    };
+
+    println!("Traces diverge at line {}", divergence.line_number);
+    if let Some(previous) = &divergence.previous {
+        println!("  both: {}", previous);
+    }
+    for (path, line) in [(left, &divergence.left), (right, &divergence.right)] {
+        match line {
+            Some(line) => println!("  {}: {}", path.display(), line),
+            None => println!("  {}: <end of trace>", path.display()),
+        }
+    }
+    for (name, left_value, right_value) in divergence.differing_fields() {
+        println!("  {} differs: {} vs {}", name, left_value, right_value);
+    }
+
+    Ok(false)
+}
+
 #[derive(Parser)]
 #[clap(author = "Austin Bourgerie", about = "A GameBoy emulator in Rust")]
 struct Cli {
@@ -42,11 +613,350 @@ struct Cli {
 enum Commands {
     #[clap(about = "Parse, validate, and display the header without running")]
     Header {
-        #[clap(parse(try_from_str = open_file))]
-        file: fs::File,
+        #[clap(
+            required = true,
+            parse(from_os_str),
+            help = "ROM files, or directories to search for .gb, .gbc, and .sgb files"
+        )]
+        paths: Vec<PathBuf>,
+        #[clap(
+            short,
+            long,
+            arg_enum,
+            default_value = "text",
+            help = "JSON prints one object per line, and TOML one [[rom]] table per ROM"
+        )]
+        format: Format,
+        #[clap(
+            long,
+            arg_enum,
+            default_value = "ascii",
+            help = "Shift-JIS also decodes half-width katakana in titles"
+        )]
+        title_encoding: Encoding,
+    },
+    #[clap(
+        about = "Rewrite the logo and checksums of a ROM's header, optionally setting other fields"
+    )]
+    Fix {
+        #[clap(parse(from_os_str))]
+        file: PathBuf,
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            help = "Write the fixed ROM here instead of overwriting FILE"
+        )]
+        output: Option<PathBuf>,
+        #[clap(short, long)]
+        title: Option<String>,
+        #[clap(short = 'm', long, parse(try_from_str = parse_byte))]
+        cartridge_type: Option<u8>,
+        #[clap(long, parse(try_from_str = parse_byte), help = "ROM size code")]
+        rom_size: Option<u8>,
+        #[clap(short, long, parse(try_from_str = parse_byte), help = "RAM size code")]
+        ram_size: Option<u8>,
+        #[clap(short, long, arg_enum)]
+        cgb: Option<CgbFlag>,
+        #[clap(short, long, help = "Enable SGB support")]
+        sgb: bool,
+        #[clap(
+            short,
+            long,
+            parse(try_from_str = parse_byte),
+            help = "Pad the ROM to a valid size with this byte"
+        )]
+        pad: Option<u8>,
+    },
+    #[clap(about = "Run a ROM headlessly, exiting with 1 unless it gets where it should")]
+    Run {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "600",
+            help = "The most frames to run"
+        )]
+        frames: u64,
+        #[clap(
+            long,
+            value_name = "ADDRESS",
+            help = "Stop once the instruction at this address, BANK:ADDRESS, or label has run"
+        )]
+        until_pc: Option<String>,
+        #[clap(
+            long,
+            value_name = "TEXT",
+            help = "Stop once the ROM has sent this text over the serial port"
+        )]
+        until_serial: Option<String>,
+        #[clap(
+            short = 'o',
+            long,
+            parse(from_os_str),
+            help = "Save the last frame here as a PNG, with the border on a Super Game Boy"
+        )]
+        screenshot: Option<PathBuf>,
+        #[clap(flatten)]
+        faults: FaultArgs,
+        #[clap(flatten)]
+        symbols: SymbolsArgs,
+        #[clap(
+            long,
+            arg_enum,
+            help = "The console to run in, by default a Super Game Boy if the header asks for one"
+        )]
+        model: Option<Console>,
+    },
+    #[clap(
+        about = "Run test ROMs from suites like Blargg's, Mooneye's and acid2, tabling which pass"
+    )]
+    Conformance {
+        #[clap(
+            required = true,
+            parse(from_os_str),
+            help = "ROM files, or directories to search for .gb, .gbc, and .sgb files"
+        )]
+        paths: Vec<PathBuf>,
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "3600",
+            help = "The most frames to run each ROM for"
+        )]
+        frames: u64,
+    },
+    #[clap(about = "Replay a recorded movie headlessly, checking it stays in sync")]
+    Play {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(parse(from_os_str))]
+        movie: PathBuf,
+        #[clap(
+            long,
+            parse(from_os_str),
+            help = "Log which ROM bytes are code, data, or DMA sources to this file, as JSON if it ends in .json"
+        )]
+        cdl: Option<PathBuf>,
+    },
+    #[clap(about = "Step through a ROM interactively, with breakpoints and memory editing")]
+    Debug {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(flatten)]
+        faults: FaultArgs,
+        #[clap(flatten)]
+        symbols: SymbolsArgs,
+    },
+    #[clap(about = "Serve a ROM to GDB over its remote serial protocol")]
+    Gdb {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(
+            short,
+            long,
+            default_value = "127.0.0.1:2331",
+            help = "Where to listen for GDB to connect"
+        )]
+        address: String,
+        #[clap(flatten)]
+        faults: FaultArgs,
+    },
+    #[clap(about = "Disassemble a ROM bank by bank")]
+    Disasm {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(
+            short,
+            long,
+            help = "Only decode code reachable from the entry point and interrupt vectors, showing the rest as data"
+        )]
+        follow: bool,
+        #[clap(short, long, help = "Only show this bank")]
+        bank: Option<usize>,
+        #[clap(flatten)]
+        symbols: SymbolsArgs,
+    },
+    #[clap(about = "Run a ROM, logging the CPU state before every instruction")]
+    Trace {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            help = "Write the trace here instead of stdout"
+        )]
+        output: Option<PathBuf>,
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "100000",
+            help = "How many instructions to run"
+        )]
+        instructions: usize,
+        #[clap(
+            short,
+            long,
+            parse(try_from_str = TraceFormat::parse),
+            default_value = trace::DOCTOR_FORMAT,
+            help = "A template of {FIELD}s: A F B C D E H L SP PC PCMEM BANK CYCLES INSTRUCTION SYMBOL"
+        )]
+        format: TraceFormat,
+        #[clap(flatten)]
+        symbols: SymbolsArgs,
+    },
+    #[clap(about = "Find the first line where two traces differ")]
+    TraceDiff {
+        #[clap(parse(from_os_str))]
+        left: PathBuf,
+        #[clap(parse(from_os_str))]
+        right: PathBuf,
+    },
+    #[clap(about = "Run a ROM, reporting where the machine cycles went")]
+    Profile {
+        #[clap(parse(from_os_str))]
+        rom: PathBuf,
+        #[clap(
+            short = 'n',
+            long,
+            default_value = "60",
+            help = "How many frames to run"
+        )]
+        frames: u64,
+        #[clap(
+            short,
+            long,
+            arg_enum,
+            default_value = "flat",
+            help = "Routines and instructions by cycles, the call tree, or collapsed stacks for flamegraphs"
+        )]
+        report: Report,
+        #[clap(
+            short,
+            long,
+            parse(from_os_str),
+            help = "Write the report here instead of stdout"
+        )]
+        output: Option<PathBuf>,
+        #[clap(flatten)]
+        symbols: SymbolsArgs,
     },
 }
 
-fn open_file(path: &str) -> Result<fs::File, String> {
-    fs::File::open(path).map_err(|e| String::from(e.to_string()))
+#[derive(Args)]
+struct SymbolsArgs {
+    #[clap(
+        short,
+        long,
+        parse(from_os_str),
+        help = "An RGBDS symbol file, by default the ROM's own .sym file if there is one"
+    )]
+    symbols: Option<PathBuf>,
+}
+
+impl SymbolsArgs {
+    /// Symbols from the given file, or else from the ROM's `.sym` file, as `rgblink -n` names it
+    fn load(&self, rom: &Path) -> Result<Symbols, Box<dyn error::Error>> {
+        let path = match &self.symbols {
+            Some(path) => path.clone(),
+            None => {
+                let path = rom.with_extension("sym");
+                if !path.exists() {
+                    return Ok(Symbols::default());
+                }
+                path
+            }
+        };
+
+        Ok(Symbols::parse(&fs::read_to_string(path)?)?)
+    }
+}
+
+#[derive(Args)]
+struct FaultArgs {
+    #[clap(
+        long,
+        help = "Stop on illegal opcodes and unmapped accesses, instead of carrying on like hardware"
+    )]
+    strict: bool,
+}
+
+impl FaultArgs {
+    fn policy(&self) -> FaultPolicy {
+        match self.strict {
+            true => FaultPolicy::Strict,
+            false => FaultPolicy::Hardware,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum Report {
+    Flat,
+    Tree,
+    Collapsed,
+}
+
+#[derive(ArgEnum, Clone)]
+enum Format {
+    Text,
+    Json,
+    Toml,
+}
+
+#[derive(ArgEnum, Clone)]
+enum Encoding {
+    Ascii,
+    ShiftJis,
+}
+
+impl From<Encoding> for TitleEncoding {
+    fn from(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Ascii => TitleEncoding::Ascii,
+            Encoding::ShiftJis => TitleEncoding::ShiftJis,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone, Copy)]
+enum Console {
+    Dmg,
+    Sgb,
+}
+
+impl From<Console> for Model {
+    fn from(console: Console) -> Self {
+        match console {
+            Console::Dmg => Model::Dmg,
+            Console::Sgb => Model::Sgb,
+        }
+    }
+}
+
+#[derive(ArgEnum, Clone)]
+enum CgbFlag {
+    Enhanced,
+    Required,
+}
+
+impl From<CgbFlag> for CgbSupport {
+    fn from(flag: CgbFlag) -> Self {
+        match flag {
+            CgbFlag::Enhanced => CgbSupport::Enhanced,
+            CgbFlag::Required => CgbSupport::Required,
+        }
+    }
+}
+
+/// Accepts decimal, or hex with a 0x or $ prefix
+fn parse_byte(value: &str) -> Result<u8, String> {
+    let parsed = match value.strip_prefix("0x").or_else(|| value.strip_prefix('$')) {
+        Some(hex) => u8::from_str_radix(hex, 16),
+        None => value.parse(),
+    };
+
+    parsed.map_err(|e| format!("{:?} isn't a byte: {}", value, e))
 }
@@ -1,3 +1,6 @@
+/// What reads see when nothing drives the data bus
+pub const OPEN_BUS_VALUE: u8 = 0xFF;
+
 pub trait MemoryMapped {
     fn read_byte(&self, address: u16) -> u8;
 
@@ -0,0 +1,366 @@
+use crate::error::EmulationError;
+use crate::joypad::Buttons;
+use crate::rtc::EmulatedClock;
+use crate::save_state::{self, SaveStateError, StateReader, StateWriter};
+use crate::system::{Model, System};
+use std::{error, fmt};
+
+const MAGIC: [u8; 4] = *b"GBRM";
+
+/// Bump this whenever the movie format changes
+pub const VERSION: u16 = 1;
+
+/// Frames between state hashes, unless the recorder is told otherwise
+pub const DEFAULT_HASH_INTERVAL: u32 = 60;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MovieError {
+    /// Not a movie at all
+    BadMagic,
+    UnsupportedVersion {
+        version: u16,
+    },
+    /// The movie was recorded while running a different ROM
+    WrongRom {
+        actual: u16,
+        expected: u16,
+    },
+    /// A value that no recorder could have written
+    InvalidValue {
+        field: &'static str,
+        value: u8,
+    },
+    /// The movie is truncated, or its starting state can't be loaded
+    State(SaveStateError),
+    /// The system faulted during playback
+    Emulation(EmulationError),
+    /// Playback stopped matching the recording
+    Desync {
+        frame: usize,
+        actual: u32,
+        expected: u32,
+    },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::BadMagic => write!(f, "not a movie"),
+            MovieError::UnsupportedVersion { version } => write!(
+                f,
+                "movie version {} is unsupported, only {} can be played",
+                version, VERSION
+            ),
+            MovieError::WrongRom { actual, expected } => write!(
+                f,
+                "movie is for a ROM with checksum {:#06X}, but this ROM's is {:#06X}",
+                expected, actual
+            ),
+            MovieError::InvalidValue { field, value } => {
+                write!(f, "invalid {} {:#04X} in movie", field, value)
+            }
+            MovieError::State(error) => write!(f, "{}", error),
+            MovieError::Emulation(error) => write!(f, "{}", error),
+            MovieError::Desync {
+                frame,
+                actual,
+                expected,
+            } => write!(
+                f,
+                "desynced by frame {}, state hash is {:#010X} but was recorded as {:#010X}",
+                frame, actual, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for MovieError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            MovieError::State(error) => Some(error),
+            MovieError::Emulation(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<SaveStateError> for MovieError {
+    fn from(error: SaveStateError) -> Self {
+        MovieError::State(error)
+    }
+}
+
+impl From<EmulationError> for MovieError {
+    fn from(error: EmulationError) -> Self {
+        MovieError::Emulation(error)
+    }
+}
+
+pub type MovieResult<T> = Result<T, MovieError>;
+
+/// Joypad input for every frame, and everything needed to replay it exactly
+///
+/// Playback starts from the recorded save state with an emulated real time clock, so nothing
+/// outside the movie can change how it plays. Hashes of the state taken while recording catch
+/// playback going a different way, say after a change to the emulator.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Movie {
+    pub rom_checksum: u16,
+    pub model: Model,
+    /// The time the real time clock starts at, in seconds since the Unix epoch
+    pub rtc_start: u64,
+    pub start_state: Vec<u8>,
+    pub hash_interval: u32,
+    pub frames: Vec<Buttons>,
+    /// The state hash after every `hash_interval` frames
+    pub hashes: Vec<u32>,
+}
+
+impl Movie {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = StateWriter::default();
+        writer.write_bytes(&MAGIC);
+        writer.write_u16(VERSION);
+        writer.write_u16(self.rom_checksum);
+        writer.write_u8(match self.model {
+            Model::Dmg => 0,
+            Model::Sgb => 1,
+        });
+        writer.write_u64(self.rtc_start);
+        writer.write_sized_bytes(&self.start_state);
+        writer.write_u32(self.hash_interval);
+
+        writer.write_u32(self.frames.len() as u32);
+        for buttons in &self.frames {
+            writer.write_u8(buttons.to_bits());
+        }
+
+        writer.write_u32(self.hashes.len() as u32);
+        for hash in &self.hashes {
+            writer.write_u32(*hash);
+        }
+
+        writer.into_bytes()
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> MovieResult<Movie> {
+        let mut reader = StateReader::new(bytes);
+
+        if reader.read_bytes(MAGIC.len())? != MAGIC {
+            return Err(MovieError::BadMagic);
+        }
+
+        let version = reader.read_u16()?;
+        if version != VERSION {
+            return Err(MovieError::UnsupportedVersion { version });
+        }
+
+        let rom_checksum = reader.read_u16()?;
+        let model = match reader.read_u8()? {
+            0 => Model::Dmg,
+            1 => Model::Sgb,
+            value => {
+                return Err(MovieError::InvalidValue {
+                    field: "model",
+                    value,
+                })
+            }
+        };
+        let rtc_start = reader.read_u64()?;
+        let start_state = reader.read_sized_bytes()?.to_vec();
+
+        let hash_interval = reader.read_u32()?;
+        if hash_interval == 0 {
+            return Err(MovieError::InvalidValue {
+                field: "hash interval",
+                value: 0,
+            });
+        }
+
+        let frame_count = reader.read_u32()? as usize;
+        let frames = reader
+            .read_bytes(frame_count)?
+            .iter()
+            .map(|bits| Buttons::from_bits(*bits))
+            .collect();
+
+        let hash_count = reader.read_u32()?;
+        let hashes = (0..hash_count)
+            .map(|_| reader.read_u32())
+            .collect::<Result<_, _>>()?;
+        reader.finish()?;
+
+        Ok(Movie {
+            rom_checksum,
+            model,
+            rtc_start,
+            start_state,
+            hash_interval,
+            frames,
+            hashes,
+        })
+    }
+
+    /// Put the system in the movie's starting state, ready to play the first frame
+    pub fn prepare(&self, system: &mut System) -> MovieResult<()> {
+        let rom_checksum = system.rom_checksum();
+        if rom_checksum != self.rom_checksum {
+            return Err(MovieError::WrongRom {
+                actual: rom_checksum,
+                expected: self.rom_checksum,
+            });
+        }
+
+        system.set_model(self.model);
+        system.set_rtc_source(Box::new(EmulatedClock {
+            start: self.rtc_start,
+        }));
+        system.load_state(&self.start_state)?;
+
+        Ok(())
+    }
+
+    /// Play every frame from the start, stopping at the first desync
+    pub fn play(&self, system: &mut System) -> MovieResult<()> {
+        self.prepare(system)?;
+
+        for (index, buttons) in self.frames.iter().enumerate() {
+            system.bus_mut().joypad.buttons = *buttons;
+            system.run_frame()?;
+
+            let frame = index + 1;
+            if !frame.is_multiple_of(self.hash_interval as usize) {
+                continue;
+            }
+
+            let hash_index = frame / self.hash_interval as usize - 1;
+            if let Some(&expected) = self.hashes.get(hash_index) {
+                let actual = state_hash(system);
+                if actual != expected {
+                    return Err(MovieError::Desync {
+                        frame,
+                        actual,
+                        expected,
+                    });
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Records a movie from a system as it runs
+pub struct Recorder {
+    movie: Movie,
+}
+
+impl Recorder {
+    /// Start recording from the system's current state
+    /// The system's real time clock is replaced with one starting at `rtc_start` that only
+    /// advances with emulated time, so playback sees exactly the same times
+    pub fn start(system: &mut System, rtc_start: u64) -> Recorder {
+        Recorder::with_hash_interval(system, rtc_start, DEFAULT_HASH_INTERVAL)
+    }
+
+    /// Hashing more often pins down desyncs more precisely, at the cost of slower recording
+    pub fn with_hash_interval(system: &mut System, rtc_start: u64, hash_interval: u32) -> Recorder {
+        system.set_rtc_source(Box::new(EmulatedClock { start: rtc_start }));
+
+        Recorder {
+            movie: Movie {
+                rom_checksum: system.rom_checksum(),
+                model: system.model(),
+                rtc_start,
+                start_state: system.save_state(),
+                hash_interval: hash_interval.max(1),
+                frames: Vec::new(),
+                hashes: Vec::new(),
+            },
+        }
+    }
+
+    /// Run one frame with these buttons held, recording them
+    pub fn record_frame(
+        &mut self,
+        system: &mut System,
+        buttons: Buttons,
+    ) -> Result<(), EmulationError> {
+        system.bus_mut().joypad.buttons = buttons;
+        system.run_frame()?;
+
+        self.movie.frames.push(buttons);
+        if self
+            .movie
+            .frames
+            .len()
+            .is_multiple_of(self.movie.hash_interval as usize)
+        {
+            self.movie.hashes.push(state_hash(system));
+        }
+
+        Ok(())
+    }
+
+    pub fn finish(self) -> Movie {
+        self.movie
+    }
+}
+
+/// A hash of everything in the system's save state
+pub fn state_hash(system: &System) -> u32 {
+    // Hashing the whole state would include its checksum, and always give the same CRC
+    save_state::checksum(&system.save_state()).expect("save states end with their checksum")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn movie() -> Movie {
+        Movie {
+            rom_checksum: 0x1234,
+            model: Model::Sgb,
+            rtc_start: 1_000_000,
+            start_state: vec![1, 2, 3],
+            hash_interval: 2,
+            frames: vec![
+                Buttons::default(),
+                Buttons {
+                    a: true,
+                    left: true,
+                    ..Buttons::default()
+                },
+            ],
+            hashes: vec![0xDEADBEEF],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        assert_eq!(movie(), Movie::from_bytes(&movie().to_bytes()).unwrap());
+    }
+
+    #[test]
+    fn test_bad_movies() {
+        assert_eq!(
+            Err(MovieError::BadMagic),
+            Movie::from_bytes(b"GBRS\x01\x00")
+        );
+
+        let bytes = movie().to_bytes();
+        assert_eq!(
+            Err(MovieError::State(SaveStateError::Truncated)),
+            Movie::from_bytes(&bytes[..bytes.len() - 1])
+        );
+
+        let mut future = bytes.clone();
+        future[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Err(MovieError::UnsupportedVersion {
+                version: VERSION + 1
+            }),
+            Movie::from_bytes(&future)
+        );
+    }
+}
@@ -0,0 +1,684 @@
+//! The SM83 instruction set, shared by the CPU and the disassembler
+
+/// How an instruction affects where execution goes next
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Flow {
+    /// On to the next instruction
+    Continue,
+    /// Always to the operand's address
+    Jump,
+    /// To the operand's address, or on to the next instruction
+    ConditionalJump,
+    /// To the operand's address, coming back to the next instruction
+    Call,
+    ConditionalCall,
+    /// To the fixed address in the opcode, coming back to the next instruction
+    Restart,
+    /// Back to wherever the stack says
+    Return,
+    ConditionalReturn,
+    /// To the address in a register, which can't be known without running
+    JumpIndirect,
+    /// The next byte is an opcode from `CB_OPCODES`
+    Prefix,
+    /// Not an instruction at all, which locks up the CPU
+    Illegal,
+}
+
+/// The operand that follows an opcode, which its mnemonic has a placeholder for
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Operand {
+    None,
+    /// `n8`, an 8-bit immediate
+    N8,
+    /// `n16`, a 16-bit immediate
+    N16,
+    /// `a8`, an offset into $FF00
+    A8,
+    /// `a16`, an address
+    A16,
+    /// `e8`, a relative jump
+    E8,
+    /// `s8`, a signed offset added to SP
+    S8,
+}
+
+impl Operand {
+    /// How the mnemonic writes the operand, or None if there isn't one
+    pub fn placeholder(self) -> Option<&'static str> {
+        match self {
+            Operand::None => None,
+            Operand::N8 => Some("n8"),
+            Operand::N16 => Some("n16"),
+            Operand::A8 => Some("a8"),
+            Operand::A16 => Some("a16"),
+            Operand::E8 => Some("e8"),
+            Operand::S8 => Some("s8"),
+        }
+    }
+}
+
+/// One opcode, in RGBDS syntax, with a placeholder for its operand
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Opcode {
+    pub mnemonic: &'static str,
+    pub operand: Operand,
+    /// Length in bytes, including the opcode and any prefix
+    pub length: u8,
+    /// Machine cycles taken, when a conditional instruction doesn't branch
+    pub cycles: u8,
+    /// Machine cycles taken, when a conditional instruction branches
+    pub branch_cycles: u8,
+    pub flow: Flow,
+}
+
+impl Opcode {
+    pub fn is_illegal(&self) -> bool {
+        self.flow == Flow::Illegal
+    }
+
+    const fn with_operand(self, operand: Operand) -> Opcode {
+        Opcode { operand, ..self }
+    }
+}
+
+const fn op(mnemonic: &'static str, length: u8, cycles: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        operand: Operand::None,
+        length,
+        cycles,
+        branch_cycles: cycles,
+        flow: Flow::Continue,
+    }
+}
+
+const fn branch(
+    mnemonic: &'static str,
+    length: u8,
+    cycles: u8,
+    branch_cycles: u8,
+    flow: Flow,
+) -> Opcode {
+    Opcode {
+        mnemonic,
+        operand: Operand::None,
+        length,
+        cycles,
+        branch_cycles,
+        flow,
+    }
+}
+
+pub const OPCODES: [Opcode; 256] = [
+    op("nop", 1, 1),                                                               // $00
+    op("ld bc, n16", 3, 3).with_operand(Operand::N16),                             // $01
+    op("ld [bc], a", 1, 2),                                                        // $02
+    op("inc bc", 1, 2),                                                            // $03
+    op("inc b", 1, 1),                                                             // $04
+    op("dec b", 1, 1),                                                             // $05
+    op("ld b, n8", 2, 2).with_operand(Operand::N8),                                // $06
+    op("rlca", 1, 1),                                                              // $07
+    op("ld [a16], sp", 3, 5).with_operand(Operand::A16),                           // $08
+    op("add hl, bc", 1, 2),                                                        // $09
+    op("ld a, [bc]", 1, 2),                                                        // $0A
+    op("dec bc", 1, 2),                                                            // $0B
+    op("inc c", 1, 1),                                                             // $0C
+    op("dec c", 1, 1),                                                             // $0D
+    op("ld c, n8", 2, 2).with_operand(Operand::N8),                                // $0E
+    op("rrca", 1, 1),                                                              // $0F
+    op("stop", 2, 1),                                                              // $10
+    op("ld de, n16", 3, 3).with_operand(Operand::N16),                             // $11
+    op("ld [de], a", 1, 2),                                                        // $12
+    op("inc de", 1, 2),                                                            // $13
+    op("inc d", 1, 1),                                                             // $14
+    op("dec d", 1, 1),                                                             // $15
+    op("ld d, n8", 2, 2).with_operand(Operand::N8),                                // $16
+    op("rla", 1, 1),                                                               // $17
+    branch("jr e8", 2, 3, 3, Flow::Jump).with_operand(Operand::E8),                // $18
+    op("add hl, de", 1, 2),                                                        // $19
+    op("ld a, [de]", 1, 2),                                                        // $1A
+    op("dec de", 1, 2),                                                            // $1B
+    op("inc e", 1, 1),                                                             // $1C
+    op("dec e", 1, 1),                                                             // $1D
+    op("ld e, n8", 2, 2).with_operand(Operand::N8),                                // $1E
+    op("rra", 1, 1),                                                               // $1F
+    branch("jr nz, e8", 2, 2, 3, Flow::ConditionalJump).with_operand(Operand::E8), // $20
+    op("ld hl, n16", 3, 3).with_operand(Operand::N16),                             // $21
+    op("ld [hl+], a", 1, 2),                                                       // $22
+    op("inc hl", 1, 2),                                                            // $23
+    op("inc h", 1, 1),                                                             // $24
+    op("dec h", 1, 1),                                                             // $25
+    op("ld h, n8", 2, 2).with_operand(Operand::N8),                                // $26
+    op("daa", 1, 1),                                                               // $27
+    branch("jr z, e8", 2, 2, 3, Flow::ConditionalJump).with_operand(Operand::E8),  // $28
+    op("add hl, hl", 1, 2),                                                        // $29
+    op("ld a, [hl+]", 1, 2),                                                       // $2A
+    op("dec hl", 1, 2),                                                            // $2B
+    op("inc l", 1, 1),                                                             // $2C
+    op("dec l", 1, 1),                                                             // $2D
+    op("ld l, n8", 2, 2).with_operand(Operand::N8),                                // $2E
+    op("cpl", 1, 1),                                                               // $2F
+    branch("jr nc, e8", 2, 2, 3, Flow::ConditionalJump).with_operand(Operand::E8), // $30
+    op("ld sp, n16", 3, 3).with_operand(Operand::N16),                             // $31
+    op("ld [hl-], a", 1, 2),                                                       // $32
+    op("inc sp", 1, 2),                                                            // $33
+    op("inc [hl]", 1, 3),                                                          // $34
+    op("dec [hl]", 1, 3),                                                          // $35
+    op("ld [hl], n8", 2, 3).with_operand(Operand::N8),                             // $36
+    op("scf", 1, 1),                                                               // $37
+    branch("jr c, e8", 2, 2, 3, Flow::ConditionalJump).with_operand(Operand::E8),  // $38
+    op("add hl, sp", 1, 2),                                                        // $39
+    op("ld a, [hl-]", 1, 2),                                                       // $3A
+    op("dec sp", 1, 2),                                                            // $3B
+    op("inc a", 1, 1),                                                             // $3C
+    op("dec a", 1, 1),                                                             // $3D
+    op("ld a, n8", 2, 2).with_operand(Operand::N8),                                // $3E
+    op("ccf", 1, 1),                                                               // $3F
+    op("ld b, b", 1, 1),                                                           // $40
+    op("ld b, c", 1, 1),                                                           // $41
+    op("ld b, d", 1, 1),                                                           // $42
+    op("ld b, e", 1, 1),                                                           // $43
+    op("ld b, h", 1, 1),                                                           // $44
+    op("ld b, l", 1, 1),                                                           // $45
+    op("ld b, [hl]", 1, 2),                                                        // $46
+    op("ld b, a", 1, 1),                                                           // $47
+    op("ld c, b", 1, 1),                                                           // $48
+    op("ld c, c", 1, 1),                                                           // $49
+    op("ld c, d", 1, 1),                                                           // $4A
+    op("ld c, e", 1, 1),                                                           // $4B
+    op("ld c, h", 1, 1),                                                           // $4C
+    op("ld c, l", 1, 1),                                                           // $4D
+    op("ld c, [hl]", 1, 2),                                                        // $4E
+    op("ld c, a", 1, 1),                                                           // $4F
+    op("ld d, b", 1, 1),                                                           // $50
+    op("ld d, c", 1, 1),                                                           // $51
+    op("ld d, d", 1, 1),                                                           // $52
+    op("ld d, e", 1, 1),                                                           // $53
+    op("ld d, h", 1, 1),                                                           // $54
+    op("ld d, l", 1, 1),                                                           // $55
+    op("ld d, [hl]", 1, 2),                                                        // $56
+    op("ld d, a", 1, 1),                                                           // $57
+    op("ld e, b", 1, 1),                                                           // $58
+    op("ld e, c", 1, 1),                                                           // $59
+    op("ld e, d", 1, 1),                                                           // $5A
+    op("ld e, e", 1, 1),                                                           // $5B
+    op("ld e, h", 1, 1),                                                           // $5C
+    op("ld e, l", 1, 1),                                                           // $5D
+    op("ld e, [hl]", 1, 2),                                                        // $5E
+    op("ld e, a", 1, 1),                                                           // $5F
+    op("ld h, b", 1, 1),                                                           // $60
+    op("ld h, c", 1, 1),                                                           // $61
+    op("ld h, d", 1, 1),                                                           // $62
+    op("ld h, e", 1, 1),                                                           // $63
+    op("ld h, h", 1, 1),                                                           // $64
+    op("ld h, l", 1, 1),                                                           // $65
+    op("ld h, [hl]", 1, 2),                                                        // $66
+    op("ld h, a", 1, 1),                                                           // $67
+    op("ld l, b", 1, 1),                                                           // $68
+    op("ld l, c", 1, 1),                                                           // $69
+    op("ld l, d", 1, 1),                                                           // $6A
+    op("ld l, e", 1, 1),                                                           // $6B
+    op("ld l, h", 1, 1),                                                           // $6C
+    op("ld l, l", 1, 1),                                                           // $6D
+    op("ld l, [hl]", 1, 2),                                                        // $6E
+    op("ld l, a", 1, 1),                                                           // $6F
+    op("ld [hl], b", 1, 2),                                                        // $70
+    op("ld [hl], c", 1, 2),                                                        // $71
+    op("ld [hl], d", 1, 2),                                                        // $72
+    op("ld [hl], e", 1, 2),                                                        // $73
+    op("ld [hl], h", 1, 2),                                                        // $74
+    op("ld [hl], l", 1, 2),                                                        // $75
+    op("halt", 1, 1),                                                              // $76
+    op("ld [hl], a", 1, 2),                                                        // $77
+    op("ld a, b", 1, 1),                                                           // $78
+    op("ld a, c", 1, 1),                                                           // $79
+    op("ld a, d", 1, 1),                                                           // $7A
+    op("ld a, e", 1, 1),                                                           // $7B
+    op("ld a, h", 1, 1),                                                           // $7C
+    op("ld a, l", 1, 1),                                                           // $7D
+    op("ld a, [hl]", 1, 2),                                                        // $7E
+    op("ld a, a", 1, 1),                                                           // $7F
+    op("add a, b", 1, 1),                                                          // $80
+    op("add a, c", 1, 1),                                                          // $81
+    op("add a, d", 1, 1),                                                          // $82
+    op("add a, e", 1, 1),                                                          // $83
+    op("add a, h", 1, 1),                                                          // $84
+    op("add a, l", 1, 1),                                                          // $85
+    op("add a, [hl]", 1, 2),                                                       // $86
+    op("add a, a", 1, 1),                                                          // $87
+    op("adc a, b", 1, 1),                                                          // $88
+    op("adc a, c", 1, 1),                                                          // $89
+    op("adc a, d", 1, 1),                                                          // $8A
+    op("adc a, e", 1, 1),                                                          // $8B
+    op("adc a, h", 1, 1),                                                          // $8C
+    op("adc a, l", 1, 1),                                                          // $8D
+    op("adc a, [hl]", 1, 2),                                                       // $8E
+    op("adc a, a", 1, 1),                                                          // $8F
+    op("sub a, b", 1, 1),                                                          // $90
+    op("sub a, c", 1, 1),                                                          // $91
+    op("sub a, d", 1, 1),                                                          // $92
+    op("sub a, e", 1, 1),                                                          // $93
+    op("sub a, h", 1, 1),                                                          // $94
+    op("sub a, l", 1, 1),                                                          // $95
+    op("sub a, [hl]", 1, 2),                                                       // $96
+    op("sub a, a", 1, 1),                                                          // $97
+    op("sbc a, b", 1, 1),                                                          // $98
+    op("sbc a, c", 1, 1),                                                          // $99
+    op("sbc a, d", 1, 1),                                                          // $9A
+    op("sbc a, e", 1, 1),                                                          // $9B
+    op("sbc a, h", 1, 1),                                                          // $9C
+    op("sbc a, l", 1, 1),                                                          // $9D
+    op("sbc a, [hl]", 1, 2),                                                       // $9E
+    op("sbc a, a", 1, 1),                                                          // $9F
+    op("and a, b", 1, 1),                                                          // $A0
+    op("and a, c", 1, 1),                                                          // $A1
+    op("and a, d", 1, 1),                                                          // $A2
+    op("and a, e", 1, 1),                                                          // $A3
+    op("and a, h", 1, 1),                                                          // $A4
+    op("and a, l", 1, 1),                                                          // $A5
+    op("and a, [hl]", 1, 2),                                                       // $A6
+    op("and a, a", 1, 1),                                                          // $A7
+    op("xor a, b", 1, 1),                                                          // $A8
+    op("xor a, c", 1, 1),                                                          // $A9
+    op("xor a, d", 1, 1),                                                          // $AA
+    op("xor a, e", 1, 1),                                                          // $AB
+    op("xor a, h", 1, 1),                                                          // $AC
+    op("xor a, l", 1, 1),                                                          // $AD
+    op("xor a, [hl]", 1, 2),                                                       // $AE
+    op("xor a, a", 1, 1),                                                          // $AF
+    op("or a, b", 1, 1),                                                           // $B0
+    op("or a, c", 1, 1),                                                           // $B1
+    op("or a, d", 1, 1),                                                           // $B2
+    op("or a, e", 1, 1),                                                           // $B3
+    op("or a, h", 1, 1),                                                           // $B4
+    op("or a, l", 1, 1),                                                           // $B5
+    op("or a, [hl]", 1, 2),                                                        // $B6
+    op("or a, a", 1, 1),                                                           // $B7
+    op("cp a, b", 1, 1),                                                           // $B8
+    op("cp a, c", 1, 1),                                                           // $B9
+    op("cp a, d", 1, 1),                                                           // $BA
+    op("cp a, e", 1, 1),                                                           // $BB
+    op("cp a, h", 1, 1),                                                           // $BC
+    op("cp a, l", 1, 1),                                                           // $BD
+    op("cp a, [hl]", 1, 2),                                                        // $BE
+    op("cp a, a", 1, 1),                                                           // $BF
+    branch("ret nz", 1, 2, 5, Flow::ConditionalReturn),                            // $C0
+    op("pop bc", 1, 3),                                                            // $C1
+    branch("jp nz, a16", 3, 3, 4, Flow::ConditionalJump).with_operand(Operand::A16), // $C2
+    branch("jp a16", 3, 4, 4, Flow::Jump).with_operand(Operand::A16),              // $C3
+    branch("call nz, a16", 3, 3, 6, Flow::ConditionalCall).with_operand(Operand::A16), // $C4
+    op("push bc", 1, 4),                                                           // $C5
+    op("add a, n8", 2, 2).with_operand(Operand::N8),                               // $C6
+    branch("rst $00", 1, 4, 4, Flow::Restart),                                     // $C7
+    branch("ret z", 1, 2, 5, Flow::ConditionalReturn),                             // $C8
+    branch("ret", 1, 4, 4, Flow::Return),                                          // $C9
+    branch("jp z, a16", 3, 3, 4, Flow::ConditionalJump).with_operand(Operand::A16), // $CA
+    branch("prefix", 1, 1, 1, Flow::Prefix),                                       // $CB
+    branch("call z, a16", 3, 3, 6, Flow::ConditionalCall).with_operand(Operand::A16), // $CC
+    branch("call a16", 3, 6, 6, Flow::Call).with_operand(Operand::A16),            // $CD
+    op("adc a, n8", 2, 2).with_operand(Operand::N8),                               // $CE
+    branch("rst $08", 1, 4, 4, Flow::Restart),                                     // $CF
+    branch("ret nc", 1, 2, 5, Flow::ConditionalReturn),                            // $D0
+    op("pop de", 1, 3),                                                            // $D1
+    branch("jp nc, a16", 3, 3, 4, Flow::ConditionalJump).with_operand(Operand::A16), // $D2
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $D3
+    branch("call nc, a16", 3, 3, 6, Flow::ConditionalCall).with_operand(Operand::A16), // $D4
+    op("push de", 1, 4),                                                           // $D5
+    op("sub a, n8", 2, 2).with_operand(Operand::N8),                               // $D6
+    branch("rst $10", 1, 4, 4, Flow::Restart),                                     // $D7
+    branch("ret c", 1, 2, 5, Flow::ConditionalReturn),                             // $D8
+    branch("reti", 1, 4, 4, Flow::Return),                                         // $D9
+    branch("jp c, a16", 3, 3, 4, Flow::ConditionalJump).with_operand(Operand::A16), // $DA
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $DB
+    branch("call c, a16", 3, 3, 6, Flow::ConditionalCall).with_operand(Operand::A16), // $DC
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $DD
+    op("sbc a, n8", 2, 2).with_operand(Operand::N8),                               // $DE
+    branch("rst $18", 1, 4, 4, Flow::Restart),                                     // $DF
+    op("ldh [a8], a", 2, 3).with_operand(Operand::A8),                             // $E0
+    op("pop hl", 1, 3),                                                            // $E1
+    op("ldh [c], a", 1, 2),                                                        // $E2
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $E3
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $E4
+    op("push hl", 1, 4),                                                           // $E5
+    op("and a, n8", 2, 2).with_operand(Operand::N8),                               // $E6
+    branch("rst $20", 1, 4, 4, Flow::Restart),                                     // $E7
+    op("add sp, s8", 2, 4).with_operand(Operand::S8),                              // $E8
+    branch("jp hl", 1, 1, 1, Flow::JumpIndirect),                                  // $E9
+    op("ld [a16], a", 3, 4).with_operand(Operand::A16),                            // $EA
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $EB
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $EC
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $ED
+    op("xor a, n8", 2, 2).with_operand(Operand::N8),                               // $EE
+    branch("rst $28", 1, 4, 4, Flow::Restart),                                     // $EF
+    op("ldh a, [a8]", 2, 3).with_operand(Operand::A8),                             // $F0
+    op("pop af", 1, 3),                                                            // $F1
+    op("ldh a, [c]", 1, 2),                                                        // $F2
+    op("di", 1, 1),                                                                // $F3
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $F4
+    op("push af", 1, 4),                                                           // $F5
+    op("or a, n8", 2, 2).with_operand(Operand::N8),                                // $F6
+    branch("rst $30", 1, 4, 4, Flow::Restart),                                     // $F7
+    op("ld hl, sp+s8", 2, 3).with_operand(Operand::S8),                            // $F8
+    op("ld sp, hl", 1, 2),                                                         // $F9
+    op("ld a, [a16]", 3, 4).with_operand(Operand::A16),                            // $FA
+    op("ei", 1, 1),                                                                // $FB
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $FC
+    branch("illegal", 1, 1, 1, Flow::Illegal),                                     // $FD
+    op("cp a, n8", 2, 2).with_operand(Operand::N8),                                // $FE
+    branch("rst $38", 1, 4, 4, Flow::Restart),                                     // $FF
+];
+
+/// Instructions after the $CB prefix, which are all 2 bytes including the prefix
+pub const CB_OPCODES: [Opcode; 256] = [
+    op("rlc b", 2, 2),       // $00
+    op("rlc c", 2, 2),       // $01
+    op("rlc d", 2, 2),       // $02
+    op("rlc e", 2, 2),       // $03
+    op("rlc h", 2, 2),       // $04
+    op("rlc l", 2, 2),       // $05
+    op("rlc [hl]", 2, 4),    // $06
+    op("rlc a", 2, 2),       // $07
+    op("rrc b", 2, 2),       // $08
+    op("rrc c", 2, 2),       // $09
+    op("rrc d", 2, 2),       // $0A
+    op("rrc e", 2, 2),       // $0B
+    op("rrc h", 2, 2),       // $0C
+    op("rrc l", 2, 2),       // $0D
+    op("rrc [hl]", 2, 4),    // $0E
+    op("rrc a", 2, 2),       // $0F
+    op("rl b", 2, 2),        // $10
+    op("rl c", 2, 2),        // $11
+    op("rl d", 2, 2),        // $12
+    op("rl e", 2, 2),        // $13
+    op("rl h", 2, 2),        // $14
+    op("rl l", 2, 2),        // $15
+    op("rl [hl]", 2, 4),     // $16
+    op("rl a", 2, 2),        // $17
+    op("rr b", 2, 2),        // $18
+    op("rr c", 2, 2),        // $19
+    op("rr d", 2, 2),        // $1A
+    op("rr e", 2, 2),        // $1B
+    op("rr h", 2, 2),        // $1C
+    op("rr l", 2, 2),        // $1D
+    op("rr [hl]", 2, 4),     // $1E
+    op("rr a", 2, 2),        // $1F
+    op("sla b", 2, 2),       // $20
+    op("sla c", 2, 2),       // $21
+    op("sla d", 2, 2),       // $22
+    op("sla e", 2, 2),       // $23
+    op("sla h", 2, 2),       // $24
+    op("sla l", 2, 2),       // $25
+    op("sla [hl]", 2, 4),    // $26
+    op("sla a", 2, 2),       // $27
+    op("sra b", 2, 2),       // $28
+    op("sra c", 2, 2),       // $29
+    op("sra d", 2, 2),       // $2A
+    op("sra e", 2, 2),       // $2B
+    op("sra h", 2, 2),       // $2C
+    op("sra l", 2, 2),       // $2D
+    op("sra [hl]", 2, 4),    // $2E
+    op("sra a", 2, 2),       // $2F
+    op("swap b", 2, 2),      // $30
+    op("swap c", 2, 2),      // $31
+    op("swap d", 2, 2),      // $32
+    op("swap e", 2, 2),      // $33
+    op("swap h", 2, 2),      // $34
+    op("swap l", 2, 2),      // $35
+    op("swap [hl]", 2, 4),   // $36
+    op("swap a", 2, 2),      // $37
+    op("srl b", 2, 2),       // $38
+    op("srl c", 2, 2),       // $39
+    op("srl d", 2, 2),       // $3A
+    op("srl e", 2, 2),       // $3B
+    op("srl h", 2, 2),       // $3C
+    op("srl l", 2, 2),       // $3D
+    op("srl [hl]", 2, 4),    // $3E
+    op("srl a", 2, 2),       // $3F
+    op("bit 0, b", 2, 2),    // $40
+    op("bit 0, c", 2, 2),    // $41
+    op("bit 0, d", 2, 2),    // $42
+    op("bit 0, e", 2, 2),    // $43
+    op("bit 0, h", 2, 2),    // $44
+    op("bit 0, l", 2, 2),    // $45
+    op("bit 0, [hl]", 2, 3), // $46
+    op("bit 0, a", 2, 2),    // $47
+    op("bit 1, b", 2, 2),    // $48
+    op("bit 1, c", 2, 2),    // $49
+    op("bit 1, d", 2, 2),    // $4A
+    op("bit 1, e", 2, 2),    // $4B
+    op("bit 1, h", 2, 2),    // $4C
+    op("bit 1, l", 2, 2),    // $4D
+    op("bit 1, [hl]", 2, 3), // $4E
+    op("bit 1, a", 2, 2),    // $4F
+    op("bit 2, b", 2, 2),    // $50
+    op("bit 2, c", 2, 2),    // $51
+    op("bit 2, d", 2, 2),    // $52
+    op("bit 2, e", 2, 2),    // $53
+    op("bit 2, h", 2, 2),    // $54
+    op("bit 2, l", 2, 2),    // $55
+    op("bit 2, [hl]", 2, 3), // $56
+    op("bit 2, a", 2, 2),    // $57
+    op("bit 3, b", 2, 2),    // $58
+    op("bit 3, c", 2, 2),    // $59
+    op("bit 3, d", 2, 2),    // $5A
+    op("bit 3, e", 2, 2),    // $5B
+    op("bit 3, h", 2, 2),    // $5C
+    op("bit 3, l", 2, 2),    // $5D
+    op("bit 3, [hl]", 2, 3), // $5E
+    op("bit 3, a", 2, 2),    // $5F
+    op("bit 4, b", 2, 2),    // $60
+    op("bit 4, c", 2, 2),    // $61
+    op("bit 4, d", 2, 2),    // $62
+    op("bit 4, e", 2, 2),    // $63
+    op("bit 4, h", 2, 2),    // $64
+    op("bit 4, l", 2, 2),    // $65
+    op("bit 4, [hl]", 2, 3), // $66
+    op("bit 4, a", 2, 2),    // $67
+    op("bit 5, b", 2, 2),    // $68
+    op("bit 5, c", 2, 2),    // $69
+    op("bit 5, d", 2, 2),    // $6A
+    op("bit 5, e", 2, 2),    // $6B
+    op("bit 5, h", 2, 2),    // $6C
+    op("bit 5, l", 2, 2),    // $6D
+    op("bit 5, [hl]", 2, 3), // $6E
+    op("bit 5, a", 2, 2),    // $6F
+    op("bit 6, b", 2, 2),    // $70
+    op("bit 6, c", 2, 2),    // $71
+    op("bit 6, d", 2, 2),    // $72
+    op("bit 6, e", 2, 2),    // $73
+    op("bit 6, h", 2, 2),    // $74
+    op("bit 6, l", 2, 2),    // $75
+    op("bit 6, [hl]", 2, 3), // $76
+    op("bit 6, a", 2, 2),    // $77
+    op("bit 7, b", 2, 2),    // $78
+    op("bit 7, c", 2, 2),    // $79
+    op("bit 7, d", 2, 2),    // $7A
+    op("bit 7, e", 2, 2),    // $7B
+    op("bit 7, h", 2, 2),    // $7C
+    op("bit 7, l", 2, 2),    // $7D
+    op("bit 7, [hl]", 2, 3), // $7E
+    op("bit 7, a", 2, 2),    // $7F
+    op("res 0, b", 2, 2),    // $80
+    op("res 0, c", 2, 2),    // $81
+    op("res 0, d", 2, 2),    // $82
+    op("res 0, e", 2, 2),    // $83
+    op("res 0, h", 2, 2),    // $84
+    op("res 0, l", 2, 2),    // $85
+    op("res 0, [hl]", 2, 4), // $86
+    op("res 0, a", 2, 2),    // $87
+    op("res 1, b", 2, 2),    // $88
+    op("res 1, c", 2, 2),    // $89
+    op("res 1, d", 2, 2),    // $8A
+    op("res 1, e", 2, 2),    // $8B
+    op("res 1, h", 2, 2),    // $8C
+    op("res 1, l", 2, 2),    // $8D
+    op("res 1, [hl]", 2, 4), // $8E
+    op("res 1, a", 2, 2),    // $8F
+    op("res 2, b", 2, 2),    // $90
+    op("res 2, c", 2, 2),    // $91
+    op("res 2, d", 2, 2),    // $92
+    op("res 2, e", 2, 2),    // $93
+    op("res 2, h", 2, 2),    // $94
+    op("res 2, l", 2, 2),    // $95
+    op("res 2, [hl]", 2, 4), // $96
+    op("res 2, a", 2, 2),    // $97
+    op("res 3, b", 2, 2),    // $98
+    op("res 3, c", 2, 2),    // $99
+    op("res 3, d", 2, 2),    // $9A
+    op("res 3, e", 2, 2),    // $9B
+    op("res 3, h", 2, 2),    // $9C
+    op("res 3, l", 2, 2),    // $9D
+    op("res 3, [hl]", 2, 4), // $9E
+    op("res 3, a", 2, 2),    // $9F
+    op("res 4, b", 2, 2),    // $A0
+    op("res 4, c", 2, 2),    // $A1
+    op("res 4, d", 2, 2),    // $A2
+    op("res 4, e", 2, 2),    // $A3
+    op("res 4, h", 2, 2),    // $A4
+    op("res 4, l", 2, 2),    // $A5
+    op("res 4, [hl]", 2, 4), // $A6
+    op("res 4, a", 2, 2),    // $A7
+    op("res 5, b", 2, 2),    // $A8
+    op("res 5, c", 2, 2),    // $A9
+    op("res 5, d", 2, 2),    // $AA
+    op("res 5, e", 2, 2),    // $AB
+    op("res 5, h", 2, 2),    // $AC
+    op("res 5, l", 2, 2),    // $AD
+    op("res 5, [hl]", 2, 4), // $AE
+    op("res 5, a", 2, 2),    // $AF
+    op("res 6, b", 2, 2),    // $B0
+    op("res 6, c", 2, 2),    // $B1
+    op("res 6, d", 2, 2),    // $B2
+    op("res 6, e", 2, 2),    // $B3
+    op("res 6, h", 2, 2),    // $B4
+    op("res 6, l", 2, 2),    // $B5
+    op("res 6, [hl]", 2, 4), // $B6
+    op("res 6, a", 2, 2),    // $B7
+    op("res 7, b", 2, 2),    // $B8
+    op("res 7, c", 2, 2),    // $B9
+    op("res 7, d", 2, 2),    // $BA
+    op("res 7, e", 2, 2),    // $BB
+    op("res 7, h", 2, 2),    // $BC
+    op("res 7, l", 2, 2),    // $BD
+    op("res 7, [hl]", 2, 4), // $BE
+    op("res 7, a", 2, 2),    // $BF
+    op("set 0, b", 2, 2),    // $C0
+    op("set 0, c", 2, 2),    // $C1
+    op("set 0, d", 2, 2),    // $C2
+    op("set 0, e", 2, 2),    // $C3
+    op("set 0, h", 2, 2),    // $C4
+    op("set 0, l", 2, 2),    // $C5
+    op("set 0, [hl]", 2, 4), // $C6
+    op("set 0, a", 2, 2),    // $C7
+    op("set 1, b", 2, 2),    // $C8
+    op("set 1, c", 2, 2),    // $C9
+    op("set 1, d", 2, 2),    // $CA
+    op("set 1, e", 2, 2),    // $CB
+    op("set 1, h", 2, 2),    // $CC
+    op("set 1, l", 2, 2),    // $CD
+    op("set 1, [hl]", 2, 4), // $CE
+    op("set 1, a", 2, 2),    // $CF
+    op("set 2, b", 2, 2),    // $D0
+    op("set 2, c", 2, 2),    // $D1
+    op("set 2, d", 2, 2),    // $D2
+    op("set 2, e", 2, 2),    // $D3
+    op("set 2, h", 2, 2),    // $D4
+    op("set 2, l", 2, 2),    // $D5
+    op("set 2, [hl]", 2, 4), // $D6
+    op("set 2, a", 2, 2),    // $D7
+    op("set 3, b", 2, 2),    // $D8
+    op("set 3, c", 2, 2),    // $D9
+    op("set 3, d", 2, 2),    // $DA
+    op("set 3, e", 2, 2),    // $DB
+    op("set 3, h", 2, 2),    // $DC
+    op("set 3, l", 2, 2),    // $DD
+    op("set 3, [hl]", 2, 4), // $DE
+    op("set 3, a", 2, 2),    // $DF
+    op("set 4, b", 2, 2),    // $E0
+    op("set 4, c", 2, 2),    // $E1
+    op("set 4, d", 2, 2),    // $E2
+    op("set 4, e", 2, 2),    // $E3
+    op("set 4, h", 2, 2),    // $E4
+    op("set 4, l", 2, 2),    // $E5
+    op("set 4, [hl]", 2, 4), // $E6
+    op("set 4, a", 2, 2),    // $E7
+    op("set 5, b", 2, 2),    // $E8
+    op("set 5, c", 2, 2),    // $E9
+    op("set 5, d", 2, 2),    // $EA
+    op("set 5, e", 2, 2),    // $EB
+    op("set 5, h", 2, 2),    // $EC
+    op("set 5, l", 2, 2),    // $ED
+    op("set 5, [hl]", 2, 4), // $EE
+    op("set 5, a", 2, 2),    // $EF
+    op("set 6, b", 2, 2),    // $F0
+    op("set 6, c", 2, 2),    // $F1
+    op("set 6, d", 2, 2),    // $F2
+    op("set 6, e", 2, 2),    // $F3
+    op("set 6, h", 2, 2),    // $F4
+    op("set 6, l", 2, 2),    // $F5
+    op("set 6, [hl]", 2, 4), // $F6
+    op("set 6, a", 2, 2),    // $F7
+    op("set 7, b", 2, 2),    // $F8
+    op("set 7, c", 2, 2),    // $F9
+    op("set 7, d", 2, 2),    // $FA
+    op("set 7, e", 2, 2),    // $FB
+    op("set 7, h", 2, 2),    // $FC
+    op("set 7, l", 2, 2),    // $FD
+    op("set 7, [hl]", 2, 4), // $FE
+    op("set 7, a", 2, 2),    // $FF
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_illegal_opcodes() {
+        let illegal: Vec<usize> = (0..256).filter(|op| OPCODES[*op].is_illegal()).collect();
+
+        assert_eq!(
+            vec![0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD],
+            illegal
+        );
+    }
+
+    #[test]
+    fn test_operands_match_mnemonics() {
+        let placeholders = ["n8", "n16", "a8", "a16", "e8", "s8"];
+
+        for opcode in OPCODES.iter().chain(CB_OPCODES.iter()) {
+            let written: Vec<&str> = placeholders
+                .into_iter()
+                .filter(|placeholder| opcode.mnemonic.contains(placeholder))
+                .collect();
+            let expected: Vec<&str> = opcode.operand.placeholder().into_iter().collect();
+
+            assert_eq!(expected, written, "{}", opcode.mnemonic);
+        }
+    }
+
+    #[test]
+    fn test_lengths_match_operands() {
+        for opcode in OPCODES.iter().filter(|opcode| opcode.flow != Flow::Prefix) {
+            let operand_bytes = match opcode.operand {
+                Operand::None => 0,
+                Operand::N16 | Operand::A16 => 2,
+                _ => 1,
+            };
+
+            // STOP's second byte is padding rather than an operand
+            if opcode.mnemonic != "stop" {
+                assert_eq!(1 + operand_bytes, opcode.length, "{}", opcode.mnemonic);
+            }
+        }
+    }
+
+    #[test]
+    fn test_cb_timing() {
+        assert_eq!("bit 7, [hl]", CB_OPCODES[0x7E].mnemonic);
+        assert_eq!(3, CB_OPCODES[0x7E].cycles);
+        assert_eq!("set 0, [hl]", CB_OPCODES[0xC6].mnemonic);
+        assert_eq!(4, CB_OPCODES[0xC6].cycles);
+    }
+}
@@ -0,0 +1,437 @@
+use crate::disassembler::{Instruction, ENTRY_POINTS};
+use crate::opcodes::Flow;
+use crate::symbols::{self, Symbols};
+use crate::system::System;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write as _;
+
+/// Instructions listed in the flat report, hottest first
+const HOTTEST_INSTRUCTIONS: usize = 20;
+
+/// Where a routine starts, in the bank that was mapped there when it was entered
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Routine {
+    pub bank: u8,
+    pub address: u16,
+}
+
+/// The cycles spent on one instruction, wherever it was called from
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct InstructionProfile {
+    pub cycles: u64,
+    pub executions: u64,
+}
+
+/// How one routine's cycles add up, over every call stack it appears in
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct RoutineProfile {
+    /// Spent in the routine itself
+    pub self_cycles: u64,
+    /// Spent in the routine and everything it called, counting recursion once
+    pub total_cycles: u64,
+    /// Times it was called or, for an interrupt handler, interrupted into
+    pub calls: u64,
+}
+
+/// The instruction being run, between `begin` and `end`
+struct Pending {
+    instruction: Instruction,
+    bank: u8,
+    cycles: u64,
+}
+
+/// Attributes machine cycles to instructions and call stacks as a system runs
+///
+/// The call stack is followed from what each instruction does to PC: a CALL or RST that is taken
+/// enters a routine, a RET or RETI that is taken leaves one, and PC landing on an interrupt vector
+/// that the instruction couldn't have jumped to enters an interrupt handler. Cycles a DMA stalls
+/// the CPU for are put down to the instruction that started it.
+pub struct Profiler {
+    symbols: Symbols,
+    pending: Option<Pending>,
+    /// The routines being run, outermost first
+    stack: Vec<Routine>,
+    /// Cycles spent with exactly this call stack
+    stacks: HashMap<Vec<Routine>, u64>,
+    instructions: HashMap<(u8, u16), InstructionProfile>,
+    calls: HashMap<Routine, u64>,
+    total_cycles: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler {
+            symbols: Symbols::default(),
+            pending: None,
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            instructions: HashMap::new(),
+            calls: HashMap::new(),
+            total_cycles: 0,
+        }
+    }
+
+    /// Name routines and instructions in reports with these symbols
+    pub fn with_symbols(self, symbols: Symbols) -> Profiler {
+        Profiler { symbols, ..self }
+    }
+
+    /// Note the instruction at PC, which is about to run
+    pub fn begin(&mut self, system: &System) {
+        let pc = system.cpu().pc;
+        let bank = symbols::bank_at(&system.bus().cartridge, pc);
+
+        // Whatever was running when profiling started is the outermost routine
+        if self.stack.is_empty() {
+            self.enter(Routine { bank, address: pc });
+        }
+
+        self.pending = Some(Pending {
+            instruction: Instruction::decode_with(pc, |address| system.bus().peek(address)),
+            bank,
+            cycles: system.cycles(),
+        });
+    }
+
+    /// Attribute the cycles the instruction from `begin` took, and follow where it went
+    pub fn end(&mut self, system: &System) {
+        let Some(pending) = self.pending.take() else {
+            return;
+        };
+        let instruction = pending.instruction;
+        let cycles = system.cycles() - pending.cycles;
+
+        self.total_cycles += cycles;
+        match self.stacks.get_mut(&self.stack) {
+            Some(stack_cycles) => *stack_cycles += cycles,
+            None => {
+                self.stacks.insert(self.stack.clone(), cycles);
+            }
+        }
+        let profile = self
+            .instructions
+            .entry((pending.bank, instruction.address))
+            .or_default();
+        profile.cycles += cycles;
+        profile.executions += 1;
+
+        let next_pc = system.cpu().pc;
+        let next = Routine {
+            bank: symbols::bank_at(&system.bus().cartridge, next_pc),
+            address: next_pc,
+        };
+        let fall_through = instruction.address.wrapping_add(instruction.len() as u16);
+        let target = instruction.target();
+
+        match instruction.opcode.flow {
+            Flow::Call | Flow::ConditionalCall | Flow::Restart if target == Some(next_pc) => {
+                self.enter(next)
+            }
+            Flow::Return | Flow::ConditionalReturn if next_pc != fall_through => {
+                self.stack.pop();
+                // Returning from the outermost routine goes to a caller that was never seen
+                if self.stack.is_empty() {
+                    self.enter(next);
+                }
+            }
+            Flow::Return | Flow::ConditionalReturn | Flow::JumpIndirect => {}
+            _ if next_pc != fall_through
+                && target != Some(next_pc)
+                && ENTRY_POINTS[1..].contains(&next_pc) =>
+            {
+                self.enter(next)
+            }
+            _ => {}
+        }
+    }
+
+    fn enter(&mut self, routine: Routine) {
+        self.stack.push(routine);
+        *self.calls.entry(routine).or_default() += 1;
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    /// The instruction at this address in this bank
+    pub fn instruction(&self, bank: u8, address: u16) -> InstructionProfile {
+        self.instructions
+            .get(&(bank, address))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Every routine that has been run
+    pub fn routines(&self) -> BTreeMap<Routine, RoutineProfile> {
+        let mut routines: BTreeMap<Routine, RoutineProfile> = BTreeMap::new();
+
+        for (stack, cycles) in &self.stacks {
+            for (depth, routine) in stack.iter().enumerate() {
+                let profile = routines.entry(*routine).or_default();
+                if !stack[..depth].contains(routine) {
+                    profile.total_cycles += cycles;
+                }
+                if depth == stack.len() - 1 {
+                    profile.self_cycles += cycles;
+                }
+            }
+        }
+        for (routine, calls) in &self.calls {
+            routines.entry(*routine).or_default().calls = *calls;
+        }
+
+        routines
+    }
+
+    /// The routines by the cycles spent in them, then the hottest instructions
+    pub fn flat_report(&self) -> String {
+        let mut routines: Vec<(Routine, RoutineProfile)> = self.routines().into_iter().collect();
+        routines.sort_by(|(a, a_profile), (b, b_profile)| {
+            b_profile
+                .self_cycles
+                .cmp(&a_profile.self_cycles)
+                .then(a.cmp(b))
+        });
+
+        let mut report = format!(
+            "{:>12} {:>7} {:>12} {:>7} {:>8}  Routine\n",
+            "Self cycles", "Self", "Total cycles", "Total", "Calls"
+        );
+        for (routine, profile) in routines {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>7} {:>12} {:>7} {:>8}  {}",
+                profile.self_cycles,
+                self.percentage(profile.self_cycles),
+                profile.total_cycles,
+                self.percentage(profile.total_cycles),
+                profile.calls,
+                self.routine_name(routine)
+            );
+        }
+
+        let mut instructions: Vec<(&(u8, u16), &InstructionProfile)> =
+            self.instructions.iter().collect();
+        instructions.sort_by(|(a, a_profile), (b, b_profile)| {
+            b_profile.cycles.cmp(&a_profile.cycles).then(a.cmp(b))
+        });
+
+        let _ = write!(
+            report,
+            "\n{:>12} {:>7} {:>12}  Instruction\n",
+            "Cycles", "", "Executions"
+        );
+        for ((bank, address), profile) in instructions.into_iter().take(HOTTEST_INSTRUCTIONS) {
+            let _ = writeln!(
+                report,
+                "{:>12} {:>7} {:>12}  {}",
+                profile.cycles,
+                self.percentage(profile.cycles),
+                profile.executions,
+                self.location_name(*bank, *address)
+            );
+        }
+
+        report
+    }
+
+    /// The call tree, with each routine's callees indented under it, busiest first
+    pub fn tree_report(&self) -> String {
+        let mut root = Node::default();
+        for (stack, cycles) in &self.stacks {
+            root.add(stack, *cycles);
+        }
+
+        let mut report = String::new();
+        self.write_tree(&mut report, &root, 0);
+        report
+    }
+
+    fn write_tree(&self, report: &mut String, node: &Node, depth: usize) {
+        for (routine, child) in node.children_by_total() {
+            let _ = writeln!(
+                report,
+                "{:indent$}{} {} cycles, {} in itself: {}",
+                "",
+                self.percentage(child.total_cycles),
+                child.total_cycles,
+                child.self_cycles,
+                self.routine_name(routine),
+                indent = depth * 2
+            );
+            self.write_tree(report, child, depth + 1);
+        }
+    }
+
+    /// One line of `outermost;...;innermost cycles` per call stack, as flamegraph tools take
+    pub fn collapsed_stacks(&self) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let names: Vec<String> = stack
+                    .iter()
+                    .map(|routine| self.routine_name(*routine))
+                    .collect();
+                format!("{} {}", names.join(";"), cycles)
+            })
+            .collect();
+        lines.sort();
+
+        lines.into_iter().map(|line| line + "\n").collect()
+    }
+
+    fn routine_name(&self, routine: Routine) -> String {
+        self.symbols
+            .describe_in_bank(routine.bank, routine.address)
+            .unwrap_or_else(|| format!("{:02X}:{:04X}", routine.bank, routine.address))
+    }
+
+    fn location_name(&self, bank: u8, address: u16) -> String {
+        match self.symbols.describe_in_bank(bank, address) {
+            Some(symbol) => format!("{:02X}:{:04X} {}", bank, address, symbol),
+            None => format!("{:02X}:{:04X}", bank, address),
+        }
+    }
+
+    fn percentage(&self, cycles: u64) -> String {
+        match self.total_cycles {
+            0 => "-".to_string(),
+            total => format!("{:.1}%", cycles as f64 * 100.0 / total as f64),
+        }
+    }
+}
+
+/// A routine in the call tree, under the routine that called it
+#[derive(Default)]
+struct Node {
+    self_cycles: u64,
+    total_cycles: u64,
+    children: BTreeMap<Routine, Node>,
+}
+
+impl Node {
+    fn add(&mut self, stack: &[Routine], cycles: u64) {
+        if let Some((routine, callees)) = stack.split_first() {
+            let child = self.children.entry(*routine).or_default();
+            child.total_cycles += cycles;
+            if callees.is_empty() {
+                child.self_cycles += cycles;
+            }
+            child.add(callees, cycles);
+        }
+    }
+
+    fn children_by_total(&self) -> Vec<(Routine, &Node)> {
+        let mut children: Vec<(Routine, &Node)> = self
+            .children
+            .iter()
+            .map(|(routine, node)| (*routine, node))
+            .collect();
+        children.sort_by(|(a, a_node), (b, b_node)| {
+            b_node.total_cycles.cmp(&a_node.total_cycles).then(a.cmp(b))
+        });
+        children
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn routine(address: u16) -> Routine {
+        Routine { bank: 0, address }
+    }
+
+    fn profiler(stacks: &[(&[u16], u64)]) -> Profiler {
+        let mut symbols = Symbols::default();
+        symbols.insert(0, 0x0150, "Main");
+        symbols.insert(0, 0x0200, "Draw");
+        symbols.insert(0, 0x0040, "VBlank");
+
+        let mut profiler = Profiler::new().with_symbols(symbols);
+        for (stack, cycles) in stacks {
+            let stack: Vec<Routine> = stack.iter().map(|address| routine(*address)).collect();
+            profiler.stacks.insert(stack.clone(), *cycles);
+            *profiler.calls.entry(*stack.last().unwrap()).or_default() += 1;
+            profiler.total_cycles += cycles;
+        }
+        profiler
+    }
+
+    #[test]
+    fn test_routine_totals() {
+        let profiler = profiler(&[
+            (&[0x150], 60),
+            (&[0x150, 0x200], 30),
+            (&[0x150, 0x200, 0x200], 6),
+            (&[0x150, 0x40], 4),
+        ]);
+        let routines = profiler.routines();
+
+        assert_eq!(
+            RoutineProfile {
+                self_cycles: 60,
+                total_cycles: 100,
+                calls: 1
+            },
+            routines[&routine(0x150)]
+        );
+        // Recursion isn't counted twice towards the total
+        assert_eq!(
+            RoutineProfile {
+                self_cycles: 36,
+                total_cycles: 36,
+                calls: 2
+            },
+            routines[&routine(0x200)]
+        );
+    }
+
+    #[test]
+    fn test_reports() {
+        let profiler = profiler(&[(&[0x150], 60), (&[0x150, 0x201], 36), (&[0x150, 0x40], 4)]);
+
+        assert_eq!(
+            "Main 60\nMain;Draw+1 36\nMain;VBlank 4\n",
+            profiler.collapsed_stacks()
+        );
+        assert_eq!(
+            "\
+100.0% 100 cycles, 60 in itself: Main
+  36.0% 36 cycles, 36 in itself: Draw+1
+  4.0% 4 cycles, 4 in itself: VBlank
+",
+            profiler.tree_report()
+        );
+
+        let flat = profiler.flat_report();
+        let lines: Vec<&str> = flat.lines().collect();
+        assert!(
+            lines[1].ends_with("60   60.0%          100  100.0%        1  Main"),
+            "{}",
+            flat
+        );
+        assert!(lines[2].ends_with("Draw+1"), "{}", flat);
+    }
+
+    #[test]
+    fn test_unnamed_routines() {
+        let profiler = Profiler::new();
+        assert_eq!(
+            "01:4000",
+            profiler.routine_name(Routine {
+                bank: 1,
+                address: 0x4000
+            })
+        );
+        assert_eq!("-", profiler.percentage(0));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
 
 pub struct Ram<const N: usize> {
     ram: [u8; N],
@@ -19,3 +20,14 @@ impl<const N: usize> MemoryMapped for Ram<N> {
         self.ram[address as usize] = value
     }
 }
+
+impl<const N: usize> SaveState for Ram<N> {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.ram);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.ram.copy_from_slice(reader.read_bytes(N)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use crate::save_state::SaveStateResult;
+use crate::system::System;
+use std::collections::VecDeque;
+
+const DEFAULT_KEYFRAME_INTERVAL: usize = 60;
+
+/// A keyframe holding a full save state, and the frames after it as deltas against it
+struct Segment {
+    keyframe: Vec<u8>,
+    deltas: Vec<Vec<u8>>,
+}
+
+impl Segment {
+    fn bytes(&self) -> usize {
+        self.keyframe.len() + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    fn frames(&self) -> usize {
+        1 + self.deltas.len()
+    }
+}
+
+/// A ring buffer of recent states for stepping emulation backwards
+///
+/// Every frame is saved with `System::save_state`. Most of a state doesn't change from
+/// frame to frame, so only every `keyframe_interval`th state is kept whole, and the rest are
+/// stored XORed against that keyframe and run-length encoded.
+/// The oldest frames are dropped, a whole keyframe at a time, to stay under the memory budget.
+pub struct Rewind {
+    segments: VecDeque<Segment>,
+    budget_bytes: usize,
+    keyframe_interval: usize,
+    used_bytes: usize,
+}
+
+impl Rewind {
+    pub fn new(budget_bytes: usize) -> Rewind {
+        Rewind::with_keyframe_interval(budget_bytes, DEFAULT_KEYFRAME_INTERVAL)
+    }
+
+    /// Fewer frames between keyframes makes stepping back cheaper, but takes more memory
+    pub fn with_keyframe_interval(budget_bytes: usize, keyframe_interval: usize) -> Rewind {
+        Rewind {
+            segments: VecDeque::new(),
+            budget_bytes,
+            keyframe_interval: keyframe_interval.max(1),
+            used_bytes: 0,
+        }
+    }
+
+    /// Record the system's current state as the newest frame
+    pub fn push(&mut self, system: &System) {
+        let state = system.save_state();
+
+        match self.segments.back_mut() {
+            Some(segment) if segment.frames() < self.keyframe_interval => {
+                let delta = encode_delta(&segment.keyframe, &state);
+                self.used_bytes += delta.len();
+                segment.deltas.push(delta);
+            }
+            _ => {
+                self.used_bytes += state.len();
+                self.segments.push_back(Segment {
+                    keyframe: state,
+                    deltas: Vec::new(),
+                });
+            }
+        }
+
+        // The newest segment is always kept, even if it alone is over budget
+        while self.used_bytes > self.budget_bytes && self.segments.len() > 1 {
+            let segment = self.segments.pop_front().unwrap();
+            self.used_bytes -= segment.bytes();
+        }
+    }
+
+    /// Restore the newest frame and remove it, so the next step goes back one further
+    /// Returns false, leaving the system alone, when there are no frames left
+    pub fn step_back(&mut self, system: &mut System) -> SaveStateResult<bool> {
+        let segment = match self.segments.back_mut() {
+            Some(segment) => segment,
+            None => return Ok(false),
+        };
+
+        let state = match segment.deltas.pop() {
+            Some(delta) => {
+                self.used_bytes -= delta.len();
+                decode_delta(&segment.keyframe, &delta)
+            }
+            None => {
+                let segment = self.segments.pop_back().unwrap();
+                self.used_bytes -= segment.keyframe.len();
+                segment.keyframe
+            }
+        };
+
+        system.load_state(&state)?;
+        Ok(true)
+    }
+
+    /// Step back `frames` frames, returning the system to how it was when that frame was pushed
+    /// Returns how many frames were actually stepped back, which is fewer if the buffer ran out
+    pub fn rewind(&mut self, system: &mut System, frames: usize) -> SaveStateResult<usize> {
+        let mut stepped = 0;
+        while stepped < frames && self.step_back(system)? {
+            stepped += 1;
+        }
+
+        Ok(stepped)
+    }
+
+    /// The number of frames that can be stepped back
+    pub fn len(&self) -> usize {
+        self.segments.iter().map(Segment::frames).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.is_empty()
+    }
+
+    pub fn used_bytes(&self) -> usize {
+        self.used_bytes
+    }
+
+    pub fn clear(&mut self) {
+        self.segments.clear();
+        self.used_bytes = 0;
+    }
+}
+
+/// XOR `state` against `keyframe`, then encode runs of unchanged bytes
+///
+/// The delta starts with the state's length, followed by pairs of an unchanged run length
+/// and a changed run length, each as a LEB128 varint, with the changed bytes after each pair.
+/// Bytes past the end of the keyframe are XORed against zero.
+fn encode_delta(keyframe: &[u8], state: &[u8]) -> Vec<u8> {
+    let xor = |index: usize| state[index] ^ keyframe.get(index).copied().unwrap_or(0);
+
+    let mut delta = Vec::new();
+    write_varint(&mut delta, state.len());
+
+    let mut index = 0;
+    while index < state.len() {
+        let unchanged_start = index;
+        while index < state.len() && xor(index) == 0 {
+            index += 1;
+        }
+
+        let changed_start = index;
+        while index < state.len() && xor(index) != 0 {
+            index += 1;
+        }
+
+        write_varint(&mut delta, changed_start - unchanged_start);
+        write_varint(&mut delta, index - changed_start);
+        delta.extend((changed_start..index).map(xor));
+    }
+
+    delta
+}
+
+fn decode_delta(keyframe: &[u8], delta: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let len = read_varint(delta, &mut position);
+
+    let mut state: Vec<u8> = (0..len)
+        .map(|index| keyframe.get(index).copied().unwrap_or(0))
+        .collect();
+
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+
+        let changed = read_varint(delta, &mut position);
+        for byte in &delta[position..position + changed] {
+            state[index] ^= byte;
+            index += 1;
+        }
+        position += changed;
+    }
+
+    state
+}
+
+fn write_varint(bytes: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+
+        if value == 0 {
+            bytes.push(byte);
+            return;
+        }
+        bytes.push(byte | 0x80);
+    }
+}
+
+fn read_varint(bytes: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = bytes[*position];
+        *position += 1;
+
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 0x7F, 0x80, 0x3FFF, 0x4000, 0x12345678] {
+            let mut bytes = Vec::new();
+            write_varint(&mut bytes, value);
+
+            let mut position = 0;
+            assert_eq!(value, read_varint(&bytes, &mut position));
+            assert_eq!(bytes.len(), position);
+        }
+    }
+
+    #[test]
+    fn test_delta_round_trip() {
+        let keyframe = vec![0x11; 1000];
+        let mut state = keyframe.clone();
+        state[10] = 0x22;
+        state[500..505].fill(0x33);
+
+        let delta = encode_delta(&keyframe, &state);
+        assert!(delta.len() < 20);
+        assert_eq!(state, decode_delta(&keyframe, &delta));
+    }
+
+    #[test]
+    fn test_delta_length_changes() {
+        let keyframe = vec![1, 2, 3, 4];
+
+        let longer = vec![1, 2, 3, 4, 5, 6];
+        assert_eq!(
+            longer,
+            decode_delta(&keyframe, &encode_delta(&keyframe, &longer))
+        );
+
+        let shorter = vec![1, 9];
+        assert_eq!(
+            shorter,
+            decode_delta(&keyframe, &encode_delta(&keyframe, &shorter))
+        );
+    }
+}
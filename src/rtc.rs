@@ -0,0 +1,52 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Machine cycles per second at normal speed
+pub const CYCLES_PER_SECOND: u64 = 1 << 20;
+
+/// Where a cartridge's real time clock gets the time from
+///
+/// This is the only way the wall clock can reach a `System`, so anything that needs to run
+/// the same way twice, like movie playback, must use a source that doesn't read it.
+pub trait RtcSource {
+    /// Seconds since the Unix epoch, given the machine cycles elapsed since power on
+    fn seconds(&self, cycles: u64) -> u64;
+}
+
+/// The host's clock, as a real cartridge would see it
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WallClock;
+
+impl RtcSource for WallClock {
+    fn seconds(&self, _cycles: u64) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+/// A clock that starts at a fixed time and only advances as the system runs
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct EmulatedClock {
+    pub start: u64,
+}
+
+impl RtcSource for EmulatedClock {
+    fn seconds(&self, cycles: u64) -> u64 {
+        self.start + cycles / CYCLES_PER_SECOND
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emulated_clock() {
+        let clock = EmulatedClock { start: 1000 };
+
+        assert_eq!(1000, clock.seconds(0));
+        assert_eq!(1000, clock.seconds(CYCLES_PER_SECOND - 1));
+        assert_eq!(1002, clock.seconds(2 * CYCLES_PER_SECOND));
+    }
+}
@@ -0,0 +1,483 @@
+use std::{error, fmt};
+
+const MAGIC: [u8; 4] = *b"GBRS";
+
+/// Bump this whenever any component changes what it saves
+/// Components that change read `StateReader::version` to keep loading older states
+pub const VERSION: u16 = 5;
+/// The oldest version that can still be loaded
+const MIN_VERSION: u16 = 1;
+
+// Magic, version, ROM checksum, and payload length
+const HEADER_BYTES: usize = 4 + 2 + 2 + 4;
+const CHECKSUM_BYTES: usize = 4;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SaveStateError {
+    /// Not a save state at all
+    BadMagic,
+    UnsupportedVersion {
+        version: u16,
+    },
+    /// The state ended before everything was loaded
+    Truncated,
+    ChecksumMismatch {
+        actual: u32,
+        expected: u32,
+    },
+    /// The state was saved while running a different ROM
+    WrongRom {
+        actual: u16,
+        expected: u16,
+    },
+    /// A value that no component could have saved
+    InvalidValue {
+        field: &'static str,
+        value: u8,
+    },
+    /// Bytes were left over once everything was loaded, so the state doesn't match its version
+    TrailingBytes {
+        count: usize,
+    },
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { version } => write!(
+                f,
+                "save state version {} is unsupported, only {}..={} can be loaded",
+                version, MIN_VERSION, VERSION
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::ChecksumMismatch { actual, expected } => write!(
+                f,
+                "save state checksum is {:#010X}, but should be {:#010X}",
+                actual, expected
+            ),
+            SaveStateError::WrongRom { actual, expected } => write!(
+                f,
+                "save state is for a ROM with checksum {:#06X}, but this ROM's is {:#06X}",
+                expected, actual
+            ),
+            SaveStateError::InvalidValue { field, value } => {
+                write!(f, "invalid {} {:#04X} in save state", field, value)
+            }
+            SaveStateError::TrailingBytes { count } => {
+                write!(f, "save state has {} bytes left over after loading", count)
+            }
+        }
+    }
+}
+
+impl error::Error for SaveStateError {}
+
+pub type SaveStateResult<T> = Result<T, SaveStateError>;
+
+/// A component whose state can be saved and restored
+/// Everything is written in a fixed order with no field names, so saving and loading must mirror
+/// each other exactly
+pub trait SaveState {
+    fn save_state(&self, writer: &mut StateWriter);
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()>;
+}
+
+/// Writes values little-endian, like the rest of the GameBoy
+#[derive(Default)]
+pub struct StateWriter {
+    bytes: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn write_u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.write_u8(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// Write the bytes prefixed with their length, for data that doesn't have a fixed size
+    pub fn write_sized_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.write_bytes(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+pub struct StateReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    version: u16,
+}
+
+impl<'a> StateReader<'a> {
+    /// Read bytes that have no save state header, such as other formats built on these values
+    pub fn new(bytes: &'a [u8]) -> StateReader<'a> {
+        StateReader {
+            bytes,
+            position: 0,
+            version: VERSION,
+        }
+    }
+
+    /// Read bytes as if saved by an older version, to test that components still load them
+    #[cfg(test)]
+    pub(crate) fn with_version(bytes: &'a [u8], version: u16) -> StateReader<'a> {
+        StateReader {
+            bytes,
+            position: 0,
+            version,
+        }
+    }
+
+    /// The version the state was saved with
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn read_u8(&mut self) -> SaveStateResult<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> SaveStateResult<bool> {
+        match self.read_u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(SaveStateError::InvalidValue {
+                field: "bool",
+                value,
+            }),
+        }
+    }
+
+    pub fn read_u16(&mut self) -> SaveStateResult<u16> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u32(&mut self) -> SaveStateResult<u32> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_u64(&mut self) -> SaveStateResult<u64> {
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(self.read_bytes(8)?);
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> SaveStateResult<&'a [u8]> {
+        let end = self.position + len;
+        if end > self.bytes.len() {
+            return Err(SaveStateError::Truncated);
+        }
+
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    pub fn read_sized_bytes(&mut self) -> SaveStateResult<&'a [u8]> {
+        let len = self.read_u32()? as usize;
+        self.read_bytes(len)
+    }
+
+    /// Check that every byte has been read, as it will have been if loading mirrored saving
+    pub fn finish(&self) -> SaveStateResult<()> {
+        match self.bytes.len() - self.position {
+            0 => Ok(()),
+            count => Err(SaveStateError::TrailingBytes { count }),
+        }
+    }
+}
+
+/// Wrap a component's state with a header and checksum
+/// `rom_checksum` identifies the ROM, so a state can't be loaded into a different game
+pub fn save(component: &impl SaveState, rom_checksum: u16) -> Vec<u8> {
+    let mut payload = StateWriter::default();
+    component.save_state(&mut payload);
+
+    let mut writer = StateWriter::default();
+    writer.write_bytes(&MAGIC);
+    writer.write_u16(VERSION);
+    writer.write_u16(rom_checksum);
+    writer.write_sized_bytes(&payload.bytes);
+
+    let checksum = crc32(&writer.bytes);
+    writer.write_u32(checksum);
+
+    writer.bytes
+}
+
+/// Check a state's header and checksum, then load it into the component
+/// The component is left as it was unless the whole state loads, so a bad payload can't leave it
+/// partly loaded
+pub fn load(
+    component: &mut impl SaveState,
+    state: &[u8],
+    rom_checksum: u16,
+) -> SaveStateResult<()> {
+    if state.len() < HEADER_BYTES + CHECKSUM_BYTES {
+        return Err(SaveStateError::Truncated);
+    }
+
+    let mut reader = StateReader {
+        bytes: state,
+        position: 0,
+        version: VERSION,
+    };
+
+    if reader.read_bytes(MAGIC.len())? != MAGIC {
+        return Err(SaveStateError::BadMagic);
+    }
+
+    let version = reader.read_u16()?;
+    if !(MIN_VERSION..=VERSION).contains(&version) {
+        return Err(SaveStateError::UnsupportedVersion { version });
+    }
+
+    let saved_rom_checksum = reader.read_u16()?;
+    let payload = reader.read_sized_bytes()?;
+
+    let checksummed_bytes = reader.position;
+    let expected_checksum = reader.read_u32()?;
+    reader.finish()?;
+    let actual_checksum = crc32(&state[..checksummed_bytes]);
+    if actual_checksum != expected_checksum {
+        return Err(SaveStateError::ChecksumMismatch {
+            actual: actual_checksum,
+            expected: expected_checksum,
+        });
+    }
+
+    if saved_rom_checksum != rom_checksum {
+        return Err(SaveStateError::WrongRom {
+            actual: rom_checksum,
+            expected: saved_rom_checksum,
+        });
+    }
+
+    // Whatever the payload does to the component, it can be put back from this
+    let mut backup = StateWriter::default();
+    component.save_state(&mut backup);
+
+    let mut reader = StateReader {
+        bytes: payload,
+        position: 0,
+        version,
+    };
+    let result = component
+        .load_state(&mut reader)
+        .and_then(|()| reader.finish());
+
+    if result.is_err() {
+        component
+            .load_state(&mut StateReader::new(&backup.bytes))
+            .expect("components load what they have just saved");
+    }
+
+    result
+}
+
+/// The checksum at the end of a state from `save`, which is also a hash of everything in it
+/// None when there are too few bytes to hold one
+pub fn checksum(state: &[u8]) -> Option<u32> {
+    let bytes = state.get(state.len().checked_sub(CHECKSUM_BYTES)?..)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// CRC-32 as used by zlib and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB88320 & mask);
+        }
+    }
+
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Default, Eq, PartialEq)]
+    struct Component {
+        flag: bool,
+        byte: u8,
+        word: u16,
+        bytes: Vec<u8>,
+    }
+
+    impl SaveState for Component {
+        fn save_state(&self, writer: &mut StateWriter) {
+            writer.write_bool(self.flag);
+            writer.write_u8(self.byte);
+            writer.write_u16(self.word);
+            writer.write_sized_bytes(&self.bytes);
+        }
+
+        fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+            self.flag = reader.read_bool()?;
+            self.byte = reader.read_u8()?;
+            self.word = reader.read_u16()?;
+            self.bytes = reader.read_sized_bytes()?.to_vec();
+            Ok(())
+        }
+    }
+
+    /// A payload saved as is, to build states no component would save
+    struct Saved(Vec<u8>);
+
+    impl SaveState for Saved {
+        fn save_state(&self, writer: &mut StateWriter) {
+            writer.write_bytes(&self.0);
+        }
+
+        fn load_state(&mut self, _reader: &mut StateReader) -> SaveStateResult<()> {
+            Ok(())
+        }
+    }
+
+    fn component() -> Component {
+        Component {
+            flag: true,
+            byte: 0x12,
+            word: 0x3456,
+            bytes: vec![1, 2, 3],
+        }
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0xCBF43926, crc32(b"123456789"));
+    }
+
+    #[test]
+    fn test_checksum() {
+        let state = save(&component(), 0xABCD);
+        assert_eq!(
+            Some(crc32(&state[..state.len() - CHECKSUM_BYTES])),
+            checksum(&state)
+        );
+        assert_eq!(None, checksum(&state[..3]));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let state = save(&component(), 0xABCD);
+
+        let mut loaded = Component::default();
+        load(&mut loaded, &state, 0xABCD).unwrap();
+
+        assert_eq!(component(), loaded);
+    }
+
+    #[test]
+    fn test_corrupt_state_is_not_loaded() {
+        let mut state = save(&component(), 0xABCD);
+        state[HEADER_BYTES] ^= 0x01;
+
+        let mut loaded = Component::default();
+        assert!(matches!(
+            load(&mut loaded, &state, 0xABCD),
+            Err(SaveStateError::ChecksumMismatch { .. })
+        ));
+        assert_eq!(Component::default(), loaded);
+    }
+
+    #[test]
+    fn test_header_errors() {
+        let state = save(&component(), 0xABCD);
+        let mut loaded = Component::default();
+
+        assert_eq!(
+            Err(SaveStateError::WrongRom {
+                actual: 0x1234,
+                expected: 0xABCD
+            }),
+            load(&mut loaded, &state, 0x1234)
+        );
+
+        assert_eq!(
+            Err(SaveStateError::Truncated),
+            load(&mut loaded, &state[..state.len() - 1], 0xABCD)
+        );
+
+        let mut future_state = state.clone();
+        future_state[4..6].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert_eq!(
+            Err(SaveStateError::UnsupportedVersion {
+                version: VERSION + 1
+            }),
+            load(&mut loaded, &future_state, 0xABCD)
+        );
+
+        assert_eq!(
+            Err(SaveStateError::BadMagic),
+            load(&mut loaded, &[0; 32], 0xABCD)
+        );
+
+        let mut padded_state = state.clone();
+        padded_state.push(0);
+        assert_eq!(
+            Err(SaveStateError::TrailingBytes { count: 1 }),
+            load(&mut loaded, &padded_state, 0xABCD)
+        );
+    }
+
+    #[test]
+    fn test_unread_payload_is_an_error() {
+        // As if a newer component had saved a field this one doesn't know to load
+        let mut writer = StateWriter::default();
+        component().save_state(&mut writer);
+        writer.write_u8(0xFF);
+        let state = save(&Saved(writer.into_bytes()), 0xABCD);
+
+        let mut loaded = Component::default();
+        assert_eq!(
+            Err(SaveStateError::TrailingBytes { count: 1 }),
+            load(&mut loaded, &state, 0xABCD)
+        );
+        assert_eq!(Component::default(), loaded);
+    }
+
+    #[test]
+    fn test_bad_payload_leaves_component_alone() {
+        // The flag and byte load before the word runs out
+        let state = save(&Saved(vec![0x01, 0x12, 0x56]), 0xABCD);
+
+        let mut loaded = component();
+        assert_eq!(
+            Err(SaveStateError::Truncated),
+            load(&mut loaded, &state, 0xABCD)
+        );
+        assert_eq!(component(), loaded);
+    }
+}
@@ -0,0 +1,198 @@
+use crate::memory::MemoryMapped;
+use std::io;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+/// Offsets into VRAM, for the tile map and tile data the boot ROM leaves LCDC pointing at
+const BACKGROUND_MAP: usize = 0x1800;
+const TILE_DATA: usize = 0x0000;
+const MAP_COLUMNS: usize = 32;
+const TILE_BYTES: usize = 16;
+
+/// How each shade looks, from lightest to darkest
+const GREYS: [u8; 4] = [0xFF, 0xAA, 0x55, 0x00];
+
+/// The background layer as VRAM holds it, as 160x144 shades in 0..=3, row by row
+/// This uses the tile map at $9800 and tiles at $8000, unscrolled, with each colour as its own
+/// shade, since there are no LCDC, SCX, SCY or BGP registers to say otherwise.
+// TODO: Take the frame from the PPU once there is one, with the window and sprites
+pub fn background(vram: &impl MemoryMapped) -> Vec<u8> {
+    let mut screen = Vec::with_capacity(SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    for y in 0..SCREEN_HEIGHT {
+        for x in 0..SCREEN_WIDTH {
+            let tile = vram.read_byte((BACKGROUND_MAP + (y / 8) * MAP_COLUMNS + x / 8) as u16);
+            let row = TILE_DATA + tile as usize * TILE_BYTES + (y % 8) * 2;
+            let low = vram.read_byte(row as u16);
+            let high = vram.read_byte(row as u16 + 1);
+
+            let bit = 7 - x % 8;
+            screen.push((((high >> bit) & 1) << 1) | ((low >> bit) & 1));
+        }
+    }
+
+    screen
+}
+
+/// Encode a screen of shades as a greyscale PNG
+pub fn to_png(screen: &[u8], writer: impl io::Write) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let pixels: Vec<u8> = screen
+        .iter()
+        .map(|shade| GREYS[(shade & 0b11) as usize])
+        .collect();
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(io::Error::other)
+}
+
+/// Encode a frame of any size, such as the Super Game Boy's with its border, as an RGB PNG
+pub fn to_rgb_png(
+    pixels: &[[u8; 3]],
+    width: usize,
+    height: usize,
+    writer: impl io::Write,
+) -> io::Result<()> {
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(pixels.as_flattened()))
+        .map_err(io::Error::other)
+}
+
+/// Decode a 160x144 PNG into shades, taking each pixel's brightness to the nearest shade
+/// so that reference images in any palette of greens or greys can be compared
+pub fn from_png(reader: impl io::Read) -> io::Result<Vec<u8>> {
+    let mut decoder = png::Decoder::new(reader);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut pixels).map_err(io::Error::other)?;
+    if (info.width as usize, info.height as usize) != (SCREEN_WIDTH, SCREEN_HEIGHT) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("the image is {}x{}, not 160x144", info.width, info.height),
+        ));
+    }
+
+    let channels = info.color_type.samples();
+    let screen = pixels[..info.buffer_size()]
+        .chunks(channels)
+        .map(|pixel| {
+            let brightness = match pixel {
+                [grey] | [grey, _] => *grey as u32,
+                [red, green, blue, ..] => {
+                    (*red as u32 * 299 + *green as u32 * 587 + *blue as u32 * 114) / 1000
+                }
+                _ => unreachable!("PNG pixels have 1 to 4 samples"),
+            };
+            3 - (brightness / 64) as u8
+        })
+        .collect();
+
+    Ok(screen)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ram::Ram;
+
+    #[test]
+    fn test_background() {
+        let mut vram: Ram<0x2000> = Ram::default();
+
+        // Tile 1's first row has every shade, twice over
+        vram.write_byte(0x0010, 0b0101_0101);
+        vram.write_byte(0x0011, 0b0011_0011);
+        // At the second tile across and the second tile down
+        vram.write_byte((BACKGROUND_MAP + MAP_COLUMNS + 1) as u16, 1);
+
+        let screen = background(&vram);
+        assert_eq!(SCREEN_WIDTH * SCREEN_HEIGHT, screen.len());
+        assert_eq!(
+            [0, 1, 2, 3, 0, 1, 2, 3],
+            screen[8 * SCREEN_WIDTH + 8..8 * SCREEN_WIDTH + 16]
+        );
+        assert_eq!(6, screen.iter().filter(|shade| **shade != 0).count());
+    }
+
+    #[test]
+    fn test_png() {
+        let mut bytes = Vec::new();
+        to_png(&vec![3; SCREEN_WIDTH * SCREEN_HEIGHT], &mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!((160, 144), (info.width, info.height));
+        assert!(pixels.iter().all(|grey| *grey == 0));
+    }
+
+    #[test]
+    fn test_rgb_png() {
+        let mut bytes = Vec::new();
+        to_rgb_png(&[[0xFF, 0x00, 0x80]; 4 * 2], 4, 2, &mut bytes).unwrap();
+
+        let mut reader = png::Decoder::new(bytes.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+
+        assert_eq!((4, 2), (info.width, info.height));
+        assert_eq!([0xFF, 0x00, 0x80], pixels[21..24]);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        let screen: Vec<u8> = (0..SCREEN_WIDTH * SCREEN_HEIGHT)
+            .map(|pixel| (pixel % 4) as u8)
+            .collect();
+
+        let mut bytes = Vec::new();
+        to_png(&screen, &mut bytes).unwrap();
+        assert_eq!(screen, from_png(bytes.as_slice()).unwrap());
+    }
+
+    #[test]
+    fn test_reference_png() {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 160, 144);
+        encoder.set_color(png::ColorType::Rgb);
+        // The classic green of the DMG's darkest shade
+        let pixels = [0x0F, 0x38, 0x0F].repeat(160 * 144);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&pixels)
+            .unwrap();
+
+        assert!(from_png(bytes.as_slice())
+            .unwrap()
+            .iter()
+            .all(|shade| *shade == 3));
+
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, 8, 8);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder
+            .write_header()
+            .unwrap()
+            .write_image_data(&[0; 64])
+            .unwrap();
+        assert_eq!(
+            io::ErrorKind::InvalidData,
+            from_png(bytes.as_slice()).unwrap_err().kind()
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
+
+pub const SB_ADDRESS: u16 = 0xFF01;
+pub const SC_ADDRESS: u16 = 0xFF02;
+
+const TRANSFER_START: u8 = 0x80;
+const INTERNAL_CLOCK: u8 = 0x01;
+
+/// The save state version serial registers were first saved in
+const SAVED_SINCE_VERSION: u16 = 2;
+
+/// SB and SC - the serial port, with no link cable connected
+/// Writing SC with bits 7 and 0 set sends SB using the GameBoy's own clock. Nothing is on the
+/// other end, so 1 bits are shifted in and SB reads back $FF afterwards.
+/// Every byte sent is kept, since test ROMs report their results this way.
+// TODO: Take 8 bits' worth of cycles and request the serial interrupt
+#[derive(Default)]
+pub struct Serial {
+    data: u8,
+    control: u8,
+    output: Vec<u8>,
+}
+
+impl Serial {
+    /// Every byte sent since the system started
+    pub fn output(&self) -> &[u8] {
+        &self.output
+    }
+}
+
+impl MemoryMapped for Serial {
+    fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            SB_ADDRESS => self.data,
+            // Unused bits read back as 1
+            _ => self.control | 0x7E,
+        }
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        match address {
+            SB_ADDRESS => self.data = value,
+            _ if value & (TRANSFER_START | INTERNAL_CLOCK) == TRANSFER_START | INTERNAL_CLOCK => {
+                self.output.push(self.data);
+                self.data = 0xFF;
+                // The transfer finishes at once, so it is never seen in progress
+                self.control = value & !TRANSFER_START;
+            }
+            _ => self.control = value,
+        }
+    }
+}
+
+/// The output is a log for tools rather than state, so it isn't saved
+impl SaveState for Serial {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(self.data);
+        writer.write_u8(self.control);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        if reader.version() >= SAVED_SINCE_VERSION {
+            self.data = reader.read_u8()?;
+            self.control = reader.read_u8()?;
+        } else {
+            *self = Serial {
+                output: std::mem::take(&mut self.output),
+                ..Serial::default()
+            };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_send() {
+        let mut serial = Serial::default();
+
+        for byte in b"Ok" {
+            serial.write_byte(SB_ADDRESS, *byte);
+            serial.write_byte(SC_ADDRESS, 0x81);
+        }
+        assert_eq!(b"Ok", serial.output());
+        assert_eq!(0xFF, serial.read_byte(SB_ADDRESS));
+        assert_eq!(0x7F, serial.read_byte(SC_ADDRESS));
+
+        // Waiting for another GameBoy to clock the transfer, which never happens
+        serial.write_byte(SB_ADDRESS, b'!');
+        serial.write_byte(SC_ADDRESS, 0x80);
+        assert_eq!(b"Ok", serial.output());
+        assert_eq!(0xFE, serial.read_byte(SC_ADDRESS));
+    }
+}
@@ -0,0 +1,25 @@
+/// A 15-bit SNES color, laid out as 0bbbbbgggggrrrrr
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct Color(pub u16);
+
+impl Color {
+    pub const WHITE: Color = Color(0x7FFF);
+    pub const LIGHT_GREY: Color = Color(0x56B5);
+    pub const DARK_GREY: Color = Color(0x294A);
+    pub const BLACK: Color = Color(0x0000);
+
+    pub fn from_le_bytes(bytes: [u8; 2]) -> Color {
+        // The top bit is unused
+        Color(u16::from_le_bytes(bytes) & 0x7FFF)
+    }
+
+    /// Expand to 8 bits per channel, in red, green, blue order
+    pub fn to_rgb(self) -> [u8; 3] {
+        let expand = |channel: u16| {
+            let channel = (channel & 0x1F) as u8;
+            (channel << 3) | (channel >> 2)
+        };
+
+        [expand(self.0), expand(self.0 >> 5), expand(self.0 >> 10)]
+    }
+}
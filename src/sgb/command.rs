@@ -0,0 +1,402 @@
+use crate::cartridge::parse::{Field, Parse, ParseError, ParseResult};
+use crate::sgb::color::Color;
+use crate::sgb::packet::Packet;
+
+const PAL01: u8 = 0x00;
+const PAL23: u8 = 0x01;
+const PAL03: u8 = 0x02;
+const PAL12: u8 = 0x03;
+const ATTR_BLK: u8 = 0x04;
+const ATTR_LIN: u8 = 0x05;
+const ATTR_DIV: u8 = 0x06;
+const ATTR_CHR: u8 = 0x07;
+const PAL_SET: u8 = 0x0A;
+const PAL_TRN: u8 = 0x0B;
+const MLT_REQ: u8 = 0x11;
+const CHR_TRN: u8 = 0x13;
+const PCT_TRN: u8 = 0x14;
+const MASK_EN: u8 = 0x17;
+
+pub const SYSTEM_PALETTES: u16 = 512;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    /// PAL01, PAL23, PAL03 and PAL12
+    /// Sets the shared color 0, then colors 1-3 of each of the two palettes
+    Pal {
+        palettes: [usize; 2],
+        colors: [Color; 7],
+    },
+    AttrBlk(Vec<AttrBlock>),
+    AttrLin(Vec<AttrLine>),
+    AttrDiv(AttrDiv),
+    AttrChr(AttrChr),
+    PalSet {
+        system_palettes: [u16; 4],
+        cancel_mask: bool,
+    },
+    PalTrn,
+    ChrTrn {
+        high_tiles: bool,
+    },
+    PctTrn,
+    MltReq {
+        players: u8,
+    },
+    MaskEn(Mask),
+}
+
+/// A rectangle of the screen, in 8x8 character units, with a palette for each region around it
+/// A region left as None is not changed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttrBlock {
+    pub inside: Option<u8>,
+    pub line: Option<u8>,
+    pub outside: Option<u8>,
+
+    pub x1: u8,
+    pub y1: u8,
+    pub x2: u8,
+    pub y2: u8,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttrLine {
+    pub line: u8,
+    pub palette: u8,
+    pub horizontal: bool,
+}
+
+/// Splits the screen in two along a row or column
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttrDiv {
+    /// Palette left of or above the line
+    pub before: u8,
+    /// Palette right of or below the line
+    pub after: u8,
+    pub line: u8,
+    pub horizontal: bool,
+    pub coordinate: u8,
+}
+
+/// Per-character palettes, written from a starting character row by row or column by column
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct AttrChr {
+    pub x: u8,
+    pub y: u8,
+    pub vertical: bool,
+    pub palettes: Vec<u8>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Mask {
+    Cancel,
+    Freeze,
+    Black,
+    Color0,
+}
+
+impl Parse<&[Packet]> for Command {
+    /// Parse a command from all of its packets
+    /// The command code is the top 5 bits of the first byte, and the packet count the low 3
+    /// Only the first packet carries that header byte, the data continues through the rest
+    fn parse(packets: &[Packet]) -> ParseResult<Command> {
+        let first_packet = packets.first().ok_or(ParseError::TooShort {
+            field: Field::SgbPackets,
+            minimum: 1,
+            actual: 0,
+        })?;
+        let code = first_packet[0] >> 3;
+        let data: Vec<u8> = packets.iter().flatten().skip(1).copied().collect();
+
+        match code {
+            PAL01 => Ok(Command::parse_pal([0, 1], &data)),
+            PAL23 => Ok(Command::parse_pal([2, 3], &data)),
+            PAL03 => Ok(Command::parse_pal([0, 3], &data)),
+            PAL12 => Ok(Command::parse_pal([1, 2], &data)),
+            ATTR_BLK => Command::parse_attr_blk(&data),
+            ATTR_LIN => Command::parse_attr_lin(&data),
+            ATTR_DIV => Ok(Command::parse_attr_div(&data)),
+            ATTR_CHR => Command::parse_attr_chr(&data),
+            PAL_SET => Ok(Command::parse_pal_set(&data)),
+            PAL_TRN => Ok(Command::PalTrn),
+            MLT_REQ => Command::parse_mlt_req(data[0]),
+            CHR_TRN => Ok(Command::ChrTrn {
+                high_tiles: data[0] & 0x1 == 1,
+            }),
+            PCT_TRN => Ok(Command::PctTrn),
+            MASK_EN => Ok(Command::parse_mask_en(data[0])),
+            _ => Err(ParseError::invalid_code(Field::SgbCommand, code)),
+        }
+    }
+}
+
+impl Command {
+    fn parse_pal(palettes: [usize; 2], data: &[u8]) -> Command {
+        let mut colors = [Color::default(); 7];
+        for (i, color) in colors.iter_mut().enumerate() {
+            *color = Color::from_le_bytes([data[i * 2], data[i * 2 + 1]]);
+        }
+
+        Command::Pal { palettes, colors }
+    }
+
+    fn parse_attr_blk(data: &[u8]) -> ParseResult<Command> {
+        let data_sets = Command::data_sets(data[0] as usize, 6, &data[1..])?;
+
+        let blocks = data_sets
+            .chunks_exact(6)
+            .map(|set| {
+                let control = set[0] & 0x07;
+                let inside = set[1] & 0b11;
+                let line = (set[1] >> 2) & 0b11;
+                let outside = (set[1] >> 4) & 0b11;
+
+                let inside_set = control & 0b001 != 0;
+                let line_set = control & 0b010 != 0;
+                let outside_set = control & 0b100 != 0;
+
+                // When only the inside or only the outside is changed, the line follows it
+                let line = match (inside_set, line_set, outside_set) {
+                    (_, true, _) => Some(line),
+                    (true, false, false) => Some(inside),
+                    (false, false, true) => Some(outside),
+                    _ => None,
+                };
+
+                AttrBlock {
+                    inside: inside_set.then_some(inside),
+                    line,
+                    outside: outside_set.then_some(outside),
+
+                    x1: set[2] & 0x1F,
+                    y1: set[3] & 0x1F,
+                    x2: set[4] & 0x1F,
+                    y2: set[5] & 0x1F,
+                }
+            })
+            .collect();
+
+        Ok(Command::AttrBlk(blocks))
+    }
+
+    fn parse_attr_lin(data: &[u8]) -> ParseResult<Command> {
+        let data_sets = Command::data_sets(data[0] as usize, 1, &data[1..])?;
+
+        let lines = data_sets
+            .iter()
+            .map(|set| AttrLine {
+                line: set & 0x1F,
+                palette: (set >> 5) & 0b11,
+                horizontal: set & 0x80 != 0,
+            })
+            .collect();
+
+        Ok(Command::AttrLin(lines))
+    }
+
+    fn parse_attr_div(data: &[u8]) -> Command {
+        Command::AttrDiv(AttrDiv {
+            after: data[0] & 0b11,
+            before: (data[0] >> 2) & 0b11,
+            line: (data[0] >> 4) & 0b11,
+            horizontal: data[0] & 0x40 != 0,
+            coordinate: data[1] & 0x1F,
+        })
+    }
+
+    fn parse_attr_chr(data: &[u8]) -> ParseResult<Command> {
+        let count = u16::from_le_bytes([data[2], data[3]]) as usize;
+        let packed_bytes = count.div_ceil(4);
+        let packed = Command::data_sets(packed_bytes, 1, &data[5..])?;
+
+        // Four palettes per byte, most significant bits first
+        let palettes = (0..count)
+            .map(|i| (packed[i / 4] >> (6 - (i % 4) * 2)) & 0b11)
+            .collect();
+
+        Ok(Command::AttrChr(AttrChr {
+            x: data[0] & 0x1F,
+            y: data[1] & 0x1F,
+            vertical: data[4] & 0x1 == 1,
+            palettes,
+        }))
+    }
+
+    fn parse_pal_set(data: &[u8]) -> Command {
+        let mut system_palettes = [0; 4];
+        for (i, palette) in system_palettes.iter_mut().enumerate() {
+            *palette = u16::from_le_bytes([data[i * 2], data[i * 2 + 1]]) & 0x1FF;
+        }
+
+        // TODO bits 0-5 and 7 of the attribute byte apply an ATF from ATTR_TRN
+        let cancel_mask = data[8] & 0x40 != 0;
+
+        Command::PalSet {
+            system_palettes,
+            cancel_mask,
+        }
+    }
+
+    fn parse_mlt_req(code: u8) -> ParseResult<Command> {
+        match code & 0b11 {
+            0b00 => Ok(Command::MltReq { players: 1 }),
+            0b01 => Ok(Command::MltReq { players: 2 }),
+            0b11 => Ok(Command::MltReq { players: 4 }),
+            _ => Err(ParseError::invalid_code(Field::SgbPlayers, code)),
+        }
+    }
+
+    fn parse_mask_en(code: u8) -> Command {
+        match code & 0b11 {
+            0 => Command::MaskEn(Mask::Cancel),
+            1 => Command::MaskEn(Mask::Freeze),
+            2 => Command::MaskEn(Mask::Black),
+            _ => Command::MaskEn(Mask::Color0),
+        }
+    }
+
+    /// Take `count` data sets of `set_bytes` each, failing if the packets were too short to hold them
+    fn data_sets(count: usize, set_bytes: usize, data: &[u8]) -> ParseResult<&[u8]> {
+        let len = count * set_bytes;
+        if len > data.len() {
+            return Err(ParseError::TooShort {
+                field: Field::SgbData,
+                minimum: len,
+                actual: data.len(),
+            });
+        }
+
+        Ok(&data[..len])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sgb::packet::PACKET_BYTES;
+
+    fn packet(bytes: &[u8]) -> Packet {
+        let mut packet = [0; PACKET_BYTES];
+        packet[..bytes.len()].copy_from_slice(bytes);
+        packet
+    }
+
+    #[test]
+    fn test_pal23() {
+        let command = Command::parse(&[packet(&[
+            0x09, 0xFF, 0x7F, 0x1F, 0x00, 0xE0, 0x03, 0x00, 0x7C, 0x01, 0x00, 0x02, 0x00, 0x03,
+            0x00,
+        ])])
+        .unwrap();
+
+        assert_eq!(
+            Command::Pal {
+                palettes: [2, 3],
+                colors: [
+                    Color::WHITE,
+                    Color(0x001F),
+                    Color(0x03E0),
+                    Color(0x7C00),
+                    Color(0x0001),
+                    Color(0x0002),
+                    Color(0x0003),
+                ],
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn test_attr_blk_line_follows_inside() {
+        let command = Command::parse(&[packet(&[
+            0x21, 0x02, 0x01, 0b00_10_11, 1, 2, 3, 4, 0x07, 0b00_10_01, 0, 0, 19, 17,
+        ])])
+        .unwrap();
+
+        assert_eq!(
+            Command::AttrBlk(vec![
+                AttrBlock {
+                    inside: Some(3),
+                    line: Some(3),
+                    outside: None,
+                    x1: 1,
+                    y1: 2,
+                    x2: 3,
+                    y2: 4,
+                },
+                AttrBlock {
+                    inside: Some(1),
+                    line: Some(2),
+                    outside: Some(0),
+                    x1: 0,
+                    y1: 0,
+                    x2: 19,
+                    y2: 17,
+                },
+            ]),
+            command
+        );
+    }
+
+    #[test]
+    fn test_attr_blk_spans_packets() {
+        let mut first = packet(&[0x22, 0x03]);
+        first[2..].copy_from_slice(&[0x04, 0x01, 0, 0, 1, 1, 0x04, 0x02, 2, 2, 3, 3, 0x04, 0x03]);
+        let second = packet(&[4, 4, 5, 5]);
+
+        match Command::parse(&[first, second]).unwrap() {
+            Command::AttrBlk(blocks) => {
+                assert_eq!(3, blocks.len());
+                assert_eq!(Some(0), blocks[2].outside);
+                assert_eq!(5, blocks[2].y2);
+            }
+            command => panic!("expected ATTR_BLK, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn test_attr_blk_too_many_sets() {
+        assert_eq!(
+            Err(ParseError::TooShort {
+                field: Field::SgbData,
+                minimum: 18,
+                actual: 14,
+            }),
+            Command::parse(&[packet(&[0x21, 0x03])])
+        );
+    }
+
+    #[test]
+    fn test_attr_chr() {
+        let command = Command::parse(&[packet(&[0x39, 18, 16, 5, 0, 1, 0b11_10_01_00, 0b01])]);
+
+        assert_eq!(
+            Ok(Command::AttrChr(AttrChr {
+                x: 18,
+                y: 16,
+                vertical: true,
+                palettes: vec![3, 2, 1, 0, 0],
+            })),
+            command
+        );
+    }
+
+    #[test]
+    fn test_mlt_req() {
+        assert_eq!(
+            Ok(Command::MltReq { players: 4 }),
+            Command::parse(&[packet(&[0x89, 0x03])])
+        );
+        assert!(Command::parse(&[packet(&[0x89, 0x02])]).is_err());
+    }
+
+    #[test]
+    fn test_unsupported() {
+        // SOUND is a real command, but has no effect on emulation
+        assert_eq!(
+            Err(ParseError::invalid_code(Field::SgbCommand, 0x08)),
+            Command::parse(&[packet(&[0x41])])
+        );
+    }
+}
@@ -0,0 +1,4 @@
+pub mod color;
+pub mod command;
+pub mod packet;
+pub mod super_game_boy;
@@ -0,0 +1,207 @@
+use crate::save_state::{SaveState, SaveStateError, SaveStateResult, StateReader, StateWriter};
+
+pub const PACKET_BYTES: usize = 16;
+
+const PACKET_BITS: usize = PACKET_BYTES * 8;
+
+pub type Packet = [u8; PACKET_BYTES];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum State {
+    Idle,
+    Receiving,
+    AwaitingStop,
+}
+
+/// Reassembles SGB command packets from writes to the joypad register
+///
+/// Each packet starts with a reset pulse (P14 and P15 both low), then sends 128 bits
+/// least significant bit first, then a stop bit of 0.
+/// A bit is a pulse of P14 low for 0 or P15 low for 1, with both lines raised between bits.
+pub struct PacketReceiver {
+    state: State,
+    ready_for_pulse: bool,
+    packet: Packet,
+    bit_index: usize,
+    packets: Vec<Packet>,
+}
+
+impl Default for PacketReceiver {
+    fn default() -> Self {
+        PacketReceiver {
+            state: State::Idle,
+            ready_for_pulse: false,
+            packet: [0; PACKET_BYTES],
+            bit_index: 0,
+            packets: Vec::new(),
+        }
+    }
+}
+
+impl PacketReceiver {
+    /// Feed a value written to P1
+    /// Returns every packet of a command once its final packet has been received
+    pub fn write(&mut self, value: u8) -> Option<Vec<Packet>> {
+        match (value >> 4) & 0b11 {
+            0b00 => {
+                self.state = State::Receiving;
+                self.ready_for_pulse = false;
+                self.packet = [0; PACKET_BYTES];
+                self.bit_index = 0;
+                None
+            }
+            0b11 => {
+                self.ready_for_pulse = true;
+                None
+            }
+            // P14 low sends a 0, P15 low sends a 1
+            lines => {
+                if !self.ready_for_pulse {
+                    return None;
+                }
+                self.ready_for_pulse = false;
+
+                self.receive_bit(lines == 0b01)
+            }
+        }
+    }
+
+    fn receive_bit(&mut self, bit: bool) -> Option<Vec<Packet>> {
+        match self.state {
+            State::Idle => None,
+            State::Receiving => {
+                if bit {
+                    self.packet[self.bit_index / 8] |= 1 << (self.bit_index % 8);
+                }
+
+                self.bit_index += 1;
+                if self.bit_index == PACKET_BITS {
+                    self.state = State::AwaitingStop;
+                }
+
+                None
+            }
+            State::AwaitingStop => {
+                self.state = State::Idle;
+
+                // A stop bit of 1 is malformed, so the whole command is dropped
+                if bit {
+                    self.packets.clear();
+                    return None;
+                }
+
+                self.packets.push(self.packet);
+                if self.packets.len() >= PacketReceiver::command_length(&self.packets[0]) {
+                    return Some(std::mem::take(&mut self.packets));
+                }
+
+                None
+            }
+        }
+    }
+
+    /// The number of packets in a command is given by the low 3 bits of its first byte
+    fn command_length(first_packet: &Packet) -> usize {
+        (first_packet[0] & 0x07).max(1) as usize
+    }
+}
+
+impl SaveState for PacketReceiver {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u8(match self.state {
+            State::Idle => 0,
+            State::Receiving => 1,
+            State::AwaitingStop => 2,
+        });
+        writer.write_bool(self.ready_for_pulse);
+        writer.write_bytes(&self.packet);
+        writer.write_u8(self.bit_index as u8);
+        writer.write_sized_bytes(&self.packets.concat());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.state = match reader.read_u8()? {
+            0 => State::Idle,
+            1 => State::Receiving,
+            2 => State::AwaitingStop,
+            value => {
+                return Err(SaveStateError::InvalidValue {
+                    field: "SGB packet state",
+                    value,
+                })
+            }
+        };
+        self.ready_for_pulse = reader.read_bool()?;
+        self.packet
+            .copy_from_slice(reader.read_bytes(PACKET_BYTES)?);
+        self.bit_index = (reader.read_u8()? as usize).min(PACKET_BITS);
+        self.packets = reader
+            .read_sized_bytes()?
+            .chunks_exact(PACKET_BYTES)
+            .map(|packet| packet.try_into().unwrap())
+            .collect();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn send(receiver: &mut PacketReceiver, packet: &Packet) -> Option<Vec<Packet>> {
+        receiver.write(0x00);
+        receiver.write(0x30);
+
+        for bit in 0..PACKET_BITS {
+            let value = if packet[bit / 8] & (1 << (bit % 8)) != 0 {
+                0x10
+            } else {
+                0x20
+            };
+            assert_eq!(None, receiver.write(value));
+            receiver.write(0x30);
+        }
+
+        let result = receiver.write(0x20);
+        receiver.write(0x30);
+        result
+    }
+
+    #[test]
+    fn test_single_packet() {
+        let mut receiver = PacketReceiver::default();
+        let mut packet = [0; PACKET_BYTES];
+        packet[0] = 0x89;
+        packet[1] = 0x01;
+        packet[15] = 0xA5;
+
+        assert_eq!(Some(vec![packet]), send(&mut receiver, &packet));
+    }
+
+    #[test]
+    fn test_multiple_packets() {
+        let mut receiver = PacketReceiver::default();
+        let first = [0x22; PACKET_BYTES];
+        let second = [0xFF; PACKET_BYTES];
+
+        assert_eq!(None, send(&mut receiver, &first));
+        assert_eq!(Some(vec![first, second]), send(&mut receiver, &second));
+    }
+
+    #[test]
+    fn test_bits_need_a_rising_edge() {
+        let mut receiver = PacketReceiver::default();
+        receiver.write(0x00);
+
+        // Without raising both lines first, pulses are ignored
+        receiver.write(0x10);
+        receiver.write(0x10);
+        assert_eq!(0, receiver.bit_index);
+
+        receiver.write(0x30);
+        receiver.write(0x10);
+        assert_eq!(1, receiver.bit_index);
+        assert_eq!(0x01, receiver.packet[0]);
+    }
+}
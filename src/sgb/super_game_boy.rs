@@ -0,0 +1,574 @@
+use crate::cartridge::parse::Parse;
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateError, SaveStateResult, StateReader, StateWriter};
+use crate::sgb::color::Color;
+use crate::sgb::command::{AttrBlock, AttrChr, AttrDiv, AttrLine, Command, Mask, SYSTEM_PALETTES};
+use crate::sgb::packet::PacketReceiver;
+
+pub use crate::screen::{SCREEN_HEIGHT, SCREEN_WIDTH};
+pub const BORDER_WIDTH: usize = 256;
+pub const BORDER_HEIGHT: usize = 224;
+
+// Where the Game Boy screen sits within the border
+const SCREEN_X: usize = 48;
+const SCREEN_Y: usize = 40;
+
+const ATTRIBUTE_COLUMNS: usize = SCREEN_WIDTH / 8;
+const ATTRIBUTE_ROWS: usize = SCREEN_HEIGHT / 8;
+
+const TRANSFER_BYTES: usize = 0x1000;
+const BORDER_TILES: usize = 256;
+const BORDER_TILE_BYTES: usize = 32;
+const BORDER_MAP_COLUMNS: usize = 32;
+const BORDER_MAP_ROWS: usize = BORDER_HEIGHT / 8;
+const BORDER_PALETTES_OFFSET: usize = 0x800;
+const FIRST_BORDER_PALETTE: usize = 4;
+
+const DEFAULT_PALETTE: [Color; 4] = [
+    Color::WHITE,
+    Color::LIGHT_GREY,
+    Color::DARK_GREY,
+    Color::BLACK,
+];
+
+/// Super Game Boy state driven by command packets sent over the joypad register
+///
+/// Rendering takes the Game Boy's screen as 2-bit shades after DMG palette mapping,
+/// and produces the full 256x224 SNES picture with the border around it.
+pub struct SuperGameBoy {
+    receiver: PacketReceiver,
+
+    palettes: [[Color; 4]; 4],
+    system_palettes: Vec<[Color; 4]>,
+    attributes: [[u8; ATTRIBUTE_COLUMNS]; ATTRIBUTE_ROWS],
+
+    border_tiles: Vec<u8>,
+    border_map: Vec<u16>,
+    border_palettes: [[Color; 16]; 4],
+
+    players: u8,
+    current_player: u8,
+    player_advanced: bool,
+
+    mask: Mask,
+    frozen_screen: Option<Vec<u8>>,
+}
+
+impl Default for SuperGameBoy {
+    fn default() -> Self {
+        SuperGameBoy {
+            receiver: PacketReceiver::default(),
+
+            palettes: [DEFAULT_PALETTE; 4],
+            system_palettes: vec![DEFAULT_PALETTE; SYSTEM_PALETTES as usize],
+            attributes: [[0; ATTRIBUTE_COLUMNS]; ATTRIBUTE_ROWS],
+
+            border_tiles: vec![0; BORDER_TILES * BORDER_TILE_BYTES],
+            border_map: vec![0; BORDER_MAP_COLUMNS * BORDER_MAP_ROWS],
+            border_palettes: [[Color::BLACK; 16]; 4],
+
+            players: 1,
+            current_player: 0,
+            player_advanced: false,
+
+            mask: Mask::Cancel,
+            frozen_screen: None,
+        }
+    }
+}
+
+impl SuperGameBoy {
+    /// Observe a write to the joypad register
+    /// `vram` is read from for PAL_TRN, CHR_TRN, and PCT_TRN
+    pub fn write_joypad(&mut self, value: u8, vram: &impl MemoryMapped) {
+        // With multiple players, deselecting both lines after selecting P15 moves to the next joypad
+        match (value >> 4) & 0b11 {
+            0b11 if !self.player_advanced => {
+                self.current_player = (self.current_player + 1) % self.players;
+                self.player_advanced = true;
+            }
+            0b01 | 0b00 => self.player_advanced = false,
+            _ => {}
+        }
+
+        if let Some(packets) = self.receiver.write(value) {
+            // Commands that can't be parsed are ignored, like on hardware
+            if let Ok(command) = Command::parse(&packets[..]) {
+                self.apply(command, vram);
+            }
+        }
+    }
+
+    /// The ID returned in the low nibble of P1 when neither button group is selected
+    pub fn joypad_id(&self) -> u8 {
+        0x0F - self.current_player
+    }
+
+    pub fn palettes(&self) -> &[[Color; 4]; 4] {
+        &self.palettes
+    }
+
+    /// Palette numbers for each 8x8 character of the screen, indexed by row then column
+    pub fn attributes(&self) -> &[[u8; ATTRIBUTE_COLUMNS]; ATTRIBUTE_ROWS] {
+        &self.attributes
+    }
+
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    pub fn mask(&self) -> Mask {
+        self.mask
+    }
+
+    pub fn apply(&mut self, command: Command, vram: &impl MemoryMapped) {
+        match command {
+            Command::Pal { palettes, colors } => {
+                self.set_color_0(colors[0]);
+                self.palettes[palettes[0]][1..].copy_from_slice(&colors[1..4]);
+                self.palettes[palettes[1]][1..].copy_from_slice(&colors[4..7]);
+            }
+            Command::AttrBlk(blocks) => blocks.iter().for_each(|block| self.attr_block(block)),
+            Command::AttrLin(lines) => lines.iter().for_each(|line| self.attr_line(line)),
+            Command::AttrDiv(div) => self.attr_div(&div),
+            Command::AttrChr(chr) => self.attr_chr(&chr),
+            Command::PalSet {
+                system_palettes,
+                cancel_mask,
+            } => {
+                for (palette, system_palette) in system_palettes.iter().enumerate() {
+                    self.palettes[palette] = self.system_palettes[*system_palette as usize];
+                }
+                self.set_color_0(self.palettes[0][0]);
+
+                if cancel_mask {
+                    self.set_mask(Mask::Cancel);
+                }
+            }
+            Command::PalTrn => {
+                let data = SuperGameBoy::transfer(vram);
+                for (palette, colors) in self.system_palettes.iter_mut().zip(data.chunks_exact(8)) {
+                    for (color, bytes) in palette.iter_mut().zip(colors.chunks_exact(2)) {
+                        *color = Color::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            Command::ChrTrn { high_tiles } => {
+                let offset = if high_tiles { TRANSFER_BYTES } else { 0 };
+                self.border_tiles[offset..offset + TRANSFER_BYTES]
+                    .copy_from_slice(&SuperGameBoy::transfer(vram));
+            }
+            Command::PctTrn => {
+                let data = SuperGameBoy::transfer(vram);
+
+                for (entry, bytes) in self.border_map.iter_mut().zip(data.chunks_exact(2)) {
+                    *entry = u16::from_le_bytes([bytes[0], bytes[1]]);
+                }
+
+                let palette_bytes = &data[BORDER_PALETTES_OFFSET..];
+                for (palette, colors) in self
+                    .border_palettes
+                    .iter_mut()
+                    .zip(palette_bytes.chunks_exact(32))
+                {
+                    for (color, bytes) in palette.iter_mut().zip(colors.chunks_exact(2)) {
+                        *color = Color::from_le_bytes([bytes[0], bytes[1]]);
+                    }
+                }
+            }
+            Command::MltReq { players } => {
+                self.players = players;
+                self.current_player = 0;
+            }
+            Command::MaskEn(mask) => self.set_mask(mask),
+        }
+    }
+
+    /// Composite the Game Boy screen into the border
+    /// `screen` is 160x144 shades in 0..=3, row by row
+    pub fn render(&mut self, screen: &[u8]) -> Vec<Color> {
+        let color_0 = self.palettes[0][0];
+        let mut frame = vec![color_0; BORDER_WIDTH * BORDER_HEIGHT];
+
+        if self.mask == Mask::Freeze && self.frozen_screen.is_none() {
+            self.frozen_screen = Some(screen.to_vec());
+        }
+        let screen = self.frozen_screen.as_deref().unwrap_or(screen);
+
+        for y in 0..SCREEN_HEIGHT {
+            for x in 0..SCREEN_WIDTH {
+                let color = match self.mask {
+                    Mask::Black => Color::BLACK,
+                    Mask::Color0 => color_0,
+                    Mask::Cancel | Mask::Freeze => {
+                        let palette = self.attributes[y / 8][x / 8] as usize;
+                        self.palettes[palette][(screen[y * SCREEN_WIDTH + x] & 0b11) as usize]
+                    }
+                };
+
+                frame[(y + SCREEN_Y) * BORDER_WIDTH + x + SCREEN_X] = color;
+            }
+        }
+
+        self.render_border(&mut frame);
+
+        frame
+    }
+
+    fn render_border(&self, frame: &mut [Color]) {
+        for row in 0..BORDER_MAP_ROWS {
+            for column in 0..BORDER_MAP_COLUMNS {
+                let entry = self.border_map[row * BORDER_MAP_COLUMNS + column];
+                let tile = (entry & 0xFF) as usize;
+                let palette = ((entry >> 10) & 0b111) as usize;
+                let flip_x = entry & 0x4000 != 0;
+                let flip_y = entry & 0x8000 != 0;
+
+                // Only palettes 4-7 are loaded by PCT_TRN
+                let palette = match palette.checked_sub(FIRST_BORDER_PALETTE) {
+                    Some(palette) => &self.border_palettes[palette],
+                    None => continue,
+                };
+
+                for y in 0..8 {
+                    for x in 0..8 {
+                        let tile_x = if flip_x { 7 - x } else { x };
+                        let tile_y = if flip_y { 7 - y } else { y };
+                        let color_index = self.border_pixel(tile, tile_x, tile_y);
+
+                        // Color 0 is transparent, showing the Game Boy screen or backdrop behind
+                        if color_index != 0 {
+                            frame[(row * 8 + y) * BORDER_WIDTH + column * 8 + x] =
+                                palette[color_index];
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Border tiles are SNES 4 bits per pixel: bit planes 0 and 1 interleaved by row,
+    /// followed by bit planes 2 and 3 interleaved by row
+    fn border_pixel(&self, tile: usize, x: usize, y: usize) -> usize {
+        let tile = &self.border_tiles[tile * BORDER_TILE_BYTES..(tile + 1) * BORDER_TILE_BYTES];
+        let bit = 7 - x;
+
+        [
+            tile[y * 2],
+            tile[y * 2 + 1],
+            tile[16 + y * 2],
+            tile[16 + y * 2 + 1],
+        ]
+        .iter()
+        .enumerate()
+        .map(|(plane, byte)| (((byte >> bit) & 1) as usize) << plane)
+        .sum()
+    }
+
+    /// Color 0 is shared by all four palettes
+    fn set_color_0(&mut self, color: Color) {
+        for palette in self.palettes.iter_mut() {
+            palette[0] = color;
+        }
+    }
+
+    fn set_mask(&mut self, mask: Mask) {
+        self.mask = mask;
+        self.frozen_screen = None;
+    }
+
+    fn attr_block(&mut self, block: &AttrBlock) {
+        for (y, row) in self.attributes.iter_mut().enumerate() {
+            for (x, attribute) in row.iter_mut().enumerate() {
+                let (x, y) = (x as u8, y as u8);
+                let within =
+                    (block.x1..=block.x2).contains(&x) && (block.y1..=block.y2).contains(&y);
+                let on_line =
+                    within && (x == block.x1 || x == block.x2 || y == block.y1 || y == block.y2);
+
+                let palette = if on_line {
+                    block.line
+                } else if within {
+                    block.inside
+                } else {
+                    block.outside
+                };
+
+                if let Some(palette) = palette {
+                    *attribute = palette;
+                }
+            }
+        }
+    }
+
+    fn attr_line(&mut self, line: &AttrLine) {
+        let index = line.line as usize;
+
+        if line.horizontal {
+            if let Some(row) = self.attributes.get_mut(index) {
+                row.fill(line.palette);
+            }
+        } else if index < ATTRIBUTE_COLUMNS {
+            for row in self.attributes.iter_mut() {
+                row[index] = line.palette;
+            }
+        }
+    }
+
+    fn attr_div(&mut self, div: &AttrDiv) {
+        for (y, row) in self.attributes.iter_mut().enumerate() {
+            for (x, attribute) in row.iter_mut().enumerate() {
+                let position = if div.horizontal { y } else { x } as u8;
+
+                *attribute = match position.cmp(&div.coordinate) {
+                    std::cmp::Ordering::Less => div.before,
+                    std::cmp::Ordering::Equal => div.line,
+                    std::cmp::Ordering::Greater => div.after,
+                };
+            }
+        }
+    }
+
+    fn attr_chr(&mut self, chr: &AttrChr) {
+        let (mut x, mut y) = (chr.x as usize, chr.y as usize);
+
+        for palette in &chr.palettes {
+            if x >= ATTRIBUTE_COLUMNS || y >= ATTRIBUTE_ROWS {
+                return;
+            }
+            self.attributes[y][x] = *palette;
+
+            if chr.vertical {
+                y += 1;
+                if y == ATTRIBUTE_ROWS {
+                    y = 0;
+                    x += 1;
+                }
+            } else {
+                x += 1;
+                if x == ATTRIBUTE_COLUMNS {
+                    x = 0;
+                    y += 1;
+                }
+            }
+        }
+    }
+
+    // TODO read the tiles the PPU is displaying, rather than assuming they are laid out from 0x8000
+    fn transfer(vram: &impl MemoryMapped) -> Vec<u8> {
+        (0..TRANSFER_BYTES as u16)
+            .map(|address| vram.read_byte(address))
+            .collect()
+    }
+}
+
+impl SaveState for SuperGameBoy {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.receiver.save_state(writer);
+
+        let colors = self
+            .palettes
+            .iter()
+            .flatten()
+            .chain(self.system_palettes.iter().flatten())
+            .chain(self.border_palettes.iter().flatten());
+        for color in colors {
+            writer.write_u16(color.0);
+        }
+        writer.write_bytes(&self.attributes.concat());
+
+        writer.write_bytes(&self.border_tiles);
+        for entry in &self.border_map {
+            writer.write_u16(*entry);
+        }
+
+        writer.write_u8(self.players);
+        writer.write_u8(self.current_player);
+        writer.write_bool(self.player_advanced);
+
+        writer.write_u8(match self.mask {
+            Mask::Cancel => 0,
+            Mask::Freeze => 1,
+            Mask::Black => 2,
+            Mask::Color0 => 3,
+        });
+        writer.write_bool(self.frozen_screen.is_some());
+        if let Some(frozen_screen) = &self.frozen_screen {
+            writer.write_sized_bytes(frozen_screen);
+        }
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.receiver.load_state(reader)?;
+
+        let colors = self
+            .palettes
+            .iter_mut()
+            .flatten()
+            .chain(self.system_palettes.iter_mut().flatten())
+            .chain(self.border_palettes.iter_mut().flatten());
+        for color in colors {
+            *color = Color(reader.read_u16()? & 0x7FFF);
+        }
+        for row in self.attributes.iter_mut() {
+            row.copy_from_slice(reader.read_bytes(ATTRIBUTE_COLUMNS)?);
+        }
+
+        let border_tiles_len = self.border_tiles.len();
+        self.border_tiles
+            .copy_from_slice(reader.read_bytes(border_tiles_len)?);
+        for entry in self.border_map.iter_mut() {
+            *entry = reader.read_u16()?;
+        }
+
+        self.players = reader.read_u8()?.max(1);
+        self.current_player = reader.read_u8()? % self.players;
+        self.player_advanced = reader.read_bool()?;
+
+        self.mask = match reader.read_u8()? {
+            0 => Mask::Cancel,
+            1 => Mask::Freeze,
+            2 => Mask::Black,
+            3 => Mask::Color0,
+            value => {
+                return Err(SaveStateError::InvalidValue {
+                    field: "SGB mask",
+                    value,
+                })
+            }
+        };
+        self.frozen_screen = if reader.read_bool()? {
+            Some(reader.read_sized_bytes()?.to_vec())
+        } else {
+            None
+        };
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ram::Ram;
+
+    #[test]
+    fn test_attr_block_regions() {
+        let mut sgb = SuperGameBoy::default();
+
+        sgb.attr_block(&AttrBlock {
+            inside: Some(1),
+            line: Some(2),
+            outside: Some(3),
+            x1: 2,
+            y1: 2,
+            x2: 5,
+            y2: 4,
+        });
+
+        assert_eq!(3, sgb.attributes[0][0]);
+        assert_eq!(2, sgb.attributes[2][2]);
+        assert_eq!(2, sgb.attributes[4][3]);
+        assert_eq!(1, sgb.attributes[3][3]);
+        assert_eq!(3, sgb.attributes[3][6]);
+    }
+
+    #[test]
+    fn test_attr_chr_wraps_rows() {
+        let mut sgb = SuperGameBoy::default();
+
+        sgb.attr_chr(&AttrChr {
+            x: 19,
+            y: 0,
+            vertical: false,
+            palettes: vec![1, 2],
+        });
+
+        assert_eq!(1, sgb.attributes[0][19]);
+        assert_eq!(2, sgb.attributes[1][0]);
+    }
+
+    #[test]
+    fn test_render_screen_with_attributes() {
+        let mut sgb = SuperGameBoy::default();
+        let vram: Ram<0x2000> = Ram::default();
+
+        sgb.apply(
+            Command::Pal {
+                palettes: [0, 1],
+                colors: [
+                    Color(0x1111),
+                    Color(0x0001),
+                    Color(0x0002),
+                    Color(0x0003),
+                    Color(0x0011),
+                    Color(0x0012),
+                    Color(0x0013),
+                ],
+            },
+            &vram,
+        );
+        sgb.attr_line(&AttrLine {
+            line: 1,
+            palette: 1,
+            horizontal: false,
+        });
+
+        let mut screen = vec![0; SCREEN_WIDTH * SCREEN_HEIGHT];
+        screen[0] = 3;
+        screen[8] = 3;
+        screen[9] = 1;
+
+        let frame = sgb.render(&screen);
+
+        let at = |x: usize, y: usize| frame[(y + SCREEN_Y) * BORDER_WIDTH + x + SCREEN_X];
+        assert_eq!(Color(0x0003), at(0, 0));
+        assert_eq!(Color(0x0013), at(8, 0));
+        assert_eq!(Color(0x0011), at(9, 0));
+        // Shade 0 and the backdrop both use the shared color 0
+        assert_eq!(Color(0x1111), at(1, 0));
+        assert_eq!(Color(0x1111), frame[0]);
+    }
+
+    #[test]
+    fn test_render_border() {
+        let mut sgb = SuperGameBoy::default();
+        let mut vram: Ram<0x2000> = Ram::default();
+
+        // Tile 1, row 0: only the leftmost pixel has color 15
+        for offset in [32, 33, 48, 49] {
+            vram.write_byte(offset, 0x80);
+        }
+        sgb.apply(Command::ChrTrn { high_tiles: false }, &vram);
+
+        // Map entry (0, 0) is tile 1 with palette 4, flipped horizontally
+        let mut vram: Ram<0x2000> = Ram::default();
+        vram.write_byte(0, 0x01);
+        vram.write_byte(1, 0x50);
+        vram.write_byte(0x800 + 30, 0x34);
+        vram.write_byte(0x800 + 31, 0x12);
+        sgb.apply(Command::PctTrn, &vram);
+
+        let frame = sgb.render(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+
+        assert_eq!(Color(0x1234), frame[7]);
+        assert_eq!(Color::WHITE, frame[0]);
+        assert_eq!(Color::WHITE, frame[BORDER_WIDTH + 7]);
+    }
+
+    #[test]
+    fn test_mask_freeze() {
+        let mut sgb = SuperGameBoy::default();
+        let vram: Ram<0x2000> = Ram::default();
+        let pixel = SCREEN_Y * BORDER_WIDTH + SCREEN_X;
+
+        sgb.apply(Command::MaskEn(Mask::Freeze), &vram);
+        sgb.render(&vec![3; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        let frame = sgb.render(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        assert_eq!(Color::BLACK, frame[pixel]);
+
+        sgb.apply(Command::MaskEn(Mask::Cancel), &vram);
+        let frame = sgb.render(&vec![0; SCREEN_WIDTH * SCREEN_HEIGHT]);
+        assert_eq!(Color::WHITE, frame[pixel]);
+    }
+}
@@ -0,0 +1,62 @@
+use crate::memory::MemoryMapped;
+use crate::save_state::{SaveState, SaveStateResult, StateReader, StateWriter};
+
+pub const KEY1_ADDRESS: u16 = 0xFF4D;
+
+/// The CGB CPU can run at its normal speed or at double speed
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Speed {
+    Normal,
+    Double,
+}
+
+/// KEY1 - the CGB speed switch register
+/// Bit 7 reports the current speed, bit 0 arms a switch for the next STOP
+// TODO perform the switch when STOP is executed with the switch armed
+pub struct Key1 {
+    pub speed: Speed,
+    switch_armed: bool,
+}
+
+impl Default for Key1 {
+    fn default() -> Self {
+        Key1 {
+            speed: Speed::Normal,
+            switch_armed: false,
+        }
+    }
+}
+
+impl MemoryMapped for Key1 {
+    fn read_byte(&self, _address: u16) -> u8 {
+        let speed_bit = match self.speed {
+            Speed::Normal => 0,
+            Speed::Double => 0x80,
+        };
+
+        // Unused bits read back as 1
+        speed_bit | 0x7E | self.switch_armed as u8
+    }
+
+    fn write_byte(&mut self, _address: u16, value: u8) {
+        self.switch_armed = value & 0x1 == 1;
+    }
+}
+
+impl SaveState for Key1 {
+    fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.speed == Speed::Double);
+        writer.write_bool(self.switch_armed);
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.speed = if reader.read_bool()? {
+            Speed::Double
+        } else {
+            Speed::Normal
+        };
+        self.switch_armed = reader.read_bool()?;
+
+        Ok(())
+    }
+}
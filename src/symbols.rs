@@ -0,0 +1,221 @@
+use crate::cartridge::mbc1::Mbc1;
+use std::collections::{BTreeMap, HashMap};
+use std::ops::RangeInclusive;
+use std::{error, fmt};
+
+/// Where switchable work RAM is mapped, which RGBDS puts in bank 1 and up
+const WRAMX_ADDRESSES: RangeInclusive<u16> = 0xD000..=0xDFFF;
+
+/// A line of a symbol file that isn't `BANK:ADDRESS label`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SymbolError {
+    /// Counting from 1, like a text editor
+    pub line_number: usize,
+    pub line: String,
+}
+
+impl fmt::Display for SymbolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {} of the symbol file isn't `BANK:ADDRESS label`: {:?}",
+            self.line_number, self.line
+        )
+    }
+}
+
+impl error::Error for SymbolError {}
+
+/// Labels from an RGBDS symbol file, as `rgblink -n` writes them
+///
+/// Every label belongs to a bank, so which one an address refers to depends on what is mapped
+/// there at the time. Several labels can share an address, in which case the first is shown.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Symbols {
+    by_location: BTreeMap<(u8, u16), Vec<String>>,
+    by_name: HashMap<String, (u8, u16)>,
+}
+
+impl Symbols {
+    /// Parses lines of `BANK:ADDRESS label`, in hex, ignoring blank lines and `;` comments
+    pub fn parse(text: &str) -> Result<Symbols, SymbolError> {
+        let mut symbols = Symbols::default();
+
+        for (index, line) in text.lines().enumerate() {
+            let content = line.split(';').next().unwrap_or_default().trim();
+            if content.is_empty() {
+                continue;
+            }
+
+            let symbol = content
+                .split_once(char::is_whitespace)
+                .and_then(|(location, name)| {
+                    let (bank, address) = location.split_once(':')?;
+                    Some((
+                        u8::from_str_radix(bank, 16).ok()?,
+                        u16::from_str_radix(address, 16).ok()?,
+                        name.trim(),
+                    ))
+                });
+
+            match symbol {
+                Some((bank, address, name)) => symbols.insert(bank, address, name),
+                None => {
+                    return Err(SymbolError {
+                        line_number: index + 1,
+                        line: line.to_string(),
+                    })
+                }
+            }
+        }
+
+        Ok(symbols)
+    }
+
+    pub fn insert(&mut self, bank: u8, address: u16, name: &str) {
+        self.by_location
+            .entry((bank, address))
+            .or_default()
+            .push(name.to_string());
+        self.by_name.insert(name.to_string(), (bank, address));
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_name.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_name.is_empty()
+    }
+
+    /// The bank and address of a label
+    pub fn get(&self, name: &str) -> Option<(u8, u16)> {
+        self.by_name.get(name).copied()
+    }
+
+    /// The label at exactly this address in this bank
+    pub fn label_in_bank(&self, bank: u8, address: u16) -> Option<&str> {
+        self.by_location
+            .get(&(bank, address))
+            .and_then(|names| names.first())
+            .map(String::as_str)
+    }
+
+    /// The closest label at or before this address in this bank, and how far past it the address is
+    /// Labels in another area of memory don't count, like the last label in ROM for code in
+    /// work RAM, or the last label in work RAM for code in HRAM.
+    pub fn nearest_in_bank(&self, bank: u8, address: u16) -> Option<(&str, u16)> {
+        self.by_location
+            .range((bank, 0)..=(bank, address))
+            .next_back()
+            .filter(|((_, label_address), _)| memory_area(*label_address) == memory_area(address))
+            .and_then(|((_, label_address), names)| {
+                Some((names.first()?.as_str(), address - label_address))
+            })
+    }
+
+    /// The address as `label` or `label+offset` in this bank
+    pub fn describe_in_bank(&self, bank: u8, address: u16) -> Option<String> {
+        match self.nearest_in_bank(bank, address)? {
+            (name, 0) => Some(name.to_string()),
+            (name, offset) => Some(format!("{}+{}", name, offset)),
+        }
+    }
+
+    /// The label at exactly this address, in whichever bank the cartridge has mapped there
+    pub fn label(&self, cartridge: &Mbc1, address: u16) -> Option<&str> {
+        self.label_in_bank(bank_at(cartridge, address), address)
+    }
+
+    /// The address as `label` or `label+offset`, in whichever bank the cartridge has mapped there
+    pub fn describe(&self, cartridge: &Mbc1, address: u16) -> Option<String> {
+        self.describe_in_bank(bank_at(cartridge, address), address)
+    }
+}
+
+/// ROM in 16KB banks, then everything else in the 8KB areas the memory map is made of
+fn memory_area(address: u16) -> u16 {
+    match address {
+        0x0000..=0x7FFF => address >> 14,
+        _ => address >> 13,
+    }
+}
+
+/// The bank RGBDS would give a label at this address, with the cartridge mapped as it is
+// TODO: Use the mapped WRAM and cartridge RAM banks once they can be switched
+pub fn bank_at(cartridge: &Mbc1, address: u16) -> u8 {
+    match cartridge.rom_bank(address) {
+        Some(bank) => bank,
+        None if WRAMX_ADDRESSES.contains(&address) => 1,
+        None => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemoryMapped;
+
+    const SYM: &str = "\
+; File generated by rgblink
+00:0150 Entrypoint
+00:0154 Loop
+01:4000 BankedRoutine
+02:4000 OtherBankedRoutine
+00:c000 wBuffer
+01:d000 wBankedBuffer
+";
+
+    #[test]
+    fn test_parse() {
+        let symbols = Symbols::parse(SYM).unwrap();
+
+        assert_eq!(6, symbols.len());
+        assert_eq!(Some((0, 0x0154)), symbols.get("Loop"));
+        assert_eq!(Some("BankedRoutine"), symbols.label_in_bank(1, 0x4000));
+        assert_eq!(None, symbols.get("Missing"));
+
+        assert_eq!(
+            Err(SymbolError {
+                line_number: 2,
+                line: "0150 Entrypoint".to_string()
+            }),
+            Symbols::parse("; comment\n0150 Entrypoint")
+        );
+    }
+
+    #[test]
+    fn test_nearest() {
+        let symbols = Symbols::parse(SYM).unwrap();
+
+        assert_eq!(Some(("Loop", 0)), symbols.nearest_in_bank(0, 0x0154));
+        assert_eq!(Some(("Loop", 4)), symbols.nearest_in_bank(0, 0x0158));
+        assert_eq!(None, symbols.nearest_in_bank(0, 0x0100));
+        // Labels in other banks are never nearest
+        assert_eq!(None, symbols.nearest_in_bank(3, 0x4010));
+        // Nor are labels in other areas, like HRAM after the last label in work RAM
+        assert_eq!(Some(("wBuffer", 0x10)), symbols.nearest_in_bank(0, 0xC010));
+        assert_eq!(None, symbols.nearest_in_bank(0, 0xFF80));
+        assert_eq!(None, symbols.describe_in_bank(0, 0xFF80));
+    }
+
+    #[test]
+    fn test_labels_follow_mapped_bank() {
+        let symbols = Symbols::parse(SYM).unwrap();
+        let mut cartridge = Mbc1::new();
+
+        assert_eq!(Some("BankedRoutine"), symbols.label(&cartridge, 0x4000));
+        cartridge.write_byte(0x2000, 2);
+        assert_eq!(
+            Some("OtherBankedRoutine"),
+            symbols.label(&cartridge, 0x4000)
+        );
+        assert_eq!(
+            Some("OtherBankedRoutine+16".to_string()),
+            symbols.describe(&cartridge, 0x4010)
+        );
+
+        assert_eq!(Some("wBuffer"), symbols.label(&cartridge, 0xC000));
+        assert_eq!(Some("wBankedBuffer"), symbols.label(&cartridge, 0xD000));
+    }
+}
@@ -1,15 +1,65 @@
-use crate::bus::Bus;
+use crate::bus::{Access, Bus, UnmappedAccess};
 use crate::cartridge::mbc1::Mbc1;
 use crate::cpu::Cpu;
+use crate::error::EmulationError;
+use crate::interrupts;
+use crate::profiler::Profiler;
+use crate::rtc::{RtcSource, WallClock};
+use crate::save_state::{self, SaveState, SaveStateResult, StateReader, StateWriter};
+use crate::sgb::super_game_boy::SuperGameBoy;
+use crate::trace::Tracer;
+
+/// Machine cycles in one frame, 154 lines of 114 cycles each
+pub const CYCLES_PER_FRAME: u64 = 17556;
+const CYCLES_PER_LINE: u64 = 114;
+const LINES_PER_FRAME: u64 = 154;
+/// Lines 144 to 153 are VBlank, which has no HBlanks
+const VISIBLE_LINES: u64 = 144;
+/// How far into a visible line HBlank starts, after 20 cycles of OAM scan and 43 of drawing
+// TODO: Drawing takes longer with sprites, the window, and fine scrolling
+const HBLANK_START_CYCLE: u64 = 63;
+
+/// The save state version the address of a lockup was first saved in
+const LOCKED_UP_AT_SAVED_SINCE_VERSION: u16 = 5;
 
 pub enum Gas {
     UNLIMITED,
     LIMITED(usize),
 }
 
+/// How the system reacts when a ROM does something the hardware has no sensible answer for
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum FaultPolicy {
+    /// Do what the hardware does: illegal opcodes lock up the CPU,
+    /// unmapped reads see an open bus, and unmapped writes are ignored
+    #[default]
+    Hardware,
+    /// Stop with an `EmulationError` on illegal opcodes and unmapped accesses
+    Strict,
+}
+
+/// Which console the cartridge is running in
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Model {
+    #[default]
+    Dmg,
+    /// A DMG with a Super GameBoy listening on the joypad register
+    Sgb,
+}
+
 pub struct System {
     bus: Bus,
     cpu: Cpu,
+
+    cycles: u64,
+    fault_policy: FaultPolicy,
+    /// Where the illegal opcode the CPU hung on is, once it has
+    locked_up: Option<u16>,
+    rtc: Box<dyn RtcSource>,
+    /// The access that paused the last run
+    watchpoint_hit: Option<Access>,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
 }
 
 impl System {
@@ -17,24 +67,220 @@ impl System {
         System {
             bus: Bus::new(cartridge),
             cpu: Cpu::default(),
+
+            cycles: 0,
+            fault_policy: FaultPolicy::default(),
+            locked_up: None,
+            rtc: Box::new(WallClock),
+            watchpoint_hit: None,
+            tracer: None,
+            profiler: None,
         }
     }
 
-    pub fn run(&mut self) {
+    /// Snapshot everything needed to resume emulation later, except the ROM itself
+    pub fn save_state(&self) -> Vec<u8> {
+        save_state::save(self, self.rom_checksum())
+    }
+
+    /// Resume from a snapshot taken by `save_state` while running the same ROM
+    /// The fault policy is configuration rather than state, so it is left as it is
+    pub fn load_state(&mut self, state: &[u8]) -> SaveStateResult<()> {
+        let rom_checksum = self.rom_checksum();
+        save_state::load(self, state, rom_checksum)
+    }
+
+    /// Identifies the ROM that is running
+    pub fn rom_checksum(&self) -> u16 {
+        self.bus.cartridge.rom_checksum()
+    }
+
+    pub fn set_fault_policy(&mut self, fault_policy: FaultPolicy) {
+        self.fault_policy = fault_policy;
+    }
+
+    /// Replace the wall clock, which is otherwise the only thing that can make runs differ
+    pub fn set_rtc_source(&mut self, rtc: Box<dyn RtcSource>) {
+        self.rtc = rtc;
+    }
+
+    /// The time a cartridge's real time clock would see now
+    // TODO: Latch this into the RTC registers once there is an MBC3
+    pub fn rtc_seconds(&self) -> u64 {
+        self.rtc.seconds(self.cycles)
+    }
+
+    /// Trace every instruction from now on, before it runs
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stop tracing, handing back the tracer so it can be finished
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Profile every instruction from now on
+    pub fn set_profiler(&mut self, profiler: Profiler) {
+        self.profiler = Some(profiler);
+    }
+
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Stop profiling, handing back the profiler for its reports
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    pub fn model(&self) -> Model {
+        match self.bus.sgb {
+            Some(_) => Model::Sgb,
+            None => Model::Dmg,
+        }
+    }
+
+    /// Switching to the Super GameBoy connects a freshly reset one
+    pub fn set_model(&mut self, model: Model) {
+        match model {
+            Model::Dmg => self.bus.sgb = None,
+            Model::Sgb => {
+                if self.bus.sgb.is_none() {
+                    self.bus.sgb = Some(SuperGameBoy::default());
+                }
+            }
+        }
+    }
+
+    pub fn run(&mut self) -> Result<(), EmulationError> {
         self.run_with_gas(Gas::UNLIMITED)
     }
 
-    pub fn run_with_gas(&mut self, mut gas: Gas) {
+    /// Runs until the gas runs out, the CPU locks up, or an instruction hits a watchpoint
+    /// Unimplemented opcodes are always an error, whatever the fault policy
+    pub fn run_with_gas(&mut self, mut gas: Gas) -> Result<(), EmulationError> {
+        self.clear_watchpoint_hit();
+
         loop {
+            // Nothing can bring the CPU back from a lockup, so there is nothing left to run
+            if self.locked_up.is_some() {
+                return Ok(());
+            }
+
             if let Gas::LIMITED(remaining_gas) = gas {
-                if remaining_gas <= 0 {
-                    return;
+                if remaining_gas == 0 {
+                    return Ok(());
                 }
 
                 gas = Gas::LIMITED(remaining_gas - 1);
             }
 
-            self.cpu.read_decode_execute(&mut self.bus);
+            self.step()?;
+            if self.watchpoint_hit.is_some() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs until the end of the current frame, the CPU locks up, or a watchpoint is hit
+    /// The last instruction may finish a few cycles into the next frame, which then ends sooner
+    // TODO: Use the PPU's vblank once there is a PPU, rather than only counting cycles
+    pub fn run_frame(&mut self) -> Result<(), EmulationError> {
+        let frame_end = (self.cycles / CYCLES_PER_FRAME + 1) * CYCLES_PER_FRAME;
+        self.clear_watchpoint_hit();
+
+        while self.cycles < frame_end && self.locked_up.is_none() && self.watchpoint_hit.is_none() {
+            self.step()?;
+        }
+
+        Ok(())
+    }
+
+    /// Forget hits from before this run, including any left by accesses from outside the CPU
+    fn clear_watchpoint_hit(&mut self) {
+        self.watchpoint_hit = None;
+        self.bus.take_watchpoint_hit();
+    }
+
+    fn step(&mut self) -> Result<(), EmulationError> {
+        let start = self.cycles;
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.trace(self);
+            self.tracer = Some(tracer);
+        }
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.begin(self);
+            self.profiler = Some(profiler);
+        }
+
+        let pc = self.cpu.pc;
+        self.bus.begin_instruction(pc);
+
+        match self.cpu.read_decode_execute(&mut self.bus) {
+            Ok(cycles) => self.cycles += cycles as u64,
+            Err(EmulationError::IllegalOpcode { pc, .. })
+                if self.fault_policy == FaultPolicy::Hardware =>
+            {
+                self.locked_up = Some(pc);
+            }
+            Err(error) => return Err(error),
+        }
+        if self.locked_up.is_none() {
+            self.dispatch_interrupt();
+        }
+        self.cycles += self.bus.take_stalled_cycles() as u64;
+        // Blocks copied in these HBlanks stall the next instruction
+        self.enter_hblanks(start);
+        self.watchpoint_hit = self.bus.take_watchpoint_hit();
+
+        if let Some(mut profiler) = self.profiler.take() {
+            profiler.end(self);
+            self.profiler = Some(profiler);
+        }
+
+        let unmapped_access = self.bus.take_unmapped_access();
+        match (self.fault_policy, unmapped_access) {
+            (FaultPolicy::Strict, Some(UnmappedAccess::Read { address })) => {
+                Err(EmulationError::UnmappedRead { pc, address })
+            }
+            (FaultPolicy::Strict, Some(UnmappedAccess::Write { address, value })) => {
+                Err(EmulationError::UnmappedWrite { pc, address, value })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Signal every HBlank that started from cycle `from` up to now
+    // TODO: Let the PPU say when HBlank starts once it exists
+    fn enter_hblanks(&mut self, from: u64) {
+        let mut line = from / CYCLES_PER_LINE;
+
+        loop {
+            let hblank = line * CYCLES_PER_LINE + HBLANK_START_CYCLE;
+            if hblank >= self.cycles {
+                return;
+            }
+            if hblank >= from && line % LINES_PER_FRAME < VISIBLE_LINES {
+                self.bus.hblank();
+            }
+
+            line += 1;
+        }
+    }
+
+    /// Enter the handler of the highest priority interrupt that is pending, if IME allows it
+    fn dispatch_interrupt(&mut self) {
+        if !self.cpu.ime {
+            return;
+        }
+
+        if let Some(interrupt) = self.bus.interrupts.pending() {
+            self.bus.interrupts.acknowledge(interrupt);
+            let cycles = self
+                .cpu
+                .interrupt(&mut self.bus, interrupts::VECTORS[interrupt]);
+            self.cycles += cycles as u64;
         }
     }
 
@@ -42,7 +288,64 @@ impl System {
         &self.bus
     }
 
+    pub fn bus_mut(&mut self) -> &mut Bus {
+        &mut self.bus
+    }
+
     pub fn cpu(&self) -> &Cpu {
         &self.cpu
     }
+
+    pub fn cpu_mut(&mut self) -> &mut Cpu {
+        &mut self.cpu
+    }
+
+    /// The access that paused the last run, if a watchpoint paused it
+    pub fn watchpoint_hit(&self) -> Option<Access> {
+        self.watchpoint_hit
+    }
+
+    /// Whether the CPU has hung on an illegal opcode
+    pub fn is_locked_up(&self) -> bool {
+        self.locked_up.is_some()
+    }
+
+    /// The address of the illegal opcode the CPU hung on, if it has
+    /// The CPU's own PC is already past it.
+    pub fn locked_up_at(&self) -> Option<u16> {
+        self.locked_up
+    }
+
+    /// Total machine cycles elapsed, including cycles the CPU spent halted by DMA
+    pub fn cycles(&self) -> u64 {
+        self.cycles
+    }
+}
+
+impl SaveState for System {
+    fn save_state(&self, writer: &mut StateWriter) {
+        self.cpu.save_state(writer);
+        self.bus.save_state(writer);
+
+        writer.write_u64(self.cycles);
+        writer.write_bool(self.locked_up.is_some());
+        writer.write_u16(self.locked_up.unwrap_or_default());
+    }
+
+    fn load_state(&mut self, reader: &mut StateReader) -> SaveStateResult<()> {
+        self.cpu.load_state(reader)?;
+        self.bus.load_state(reader)?;
+
+        self.cycles = reader.read_u64()?;
+        let locked_up = reader.read_bool()?;
+        // Older states only say whether the CPU locked up, but illegal opcodes are one byte long
+        let address = if reader.version() >= LOCKED_UP_AT_SAVED_SINCE_VERSION {
+            reader.read_u16()?
+        } else {
+            self.cpu.pc.wrapping_sub(1)
+        };
+        self.locked_up = locked_up.then_some(address);
+
+        Ok(())
+    }
 }
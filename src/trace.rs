@@ -0,0 +1,363 @@
+use crate::disassembler::Instruction;
+use crate::symbols::Symbols;
+use crate::system::System;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, Write};
+
+/// The line Gameboy Doctor and many other emulators' trace logs use
+pub const DOCTOR_FORMAT: &str =
+    "A:{A} F:{F} B:{B} C:{C} D:{D} E:{E} H:{H} L:{L} SP:{SP} PC:{PC} PCMEM:{PCMEM}";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Field {
+    A,
+    F,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
+    Sp,
+    Pc,
+    /// The 4 bytes from PC onwards
+    PcMem,
+    /// The ROM bank mapped at PC
+    Bank,
+    Cycles,
+    /// The instruction at PC, disassembled
+    Instruction,
+    /// The label at or before PC, like `Main+3`
+    Symbol,
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Field> {
+        let field = match name {
+            "A" => Field::A,
+            "F" => Field::F,
+            "B" => Field::B,
+            "C" => Field::C,
+            "D" => Field::D,
+            "E" => Field::E,
+            "H" => Field::H,
+            "L" => Field::L,
+            "SP" => Field::Sp,
+            "PC" => Field::Pc,
+            "PCMEM" => Field::PcMem,
+            "BANK" => Field::Bank,
+            "CYCLES" => Field::Cycles,
+            "INSTRUCTION" => Field::Instruction,
+            "SYMBOL" => Field::Symbol,
+            _ => return None,
+        };
+
+        Some(field)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Segment {
+    Text(String),
+    Field(Field),
+}
+
+/// How each line of a trace is laid out
+///
+/// A template of text and `{FIELD}` placeholders, where the fields are the registers
+/// `A`, `F`, `B`, `C`, `D`, `E`, `H`, `L`, `SP`, and `PC`, and `PCMEM` for the 4 bytes
+/// from PC onwards, `BANK` for the ROM bank at PC, `CYCLES` for the machine cycles run
+/// so far, `INSTRUCTION` for the disassembled instruction at PC, and `SYMBOL` for the label at
+/// or before PC.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TraceFormat {
+    segments: Vec<Segment>,
+}
+
+impl TraceFormat {
+    pub fn parse(template: &str) -> Result<TraceFormat, String> {
+        let mut segments = Vec::new();
+        let mut rest = template;
+
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Text(rest[..start].to_string()));
+            }
+
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed {{ in trace format {:?}", template))?;
+            let name = &rest[start + 1..start + end];
+            let field =
+                Field::parse(name).ok_or_else(|| format!("unknown trace field {{{}}}", name))?;
+            segments.push(Segment::Field(field));
+
+            rest = &rest[start + end + 1..];
+        }
+
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest.to_string()));
+        }
+
+        Ok(TraceFormat { segments })
+    }
+
+    /// The line for the instruction the system is about to run
+    pub fn line(&self, system: &System) -> String {
+        self.line_with_symbols(system, &Symbols::default())
+    }
+
+    /// Like `line`, with labels for PC and the instruction's addresses where there are symbols
+    pub fn line_with_symbols(&self, system: &System, symbols: &Symbols) -> String {
+        let cpu = system.cpu();
+        let bus = system.bus();
+        let mut line = String::new();
+
+        for segment in &self.segments {
+            // Writing to a String can't fail
+            let _ = match segment {
+                Segment::Text(text) => write!(line, "{}", text),
+                Segment::Field(Field::A) => write!(line, "{:02X}", cpu.a),
+                Segment::Field(Field::F) => write!(line, "{:02X}", cpu.f),
+                Segment::Field(Field::B) => write!(line, "{:02X}", cpu.b),
+                Segment::Field(Field::C) => write!(line, "{:02X}", cpu.c),
+                Segment::Field(Field::D) => write!(line, "{:02X}", cpu.d),
+                Segment::Field(Field::E) => write!(line, "{:02X}", cpu.e),
+                Segment::Field(Field::H) => write!(line, "{:02X}", cpu.h),
+                Segment::Field(Field::L) => write!(line, "{:02X}", cpu.l),
+                Segment::Field(Field::Sp) => write!(line, "{:04X}", cpu.sp),
+                Segment::Field(Field::Pc) => write!(line, "{:04X}", cpu.pc),
+                Segment::Field(Field::PcMem) => {
+                    let bytes: Vec<String> = (0..4)
+                        .map(|offset| format!("{:02X}", bus.peek(cpu.pc.wrapping_add(offset))))
+                        .collect();
+                    write!(line, "{}", bytes.join(","))
+                }
+                Segment::Field(Field::Bank) => match bus.cartridge.rom_bank(cpu.pc) {
+                    Some(bank) => write!(line, "{:02X}", bank),
+                    None => write!(line, "--"),
+                },
+                Segment::Field(Field::Cycles) => write!(line, "{}", system.cycles()),
+                Segment::Field(Field::Instruction) => write!(
+                    line,
+                    "{}",
+                    Instruction::decode_with(cpu.pc, |address| bus.peek(address))
+                        .to_string_with_labels(|address| symbols
+                            .label(&bus.cartridge, address)
+                            .map(str::to_string))
+                ),
+                Segment::Field(Field::Symbol) => write!(
+                    line,
+                    "{}",
+                    symbols.describe(&bus.cartridge, cpu.pc).unwrap_or_default()
+                ),
+            };
+        }
+
+        line
+    }
+}
+
+impl Default for TraceFormat {
+    fn default() -> Self {
+        TraceFormat::parse(DOCTOR_FORMAT).expect("the Gameboy Doctor format is valid")
+    }
+}
+
+/// Somewhere for trace lines to go
+pub trait TraceSink {
+    fn trace(&mut self, line: &str);
+
+    /// Called when tracing stops, reporting anything that went wrong along the way
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F: FnMut(&str)> TraceSink for F {
+    fn trace(&mut self, line: &str) {
+        self(line)
+    }
+}
+
+/// Writes each line to a file or other writer
+/// Tracing can't stop emulation, so the first error is kept for `finish` and the rest dropped
+pub struct TraceWriter<W: Write> {
+    writer: W,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> TraceWriter<W> {
+    pub fn new(writer: W) -> TraceWriter<W> {
+        TraceWriter {
+            writer,
+            error: None,
+        }
+    }
+}
+
+impl<W: Write> TraceSink for TraceWriter<W> {
+    fn trace(&mut self, line: &str) {
+        if self.error.is_none() {
+            if let Err(error) = writeln!(self.writer, "{}", line) {
+                self.error = Some(error);
+            }
+        }
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(error) => Err(error),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+/// Traces every instruction a system runs, before it runs
+pub struct Tracer {
+    format: TraceFormat,
+    sink: Box<dyn TraceSink>,
+    symbols: Symbols,
+}
+
+impl Tracer {
+    pub fn new(format: TraceFormat, sink: impl TraceSink + 'static) -> Tracer {
+        Tracer {
+            format,
+            sink: Box::new(sink),
+            symbols: Symbols::default(),
+        }
+    }
+
+    /// Label addresses in the trace with these symbols
+    pub fn with_symbols(self, symbols: Symbols) -> Tracer {
+        Tracer { symbols, ..self }
+    }
+
+    pub fn trace(&mut self, system: &System) {
+        let line = self.format.line_with_symbols(system, &self.symbols);
+        self.sink.trace(&line);
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.sink.finish()
+    }
+}
+
+/// The first place two traces disagree
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Divergence {
+    /// Counting from 1, like a text editor
+    pub line_number: usize,
+    /// The last line both traces agree on
+    pub previous: Option<String>,
+    /// None where a trace ended before the other
+    pub left: Option<String>,
+    pub right: Option<String>,
+}
+
+impl Divergence {
+    /// The `NAME:VALUE` fields that differ, as (name, left value, right value)
+    /// Lines not laid out that way have no fields to compare
+    pub fn differing_fields(&self) -> Vec<(String, String, String)> {
+        let fields = |line: &Option<String>| -> Vec<(String, String)> {
+            line.iter()
+                .flat_map(|line| line.split_whitespace())
+                .filter_map(|field| field.split_once(':'))
+                .map(|(name, value)| (name.to_string(), value.to_string()))
+                .collect()
+        };
+
+        let right = fields(&self.right);
+        fields(&self.left)
+            .into_iter()
+            .filter_map(|(name, left_value)| {
+                let (_, right_value) = right.iter().find(|(right_name, _)| *right_name == name)?;
+                (left_value != *right_value).then(|| (name, left_value, right_value.clone()))
+            })
+            .collect()
+    }
+}
+
+/// Find the first line where two traces differ, or None if they are the same
+pub fn first_divergence(left: impl BufRead, right: impl BufRead) -> io::Result<Option<Divergence>> {
+    let mut left = left.lines();
+    let mut right = right.lines();
+    let mut previous = None;
+    let mut line_number = 0;
+
+    loop {
+        line_number += 1;
+
+        let left_line = left.next().transpose()?;
+        let right_line = right.next().transpose()?;
+        if left_line.is_none() && right_line.is_none() {
+            return Ok(None);
+        }
+
+        if left_line != right_line {
+            return Ok(Some(Divergence {
+                line_number,
+                previous,
+                left: left_line,
+                right: right_line,
+            }));
+        }
+
+        previous = left_line;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(
+            TraceFormat {
+                segments: vec![
+                    Segment::Field(Field::Pc),
+                    Segment::Text(": ".to_string()),
+                    Segment::Field(Field::Instruction),
+                ]
+            },
+            TraceFormat::parse("{PC}: {INSTRUCTION}").unwrap()
+        );
+
+        assert!(TraceFormat::parse("{IX}").is_err());
+        assert!(TraceFormat::parse("A:{A").is_err());
+    }
+
+    #[test]
+    fn test_first_divergence() {
+        let left = "PC:0100\nPC:0150 A:01 F:00\nPC:0152\n";
+        let right = "PC:0100\nPC:0150 A:02 F:00\nPC:0152\n";
+
+        let divergence = first_divergence(left.as_bytes(), right.as_bytes())
+            .unwrap()
+            .unwrap();
+        assert_eq!(2, divergence.line_number);
+        assert_eq!(Some("PC:0100".to_string()), divergence.previous);
+        assert_eq!(
+            vec![("A".to_string(), "01".to_string(), "02".to_string())],
+            divergence.differing_fields()
+        );
+
+        assert_eq!(
+            None,
+            first_divergence(left.as_bytes(), left.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_shorter_trace_diverges() {
+        let divergence = first_divergence("a\nb\n".as_bytes(), "a\n".as_bytes())
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(2, divergence.line_number);
+        assert_eq!(Some("b".to_string()), divergence.left);
+        assert_eq!(None, divergence.right);
+    }
+}
@@ -0,0 +1,114 @@
+use crate::bus::{Access, AccessKind};
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// Pauses the system when an access of some kind touches a range of addresses
+/// The system pauses once the instruction that made the access has finished
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Watchpoint {
+    pub kind: AccessKind,
+    pub addresses: RangeInclusive<u16>,
+    /// Only match accesses that read, write, or execute this value
+    pub value: Option<u8>,
+    /// Only match while this ROM bank is mapped at the address
+    pub bank: Option<u8>,
+}
+
+impl Watchpoint {
+    pub fn new(kind: AccessKind, addresses: RangeInclusive<u16>) -> Watchpoint {
+        Watchpoint {
+            kind,
+            addresses,
+            value: None,
+            bank: None,
+        }
+    }
+
+    /// Read watchpoints also match DMA reads, which read memory just the same
+    pub fn matches(&self, access: &Access) -> bool {
+        (access.kind == self.kind
+            || (self.kind == AccessKind::Read && access.kind == AccessKind::Dma))
+            && self.addresses.contains(&access.address)
+            && self.value.is_none_or(|value| value == access.new_value)
+            && self.bank.is_none_or(|bank| Some(bank) == access.bank)
+    }
+}
+
+impl fmt::Display for Watchpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AccessKind::Read => "read",
+            AccessKind::Write => "write",
+            AccessKind::Execute => "execute",
+            AccessKind::Dma => "DMA",
+        };
+        write!(f, "{} ", kind)?;
+
+        if let Some(bank) = self.bank {
+            write!(f, "{:02X}:", bank)?;
+        }
+        write!(f, "{:04X}", self.addresses.start())?;
+        if self.addresses.end() != self.addresses.start() {
+            write!(f, "-{:04X}", self.addresses.end())?;
+        }
+
+        match self.value {
+            Some(value) => write!(f, " = {:02X}", value),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(address: u16, value: u8) -> Access {
+        Access {
+            kind: AccessKind::Write,
+            pc: 0x150,
+            address,
+            bank: None,
+            old_value: 0,
+            new_value: value,
+        }
+    }
+
+    #[test]
+    fn test_matches() {
+        let watchpoint = Watchpoint::new(AccessKind::Write, 0xC000..=0xC0FF);
+
+        assert!(watchpoint.matches(&write(0xC000, 1)));
+        assert!(watchpoint.matches(&write(0xC0FF, 1)));
+        assert!(!watchpoint.matches(&write(0xC100, 1)));
+        assert!(!watchpoint.matches(&Access {
+            kind: AccessKind::Read,
+            ..write(0xC000, 1)
+        }));
+    }
+
+    #[test]
+    fn test_filters() {
+        let watchpoint = Watchpoint {
+            value: Some(0x42),
+            ..Watchpoint::new(AccessKind::Write, 0xC000..=0xC0FF)
+        };
+        assert!(watchpoint.matches(&write(0xC010, 0x42)));
+        assert!(!watchpoint.matches(&write(0xC010, 0x43)));
+
+        let watchpoint = Watchpoint {
+            bank: Some(1),
+            ..Watchpoint::new(AccessKind::Execute, 0x4000..=0x7FFF)
+        };
+        let execute = Access {
+            kind: AccessKind::Execute,
+            bank: Some(1),
+            ..write(0x4000, 0)
+        };
+        assert!(watchpoint.matches(&execute));
+        assert!(!watchpoint.matches(&Access {
+            bank: Some(2),
+            ..execute
+        }));
+    }
+}
@@ -0,0 +1,45 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $147 - @, 0
+
+    ; Blargg's tests leave their result in cartridge RAM
+    db CART_ROM_MBC1_RAM_BAT
+    db 0
+    db CART_SRAM_8KB
+
+	ds $150 - @, 0
+
+Text:
+    db "Passed\n", 0
+TextEnd:
+
+Entrypoint:
+    ld a, CART_SRAM_ENABLE
+    ld [rRAMG], a
+
+    ; Still running, then the signature that says the status can be trusted
+    ld a, $80
+    ld [_SRAM], a
+    ld a, $DE
+    ld [_SRAM + 1], a
+    ld a, $B0
+    ld [_SRAM + 2], a
+    ld a, $61
+    ld [_SRAM + 3], a
+
+    ; The text that would have been printed, ending in a 0
+    FOR I, TextEnd - Text
+    ld a, [Text + I]
+    ld [_SRAM + 4 + I], a
+    ENDR
+
+    ; Passed
+    ld a, 0
+    ld [_SRAM], a
+
+Loop:
+    jp Loop
@@ -0,0 +1,27 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+; What Blargg's tests print once every test has passed
+Message:
+    db "cpu_instrs\n\n01:ok\n\nPassed all tests\n"
+MessageEnd:
+
+Entrypoint:
+    ld h, HIGH(Message)
+    ld l, LOW(Message)
+
+    ; Send each byte of the message with the internal clock
+    REPT MessageEnd - Message
+    ld a, [hl+]
+    ldh [rSB], a
+    ld a, $81
+    ldh [rSC], a
+    ENDR
+
+Loop:
+    jp Loop
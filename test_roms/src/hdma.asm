@@ -0,0 +1,33 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+Entrypoint:
+    ld a, HIGH(TileData)
+    ldh [rHDMA1], a
+    ld a, LOW(TileData)
+    ldh [rHDMA2], a
+    ld a, HIGH(_VRAM)
+    ldh [rHDMA3], a
+    ld a, LOW(_VRAM)
+    ldh [rHDMA4], a
+
+    ; General purpose transfer of 2 blocks
+    ld a, $01
+    ldh [rHDMA5], a
+    ldh a, [rHDMA5]
+    ld b, a
+
+Loop:
+    jp Loop
+
+SECTION "Tile data", ROM0[$200]
+
+TileData:
+FOR N, $20
+    db N
+ENDR
@@ -0,0 +1,35 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+Entrypoint:
+    ld a, HIGH(TileData)
+    ldh [rHDMA1], a
+    ld a, LOW(TileData)
+    ldh [rHDMA2], a
+    ld a, HIGH(_VRAM)
+    ldh [rHDMA3], a
+    ld a, LOW(_VRAM)
+    ldh [rHDMA4], a
+
+    ; HBlank transfer of 3 blocks
+    ld a, $82
+    ldh [rHDMA5], a
+
+    ; Cancel whatever is left of the transfer
+    ld a, $00
+    ldh [rHDMA5], a
+
+Loop:
+    jp Loop
+
+SECTION "Tile data", ROM0[$200]
+
+TileData:
+FOR N, $30
+    db N
+ENDR
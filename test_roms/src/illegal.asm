@@ -0,0 +1,16 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+Entrypoint:
+    ld a, $42
+    ; $D3 has no instruction, so the CPU locks up here
+    db $D3
+    ld a, $43
+
+Loop:
+    jp Loop
@@ -0,0 +1,55 @@
+INCLUDE "hardware.inc"
+
+; Sends one 16 byte SGB packet over P1, least significant bit first
+MACRO sgb_packet
+    ; Reset pulse
+    ld a, $00
+    ldh [rP1], a
+    ld a, $30
+    ldh [rP1], a
+
+    REPT 16
+        DEF PACKET_BYTE = \1
+        FOR BIT, 8
+            IF PACKET_BYTE & (1 << BIT)
+                ld a, $10
+            ELSE
+                ld a, $20
+            ENDC
+            ldh [rP1], a
+            ld a, $30
+            ldh [rP1], a
+        ENDR
+        SHIFT
+    ENDR
+
+    ; Stop bit
+    ld a, $20
+    ldh [rP1], a
+    ld a, $30
+    ldh [rP1], a
+ENDM
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+Entrypoint:
+    ; MLT_REQ for 2 players
+    sgb_packet $89, $01, $00, $00, $00, $00, $00, $00, $00, $00, $00, $00, $00, $00, $00, $00
+
+    ; Select P15 and then deselect both lines to move to the second joypad
+    ld a, $10
+    ldh [rP1], a
+    ld a, $30
+    ldh [rP1], a
+    ldh a, [rP1]
+    ld b, a
+
+    ; PAL01 with color 0 white, then red, green, blue and three dark reds
+    sgb_packet $01, $FF, $7F, $1F, $00, $E0, $03, $00, $7C, $01, $00, $02, $00, $03, $00, $00
+
+Loop:
+    jp Loop
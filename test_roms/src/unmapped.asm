@@ -0,0 +1,15 @@
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+
+	jp Entrypoint
+
+	ds $150 - @, 0
+
+Entrypoint:
+    ; $FEA0..=$FEFF is prohibited, so nothing responds there
+    ld a, [$FEA0]
+    ld [$FEA0], a
+
+Loop:
+    jp Loop
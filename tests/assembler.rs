@@ -0,0 +1,132 @@
+use gameboy_dot_rs::assembler::{Assembler, AssemblyError};
+use gameboy_dot_rs::cartridge::fix::Fix;
+use gameboy_dot_rs::cartridge::header::Header;
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::symbols::Symbols;
+use gameboy_dot_rs::system::System;
+
+mod common;
+
+#[test]
+fn test_matches_rgbds() {
+    // Every test ROM that was built with RGBDS, which the assembler should build the same
+    for name in [
+        "hdma",
+        "hdma_hblank",
+        "illegal",
+        "joypad_log",
+        "jumps",
+        "load_memory",
+        "loads",
+        "sgb",
+        "unmapped",
+    ] {
+        let source = String::from_utf8(common::load_test_rom_bytes(&format!(
+            "test_roms/src/{}.asm",
+            name
+        )))
+        .unwrap();
+        let assembly = Assembler::new().assemble(&source).unwrap();
+
+        let rom = common::load_test_rom_bytes(&format!("test_roms/roms/{}.gb", name));
+        assert!(assembly.rom == rom, "{}.gb differs", name);
+
+        let symbols = String::from_utf8(common::load_test_rom_bytes(&format!(
+            "test_roms/roms/{}.sym",
+            name
+        )))
+        .unwrap();
+        assert_eq!(Symbols::parse(&symbols).unwrap(), assembly.symbols);
+    }
+}
+
+#[test]
+fn test_inline_rom() {
+    let assembly = Assembler::new()
+        .assemble(
+            r#"
+INCLUDE "hardware.inc"
+
+SECTION "Header", ROM0[$100]
+    jp Entrypoint
+    ds $150 - @, 0
+
+Entrypoint:
+    ld h, HIGH(_RAM)
+    ld l, LOW(_RAM)
+    ld a, $42
+    ld [hl+], a
+    ld [hl+], a
+    ld b, l
+Loop:
+    jp Loop
+"#,
+        )
+        .unwrap();
+
+    let header = Header::parse_rom(&assembly.rom).unwrap();
+    assert!(header.validation.logo);
+    assert_eq!(0x8000, assembly.rom.len());
+    assert_eq!(Some((0, 0x0159)), assembly.symbols.get("Loop"));
+
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&assembly.rom).unwrap());
+    system.run_frame().unwrap();
+    assert_eq!(0x42, system.bus().peek(0xC001));
+    assert_eq!(0x02, system.cpu().b);
+}
+
+#[test]
+fn test_banks() {
+    let assembly = Assembler::new()
+        .with_header(Fix {
+            title: Some("BANKS".to_string()),
+            cartridge_type: Some(0x01),
+            pad: Some(0xFF),
+            ..Fix::default()
+        })
+        .assemble(
+            r#"
+SECTION "Header", ROM0[$100]
+    jp Entrypoint
+    ds $150 - @, 0
+
+Entrypoint:
+    ld a, BANK(Answer)
+    ld [$2000], a
+    ld a, [Answer]
+    ld b, a
+Loop:
+    jp Loop
+
+SECTION "Bank 3", ROMX[$4000], BANK[3]
+    ds 2
+Answer:
+    db 42
+"#,
+        )
+        .unwrap();
+
+    assert_eq!(0x10000, assembly.rom.len());
+    assert_eq!(42, assembly.rom[0xC002]);
+    assert_eq!(Some((3, 0x4002)), assembly.symbols.get("Answer"));
+
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&assembly.rom).unwrap());
+    system.run_frame().unwrap();
+    assert_eq!(42, system.cpu().b);
+}
+
+#[test]
+fn test_errors() {
+    let error = Assembler::new()
+        .assemble("SECTION \"Code\", ROM0\n    nop\n    ld a, [Nowhere]\n")
+        .unwrap_err();
+    assert_eq!(
+        "line 3: Nowhere isn't defined: \"ld a, [Nowhere]\"",
+        error.to_string()
+    );
+
+    let error = Assembler::new()
+        .assemble("SECTION \"A\", ROM0[$150]\n    ds 4\nSECTION \"B\", ROM0[$152]\n    nop\n")
+        .unwrap_err();
+    assert!(matches!(error, AssemblyError::Line { line_number: 4, .. }));
+}
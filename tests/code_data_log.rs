@@ -0,0 +1,40 @@
+use gameboy_dot_rs::code_data_log::{CodeDataLogger, Usage};
+use gameboy_dot_rs::system::Gas;
+
+mod common;
+
+#[test]
+fn test_code_is_logged_by_rom_offset() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    let logger = CodeDataLogger::attach(&mut system);
+
+    system.run_with_gas(Gas::LIMITED(20)).unwrap();
+    let log = logger.detach(&mut system);
+
+    assert_eq!(0x8000, log.len());
+    // jp $0150
+    assert_eq!(Usage::OPCODE, log.usage(0x100));
+    assert_eq!(Usage::OPERAND, log.usage(0x101));
+    assert_eq!(Usage::OPERAND, log.usage(0x102));
+    // ld a, $1A
+    assert_eq!(Usage::OPCODE, log.usage(0x150));
+    assert_eq!(Usage::OPERAND, log.usage(0x151));
+    // The header is never run or read
+    assert!(log.usage(0x134).is_empty());
+    assert_eq!(0, log.count(Usage::DATA));
+}
+
+#[test]
+fn test_dma_sources_are_logged() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    let logger = CodeDataLogger::attach(&mut system);
+
+    system.run_with_gas(Gas::LIMITED(13)).unwrap();
+
+    let log = logger.log();
+    for offset in 0x200..0x220 {
+        assert_eq!(Usage::DMA_SOURCE, log.usage(offset), "{:04X}", offset);
+    }
+    assert!(log.usage(0x220).is_empty());
+    assert_eq!(0x20, log.count(Usage::DMA_SOURCE));
+}
@@ -1,7 +1,12 @@
+#![allow(dead_code)]
+
+use std::cell::RefCell;
 use std::fs;
 use std::io::Read;
 use std::path::PathBuf;
 use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::cpu::Cpu;
+use gameboy_dot_rs::memory::MemoryMapped;
 use gameboy_dot_rs::system::System;
 
 pub fn load_test_rom_bytes(path: &str) -> Vec<u8> {
@@ -22,3 +27,57 @@ pub fn load_test_system(path: &str) -> System {
 
     System::load_cartridge(cartridge)
 }
+
+/// An access `FlatMemory` saw, in order
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+/// A flat 64 KiB with nothing mapped, logging every access, to run the CPU without a `Bus`
+pub struct FlatMemory {
+    pub bytes: Vec<u8>,
+    pub accesses: RefCell<Vec<Access>>,
+}
+
+impl FlatMemory {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        assert_eq!(0x10000, bytes.len());
+        FlatMemory {
+            bytes,
+            accesses: RefCell::new(Vec::new()),
+        }
+    }
+}
+
+impl MemoryMapped for FlatMemory {
+    fn read_byte(&self, address: u16) -> u8 {
+        let value = self.bytes[address as usize];
+        self.accesses.borrow_mut().push(Access::Read(address, value));
+        value
+    }
+
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.accesses.get_mut().push(Access::Write(address, value));
+        self.bytes[address as usize] = value;
+    }
+}
+
+/// B, C, D, E, H, L, F and A, in the order the 3 bit register fields number them, with F where
+/// [HL] would be
+pub fn cpu_registers(cpu: &Cpu) -> [u8; 8] {
+    [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l, cpu.f, cpu.a]
+}
+
+/// The opcodes `Cpu` implements
+pub fn implemented_opcodes() -> Vec<u8> {
+    let mut opcodes = vec![0x00, 0x02, 0x0A, 0x12, 0x1A, 0x22, 0x2A, 0x32, 0x3A];
+    opcodes.extend([0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E, 0x36, 0x3E]);
+    opcodes.extend((0x40..=0x7F).filter(|opcode| *opcode != 0x76));
+    opcodes.extend([0x31, 0xC0, 0xC3, 0xC4, 0xC8, 0xC9, 0xCC, 0xCD, 0xD0, 0xD4, 0xD8, 0xD9]);
+    opcodes.extend([0xDC, 0xE0, 0xE2, 0xEA, 0xF0, 0xF2, 0xF3, 0xFA, 0xFB]);
+    opcodes.extend([0xC7, 0xCF, 0xD7, 0xDF, 0xE7, 0xEF, 0xF7, 0xFF]);
+    opcodes.extend([0xC1, 0xC5, 0xD1, 0xD5, 0xE1, 0xE5, 0xF1, 0xF5]);
+    opcodes
+}
@@ -0,0 +1,176 @@
+use gameboy_dot_rs::assembler::Assembler;
+use gameboy_dot_rs::cartridge::fix::Fix;
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::conformance::{self, Protocol, Verdict};
+use gameboy_dot_rs::memory::MemoryMapped;
+use gameboy_dot_rs::screen;
+use gameboy_dot_rs::serial::{SB_ADDRESS, SC_ADDRESS};
+use gameboy_dot_rs::system::System;
+
+mod common;
+
+/// Load one of the ROMs that report like the suites do, checking it is built from its source
+///
+/// The suites themselves aren't vendored, so these stand in for them, running from reset.
+fn load_protocol_rom(name: &str) -> System {
+    let source = String::from_utf8(common::load_test_rom_bytes(&format!(
+        "test_roms/src/{}.asm",
+        name
+    )))
+    .unwrap();
+    let rom = common::load_test_rom_bytes(&format!("test_roms/roms/{}.gb", name));
+    assert!(
+        Assembler::new().assemble(&source).unwrap().rom == rom,
+        "{}.gb differs from its source",
+        name
+    );
+
+    System::load_cartridge(Mbc1::from_bytes(&rom).unwrap())
+}
+
+#[test]
+fn test_protocol_roms() {
+    for (name, protocol) in [
+        ("blargg_serial", Protocol::BlarggSerial),
+        ("blargg_memory", Protocol::BlarggMemory),
+        ("mooneye", Protocol::Mooneye),
+    ] {
+        let mut system = load_protocol_rom(name);
+        assert_eq!(
+            Verdict::Passed(protocol),
+            conformance::run_test(&mut system, 10, None).unwrap(),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn test_lock_up() {
+    let mut system = common::load_test_system("test_roms/roms/illegal.gb");
+
+    // Where the $D3 is, not where PC ended up
+    assert_eq!(
+        Verdict::LockedUp { pc: 0x152 },
+        conformance::run_test(&mut system, 10, None).unwrap()
+    );
+}
+
+fn send(system: &mut System, text: &[u8]) {
+    for byte in text {
+        system.bus_mut().write_byte(SB_ADDRESS, *byte);
+        system.bus_mut().write_byte(SC_ADDRESS, 0x81);
+    }
+}
+
+#[test]
+fn test_blargg_serial() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    send(&mut system, b"cpu_instrs\n\n01:ok\n\nPassed all tests\n");
+    assert_eq!(
+        Verdict::Passed(Protocol::BlarggSerial),
+        conformance::run_test(&mut system, 1, None).unwrap()
+    );
+
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    send(&mut system, b"01-special\n\nFailed #6\n");
+    assert_eq!(
+        Verdict::Failed(
+            Protocol::BlarggSerial,
+            "01-special\n\nFailed #6".to_string()
+        ),
+        conformance::run_test(&mut system, 1, None).unwrap()
+    );
+}
+
+#[test]
+fn test_blargg_memory() {
+    // Blargg's tests need cartridge RAM to leave their result in
+    let mut rom = common::load_test_rom_bytes("test_roms/roms/joypad_log.gb");
+    Fix {
+        cartridge_type: Some(0x03),
+        ram_size_code: Some(0x02),
+        ..Fix::default()
+    }
+    .apply(&mut rom)
+    .unwrap();
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&rom).unwrap());
+
+    let bus = system.bus_mut();
+    bus.write_byte(0x0000, 0x0A);
+    for (offset, byte) in [0x80, 0xDE, 0xB0, 0x61].into_iter().enumerate() {
+        bus.write_byte(0xA000 + offset as u16, byte);
+    }
+    // Still running
+    assert_eq!(
+        Verdict::TimedOut,
+        conformance::run_test(&mut system, 2, None).unwrap()
+    );
+
+    let bus = system.bus_mut();
+    bus.write_byte(0xA000, 0x01);
+    for (offset, byte) in b"Failed\n\0".iter().enumerate() {
+        bus.write_byte(0xA004 + offset as u16, *byte);
+    }
+    assert_eq!(
+        Verdict::Failed(Protocol::BlarggMemory, "status 01: Failed".to_string()),
+        conformance::run_test(&mut system, 1, None).unwrap()
+    );
+
+    system.bus_mut().write_byte(0xA000, 0x00);
+    assert!(conformance::run_test(&mut system, 1, None)
+        .unwrap()
+        .is_pass());
+}
+
+/// Run `LD B,B` from work RAM with these in B, C, D, E, H and L, then loop forever
+fn run_ld_b_b(registers: [u8; 6]) -> Verdict {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    // ld b, b; jp $C000
+    for (offset, byte) in [0x40, 0xC3, 0x00, 0xC0].into_iter().enumerate() {
+        system.bus_mut().write_byte(0xC000 + offset as u16, byte);
+    }
+
+    let cpu = system.cpu_mut();
+    cpu.pc = 0xC000;
+    [cpu.b, cpu.c, cpu.d, cpu.e, cpu.h, cpu.l] = registers;
+
+    conformance::run_test(&mut system, 1, None).unwrap()
+}
+
+#[test]
+fn test_mooneye() {
+    assert_eq!(
+        Verdict::Passed(Protocol::Mooneye),
+        run_ld_b_b([3, 5, 8, 13, 21, 34])
+    );
+    assert_eq!(
+        Verdict::Failed(Protocol::Mooneye, "registers B-L are all $42".to_string()),
+        run_ld_b_b([0x42; 6])
+    );
+    // Any other LD B,B is just an instruction
+    assert_eq!(Verdict::TimedOut, run_ld_b_b([0; 6]));
+}
+
+#[test]
+fn test_screenshot() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    conformance::run_test(&mut system, 1, None).unwrap();
+    let reference = screen::background(&system.bus().vram);
+
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    assert_eq!(
+        Verdict::Passed(Protocol::Screenshot),
+        conformance::run_test(&mut system, 1, Some(&reference)).unwrap()
+    );
+
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    let blank = vec![0; reference.len()];
+    assert_eq!(
+        Verdict::Failed(
+            Protocol::Screenshot,
+            "7200 pixels differ from the reference".to_string()
+        ),
+        conformance::run_test(&mut system, 1, Some(&blank)).unwrap()
+    );
+}
@@ -0,0 +1,87 @@
+use gameboy_dot_rs::debugger::{Breakpoint, Command, Debugger, Stop};
+use gameboy_dot_rs::error::EmulationError;
+use gameboy_dot_rs::memory::MemoryMapped;
+use gameboy_dot_rs::system::FaultPolicy;
+
+mod common;
+
+fn command(debugger: &mut Debugger, line: &str) -> String {
+    debugger.execute(&Command::parse(line).unwrap())
+}
+
+#[test]
+fn test_breakpoints() {
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/loads.gb"));
+
+    command(&mut debugger, "break 0156");
+    // ld d, $4D is in bank 0, so this never matches
+    command(&mut debugger, "break 01:0158");
+
+    let output = command(&mut debugger, "continue");
+    assert!(output.starts_with("Stopped, hit breakpoint 0156"));
+    assert_eq!(0x156, debugger.system().cpu().pc);
+    assert_eq!(0x3C, debugger.system().cpu().c);
+    assert_eq!(0x00, debugger.system().cpu().d);
+
+    // Stepping from a breakpoint runs past it, and on past the other one
+    command(&mut debugger, "step 3");
+    assert_eq!(0x15C, debugger.system().cpu().pc);
+
+    assert_eq!("0156\n01:0158", command(&mut debugger, "b"));
+    command(&mut debugger, "delete 0156");
+    assert_eq!("01:0158", command(&mut debugger, "b"));
+}
+
+#[test]
+fn test_step_stops_at_breakpoint() {
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/loads.gb"));
+
+    command(&mut debugger, "break 00:015E");
+    assert_eq!(
+        Some(Stop::Breakpoint(Breakpoint {
+            bank: Some(0),
+            address: 0x15E
+        })),
+        debugger.step(100)
+    );
+    assert_eq!(0x7F, debugger.system().cpu().l);
+}
+
+#[test]
+fn test_edit_registers_and_memory() {
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/loads.gb"));
+
+    command(&mut debugger, "set hl C123");
+    command(&mut debugger, "set a $99");
+    assert_eq!(0xC1, debugger.system().cpu().h);
+    assert_eq!(0x23, debugger.system().cpu().l);
+    assert_eq!(0x99, debugger.system().cpu().a);
+
+    assert_eq!("C000: 12 34 56", command(&mut debugger, "write C000 12 34 56"));
+    assert_eq!(0x34, debugger.system().bus().read_byte(0xC001));
+    assert_eq!(
+        "0150: 3E 1A 06 2B 0E 3C 16 4D 1E 5E 26 6F 2E 7F C3 5E\n0160: 01",
+        command(&mut debugger, "x 150 17")
+    );
+
+    // Jump straight to the loop, skipping the loads
+    command(&mut debugger, "set pc 15E");
+    command(&mut debugger, "step 5");
+    assert_eq!(0x15E, debugger.system().cpu().pc);
+    assert_eq!(0x99, debugger.system().cpu().a);
+}
+
+#[test]
+fn test_faults_stop_running() {
+    let mut system = common::load_test_system("test_roms/roms/illegal.gb");
+    system.set_fault_policy(FaultPolicy::Strict);
+    let mut debugger = Debugger::new(system);
+    assert!(matches!(
+        debugger.run(),
+        Stop::Fault(EmulationError::IllegalOpcode { .. })
+    ));
+
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/illegal.gb"));
+    assert_eq!(Stop::LockedUp, debugger.run());
+    assert_eq!(Some(Stop::LockedUp), debugger.step(1));
+}
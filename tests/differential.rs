@@ -0,0 +1,388 @@
+use common::{cpu_registers, implemented_opcodes, FlatMemory};
+use gameboy_dot_rs::cpu::Cpu;
+use gameboy_dot_rs::disassembler::Instruction;
+use proptest::prelude::*;
+use std::fmt;
+
+mod common;
+
+/// Instructions to run from each generated state, before stopping
+const MAX_INSTRUCTIONS: usize = 16;
+
+/// A deliberately simple model of the SM83, to check `Cpu` against
+///
+/// It decodes opcodes by their bit fields, the way the SM83 manual tabulates them, rather than
+/// one by one. It only models the opcodes `Cpu` implements, so it grows along with `Cpu`.
+mod reference {
+    /// B, C, D, E, H, L, [HL] and A, as the 3 bit register fields number them
+    const HL_INDIRECT: u8 = 6;
+    const F: usize = 6;
+    const A: usize = 7;
+
+    #[derive(Clone, Debug, Eq, PartialEq)]
+    pub struct Reference {
+        /// Indexed by the 3 bit register field, with F where [HL] would be
+        pub registers: [u8; 8],
+        pub pc: u16,
+        pub sp: u16,
+        pub ime: bool,
+        /// Set by EI, and moved into IME as the next instruction starts
+        pub ime_scheduled: bool,
+        pub memory: Vec<u8>,
+    }
+
+    impl Reference {
+        fn hl(&self) -> u16 {
+            u16::from_be_bytes([self.registers[4], self.registers[5]])
+        }
+
+        fn set_hl(&mut self, hl: u16) {
+            [self.registers[4], self.registers[5]] = hl.to_be_bytes();
+        }
+
+        fn read(&self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+
+        fn fetch(&mut self) -> u8 {
+            let byte = self.read(self.pc);
+            self.pc = self.pc.wrapping_add(1);
+            byte
+        }
+
+        fn fetch_word(&mut self) -> u16 {
+            let low = self.fetch();
+            u16::from_le_bytes([low, self.fetch()])
+        }
+
+        fn push(&mut self, value: u16) {
+            for byte in value.to_be_bytes() {
+                self.sp = self.sp.wrapping_sub(1);
+                self.memory[self.sp as usize] = byte;
+            }
+        }
+
+        fn pop(&mut self) -> u16 {
+            let low = self.read(self.sp);
+            let high = self.read(self.sp.wrapping_add(1));
+            self.sp = self.sp.wrapping_add(2);
+            u16::from_le_bytes([low, high])
+        }
+
+        /// NZ, Z, NC or C, by the 2 bit condition field
+        fn condition(&self, condition: u8) -> bool {
+            let flag = match condition >> 1 {
+                0 => self.registers[F] & 0x80,
+                _ => self.registers[F] & 0x10,
+            };
+            (flag != 0) == (condition & 1 == 1)
+        }
+
+        fn get(&self, register: u8) -> u8 {
+            match register {
+                HL_INDIRECT => self.read(self.hl()),
+                _ => self.registers[register as usize],
+            }
+        }
+
+        fn set(&mut self, register: u8, value: u8) {
+            match register {
+                HL_INDIRECT => {
+                    let hl = self.hl();
+                    self.memory[hl as usize] = value;
+                }
+                _ => self.registers[register as usize] = value,
+            }
+        }
+
+        /// The high and low registers of BC, DE, HL or AF, by the 2 bit pair field of PUSH and POP
+        fn stack_pair(pair: u8) -> (usize, usize) {
+            match pair {
+                3 => (A, F),
+                _ => (pair as usize * 2, pair as usize * 2 + 1),
+            }
+        }
+
+        /// The address BC, DE, HL+ or HL- points at, by the 2 bit pair field, moving HL along
+        fn pair_address(&mut self, pair: u8) -> u16 {
+            let hl = self.hl();
+            match pair {
+                0 => u16::from_be_bytes([self.registers[0], self.registers[1]]),
+                1 => u16::from_be_bytes([self.registers[2], self.registers[3]]),
+                2 => {
+                    self.set_hl(hl.wrapping_add(1));
+                    hl
+                }
+                _ => {
+                    self.set_hl(hl.wrapping_sub(1));
+                    hl
+                }
+            }
+        }
+
+        /// Run one instruction, returning its machine cycles, or None for opcodes not modelled
+        /// Each memory access is a cycle, including fetches, plus one inside JP, RET and RST, one
+        /// more to check a RET's condition, and one before CALL and PUSH push. Opcodes that can't
+        /// be run are still fetched, leaving PC after them.
+        pub fn step(&mut self) -> Option<u8> {
+            let opcode = self.fetch();
+            let (x, y, z) = (opcode >> 6, (opcode >> 3) & 7, opcode & 7);
+
+            if self.ime_scheduled {
+                self.ime = true;
+                self.ime_scheduled = false;
+            }
+
+            let cycles = match (x, y, z) {
+                (0, 0, 0) => 1,
+                // LD SP, n16
+                (0, 6, 1) => {
+                    self.sp = self.fetch_word();
+                    3
+                }
+                // LD [rr], A and LD A, [rr]
+                (0, _, 2) => {
+                    let address = self.pair_address(y >> 1);
+                    match y & 1 {
+                        0 => self.memory[address as usize] = self.registers[A],
+                        _ => self.registers[A] = self.read(address),
+                    }
+                    2
+                }
+                // LD r, n8
+                (0, _, 6) => {
+                    let value = self.fetch();
+                    self.set(y, value);
+                    if y == HL_INDIRECT {
+                        3
+                    } else {
+                        2
+                    }
+                }
+                // HALT
+                (1, HL_INDIRECT, HL_INDIRECT) => return None,
+                // LD r, r'
+                (1, _, _) => {
+                    let value = self.get(z);
+                    self.set(y, value);
+                    if y == HL_INDIRECT || z == HL_INDIRECT {
+                        2
+                    } else {
+                        1
+                    }
+                }
+                // RET cc
+                (3, 0..=3, 0) => {
+                    if self.condition(y) {
+                        self.pc = self.pop();
+                        5
+                    } else {
+                        2
+                    }
+                }
+                // POP rr
+                (3, 0 | 2 | 4 | 6, 1) => {
+                    let (high, low) = Reference::stack_pair(y >> 1);
+                    [self.registers[low], self.registers[high]] = self.pop().to_le_bytes();
+                    // F's low 4 bits don't exist, so whatever was on the stack there is lost
+                    if low == F {
+                        self.registers[F] &= 0xF0;
+                    }
+                    3
+                }
+                // RET and RETI
+                (3, 1 | 3, 1) => {
+                    self.pc = self.pop();
+                    self.ime |= y == 3;
+                    4
+                }
+                // JP a16
+                (3, 0, 3) => {
+                    self.pc = self.fetch_word();
+                    4
+                }
+                // DI and EI
+                (3, 6 | 7, 3) => {
+                    self.ime &= y == 7;
+                    self.ime_scheduled = y == 7;
+                    1
+                }
+                // CALL cc, a16
+                (3, 0..=3, 4) => {
+                    let address = self.fetch_word();
+                    if self.condition(y) {
+                        self.push(self.pc);
+                        self.pc = address;
+                        6
+                    } else {
+                        3
+                    }
+                }
+                // PUSH rr
+                (3, 0 | 2 | 4 | 6, 5) => {
+                    let (high, low) = Reference::stack_pair(y >> 1);
+                    self.push(u16::from_be_bytes([self.registers[high], self.registers[low]]));
+                    4
+                }
+                // CALL a16
+                (3, 1, 5) => {
+                    let address = self.fetch_word();
+                    self.push(self.pc);
+                    self.pc = address;
+                    6
+                }
+                // RST
+                (3, _, 7) => {
+                    self.push(self.pc);
+                    self.pc = y as u16 * 8;
+                    4
+                }
+                // LDH [a8], A and LDH A, [a8]
+                (3, 4 | 6, 0) => {
+                    let address = 0xFF00 | self.fetch() as u16;
+                    self.load_a(y == 6, address);
+                    3
+                }
+                // LDH [C], A and LDH A, [C]
+                (3, 4 | 6, 2) => {
+                    self.load_a(y == 6, 0xFF00 | self.registers[1] as u16);
+                    2
+                }
+                // LD [a16], A and LD A, [a16]
+                (3, 5 | 7, 2) => {
+                    let address = self.fetch_word();
+                    self.load_a(y == 7, address);
+                    4
+                }
+                _ => return None,
+            };
+
+            Some(cycles)
+        }
+
+        fn load_a(&mut self, into_a: bool, address: u16) {
+            match into_a {
+                true => self.registers[A] = self.read(address),
+                false => self.memory[address as usize] = self.registers[A],
+            }
+        }
+    }
+}
+
+use reference::Reference;
+
+/// One instruction's bytes, shown disassembled when a failing case is reported
+#[derive(Clone)]
+struct Encoded(Vec<u8>);
+
+impl fmt::Debug for Encoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let instruction = Instruction::decode(&self.0, 0).unwrap();
+        write!(f, "{} {:02X?}", instruction.opcode.mnemonic, self.0)
+    }
+}
+
+fn instruction() -> impl Strategy<Value = Encoded> {
+    (
+        proptest::sample::select(implemented_opcodes()),
+        any::<[u8; 2]>(),
+    )
+        .prop_map(|(opcode, operands)| {
+            let length = Instruction::decode(&[opcode, 0, 0], 0).unwrap().len();
+            Encoded([&[opcode], &operands[..length - 1]].concat())
+        })
+}
+
+/// The registers, with F's low nibble clear as it always is, and HL often at an edge where
+/// HL+ and HL- wrap around
+fn registers() -> impl Strategy<Value = [u8; 8]> {
+    (
+        any::<[u8; 8]>(),
+        prop_oneof![Just(0x0000u16), Just(0xFFFF), any::<u16>()],
+    )
+        .prop_map(|(mut registers, hl)| {
+            [registers[4], registers[5]] = hl.to_be_bytes();
+            registers[6] &= 0xF0;
+            registers
+        })
+}
+
+proptest! {
+    #[test]
+    fn test_matches_reference(
+        program in proptest::collection::vec(instruction(), 1..=MAX_INSTRUCTIONS),
+        registers in registers(),
+        pc in any::<u16>(),
+        sp in prop_oneof![Just(0x0000u16), Just(0x0001), any::<u16>()],
+        ime in any::<bool>(),
+        fill in any::<u8>(),
+    ) {
+        let mut memory = vec![fill; 0x10000];
+        for (offset, byte) in program.iter().flat_map(|encoded| encoded.0.iter()).enumerate() {
+            memory[pc.wrapping_add(offset as u16) as usize] = *byte;
+        }
+
+        let [b, c, d, e, h, l, f, a] = registers;
+        let mut cpu = Cpu { a, f, b, c, d, e, h, l, pc, sp, ime, ime_scheduled: false };
+        let mut flat = FlatMemory::new(memory.clone());
+        let mut reference = Reference { registers, pc, sp, ime, ime_scheduled: false, memory };
+
+        // Jumps and writes can take execution anywhere, so both run until either stops
+        for step in 0..MAX_INSTRUCTIONS {
+            let expected = reference.step();
+            let actual = cpu.read_decode_execute(&mut flat);
+
+            prop_assert_eq!(expected, actual.as_ref().ok().copied(), "cycles at step {}: {:?}", step, actual);
+            prop_assert_eq!(reference.registers, cpu_registers(&cpu), "registers B-A at step {}", step);
+            prop_assert_eq!(reference.pc, cpu.pc, "PC at step {}", step);
+            prop_assert_eq!(reference.sp, cpu.sp, "SP at step {}", step);
+            prop_assert_eq!(
+                (reference.ime, reference.ime_scheduled),
+                (cpu.ime, cpu.ime_scheduled),
+                "IME at step {}",
+                step
+            );
+            // POP AF is the one way to load F, from memory that can hold anything
+            prop_assert_eq!(0, cpu.f & 0x0F, "F's low nibble at step {}", step);
+            prop_assert!(reference.memory == flat.bytes, "memory differs at step {}", step);
+
+            if expected.is_none() {
+                break;
+            }
+        }
+    }
+
+    #[test]
+    fn test_push_pop_round_trip(
+        registers in registers(),
+        from in 0..4u8,
+        to in 0..4u8,
+        pc in any::<u16>(),
+        sp in any::<u16>(),
+        fill in any::<u8>(),
+    ) {
+        // PUSH one of BC, DE, HL and AF, then POP it into another
+        // Pushing over the POP before it runs would be a different test
+        prop_assume!(!matches!(sp.wrapping_sub(pc), 2 | 3));
+        let mut memory = vec![fill; 0x10000];
+        memory[pc as usize] = 0xC5 | from << 4;
+        memory[pc.wrapping_add(1) as usize] = 0xC1 | to << 4;
+
+        let [b, c, d, e, h, l, f, a] = registers;
+        let mut cpu = Cpu { a, f, b, c, d, e, h, l, pc, sp, ime: false, ime_scheduled: false };
+        let mut flat = FlatMemory::new(memory);
+        let pairs = |cpu: &Cpu| [[cpu.b, cpu.c], [cpu.d, cpu.e], [cpu.h, cpu.l], [cpu.a, cpu.f]];
+        let [high, mut low] = pairs(&cpu)[from as usize];
+        // Only F loses its low 4 bits on the way
+        if to == 3 {
+            low &= 0xF0;
+        }
+
+        prop_assert_eq!(Ok(4), cpu.read_decode_execute(&mut flat));
+        prop_assert_eq!(Ok(3), cpu.read_decode_execute(&mut flat));
+
+        prop_assert_eq!([high, low], pairs(&cpu)[to as usize]);
+        prop_assert_eq!(sp, cpu.sp);
+        prop_assert_eq!(pc.wrapping_add(2), cpu.pc);
+    }
+}
@@ -0,0 +1,35 @@
+use gameboy_dot_rs::disassembler::{self, ENTRY_POINTS};
+
+mod common;
+
+#[test]
+fn test_follow_loads() {
+    let rom = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+    let lines = disassembler::disassemble_recursive(&rom, &ENTRY_POINTS);
+
+    let text_at = |address: u16| {
+        lines
+            .iter()
+            .find(|line| line.bank == 0 && line.address == address)
+            .map(|line| line.text.as_str())
+    };
+
+    assert_eq!(Some("jp $0150"), text_at(0x100));
+    assert_eq!(Some("ld a, $1A"), text_at(0x150));
+    assert_eq!(Some("ld l, $7F"), text_at(0x15C));
+    assert_eq!(Some("jp $015E"), text_at(0x15E));
+
+    // The header isn't reachable, so its logo is data rather than code
+    let logo = text_at(0x103).unwrap();
+    assert!(logo.starts_with("db $00, $CE, $ED"), "{}", logo);
+}
+
+#[test]
+fn test_linear_covers_every_byte() {
+    let rom = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+    let lines = disassembler::disassemble_linear(&rom);
+
+    let bytes: usize = lines.iter().map(|line| line.bytes.len()).sum();
+    assert_eq!(rom.len(), bytes);
+    assert_eq!(1, lines.last().unwrap().bank);
+}
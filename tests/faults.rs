@@ -0,0 +1,63 @@
+use gameboy_dot_rs::error::EmulationError;
+use gameboy_dot_rs::system::{FaultPolicy, Gas};
+
+mod common;
+
+#[test]
+fn test_illegal_opcode_locks_up() {
+    let mut system = common::load_test_system("test_roms/roms/illegal.gb");
+
+    system.run_with_gas(Gas::LIMITED(10)).unwrap();
+
+    assert!(system.is_locked_up());
+    assert_eq!(0x42, system.cpu().a);
+    // Hung on the $D3, though PC has moved past it
+    assert_eq!(Some(0x152), system.locked_up_at());
+    assert_eq!(0x153, system.cpu().pc);
+
+    // Once locked up, there is nothing left to run
+    system.run().unwrap();
+    assert_eq!(0x42, system.cpu().a);
+
+    let mut restored = common::load_test_system("test_roms/roms/illegal.gb");
+    restored.load_state(&system.save_state()).unwrap();
+    assert_eq!(Some(0x152), restored.locked_up_at());
+}
+
+#[test]
+fn test_illegal_opcode_strict() {
+    let mut system = common::load_test_system("test_roms/roms/illegal.gb");
+    system.set_fault_policy(FaultPolicy::Strict);
+
+    assert_eq!(
+        Err(EmulationError::IllegalOpcode { pc: 0x152, opcode: 0xD3 }),
+        system.run_with_gas(Gas::LIMITED(10))
+    );
+    assert!(!system.is_locked_up());
+    assert_eq!(None, system.locked_up_at());
+}
+
+#[test]
+fn test_unmapped_access_open_bus() {
+    let mut system = common::load_test_system("test_roms/roms/unmapped.gb");
+
+    system.run_with_gas(Gas::LIMITED(3)).unwrap();
+
+    assert_eq!(0xFF, system.cpu().a);
+    assert_eq!(0x156, system.cpu().pc);
+}
+
+#[test]
+fn test_unmapped_access_strict() {
+    let mut system = common::load_test_system("test_roms/roms/unmapped.gb");
+    system.set_fault_policy(FaultPolicy::Strict);
+
+    assert_eq!(
+        Err(EmulationError::UnmappedRead { pc: 0x150, address: 0xFEA0 }),
+        system.run_with_gas(Gas::LIMITED(3))
+    );
+    assert_eq!(
+        Err(EmulationError::UnmappedWrite { pc: 0x153, address: 0xFEA0, value: 0xFF }),
+        system.run_with_gas(Gas::LIMITED(1))
+    );
+}
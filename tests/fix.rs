@@ -0,0 +1,29 @@
+use gameboy_dot_rs::cartridge::fix::Fix;
+use gameboy_dot_rs::cartridge::header::Checksum;
+
+mod common;
+
+#[test]
+fn test_fix_matches_rgbfix() {
+    let expected = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+
+    // Undo what rgbfix -v -p 0xFF did: padding, logo, and both checksums
+    let mut rom = expected[..0x4000].to_vec();
+    rom[0x104..0x134].fill(0);
+    rom[0x14D] = 0;
+    rom[0x14E] = 0;
+    rom[0x14F] = 0;
+    rom[0x148] = 0;
+
+    let header = Fix {
+        pad: Some(0xFF),
+        ..Fix::default()
+    }
+    .apply(&mut rom)
+    .unwrap();
+
+    assert_eq!(expected, rom);
+    assert!(header.validation.logo);
+    assert_eq!(Checksum::Ok(0xE7), header.validation.header_checksum);
+    assert_eq!(Checksum::Ok(0xDB57), header.validation.global_checksum);
+}
@@ -0,0 +1,190 @@
+use gameboy_dot_rs::gdb::GdbStub;
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread::{self, JoinHandle};
+
+mod common;
+
+/// A scripted GDB, talking to a stub serving a ROM on another thread
+struct Client {
+    stream: TcpStream,
+    no_ack: bool,
+    /// The stub's result and final PC, once the session ends
+    server: JoinHandle<(io::Result<()>, u16)>,
+}
+
+impl Client {
+    fn connect(rom: &'static str) -> Client {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+
+        let server = thread::spawn(move || {
+            let mut stub = GdbStub::new(common::load_test_system(rom));
+            let (stream, _) = listener.accept().unwrap();
+            let result = stub.serve(stream);
+
+            (result, stub.system().cpu().pc)
+        });
+
+        let stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+
+        Client {
+            stream,
+            no_ack: false,
+            server,
+        }
+    }
+
+    fn send(&mut self, packet: &str) {
+        let checksum = packet.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        write!(self.stream, "${}#{:02x}", packet, checksum).unwrap();
+    }
+
+    fn receive(&mut self) -> String {
+        let mut byte = [0];
+
+        // Skip the acknowledgement of what was sent
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'$' {
+                break;
+            }
+        }
+
+        let mut data = Vec::new();
+        loop {
+            self.stream.read_exact(&mut byte).unwrap();
+            if byte[0] == b'#' {
+                break;
+            }
+            data.push(byte[0]);
+        }
+
+        let mut checksum = [0; 2];
+        self.stream.read_exact(&mut checksum).unwrap();
+        let expected = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        assert_eq!(format!("{:02x}", expected).as_bytes(), checksum);
+
+        if !self.no_ack {
+            self.stream.write_all(b"+").unwrap();
+        }
+        String::from_utf8(data).unwrap()
+    }
+
+    fn request(&mut self, packet: &str) -> String {
+        self.send(packet);
+        self.receive()
+    }
+
+    /// Detach, returning the PC the stub left the system at
+    fn detach(mut self) -> u16 {
+        assert_eq!("OK", self.request("D"));
+
+        let (result, pc) = self.server.join().unwrap();
+        result.unwrap();
+        pc
+    }
+}
+
+#[test]
+fn test_registers_and_memory() {
+    let mut client = Client::connect("test_roms/roms/loads.gb");
+
+    assert!(client
+        .request("qSupported:swbreak+;xmlRegisters=z80")
+        .contains("qXfer:features:read+"));
+    assert_eq!("S05", client.request("?"));
+
+    // AF, BC, DE, HL, SP, then PC, 16 bits little endian each
+    assert!(client
+        .request("g")
+        .starts_with("0000000000000000feff0001"));
+    assert_eq!("c3500100", client.request("m100,4"));
+
+    assert_eq!("OK", client.request("Mc000,3:010203"));
+    assert_eq!("010203", client.request("mc000,3"));
+
+    assert_eq!("OK", client.request("P3=00c0"));
+    assert_eq!("00c0", client.request("p3"));
+
+    // Bank 1 through the flat address space, though bank 1 isn't what the CPU sees at 0x4000
+    let rom = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+    let expected: String = rom[0x4000..0x4004]
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    assert_eq!(expected, client.request("m14000,4"));
+    assert_eq!("E01", client.request("m24000,4"));
+
+    assert_eq!(0x0100, client.detach());
+}
+
+#[test]
+fn test_breakpoints_and_stepping() {
+    let mut client = Client::connect("test_roms/roms/loads.gb");
+
+    assert_eq!("OK", client.request("Z0,15c,1"));
+    assert_eq!("S05", client.request("c"));
+    assert_eq!("5c01", client.request("p5"));
+
+    assert_eq!("S05", client.request("s"));
+    assert_eq!("5e01", client.request("p5"));
+
+    // Resuming from an address
+    assert_eq!("S05", client.request("c150"));
+    assert_eq!("5c01", client.request("p5"));
+
+    assert_eq!("OK", client.request("z0,15c,1"));
+    assert_eq!("S05", client.request("s150"));
+    assert_eq!(0x0152, client.detach());
+}
+
+#[test]
+fn test_watchpoints() {
+    let mut client = Client::connect("test_roms/roms/joypad_log.gb");
+
+    assert_eq!("OK", client.request("Z2,c001,1"));
+    assert_eq!("T05watch:c001;", client.request("c"));
+    // Stopped after the write, at the jump back to the loop
+    assert_eq!("5b01", client.request("p5"));
+    assert_eq!("efef", client.request("mc000,2"));
+    assert_eq!("T05watch:c001;", client.request("?"));
+
+    assert_eq!("OK", client.request("z2,c001,1"));
+    assert_eq!("OK", client.request("Z3,ff00,1"));
+    assert_eq!("T05rwatch:ff00;", client.request("c"));
+
+    // Access watchpoints are reported as such, whether the access read or wrote
+    assert_eq!("OK", client.request("z3,ff00,1"));
+    assert_eq!("OK", client.request("Z4,c100,1"));
+    assert_eq!("T05awatch:c100;", client.request("c"));
+
+    client.detach();
+}
+
+#[test]
+fn test_interrupt() {
+    let mut client = Client::connect("test_roms/roms/loads.gb");
+
+    // loads.gb ends in an endless loop, so only an interrupt stops it
+    client.send("c");
+    client.stream.write_all(&[0x03]).unwrap();
+    assert_eq!("S02", client.receive());
+    assert_eq!("5e01", client.request("p5"));
+
+    client.send("k");
+    let (result, _) = client.server.join().unwrap();
+    result.unwrap();
+}
+
+#[test]
+fn test_no_ack_mode() {
+    let mut client = Client::connect("test_roms/roms/loads.gb");
+
+    assert_eq!("OK", client.request("QStartNoAckMode"));
+    client.no_ack = true;
+
+    assert_eq!("0001", client.request("p5"));
+    assert_eq!(0x0100, client.detach());
+}
@@ -0,0 +1,100 @@
+use gameboy_dot_rs::memory::MemoryMapped;
+use gameboy_dot_rs::speed::Speed;
+use gameboy_dot_rs::symbols::Symbols;
+use gameboy_dot_rs::system::Gas;
+
+mod common;
+
+const HDMA5: u16 = 0xFF55;
+
+#[test]
+fn test_general_purpose_hdma() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+
+    system.run_with_gas(Gas::LIMITED(13)).unwrap();
+
+    for i in 0..0x20 {
+        assert_eq!(i as u8, system.bus().vram.read_byte(i));
+    }
+    assert_eq!(0, system.bus().vram.read_byte(0x20));
+
+    // The transfer finished before the CPU read back its status
+    assert_eq!(0xFF, system.cpu().b);
+
+    // 33 cycles of instructions plus 8 cycles per block
+    assert_eq!(49, system.cycles());
+}
+
+#[test]
+fn test_general_purpose_hdma_double_speed() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    system.bus_mut().key1.speed = Speed::Double;
+
+    system.run_with_gas(Gas::LIMITED(13)).unwrap();
+
+    assert_eq!(0x1F, system.bus().vram.read_byte(0x1F));
+
+    // A block takes twice as many cycles at double speed
+    assert_eq!(65, system.cycles());
+}
+
+#[test]
+fn test_hblank_hdma() {
+    let mut system = common::load_test_system("test_roms/roms/hdma_hblank.gb");
+
+    system.run_with_gas(Gas::LIMITED(11)).unwrap();
+
+    // Nothing is copied until the first HBlank
+    assert_eq!(0x02, system.bus().read_byte(HDMA5));
+    assert_eq!(0, system.bus().vram.read_byte(0x01));
+
+    system.bus_mut().hblank();
+    assert_eq!(0x01, system.bus().read_byte(HDMA5));
+    assert_eq!(0x0F, system.bus().vram.read_byte(0x0F));
+    assert_eq!(0, system.bus().vram.read_byte(0x10));
+
+    system.bus_mut().hblank();
+    assert_eq!(0x00, system.bus().read_byte(HDMA5));
+    assert_eq!(0x1F, system.bus().vram.read_byte(0x1F));
+
+    // Stalled cycles are charged to the next instruction
+    let cycles_before = system.cycles();
+    system.run_with_gas(Gas::LIMITED(1)).unwrap();
+    assert_eq!(cycles_before + 2 + 16, system.cycles());
+
+    // Cancelling leaves bit 7 set along with the remaining length
+    system.run_with_gas(Gas::LIMITED(1)).unwrap();
+    assert_eq!(0x80, system.bus().read_byte(HDMA5));
+
+    system.bus_mut().hblank();
+    assert_eq!(0, system.bus().vram.read_byte(0x21));
+}
+
+#[test]
+fn test_hblank_hdma_each_line() {
+    let mut system = common::load_test_system("test_roms/roms/hdma_hblank.gb");
+    let bytes = common::load_test_rom_bytes("test_roms/roms/hdma_hblank.sym");
+    let symbols = Symbols::parse(&String::from_utf8(bytes).unwrap()).unwrap();
+
+    // Start the transfer, then skip cancelling it and leave the ROM spinning in its loop
+    system.run_with_gas(Gas::LIMITED(11)).unwrap();
+    system.cpu_mut().pc = symbols.get("Loop").unwrap().1;
+
+    let mut run_until = |cycles| {
+        while system.cycles() < cycles {
+            system.run_with_gas(Gas::LIMITED(1)).unwrap();
+        }
+        (
+            system.bus().read_byte(HDMA5),
+            system.bus().vram.read_byte(0x0F),
+            system.bus().vram.read_byte(0x1F),
+            system.bus().vram.read_byte(0x2F),
+        )
+    };
+
+    // Each line enters HBlank 63 cycles in, and copies one block
+    assert_eq!((0x02, 0, 0, 0), run_until(60));
+    assert_eq!((0x01, 0x0F, 0, 0), run_until(114));
+    assert_eq!((0x00, 0x0F, 0x1F, 0), run_until(228));
+    assert_eq!((0xFF, 0x0F, 0x1F, 0x2F), run_until(342));
+}
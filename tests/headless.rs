@@ -0,0 +1,104 @@
+use gameboy_dot_rs::debugger::Breakpoint;
+use gameboy_dot_rs::headless::{self, Outcome, Until};
+use gameboy_dot_rs::memory::MemoryMapped;
+use gameboy_dot_rs::screen::{self, SCREEN_WIDTH};
+use gameboy_dot_rs::serial::{SB_ADDRESS, SC_ADDRESS};
+use gameboy_dot_rs::system::Model;
+
+mod common;
+
+// In joypad_log.gb
+const LDH_A_P1_ADDRESS: u16 = 0x158;
+
+#[test]
+fn test_run_until_pc() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let until = Until {
+        pc: Some(Breakpoint {
+            bank: Some(0),
+            address: LDH_A_P1_ADDRESS,
+        }),
+        ..Until::default()
+    };
+
+    let outcome = headless::run(&mut system, 1, &until).unwrap();
+    assert_eq!(Outcome::ReachedPc, outcome);
+    assert!(outcome.is_success(&until));
+    assert_eq!(0x15A, system.cpu().pc);
+
+    // The watchpoint is gone afterwards
+    assert!(system.bus().watchpoints().is_empty());
+}
+
+#[test]
+fn test_run_out_of_frames() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let until = Until {
+        serial: Some(b"Passed".to_vec()),
+        ..Until::default()
+    };
+
+    let outcome = headless::run(&mut system, 2, &until).unwrap();
+    assert_eq!(Outcome::RanAllFrames, outcome);
+    assert!(!outcome.is_success(&until));
+    assert_eq!(2, system.cycles() / 17556);
+
+    // Running every frame is all that was asked without anything to run until
+    assert!(outcome.is_success(&Until::default()));
+}
+
+#[test]
+fn test_run_until_serial() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    for byte in b"Passed\n" {
+        system.bus_mut().write_byte(SB_ADDRESS, *byte);
+        system.bus_mut().write_byte(SC_ADDRESS, 0x81);
+    }
+
+    let until = Until {
+        serial: Some(b"Passed".to_vec()),
+        ..Until::default()
+    };
+    assert_eq!(Outcome::ReceivedSerial, headless::run(&mut system, 1, &until).unwrap());
+}
+
+#[test]
+fn test_lock_up_fails() {
+    let mut system = common::load_test_system("test_roms/roms/illegal.gb");
+
+    let outcome = headless::run(&mut system, 10, &Until::default()).unwrap();
+    assert_eq!(Outcome::LockedUp { pc: 0x152 }, outcome);
+    assert!(!outcome.is_success(&Until::default()));
+}
+
+#[test]
+fn test_screenshot_shows_vram() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    headless::run(&mut system, 1, &Until::default()).unwrap();
+
+    // The untouched tile map shows tile 0 everywhere, whose rows are $00 $01, $02 $03, ...
+    let background = screen::background(&system.bus().vram);
+    assert_eq!([0, 0, 0, 0, 0, 0, 0, 2], background[..8]);
+    assert_eq!(
+        [0, 0, 0, 0, 0, 0, 3, 2],
+        background[SCREEN_WIDTH..SCREEN_WIDTH + 8]
+    );
+}
+
+#[test]
+fn test_screenshot_shows_sgb_border() {
+    let mut system = common::load_test_system("test_roms/roms/sgb.gb");
+    let mut bytes = Vec::new();
+    headless::screenshot(&mut system, &mut bytes).unwrap();
+    let info = png::Decoder::new(bytes.as_slice()).read_info().unwrap().info().clone();
+    assert_eq!((160, 144), (info.width, info.height));
+
+    // With a Super Game Boy connected, the screen sits inside the whole 256x224 picture
+    system.set_model(Model::Sgb);
+    headless::run(&mut system, 1, &Until::default()).unwrap();
+    let mut bytes = Vec::new();
+    headless::screenshot(&mut system, &mut bytes).unwrap();
+    let info = png::Decoder::new(bytes.as_slice()).read_info().unwrap().info().clone();
+    assert_eq!((256, 224), (info.width, info.height));
+    assert_eq!(png::ColorType::Rgb, info.color_type);
+}
@@ -6,7 +6,7 @@ mod common;
 fn test_jumps() {
     let mut system = common::load_test_system("test_roms/roms/jumps.gb");
 
-    system.run_with_gas(Gas::LIMITED(10));
+    system.run_with_gas(Gas::LIMITED(10)).unwrap();
 
     assert_eq!(0x150, system.cpu().pc);
 }
\ No newline at end of file
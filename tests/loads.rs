@@ -7,7 +7,7 @@ mod common;
 fn test_loads() {
     let mut system = common::load_test_system("test_roms/roms/loads.gb");
 
-    system.run_with_gas(Gas::LIMITED(20));
+    system.run_with_gas(Gas::LIMITED(20)).unwrap();
 
     assert_eq!(0x1A, system.cpu().a);
     assert_eq!(0x2B, system.cpu().b);
@@ -22,7 +22,7 @@ fn test_loads() {
 fn test_load_memory() {
     let mut system = common::load_test_system("test_roms/roms/load_memory.gb");
 
-    system.run_with_gas(Gas::LIMITED(20));
+    system.run_with_gas(Gas::LIMITED(20)).unwrap();
 
     assert_eq!(123, system.bus().ram.read_byte(0));
     assert_eq!(0, system.bus().ram.read_byte(1));
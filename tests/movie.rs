@@ -0,0 +1,93 @@
+use gameboy_dot_rs::joypad::Buttons;
+use gameboy_dot_rs::movie::{Movie, MovieError, Recorder};
+use gameboy_dot_rs::rtc::CYCLES_PER_SECOND;
+use gameboy_dot_rs::save_state::SaveStateError;
+use gameboy_dot_rs::system::{Gas, Model, CYCLES_PER_FRAME};
+
+mod common;
+
+// The ROM fills WRAM with input in a little over 6 frames, so movies stay shorter than that
+const FRAMES: usize = 6;
+const HASH_INTERVAL: u32 = 2;
+const RTC_START: u64 = 1_600_000_000;
+
+fn record() -> Movie {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    // Start part way in, so the starting state isn't just power on
+    system.run_with_gas(Gas::LIMITED(10)).unwrap();
+
+    let mut recorder = Recorder::with_hash_interval(&mut system, RTC_START, HASH_INTERVAL);
+    for frame in 0..FRAMES {
+        let buttons = Buttons {
+            right: frame % 2 == 1,
+            down: frame > 3,
+            ..Buttons::default()
+        };
+        recorder.record_frame(&mut system, buttons).unwrap();
+    }
+
+    recorder.finish()
+}
+
+#[test]
+fn test_record_and_play() {
+    let movie = Movie::from_bytes(&record().to_bytes()).unwrap();
+    assert_eq!(FRAMES, movie.frames.len());
+    assert_eq!(FRAMES / HASH_INTERVAL as usize, movie.hashes.len());
+    assert_eq!(Model::Dmg, movie.model);
+
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    movie.play(&mut system).unwrap();
+
+    // The clock only follows emulated time
+    let seconds = system.cycles() / CYCLES_PER_SECOND;
+    assert!(system.cycles() >= FRAMES as u64 * CYCLES_PER_FRAME);
+    assert_eq!(RTC_START + seconds, system.rtc_seconds());
+}
+
+#[test]
+fn test_trailing_bytes() {
+    let mut bytes = record().to_bytes();
+    bytes.push(0);
+
+    assert_eq!(
+        Err(MovieError::State(SaveStateError::TrailingBytes { count: 1 })),
+        Movie::from_bytes(&bytes)
+    );
+}
+
+#[test]
+fn test_playback_is_deterministic() {
+    let movie = record();
+
+    let mut first = common::load_test_system("test_roms/roms/joypad_log.gb");
+    movie.play(&mut first).unwrap();
+    let mut second = common::load_test_system("test_roms/roms/joypad_log.gb");
+    movie.play(&mut second).unwrap();
+
+    assert_eq!(first.save_state(), second.save_state());
+}
+
+#[test]
+fn test_desync() {
+    let mut movie = record();
+    // Frame 3 is played differently, which the hash after frame 4 catches
+    movie.frames[2].right = !movie.frames[2].right;
+
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    assert!(matches!(
+        movie.play(&mut system),
+        Err(MovieError::Desync { frame: 4, .. })
+    ));
+}
+
+#[test]
+fn test_wrong_rom() {
+    let movie = record();
+
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    assert!(matches!(
+        movie.play(&mut system),
+        Err(MovieError::WrongRom { .. })
+    ));
+}
@@ -0,0 +1,106 @@
+use gameboy_dot_rs::assembler::Assembler;
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::profiler::{Profiler, Routine};
+use gameboy_dot_rs::symbols::Symbols;
+use gameboy_dot_rs::system::{Gas, System};
+
+mod common;
+
+// In joypad_log.gb
+const LDH_A_P1_ADDRESS: u16 = 0x158;
+
+fn joypad_log_symbols() -> Symbols {
+    let bytes = common::load_test_rom_bytes("test_roms/roms/joypad_log.sym");
+    Symbols::parse(&String::from_utf8(bytes).unwrap()).unwrap()
+}
+
+#[test]
+fn test_profile_run() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    system.set_profiler(Profiler::new().with_symbols(joypad_log_symbols()));
+
+    system.run_with_gas(Gas::LIMITED(100)).unwrap();
+    let profiler = system.take_profiler().unwrap();
+
+    assert_eq!(system.cycles(), profiler.total_cycles());
+    let ldh = profiler.instruction(0, LDH_A_P1_ADDRESS);
+    assert_eq!(3 * ldh.executions, ldh.cycles);
+
+    // Nothing is called, so every cycle is spent where the ROM started
+    assert_eq!(
+        format!("00:0100 {}\n", system.cycles()),
+        profiler.collapsed_stacks()
+    );
+    assert!(
+        profiler.flat_report().contains("00:0158 Loop+4"),
+        "{}",
+        profiler.flat_report()
+    );
+}
+
+#[test]
+fn test_call_stack() {
+    let assembly = Assembler::new()
+        .assemble(
+            r#"
+INCLUDE "hardware.inc"
+
+SECTION "Serial interrupt", ROM0[$58]
+Serial:
+    ld b, $5A
+    reti
+
+SECTION "Header", ROM0[$100]
+Start:
+    jp Entrypoint
+    ds $150 - @, 0
+
+Entrypoint:
+    ld a, $08
+    ldh [rIE], a
+    ei
+    call Outer
+    ; Requesting the serial interrupt enters its handler straight after
+    ldh [rIF], a
+Done:
+    jp Done
+
+Outer:
+    call Inner
+    ret
+
+Inner:
+    nop
+    ret
+"#,
+        )
+        .unwrap();
+
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&assembly.rom).unwrap());
+    system.set_profiler(Profiler::new().with_symbols(assembly.symbols.clone()));
+    system.run_with_gas(Gas::LIMITED(12)).unwrap();
+    let profiler = system.take_profiler().unwrap();
+
+    assert_eq!(0x5A, system.cpu().b);
+    assert_eq!(system.cycles(), profiler.total_cycles());
+
+    let routines = profiler.routines();
+    let calls = |label: &str| {
+        let (bank, address) = assembly.symbols.get(label).unwrap();
+        routines[&Routine { bank, address }].calls
+    };
+    assert_eq!(1, calls("Outer"));
+    assert_eq!(1, calls("Inner"));
+    assert_eq!(1, calls("Serial"));
+
+    // Entering the handler is put down to the instruction that was interrupted
+    assert_eq!(
+        "Start 24\nStart;Outer 10\nStart;Outer;Inner 5\nStart;Serial 6\n",
+        profiler.collapsed_stacks()
+    );
+    assert!(
+        profiler.tree_report().contains("    11.1% 5 cycles, 5 in itself: Inner"),
+        "{}",
+        profiler.tree_report()
+    );
+}
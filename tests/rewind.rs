@@ -0,0 +1,97 @@
+use gameboy_dot_rs::joypad::Buttons;
+use gameboy_dot_rs::rewind::Rewind;
+use gameboy_dot_rs::system::System;
+
+mod common;
+
+const FRAMES: usize = 10;
+
+fn buttons(frame: usize) -> Buttons {
+    Buttons {
+        right: frame.is_multiple_of(2),
+        left: frame.is_multiple_of(3),
+        up: frame.is_multiple_of(5),
+        down: frame >= 7,
+        ..Buttons::default()
+    }
+}
+
+fn run_frames(system: &mut System, frames: std::ops::Range<usize>, mut rewind: Option<&mut Rewind>) {
+    for frame in frames {
+        if let Some(rewind) = rewind.as_mut() {
+            rewind.push(system);
+        }
+
+        system.bus_mut().joypad.buttons = buttons(frame);
+        system.run_frame().unwrap();
+    }
+}
+
+#[test]
+fn test_rewind_and_replay() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let mut rewind = Rewind::with_keyframe_interval(usize::MAX, 4);
+
+    run_frames(&mut system, 0..FRAMES, Some(&mut rewind));
+    let state = system.save_state();
+    assert_eq!(FRAMES, rewind.len());
+
+    // Back to the start of frame 7, then play the last 3 frames again
+    assert_eq!(3, rewind.rewind(&mut system, 3).unwrap());
+    assert_eq!(7, rewind.len());
+    run_frames(&mut system, 7..FRAMES, None);
+
+    assert_eq!(state, system.save_state());
+}
+
+#[test]
+fn test_rewind_to_start() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let start = system.save_state();
+    let mut rewind = Rewind::with_keyframe_interval(usize::MAX, 4);
+
+    run_frames(&mut system, 0..FRAMES, Some(&mut rewind));
+
+    // Asking for more frames than were kept stops at the oldest
+    assert_eq!(FRAMES, rewind.rewind(&mut system, FRAMES + 5).unwrap());
+    assert!(rewind.is_empty());
+    assert_eq!(0, rewind.used_bytes());
+    assert_eq!(start, system.save_state());
+
+    assert!(!rewind.step_back(&mut system).unwrap());
+}
+
+#[test]
+fn test_deltas_are_smaller_than_keyframes() {
+    // Spins in a loop once everything is loaded, so little changes between frames
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    let state_bytes = system.save_state().len();
+    let mut rewind = Rewind::with_keyframe_interval(usize::MAX, FRAMES);
+
+    run_frames(&mut system, 0..FRAMES, Some(&mut rewind));
+
+    assert_eq!(FRAMES, rewind.len());
+    assert!(rewind.used_bytes() < 2 * state_bytes);
+}
+
+#[test]
+fn test_budget_drops_oldest_frames() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let state_bytes = system.save_state().len();
+    // Room for a couple of keyframes and their deltas, but not all of them
+    let mut rewind = Rewind::with_keyframe_interval(2 * state_bytes, 2);
+
+    run_frames(&mut system, 0..FRAMES, Some(&mut rewind));
+
+    assert!(rewind.len() < FRAMES);
+    assert!(rewind.len() >= 2);
+    assert!(rewind.used_bytes() <= 2 * state_bytes);
+
+    // What is left is still the most recent frames
+    let kept = rewind.len();
+    let mut expected = common::load_test_system("test_roms/roms/joypad_log.gb");
+    run_frames(&mut expected, 0..FRAMES - kept, None);
+
+    rewind.rewind(&mut system, kept).unwrap();
+    assert_eq!(expected.save_state(), system.save_state());
+}
@@ -1,6 +1,10 @@
 use gameboy_dot_rs::cartridge::cartridge_type::CartridgeType;
+use gameboy_dot_rs::cartridge::cgb_support::CgbSupport;
+use gameboy_dot_rs::cartridge::destination::Destination;
 use gameboy_dot_rs::cartridge::header::{Checksum, Header, Validation};
-use gameboy_dot_rs::cartridge::parse::Parse;
+use gameboy_dot_rs::cartridge::licensee::{Licensee, Publisher};
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::cartridge::parse::{Field, Parse, ParseError};
 
 mod common;
 
@@ -12,6 +16,13 @@ fn test_pokemon_red_header() {
 
     assert_eq!(Header {
         title: "POKEMON RED".to_string(),
+        manufacturer_code: None,
+        cgb_support: CgbSupport::DmgOnly,
+        publisher: Publisher {
+            licensee: Licensee::New("01".to_string()),
+            name: Some("Nintendo Research & Development 1"),
+        },
+        sgb: true,
         cartridge_type: CartridgeType::Mbc3 {
             battery: true,
             ram: true,
@@ -19,11 +30,132 @@ fn test_pokemon_red_header() {
         },
         rom_banks: 64,
         ram_banks: 4,
+        destination: Destination::Overseas,
         version: 0,
         validation: Validation {
             logo: true,
             header_checksum: Checksum::Ok(0x20),
-            global_checksum: 0xE691,
+            global_checksum: Checksum::Unchecked(0x91E6),
         }
     }, header)
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_global_checksum() {
+    let mut bytes = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+
+    let header = Header::parse_rom(&bytes[..]).unwrap();
+    assert_eq!(Checksum::Ok(0xDB57), header.validation.global_checksum);
+
+    // Corrupt a byte outside of the header
+    bytes[0x7000] ^= 0x01;
+
+    let header = Header::parse_rom(&bytes[..]).unwrap();
+    assert_eq!(
+        Checksum::Err {
+            actual: 0xDB56,
+            expected: 0xDB57
+        },
+        header.validation.global_checksum
+    );
+    // The header checksum only covers the header, so it is unaffected
+    assert_eq!(Checksum::Ok(0xE7), header.validation.header_checksum);
+}
+
+#[test]
+fn test_header_json_field_names() {
+    let bytes = common::load_test_rom_bytes("test_roms/static/pokemon_red_header.gb");
+
+    let header = Header::parse(&bytes[..]).unwrap();
+    let json = serde_json::to_value(&header).unwrap();
+
+    // These names are relied on by scripts using `header --format json`, so they must stay stable
+    // Absent values are left out rather than null, as TOML has to
+    assert_eq!(serde_json::json!({
+        "title": "POKEMON RED",
+        "cgb_support": "dmg_only",
+        "publisher": {
+            "licensee": { "kind": "new", "code": "01" },
+            "name": "Nintendo Research & Development 1",
+        },
+        "sgb": true,
+        "cartridge_type": { "kind": "mbc3", "battery": true, "ram": true, "timer": false },
+        "rom_banks": 64,
+        "ram_banks": 4,
+        "destination": "overseas",
+        "version": 0,
+        "validation": {
+            "logo": true,
+            "header_checksum": { "status": "ok", "value": 0x20 },
+            "global_checksum": { "status": "unchecked", "value": 0x91E6 },
+        },
+    }), json);
+}
+
+#[test]
+fn test_header_text() {
+    let bytes = common::load_test_rom_bytes("test_roms/static/pokemon_red_header.gb");
+
+    let header = Header::parse(&bytes[..]).unwrap();
+
+    // The same names as JSON and TOML, so the text is just as stable
+    assert_eq!("\
+cartridge_type.battery: true
+cartridge_type.kind: mbc3
+cartridge_type.ram: true
+cartridge_type.timer: false
+cgb_support: dmg_only
+destination: overseas
+publisher.licensee.code: 01
+publisher.licensee.kind: new
+publisher.name: Nintendo Research & Development 1
+ram_banks: 4
+rom_banks: 64
+sgb: true
+title: POKEMON RED
+validation.global_checksum.status: unchecked
+validation.global_checksum.value: 37350
+validation.header_checksum.status: ok
+validation.header_checksum.value: 32
+validation.logo: true
+version: 0", header.to_text());
+}
+
+#[test]
+fn test_header_toml_matches_json() {
+    let mut bytes = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+    // An unknown publisher, so there is neither a name nor a manufacturer code
+    bytes[0x14B] = 0xFE;
+
+    let header = Header::parse(&bytes[0x100..]).unwrap();
+    let json = serde_json::to_value(&header).unwrap();
+    let toml = serde_json::to_value(toml::Value::try_from(&header).unwrap()).unwrap();
+
+    assert_eq!(json, toml);
+}
+
+#[test]
+fn test_parse_errors() {
+    let bytes = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+
+    let mut bad_ram = bytes.clone();
+    bad_ram[0x149] = 0x07;
+    assert_eq!(ParseError::InvalidCode {
+        field: Field::RamBanks,
+        offset: Some(0x149),
+        value: 0x07,
+    }, Mbc1::from_bytes(&bad_ram).err().unwrap());
+
+    // The header still declares 2 banks
+    assert_eq!(ParseError::WrongLength {
+        field: Field::Rom,
+        expected: 0x8000,
+        actual: 0x4000,
+    }, Mbc1::from_bytes(&bytes[..0x4000]).err().unwrap());
+
+    assert_eq!(ParseError::TooShort {
+        field: Field::Rom,
+        minimum: 0x150,
+        actual: 0x100,
+    }, Mbc1::from_bytes(&bytes[..0x100]).err().unwrap());
+}
@@ -0,0 +1,107 @@
+use gameboy_dot_rs::cartridge::fix::Fix;
+use gameboy_dot_rs::cartridge::mbc1::Mbc1;
+use gameboy_dot_rs::memory::MemoryMapped;
+use gameboy_dot_rs::save_state::{self, SaveStateError};
+use gameboy_dot_rs::sgb::super_game_boy::SuperGameBoy;
+use gameboy_dot_rs::system::{Gas, System};
+
+mod common;
+
+#[test]
+fn test_save_and_load() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+
+    // Stop part way through, after a, b, and c are loaded
+    system.run_with_gas(Gas::LIMITED(4)).unwrap();
+    let state = system.save_state();
+    let cycles = system.cycles();
+
+    system.run_with_gas(Gas::LIMITED(20)).unwrap();
+
+    let mut restored = common::load_test_system("test_roms/roms/loads.gb");
+    restored.load_state(&state).unwrap();
+
+    assert_eq!(0x1A, restored.cpu().a);
+    assert_eq!(0x3C, restored.cpu().c);
+    assert_eq!(0x00, restored.cpu().d);
+    assert_eq!(0x156, restored.cpu().pc);
+    assert_eq!(cycles, restored.cycles());
+
+    // Resuming ends up in the same place as never stopping
+    restored.run_with_gas(Gas::LIMITED(20)).unwrap();
+    assert_eq!(system.cpu().l, restored.cpu().l);
+    assert_eq!(system.cpu().pc, restored.cpu().pc);
+}
+
+#[test]
+fn test_older_versions() {
+    // Saved by the builds that wrote each version, after running loads.gb for 4 instructions
+    for version in 1..=save_state::VERSION {
+        let state = common::load_test_rom_bytes(&format!(
+            "test_roms/states/loads_v{}.state",
+            version
+        ));
+        let mut system = common::load_test_system("test_roms/roms/loads.gb");
+        system.load_state(&state).unwrap();
+
+        assert_eq!(0x1A, system.cpu().a, "version {}", version);
+        assert_eq!(0x2B, system.cpu().b, "version {}", version);
+        assert_eq!(0x3C, system.cpu().c, "version {}", version);
+        assert_eq!(0x156, system.cpu().pc, "version {}", version);
+
+        system.run_with_gas(Gas::LIMITED(4)).unwrap();
+        assert_eq!(0x7F, system.cpu().l, "version {}", version);
+    }
+}
+
+#[test]
+fn test_sgb_state() {
+    let mut system = common::load_test_system("test_roms/roms/sgb.gb");
+    system.bus_mut().sgb = Some(SuperGameBoy::default());
+    system.run_with_gas(Gas::LIMITED(2000)).unwrap();
+
+    let mut restored = common::load_test_system("test_roms/roms/sgb.gb");
+    restored.load_state(&system.save_state()).unwrap();
+
+    let sgb = system.bus().sgb.as_ref().unwrap();
+    let restored_sgb = restored.bus().sgb.as_ref().unwrap();
+    assert_eq!(sgb.players(), restored_sgb.players());
+    assert_eq!(sgb.palettes(), restored_sgb.palettes());
+}
+
+#[test]
+fn test_cartridge_ram_state() {
+    // MBC1 with one 8 KiB bank of RAM
+    let mut rom = common::load_test_rom_bytes("test_roms/roms/loads.gb");
+    Fix {
+        cartridge_type: Some(0x03),
+        ram_size_code: Some(0x02),
+        ..Fix::default()
+    }
+    .apply(&mut rom)
+    .unwrap();
+
+    let mut system = System::load_cartridge(Mbc1::from_bytes(&rom).unwrap());
+    assert_eq!(0xFF, system.bus().peek(0xA000));
+
+    system.bus_mut().write_byte(0x0000, 0x0A);
+    system.bus_mut().write_byte(0xA000, 0x12);
+    system.bus_mut().write_byte(0xBFFF, 0x34);
+
+    let mut restored = System::load_cartridge(Mbc1::from_bytes(&rom).unwrap());
+    restored.load_state(&system.save_state()).unwrap();
+    assert_eq!(0x12, restored.bus().peek(0xA000));
+    assert_eq!(0x34, restored.bus().peek(0xBFFF));
+}
+
+#[test]
+fn test_wrong_rom() {
+    let system = common::load_test_system("test_roms/roms/loads.gb");
+    let state = system.save_state();
+
+    let mut other = common::load_test_system("test_roms/roms/sgb.gb");
+    assert!(matches!(
+        other.load_state(&state),
+        Err(SaveStateError::WrongRom { .. })
+    ));
+}
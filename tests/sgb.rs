@@ -0,0 +1,41 @@
+use gameboy_dot_rs::sgb::color::Color;
+use gameboy_dot_rs::sgb::super_game_boy::SuperGameBoy;
+use gameboy_dot_rs::system::Gas;
+
+mod common;
+
+#[test]
+fn test_sgb_packets() {
+    let mut system = common::load_test_system("test_roms/roms/sgb.gb");
+    system.bus_mut().sgb = Some(SuperGameBoy::default());
+
+    system.run_with_gas(Gas::LIMITED(2000)).unwrap();
+
+    let sgb = system.bus().sgb.as_ref().unwrap();
+
+    // MLT_REQ enabled a second player, and the ROM moved on to its joypad
+    assert_eq!(2, sgb.players());
+    assert_eq!(0xFE, system.cpu().b);
+
+    // PAL01 set the shared color 0 and palettes 0 and 1
+    let palettes = sgb.palettes();
+    assert_eq!(
+        [Color::WHITE, Color(0x001F), Color(0x03E0), Color(0x7C00)],
+        palettes[0]
+    );
+    assert_eq!(
+        [Color::WHITE, Color(0x0001), Color(0x0002), Color(0x0003)],
+        palettes[1]
+    );
+    assert_eq!(Color::WHITE, palettes[3][0]);
+}
+
+#[test]
+fn test_packets_ignored_without_sgb() {
+    let mut system = common::load_test_system("test_roms/roms/sgb.gb");
+
+    system.run_with_gas(Gas::LIMITED(2000)).unwrap();
+
+    // A plain Game Boy reads back no buttons pressed
+    assert_eq!(0xFF, system.cpu().b);
+}
@@ -0,0 +1,144 @@
+use common::{cpu_registers, implemented_opcodes, Access, FlatMemory};
+use gameboy_dot_rs::cpu::Cpu;
+use gameboy_dot_rs::memory::MemoryMapped;
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+mod common;
+
+/// Where the published per-opcode vectors go, one `<opcode>.json` file of tests each
+const VECTORS_DIRECTORY: &str = "test_roms/sm83";
+/// Tests in every published file, so a file cut short fails rather than quietly testing less
+const TESTS_PER_FILE: usize = 1000;
+
+#[derive(Deserialize)]
+struct Test {
+    name: String,
+    initial: State,
+    #[serde(rename = "final")]
+    expected: State,
+    /// Every machine cycle, with internal ones having no value or being null entirely
+    cycles: Vec<Option<(u16, Option<u8>, String)>>,
+}
+
+/// IE is in the vectors too, but it is on the bus rather than in the CPU
+#[derive(Deserialize)]
+struct State {
+    pc: u16,
+    sp: u16,
+    ime: u8,
+    a: u8,
+    b: u8,
+    c: u8,
+    d: u8,
+    e: u8,
+    f: u8,
+    h: u8,
+    l: u8,
+    ram: Vec<(u16, u8)>,
+}
+
+impl State {
+    /// In the order `common::cpu_registers` gives them
+    fn registers(&self) -> [u8; 8] {
+        [
+            self.b, self.c, self.d, self.e, self.h, self.l, self.f, self.a,
+        ]
+    }
+
+    fn memory(&self) -> FlatMemory {
+        let mut bytes = vec![0; 0x10000];
+        for (address, value) in &self.ram {
+            bytes[*address as usize] = *value;
+        }
+
+        FlatMemory::new(bytes)
+    }
+}
+
+/// Run one vector, checking the state it leaves and every access on the way
+///
+/// The vectors hold the CPU as the hardware does, which fetches each opcode during the last
+/// cycle of the instruction before. So the opcode has already been read and PC is one past it,
+/// and the test ends with the fetch of the next opcode.
+fn run(test: &Test) {
+    let mut memory = test.initial.memory();
+    let mut cpu = Cpu {
+        a: test.initial.a,
+        f: test.initial.f,
+        b: test.initial.b,
+        c: test.initial.c,
+        d: test.initial.d,
+        e: test.initial.e,
+        h: test.initial.h,
+        l: test.initial.l,
+        pc: test.initial.pc.wrapping_sub(1),
+        sp: test.initial.sp,
+        ime: test.initial.ime == 1,
+        ime_scheduled: false,
+    };
+
+    let cycles = cpu
+        .read_decode_execute(&mut memory)
+        .unwrap_or_else(|error| panic!("{}: {}", test.name, error));
+    memory.read_byte(cpu.pc);
+    cpu.pc = cpu.pc.wrapping_add(1);
+
+    let name = &test.name;
+    assert_eq!(test.expected.pc, cpu.pc, "{}: PC", name);
+    assert_eq!(test.expected.sp, cpu.sp, "{}: SP", name);
+    assert_eq!(test.expected.ime == 1, cpu.ime, "{}: IME", name);
+    assert_eq!(
+        test.expected.registers(),
+        cpu_registers(&cpu),
+        "{}: registers B, C, D, E, H, L, F and A",
+        name
+    );
+    for (address, value) in &test.expected.ram {
+        assert_eq!(
+            *value, memory.bytes[*address as usize],
+            "{}: memory at {:04X}",
+            name, address
+        );
+    }
+
+    let expected_accesses: Vec<Access> = test
+        .cycles
+        .iter()
+        .flatten()
+        .filter_map(|(address, value, activity)| match (value, activity.as_bytes()) {
+            (Some(value), [b'r', ..]) => Some(Access::Read(*address, *value)),
+            (Some(value), [_, b'w', ..]) => Some(Access::Write(*address, *value)),
+            _ => None,
+        })
+        .collect();
+    // Leaving out the fetch of this instruction's opcode, which the vector starts after
+    assert_eq!(
+        expected_accesses,
+        memory.accesses.borrow()[1..],
+        "{}: bus activity",
+        name
+    );
+    assert_eq!(test.cycles.len(), cycles as usize, "{}: machine cycles", name);
+}
+
+// TODO: Vendor the published files for the implemented opcodes, which couldn't be downloaded
+// where this was written, and stop ignoring this
+#[test]
+#[ignore = "the published SM83 vectors aren't in test_roms/sm83 yet"]
+fn test_vectors() {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(VECTORS_DIRECTORY);
+
+    for opcode in implemented_opcodes() {
+        let path = directory.join(format!("{:02x}.json", opcode));
+        let bytes = fs::read(&path)
+            .unwrap_or_else(|error| panic!("{}: {}", path.display(), error));
+        let tests: Vec<Test> = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(TESTS_PER_FILE, tests.len(), "tests in {}", path.display());
+
+        for test in &tests {
+            run(test);
+        }
+    }
+}
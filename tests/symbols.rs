@@ -0,0 +1,75 @@
+use gameboy_dot_rs::debugger::{Command, Debugger, Stop};
+use gameboy_dot_rs::disassembler::{self, ENTRY_POINTS};
+use gameboy_dot_rs::symbols::Symbols;
+use gameboy_dot_rs::system::Gas;
+use gameboy_dot_rs::trace::{TraceFormat, Tracer};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod common;
+
+fn joypad_log_symbols() -> Symbols {
+    let bytes = common::load_test_rom_bytes("test_roms/roms/joypad_log.sym");
+    Symbols::parse(&String::from_utf8(bytes).unwrap()).unwrap()
+}
+
+#[test]
+fn test_break_at_label() {
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/joypad_log.gb"));
+    debugger.set_symbols(joypad_log_symbols());
+
+    let command = Command::parse_with_symbols("break Loop", debugger.symbols()).unwrap();
+    debugger.execute(&command);
+
+    assert!(matches!(debugger.run(), Stop::Breakpoint(_)));
+    assert_eq!(0x0154, debugger.system().cpu().pc);
+
+    let registers = debugger.execute(&Command::Step(4));
+    assert!(registers.contains("PC=00:015B <Loop+7>"), "{}", registers);
+    assert!(registers.ends_with("jp Loop"), "{}", registers);
+}
+
+#[test]
+fn test_trace_symbols() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    let lines = Rc::new(RefCell::new(Vec::new()));
+
+    let sink = Rc::clone(&lines);
+    let format = TraceFormat::parse("{SYMBOL}: {INSTRUCTION}").unwrap();
+    system.set_tracer(
+        Tracer::new(format, move |line: &str| {
+            sink.borrow_mut().push(line.to_string())
+        })
+        .with_symbols(joypad_log_symbols()),
+    );
+    system.run_with_gas(Gas::LIMITED(4)).unwrap();
+
+    assert_eq!(
+        vec![
+            ": jp Entrypoint",
+            "Entrypoint: ld h, $C0",
+            "Entrypoint+2: ld l, $00",
+            "Loop: ld a, $20",
+        ],
+        *lines.borrow()
+    );
+}
+
+#[test]
+fn test_disassemble_with_symbols() {
+    let rom = common::load_test_rom_bytes("test_roms/roms/joypad_log.gb");
+    let lines =
+        disassembler::disassemble_recursive_with_symbols(&rom, &ENTRY_POINTS, &joypad_log_symbols());
+
+    let loop_line = lines
+        .iter()
+        .find(|line| line.bank == 0 && line.address == 0x154)
+        .unwrap();
+    assert_eq!(Some("Loop".to_string()), loop_line.label);
+
+    let jump = lines
+        .iter()
+        .find(|line| line.bank == 0 && line.address == 0x15B)
+        .unwrap();
+    assert_eq!("jp Loop", jump.text);
+}
@@ -0,0 +1,139 @@
+use gameboy_dot_rs::cpu::Cpu;
+use gameboy_dot_rs::system::Gas;
+use gameboy_dot_rs::trace::{self, TraceFormat, TraceWriter, Tracer};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+mod common;
+
+fn trace_lines(path: &str, format: TraceFormat, instructions: usize) -> Vec<String> {
+    let mut system = common::load_test_system(path);
+    let lines = Rc::new(RefCell::new(Vec::new()));
+
+    let sink = Rc::clone(&lines);
+    system.set_tracer(Tracer::new(format, move |line: &str| {
+        sink.borrow_mut().push(line.to_string())
+    }));
+    system.run_with_gas(Gas::LIMITED(instructions)).unwrap();
+
+    let lines = lines.borrow().clone();
+    lines
+}
+
+#[test]
+fn test_doctor_format() {
+    let lines = trace_lines("test_roms/roms/loads.gb", TraceFormat::default(), 3);
+
+    assert_eq!(
+        vec![
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0100 PCMEM:C3,50,01,00",
+            "A:00 F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0150 PCMEM:3E,1A,06,2B",
+            "A:1A F:00 B:00 C:00 D:00 E:00 H:00 L:00 SP:FFFE PC:0152 PCMEM:06,2B,0E,3C",
+        ],
+        lines
+    );
+}
+
+#[test]
+fn test_doctor_format_after_boot() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    *system.cpu_mut() = Cpu::after_boot();
+    let lines = Rc::new(RefCell::new(Vec::new()));
+
+    let sink = Rc::clone(&lines);
+    system.set_tracer(Tracer::new(TraceFormat::default(), move |line: &str| {
+        sink.borrow_mut().push(line.to_string())
+    }));
+    system.run_with_gas(Gas::LIMITED(1)).unwrap();
+
+    // The first line of every Gameboy Doctor log, besides the ROM's own bytes
+    assert_eq!(
+        vec!["A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:C3,50,01,00"],
+        *lines.borrow()
+    );
+}
+
+#[test]
+fn test_custom_format() {
+    let format = TraceFormat::parse("{BANK}:{PC} {INSTRUCTION} ({CYCLES})").unwrap();
+    let lines = trace_lines("test_roms/roms/loads.gb", format, 2);
+
+    assert_eq!(vec!["00:0100 jp $0150 (0)", "00:0150 ld a, $1A (4)"], lines);
+}
+
+#[test]
+fn test_sp_is_the_cpus() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    system.cpu_mut().sp = 0xDFF0;
+    let lines = Rc::new(RefCell::new(Vec::new()));
+
+    let sink = Rc::clone(&lines);
+    system.set_tracer(Tracer::new(TraceFormat::parse("{SP}").unwrap(), move |line: &str| {
+        sink.borrow_mut().push(line.to_string())
+    }));
+    system.run_with_gas(Gas::LIMITED(1)).unwrap();
+
+    assert_eq!(vec!["DFF0"], *lines.borrow());
+}
+
+#[test]
+fn test_take_tracer_stops_tracing() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    let lines = Rc::new(RefCell::new(0));
+
+    let count = Rc::clone(&lines);
+    system.set_tracer(Tracer::new(TraceFormat::default(), move |_: &str| {
+        *count.borrow_mut() += 1
+    }));
+    system.run_with_gas(Gas::LIMITED(2)).unwrap();
+
+    system.take_tracer().unwrap().finish().unwrap();
+    system.run_with_gas(Gas::LIMITED(2)).unwrap();
+
+    assert_eq!(2, *lines.borrow());
+}
+
+struct FailingWriter;
+
+impl Write for FailingWriter {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::Error::other("disk full"))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_write_errors_are_reported_on_finish() {
+    let mut system = common::load_test_system("test_roms/roms/loads.gb");
+    system.set_tracer(Tracer::new(
+        TraceFormat::default(),
+        TraceWriter::new(FailingWriter),
+    ));
+
+    // Emulation carries on regardless
+    system.run_with_gas(Gas::LIMITED(4)).unwrap();
+
+    let error = system.take_tracer().unwrap().finish().unwrap_err();
+    assert_eq!("disk full", error.to_string());
+}
+
+#[test]
+fn test_diff_traces_from_different_roms() {
+    let left = trace_lines("test_roms/roms/loads.gb", TraceFormat::default(), 4).join("\n");
+    let right = trace_lines("test_roms/roms/joypad_log.gb", TraceFormat::default(), 4).join("\n");
+
+    let divergence = trace::first_divergence(left.as_bytes(), right.as_bytes())
+        .unwrap()
+        .unwrap();
+
+    assert_eq!(2, divergence.line_number);
+    assert!(divergence.previous.as_ref().unwrap().ends_with("PC:0100 PCMEM:C3,50,01,00"));
+    assert_eq!(
+        vec![("PCMEM".to_string(), "3E,1A,06,2B".to_string(), "26,C0,2E,00".to_string())],
+        divergence.differing_fields()
+    );
+}
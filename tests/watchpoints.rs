@@ -0,0 +1,157 @@
+use gameboy_dot_rs::bus::{Access, AccessKind};
+use gameboy_dot_rs::debugger::{Command, Debugger, Stop};
+use gameboy_dot_rs::system::Gas;
+use gameboy_dot_rs::watchpoint::Watchpoint;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod common;
+
+// In joypad_log.gb
+const LDH_A_P1_ADDRESS: u16 = 0x158;
+const LD_HL_A_ADDRESS: u16 = 0x15A;
+
+#[test]
+fn test_write_watchpoint() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    system
+        .bus_mut()
+        .add_watchpoint(Watchpoint::new(AccessKind::Write, 0xC000..=0xDFFF));
+
+    system.run().unwrap();
+
+    assert_eq!(
+        Some(Access {
+            kind: AccessKind::Write,
+            pc: LD_HL_A_ADDRESS,
+            address: 0xC000,
+            bank: None,
+            old_value: 0x00,
+            new_value: 0xEF,
+        }),
+        system.watchpoint_hit()
+    );
+    // Paused once the write finished
+    assert_eq!(LD_HL_A_ADDRESS + 1, system.cpu().pc);
+
+    // Resuming carries on to the next hit
+    system.run().unwrap();
+    assert_eq!(0xC001, system.watchpoint_hit().unwrap().address);
+}
+
+#[test]
+fn test_value_filter() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    system.bus_mut().add_watchpoint(Watchpoint {
+        // Right pressed
+        value: Some(0xEE),
+        ..Watchpoint::new(AccessKind::Write, 0xC000..=0xDFFF)
+    });
+
+    system.run_frame().unwrap();
+    assert_eq!(None, system.watchpoint_hit());
+
+    system.bus_mut().joypad.buttons.right = true;
+    system.run_frame().unwrap();
+    let hit = system.watchpoint_hit().unwrap();
+    assert_eq!(0xEE, hit.new_value);
+    assert_eq!(LD_HL_A_ADDRESS, hit.pc);
+}
+
+#[test]
+fn test_read_and_execute_watchpoints() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    system
+        .bus_mut()
+        .add_watchpoint(Watchpoint::new(AccessKind::Read, 0xFF00..=0xFF00));
+    system.run().unwrap();
+    let hit = system.watchpoint_hit().unwrap();
+    assert_eq!((AccessKind::Read, LDH_A_P1_ADDRESS, 0xEF), (hit.kind, hit.pc, hit.new_value));
+
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+    // The code is all in bank 0, so this never matches
+    system.bus_mut().add_watchpoint(Watchpoint {
+        bank: Some(1),
+        ..Watchpoint::new(AccessKind::Execute, LDH_A_P1_ADDRESS..=LDH_A_P1_ADDRESS)
+    });
+    system.run_with_gas(Gas::LIMITED(20)).unwrap();
+    assert_eq!(None, system.watchpoint_hit());
+
+    system.bus_mut().add_watchpoint(Watchpoint {
+        bank: Some(0),
+        ..Watchpoint::new(AccessKind::Execute, LDH_A_P1_ADDRESS..=LDH_A_P1_ADDRESS)
+    });
+    system.run().unwrap();
+    let hit = system.watchpoint_hit().unwrap();
+    assert_eq!(
+        (AccessKind::Execute, Some(0), 0xF0),
+        (hit.kind, hit.bank, hit.new_value)
+    );
+    assert_eq!(LD_HL_A_ADDRESS, system.cpu().pc);
+}
+
+#[test]
+fn test_observer() {
+    let mut system = common::load_test_system("test_roms/roms/joypad_log.gb");
+
+    let writes = Rc::new(RefCell::new(Vec::new()));
+    let observer_writes = Rc::clone(&writes);
+    let id = system.bus_mut().add_observer(move |access: &Access| {
+        if access.kind == AccessKind::Write {
+            observer_writes.borrow_mut().push((access.pc, access.address, access.new_value));
+        }
+    });
+
+    // Up to the second write to WRAM
+    system.run_with_gas(Gas::LIMITED(12)).unwrap();
+    assert_eq!(
+        vec![
+            (0x156, 0xFF00, 0x20),
+            (LD_HL_A_ADDRESS, 0xC000, 0xEF),
+            (0x156, 0xFF00, 0x20),
+            (LD_HL_A_ADDRESS, 0xC001, 0xEF),
+        ],
+        *writes.borrow()
+    );
+
+    assert!(system.bus_mut().remove_observer(id));
+    assert!(!system.bus_mut().remove_observer(id));
+    system.run_with_gas(Gas::LIMITED(12)).unwrap();
+    assert_eq!(4, writes.borrow().len());
+}
+
+#[test]
+fn test_debugger_watch() {
+    let mut debugger = Debugger::new(common::load_test_system("test_roms/roms/joypad_log.gb"));
+
+    debugger.execute(&Command::parse("watch w C001").unwrap());
+    assert_eq!(
+        "write C001",
+        debugger.execute(&Command::parse("watch").unwrap())
+    );
+
+    let stop = debugger.run();
+    assert!(matches!(stop, Stop::Watchpoint(Access { address: 0xC001, .. })));
+    assert_eq!(
+        "hit watchpoint, wrote EF to C001, which held 00, at PC 015A",
+        stop.to_string()
+    );
+
+    // Inspecting memory isn't an access the watchpoint sees
+    debugger.execute(&Command::parse("unwatch w C001").unwrap());
+    debugger.execute(&Command::parse("watch r C000-C0FF").unwrap());
+    debugger.execute(&Command::parse("x C000 32").unwrap());
+    assert_eq!(None, debugger.step(3));
+}
+
+#[test]
+fn test_read_watchpoint_sees_dma() {
+    let mut system = common::load_test_system("test_roms/roms/hdma.gb");
+    system
+        .bus_mut()
+        .add_watchpoint(Watchpoint::new(AccessKind::Read, 0x0200..=0x021F));
+
+    system.run().unwrap();
+    let hit = system.watchpoint_hit().unwrap();
+    assert_eq!((AccessKind::Dma, 0x0200, 0x00), (hit.kind, hit.address, hit.new_value));
+}